@@ -0,0 +1,24 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+pub fn main() -> Result<(), Box<dyn Error>> {
+    let src = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("src");
+    let proto_dir = src.join("services").join("external").join("proto");
+    let coprocessor_src = proto_dir.join("coprocessor.proto");
+
+    println!(
+        "cargo:rerun-if-changed={}",
+        coprocessor_src.to_str().unwrap()
+    );
+
+    // Only the messages are generated: the client is written by hand in
+    // `services/external/grpc.rs` so that it can use the same prost version as the rest
+    // of the router.
+    tonic_build::configure()
+        .build_client(false)
+        .build_server(false)
+        .emit_rerun_if_changed(false)
+        .compile(&[coprocessor_src], &[proto_dir])?;
+
+    Ok(())
+}
@@ -14,6 +14,7 @@ use axum::middleware;
 use axum::middleware::Next;
 use axum::response::*;
 use axum::routing::get;
+use axum::routing::MethodRouter;
 use axum::Router;
 use futures::channel::oneshot;
 use futures::future::join_all;
@@ -30,6 +31,7 @@ use serde::Serialize;
 #[cfg(unix)]
 use tokio::net::UnixListener;
 use tokio::sync::mpsc;
+use tokio::sync::watch;
 use tokio_rustls::TlsAcceptor;
 use tower::service_fn;
 use tower::BoxError;
@@ -44,7 +46,10 @@ use super::listeners::ensure_endpoints_consistency;
 use super::listeners::ensure_listenaddrs_consistency;
 use super::listeners::extra_endpoints;
 use super::listeners::ListenersAndRouters;
+use super::sse;
+use super::sse::EventStreams;
 use super::utils::PropagatingMakeSpan;
+use super::websocket;
 use super::ListenAddrAndRouter;
 use super::ENDPOINT_CALLBACK;
 use crate::axum_factory::compression::Compressor;
@@ -55,6 +60,9 @@ use crate::configuration::ListenAddr;
 use crate::http_server_factory::HttpServerFactory;
 use crate::http_server_factory::HttpServerHandle;
 use crate::http_server_factory::Listener;
+use crate::plugins::subscription::ClientEventStreamConfig;
+use crate::plugins::subscription::ClientWebSocketConfig;
+use crate::plugins::subscription::SubscriptionConfig;
 use crate::plugins::telemetry::SpanMode;
 use crate::plugins::traffic_shaping::Elapsed;
 use crate::plugins::traffic_shaping::RateLimited;
@@ -123,6 +131,7 @@ pub(crate) fn make_axum_router<RF>(
     configuration: &Configuration,
     mut endpoints: MultiMap<ListenAddr, Endpoint>,
     license: LicenseState,
+    websocket_shutdown: watch::Receiver<bool>,
 ) -> Result<ListenersAndRouters, ApolloRouterError>
 where
     RF: RouterFactory,
@@ -200,6 +209,7 @@ where
             .remove(&configuration.supergraph.listen)
             .unwrap_or_default(),
         license,
+        websocket_shutdown,
     )?;
     let mut extra_endpoints = extra_endpoints(endpoints);
 
@@ -235,6 +245,8 @@ impl HttpServerFactory for AxumHttpServerFactory {
         let live = self.live.clone();
         let ready = self.ready.clone();
         Box::pin(async move {
+            // Tells the WebSocket connections of the main server that it stopped
+            let (websocket_shutdown_sender, websocket_shutdown) = watch::channel(false);
             let all_routers = make_axum_router(
                 live.clone(),
                 ready.clone(),
@@ -242,6 +254,7 @@ impl HttpServerFactory for AxumHttpServerFactory {
                 &configuration,
                 extra_endpoints,
                 license,
+                websocket_shutdown,
             )?;
 
             // serve main router
@@ -363,6 +376,7 @@ impl HttpServerFactory for AxumHttpServerFactory {
                 oneshot::channel::<()>();
             tokio::task::spawn(async move {
                 let _ = outer_main_shutdown_receiver.await;
+                let _ = websocket_shutdown_sender.send(true);
                 if let Err(_err) = main_shutdown_sender.send(()) {
                     tracing::error!("Failed to notify http thread of shutdown");
                 }
@@ -435,6 +449,7 @@ fn main_endpoint<RF>(
     configuration: &Configuration,
     endpoints_on_main_listener: Vec<Endpoint>,
     license: LicenseState,
+    websocket_shutdown: watch::Receiver<bool>,
 ) -> Result<ListenAddrAndRouter, ApolloRouterError>
 where
     RF: RouterFactory,
@@ -452,7 +467,7 @@ where
                 .gzip(true)
                 .deflate(true),
         );
-    let mut main_route = main_router::<RF>(configuration)
+    let mut main_route = main_router::<RF>(configuration, websocket_shutdown)
         .layer(decompression)
         .layer(middleware::from_fn_with_state(
             (license, Instant::now(), Arc::new(AtomicU64::new(0))),
@@ -548,44 +563,29 @@ async fn license_handler<B>(
 
 pub(super) fn main_router<RF>(
     configuration: &Configuration,
+    websocket_shutdown: watch::Receiver<bool>,
 ) -> axum::Router<(), DecompressionBody<Body>>
 where
     RF: RouterFactory,
 {
     let early_cancel = configuration.supergraph.early_cancel;
     let experimental_log_on_broken_pipe = configuration.supergraph.experimental_log_on_broken_pipe;
+    let client_websocket = client_websocket(configuration);
+    let event_streams = client_event_stream(configuration).map(EventStreams::new);
     let mut router = Router::new().route(
         &configuration.supergraph.sanitized_path(),
-        get({
-            move |Extension(service): Extension<RF>, request: Request<DecompressionBody<Body>>| {
-                handle_graphql(
-                    service.create().boxed(),
-                    early_cancel,
-                    experimental_log_on_broken_pipe,
-                    request,
-                )
-            }
-        })
-        .post({
-            move |Extension(service): Extension<RF>, request: Request<DecompressionBody<Body>>| {
-                handle_graphql(
-                    service.create().boxed(),
-                    early_cancel,
-                    experimental_log_on_broken_pipe,
-                    request,
-                )
-            }
-        }),
-    );
-
-    if configuration.supergraph.path == "/*" {
-        router = router.route(
-            "/",
+        with_event_streams(
             get({
+                let client_websocket = client_websocket.clone();
+                let websocket_shutdown = websocket_shutdown.clone();
+                let event_streams = event_streams.clone();
                 move |Extension(service): Extension<RF>,
                       request: Request<DecompressionBody<Body>>| {
-                    handle_graphql(
-                        service.create().boxed(),
+                    handle_get(
+                        service,
+                        client_websocket.clone(),
+                        websocket_shutdown.clone(),
+                        event_streams.clone(),
                         early_cancel,
                         experimental_log_on_broken_pipe,
                         request,
@@ -593,22 +593,164 @@ where
                 }
             })
             .post({
+                let event_streams = event_streams.clone();
                 move |Extension(service): Extension<RF>,
                       request: Request<DecompressionBody<Body>>| {
-                    handle_graphql(
-                        service.create().boxed(),
+                    handle_post(
+                        service,
+                        event_streams.clone(),
                         early_cancel,
                         experimental_log_on_broken_pipe,
                         request,
                     )
                 }
             }),
+            event_streams.clone(),
+        ),
+    );
+
+    if configuration.supergraph.path == "/*" {
+        router = router.route(
+            "/",
+            with_event_streams(
+                get({
+                    let event_streams = event_streams.clone();
+                    move |Extension(service): Extension<RF>,
+                          request: Request<DecompressionBody<Body>>| {
+                        handle_get(
+                            service,
+                            client_websocket.clone(),
+                            websocket_shutdown.clone(),
+                            event_streams.clone(),
+                            early_cancel,
+                            experimental_log_on_broken_pipe,
+                            request,
+                        )
+                    }
+                })
+                .post({
+                    let event_streams = event_streams.clone();
+                    move |Extension(service): Extension<RF>,
+                          request: Request<DecompressionBody<Body>>| {
+                        handle_post(
+                            service,
+                            event_streams.clone(),
+                            early_cancel,
+                            experimental_log_on_broken_pipe,
+                            request,
+                        )
+                    }
+                }),
+                event_streams,
+            ),
         );
     }
 
     router
 }
 
+// Event streams of the single connection mode are reserved and stopped with PUT and DELETE requests
+fn with_event_streams(
+    method_router: MethodRouter<(), DecompressionBody<Body>>,
+    event_streams: Option<EventStreams>,
+) -> MethodRouter<(), DecompressionBody<Body>> {
+    match event_streams {
+        Some(event_streams) => method_router
+            .put({
+                let event_streams = event_streams.clone();
+                move || futures::future::ready(event_streams.reserve())
+            })
+            .delete(move |request: Request<DecompressionBody<Body>>| {
+                futures::future::ready(event_streams.stop(&request))
+            }),
+        None => method_router,
+    }
+}
+
+// Subscriptions over WebSocket are only accepted when enabled in the subscription plugin
+fn client_websocket(configuration: &Configuration) -> Option<ClientWebSocketConfig> {
+    configuration
+        .apollo_plugins
+        .plugins
+        .get("subscription")
+        .and_then(|v| serde_json::from_value::<SubscriptionConfig>(v.clone()).ok())
+        .filter(|config| config.enabled && config.client_websocket.enabled)
+        .map(|config| config.client_websocket)
+}
+
+// The single connection mode of Server-Sent Events is only accepted when enabled in the subscription plugin
+fn client_event_stream(configuration: &Configuration) -> Option<ClientEventStreamConfig> {
+    configuration
+        .apollo_plugins
+        .plugins
+        .get("subscription")
+        .and_then(|v| serde_json::from_value::<SubscriptionConfig>(v.clone()).ok())
+        .filter(|config| config.enabled && config.client_event_stream.enabled)
+        .map(|config| config.client_event_stream)
+}
+
+async fn handle_get<RF>(
+    service_factory: RF,
+    client_websocket: Option<ClientWebSocketConfig>,
+    websocket_shutdown: watch::Receiver<bool>,
+    event_streams: Option<EventStreams>,
+    early_cancel: bool,
+    experimental_log_on_broken_pipe: bool,
+    http_request: Request<DecompressionBody<Body>>,
+) -> Response
+where
+    RF: RouterFactory,
+{
+    match client_websocket {
+        Some(config) if websocket::is_upgrade_request(&http_request) => {
+            websocket::handle_upgrade(service_factory, config, websocket_shutdown, http_request)
+                .await
+        }
+        _ => match event_streams
+            .as_ref()
+            .and_then(|event_streams| event_streams.open(&http_request))
+        {
+            Some(response) => response,
+            None => handle_graphql(
+                service_factory.create().boxed(),
+                early_cancel,
+                experimental_log_on_broken_pipe,
+                http_request,
+            )
+            .await
+            .into_response(),
+        },
+    }
+}
+
+async fn handle_post<RF>(
+    service_factory: RF,
+    event_streams: Option<EventStreams>,
+    early_cancel: bool,
+    experimental_log_on_broken_pipe: bool,
+    http_request: Request<DecompressionBody<Body>>,
+) -> Response
+where
+    RF: RouterFactory,
+{
+    // Operations of event streams in single connection mode
+    match event_streams.zip(sse::stream_token(&http_request)) {
+        Some((event_streams, token)) => {
+            event_streams
+                .execute(service_factory, token, http_request)
+                .await
+        }
+        None => handle_graphql(
+            service_factory.create().boxed(),
+            early_cancel,
+            experimental_log_on_broken_pipe,
+            http_request,
+        )
+        .await
+        .into_response(),
+    }
+}
+
 async fn handle_graphql(
     service: router::BoxService,
     early_cancel: bool,
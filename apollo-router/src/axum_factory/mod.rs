@@ -2,9 +2,12 @@
 mod axum_http_server_factory;
 pub(crate) mod compression;
 mod listeners;
+mod operations;
+pub(crate) mod sse;
 #[cfg(test)]
 pub(crate) mod tests;
 pub(crate) mod utils;
+pub(crate) mod websocket;
 
 use std::sync::Arc;
 use std::sync::OnceLock;
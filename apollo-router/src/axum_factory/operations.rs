@@ -0,0 +1,127 @@
+//! Operations sent by clients on long lived connections.
+//!
+//! The WebSocket transport and the single connection mode of the event stream transport both
+//! run each operation through the router service as an HTTP request, then decode the responses
+//! it returns, whatever format it picked.
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use http::header::ACCEPT;
+use http::header::CONTENT_TYPE;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::Method;
+use http::Uri;
+use hyper::Body;
+use serde::Deserialize;
+use tower::BoxError;
+
+use crate::graphql;
+use crate::services::router;
+use crate::services::APPLICATION_JSON_HEADER_VALUE;
+
+// Operations accept every response format, the router service picks the one matching the operation
+static ACCEPT_HEADER_VALUE: HeaderValue = HeaderValue::from_static(
+    "multipart/mixed;subscriptionSpec=1.0, multipart/mixed;deferSpec=20220824, application/json",
+);
+
+/// Responses of an operation executed through the router service
+pub(super) enum OperationResponse {
+    /// The operation returned a single response
+    Single(graphql::Response),
+    /// The operation is a subscription or a deferred query
+    Stream(BoxStream<'static, Result<graphql::Response, BoxError>>),
+}
+
+/// Creates the router service request executing an operation
+pub(super) fn router_request(
+    uri: &Uri,
+    headers: &HeaderMap,
+    payload: &graphql::Request,
+) -> Result<router::Request, BoxError> {
+    let mut http_request = http::Request::builder()
+        .method(Method::POST)
+        .uri(uri.clone())
+        .body(Body::from(serde_json::to_vec(payload)?))?;
+    *http_request.headers_mut() = headers.clone();
+    http_request
+        .headers_mut()
+        .insert(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE.clone());
+    http_request
+        .headers_mut()
+        .insert(ACCEPT, ACCEPT_HEADER_VALUE.clone());
+
+    Ok(router::Request::from(http_request))
+}
+
+/// Decodes the response of the router service
+pub(super) async fn read_response(
+    response: router::Response,
+) -> Result<OperationResponse, BoxError> {
+    let is_multipart = response
+        .response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value.starts_with("multipart/"));
+
+    if !is_multipart {
+        let bytes = hyper::body::to_bytes(response.response.into_body()).await?;
+        return Ok(OperationResponse::Single(serde_json::from_slice(&bytes)?));
+    }
+
+    let multipart = multer::Multipart::new(response.response.into_body(), "graphql");
+    let stream = futures::stream::unfold(Some(multipart), |multipart| async move {
+        let mut multipart = multipart?;
+        loop {
+            let part = match multipart.next_field().await {
+                Ok(Some(field)) => field.bytes().await.map_err(BoxError::from),
+                Ok(None) => return None,
+                Err(err) => Err(err.into()),
+            };
+            match part.and_then(|bytes| Ok(serde_json::from_slice::<MultipartChunk>(&bytes)?)) {
+                Ok(chunk) => match chunk.into_response() {
+                    Some(response) => return Some((Ok(response), Some(multipart))),
+                    // Heartbeat
+                    None => continue,
+                },
+                Err(err) => return Some((Err(err), None)),
+            }
+        }
+    });
+    Ok(OperationResponse::Stream(stream.boxed()))
+}
+
+/// A part of a multipart response from the router service
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MultipartChunk {
+    Subscription(SubscriptionChunk),
+    Incremental(graphql::Response),
+}
+
+/// A subscription event, or a heartbeat when it's empty
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubscriptionChunk {
+    #[serde(default)]
+    payload: Option<graphql::Response>,
+    #[serde(default)]
+    errors: Vec<graphql::Error>,
+}
+
+impl MultipartChunk {
+    fn into_response(self) -> Option<graphql::Response> {
+        match self {
+            MultipartChunk::Subscription(SubscriptionChunk { payload, errors }) => {
+                if payload.is_none() && errors.is_empty() {
+                    return None;
+                }
+                let mut payload = payload.unwrap_or_default();
+                payload.errors.extend(errors);
+                Some(payload)
+            }
+            MultipartChunk::Incremental(payload) => Some(payload),
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! Single connection mode of the GraphQL over Server-Sent Events protocol.
+//!
+//! Clients reserve an event stream with a PUT request on the GraphQL endpoint, which returns a
+//! token, then open it with a GET request accepting `text/event-stream` and carrying that token.
+//! Operations are sent in POST requests carrying the token too, once the stream is open: they go
+//! through the router service like any other request, and their results are sent on the event
+//! stream, identified by the `operationId` extension of the request. A DELETE request with an
+//! `operationId` query parameter stops an operation.
+//!
+//! This mode is only enabled by `subscription.client_event_stream`, which also limits the number
+//! of streams and of operations per stream.
+//!
+//! The distinct connections mode doesn't need anything here: the router service answers
+//! requests accepting `text/event-stream` with an event stream directly.
+//!
+//! See <https://github.com/enisdenjo/graphql-sse/blob/master/PROTOCOL.md>
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::response::IntoResponse;
+use axum::response::Response;
+use bytes::Bytes;
+use futures::StreamExt;
+use http::header::ACCEPT;
+use http::header::CACHE_CONTROL;
+use http::header::CONTENT_LENGTH;
+use http::header::CONTENT_TYPE;
+use http::Request;
+use http::StatusCode;
+use hyper::Body;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::IntervalStream;
+use tokio_stream::wrappers::ReceiverStream;
+use tower::BoxError;
+use tower::ServiceExt;
+use tower_http::decompression::DecompressionBody;
+use tracing::instrument::WithSubscriber;
+
+use super::operations;
+use super::operations::OperationResponse;
+use crate::graphql;
+use crate::plugins::subscription::ClientEventStreamConfig;
+use crate::protocols::sse::complete_event;
+use crate::protocols::sse::event_id;
+use crate::protocols::sse::next_event;
+use crate::protocols::sse::HEARTBEAT_EVENT;
+use crate::protocols::sse::HEARTBEAT_INTERVAL;
+use crate::router_factory::RouterFactory;
+use crate::services::router;
+use crate::services::EVENT_STREAM_ACCEPT;
+
+const STREAM_TOKEN_HEADER: &str = "x-graphql-event-stream-token";
+const STREAM_TOKEN_PARAMETER: &str = "token";
+const OPERATION_ID: &str = "operationId";
+
+const OUTGOING_QUEUE_CAPACITY: usize = 32;
+
+/// Event streams reserved by clients, by token
+#[derive(Clone)]
+pub(crate) struct EventStreams {
+    config: Arc<ClientEventStreamConfig>,
+    streams: Arc<Mutex<HashMap<String, ReservedStream>>>,
+}
+
+struct ReservedStream {
+    sender: mpsc::Sender<Bytes>,
+    // Taken when the client opens the stream
+    receiver: Option<mpsc::Receiver<Bytes>>,
+    operations: HashMap<String, JoinHandle<()>>,
+}
+
+impl ReservedStream {
+    fn close(self) {
+        for (_, operation) in self.operations {
+            operation.abort();
+        }
+    }
+}
+
+/// Payload of the events sent for an operation
+#[derive(Serialize)]
+struct OperationEvent<'a> {
+    id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<graphql::Response>,
+}
+
+/// Returns the event stream token of a request, if it has one
+pub(crate) fn stream_token<B>(request: &Request<B>) -> Option<String> {
+    request
+        .headers()
+        .get(STREAM_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+fn query_parameter<B>(request: &Request<B>, name: &str) -> Option<String> {
+    url::form_urlencoded::parse(request.uri().query()?.as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn accepts_event_stream<B>(request: &Request<B>) -> bool {
+    request
+        .headers()
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains(EVENT_STREAM_ACCEPT))
+}
+
+impl EventStreams {
+    pub(crate) fn new(config: ClientEventStreamConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            streams: Default::default(),
+        }
+    }
+
+    /// Reserves an event stream, and returns its token
+    pub(crate) fn reserve(&self) -> Response {
+        let token = uuid::Uuid::new_v4().to_string();
+        let (sender, receiver) = mpsc::channel(OUTGOING_QUEUE_CAPACITY);
+
+        {
+            let mut streams = self.streams.lock();
+            if streams.len() >= self.config.max_streams {
+                return (StatusCode::SERVICE_UNAVAILABLE, "Too many event streams").into_response();
+            }
+            streams.insert(
+                token.clone(),
+                ReservedStream {
+                    sender,
+                    receiver: Some(receiver),
+                    operations: HashMap::new(),
+                },
+            );
+        }
+
+        // Reserved streams that are never opened are dropped after the reservation timeout
+        let event_streams = self.clone();
+        let expired_token = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(event_streams.config.reservation_timeout).await;
+            event_streams.expire(&expired_token);
+        });
+
+        (
+            StatusCode::CREATED,
+            [(CONTENT_TYPE, "text/plain; charset=utf-8")],
+            token,
+        )
+            .into_response()
+    }
+
+    /// Opens a reserved event stream, if the request is asking for it
+    pub(crate) fn open<B>(&self, request: &Request<B>) -> Option<Response> {
+        // Browsers can't set headers on `EventSource` connections
+        let token =
+            stream_token(request).or_else(|| query_parameter(request, STREAM_TOKEN_PARAMETER))?;
+        if !accepts_event_stream(request) {
+            return None;
+        }
+
+        let receiver = match self.streams.lock().get_mut(&token) {
+            None => return Some((StatusCode::NOT_FOUND, "Stream not found").into_response()),
+            Some(stream) => match stream.receiver.take() {
+                None => return Some((StatusCode::CONFLICT, "Stream already open").into_response()),
+                Some(receiver) => receiver,
+            },
+        };
+
+        // The stream is closed when the client disconnects
+        let guard = CloseOnDrop {
+            streams: self.clone(),
+            token,
+        };
+        let heartbeat = IntervalStream::new(tokio::time::interval_at(
+            tokio::time::Instant::now() + HEARTBEAT_INTERVAL,
+            HEARTBEAT_INTERVAL,
+        ))
+        .map(|_| Bytes::from_static(HEARTBEAT_EVENT));
+        let events =
+            futures::stream::select(ReceiverStream::new(receiver), heartbeat).map(move |event| {
+                let _guard = &guard;
+                Ok::<_, Infallible>(event)
+            });
+
+        Some(
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, EVENT_STREAM_ACCEPT)
+                .header(CACHE_CONTROL, "no-cache")
+                .header("x-accel-buffering", "no")
+                .body(Body::wrap_stream(events))
+                .expect("cannot fail")
+                .into_response(),
+        )
+    }
+
+    /// Starts an operation on an event stream, its results are sent on the stream
+    pub(crate) async fn execute<RF>(
+        &self,
+        service_factory: RF,
+        token: String,
+        request: Request<DecompressionBody<Body>>,
+    ) -> Response
+    where
+        RF: RouterFactory,
+    {
+        if let Err(response) = self.check_operation_slot(self.streams.lock().get(&token)) {
+            return response;
+        }
+
+        let (mut parts, body) = request.into_parts();
+        let payload = match hyper::body::to_bytes(body)
+            .await
+            .map_err(BoxError::from)
+            .and_then(|bytes| Ok(serde_json::from_slice::<graphql::Request>(&bytes)?))
+        {
+            Ok(payload) => payload,
+            Err(err) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid GraphQL request: {err}"),
+                )
+                    .into_response()
+            }
+        };
+        let Some(id) = payload
+            .extensions
+            .get(OPERATION_ID)
+            .and_then(|id| id.as_str())
+            .map(str::to_string)
+        else {
+            return (StatusCode::BAD_REQUEST, "Operation ID is missing").into_response();
+        };
+
+        parts.headers.remove(STREAM_TOKEN_HEADER);
+        parts.headers.remove(CONTENT_LENGTH);
+        let request = match operations::router_request(&parts.uri, &parts.headers, &payload) {
+            Ok(request) => request,
+            Err(err) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid GraphQL request: {err}"),
+                )
+                    .into_response()
+            }
+        };
+
+        // The stream may have been closed, or filled, while the request was read
+        let mut streams = self.streams.lock();
+        let stream = match streams.get_mut(&token) {
+            Some(stream) => match self.check_operation_slot(Some(stream)) {
+                Ok(()) => stream,
+                Err(response) => return response,
+            },
+            None => return (StatusCode::NOT_FOUND, "Stream not found").into_response(),
+        };
+        if stream.operations.contains_key(&id) {
+            return (
+                StatusCode::CONFLICT,
+                format!("Operation with ID {id} already exists"),
+            )
+                .into_response();
+        }
+        let operation = execute(
+            service_factory.create().boxed(),
+            self.clone(),
+            token,
+            id.clone(),
+            request,
+            stream.sender.clone(),
+        );
+        stream
+            .operations
+            .insert(id, tokio::task::spawn(operation.with_current_subscriber()));
+
+        StatusCode::ACCEPTED.into_response()
+    }
+
+    /// Stops an operation started on an event stream
+    pub(crate) fn stop<B>(&self, request: &Request<B>) -> Response {
+        let Some(token) = stream_token(request) else {
+            return (StatusCode::UNAUTHORIZED, "Stream token is missing").into_response();
+        };
+        let Some(id) = query_parameter(request, OPERATION_ID) else {
+            return (StatusCode::BAD_REQUEST, "Operation ID is missing").into_response();
+        };
+
+        match self.streams.lock().get_mut(&token) {
+            None => (StatusCode::NOT_FOUND, "Stream not found").into_response(),
+            Some(stream) => {
+                if let Some(operation) = stream.operations.remove(&id) {
+                    operation.abort();
+                }
+                StatusCode::OK.into_response()
+            }
+        }
+    }
+
+    // Operations are only accepted on open streams, so that their results are read
+    fn check_operation_slot(&self, stream: Option<&ReservedStream>) -> Result<(), Response> {
+        match stream {
+            None => Err((StatusCode::NOT_FOUND, "Stream not found").into_response()),
+            Some(stream) if stream.receiver.is_some() => {
+                Err((StatusCode::CONFLICT, "Stream not open").into_response())
+            }
+            Some(stream) if stream.operations.len() >= self.config.max_operations_per_stream => {
+                Err((StatusCode::TOO_MANY_REQUESTS, "Too many operations").into_response())
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn expire(&self, token: &str) {
+        let mut streams = self.streams.lock();
+        if streams
+            .get(token)
+            .is_some_and(|stream| stream.receiver.is_some())
+        {
+            if let Some(stream) = streams.remove(token) {
+                stream.close();
+            }
+        }
+    }
+
+    fn complete(&self, token: &str, id: &str) {
+        if let Some(stream) = self.streams.lock().get_mut(token) {
+            stream.operations.remove(id);
+        }
+    }
+}
+
+struct CloseOnDrop {
+    streams: EventStreams,
+    token: String,
+}
+
+impl Drop for CloseOnDrop {
+    fn drop(&mut self) {
+        if let Some(stream) = self.streams.streams.lock().remove(&self.token) {
+            stream.close();
+        }
+    }
+}
+
+/// Runs an operation through the router service, and sends its responses to the event stream
+async fn execute(
+    service: router::BoxService,
+    streams: EventStreams,
+    token: String,
+    id: String,
+    request: router::Request,
+    sender: mpsc::Sender<Bytes>,
+) {
+    let payloads = match service.oneshot(request).await {
+        Ok(response) => match operations::read_response(response).await {
+            Ok(OperationResponse::Single(payload)) => {
+                futures::stream::once(async move { Ok(payload) }).boxed()
+            }
+            Ok(OperationResponse::Stream(stream)) => stream,
+            Err(err) => futures::stream::once(async move {
+                Err::<graphql::Response, _>(format!("cannot read response: {err}").into())
+            })
+            .boxed(),
+        },
+        Err(err) => futures::stream::once(async move {
+            Err::<graphql::Response, _>(format!("router service call failed: {err}").into())
+        })
+        .boxed(),
+    };
+
+    let mut payloads = payloads.map(|payload| {
+        payload.unwrap_or_else(|err: BoxError| {
+            graphql::Response::builder()
+                .errors(vec![graphql::Error::builder()
+                    .message(err.to_string())
+                    .extension_code("EVENT_STREAM_OPERATION_ERROR")
+                    .build()])
+                .build()
+        })
+    });
+    while let Some(payload) = payloads.next().await {
+        let event_id = event_id(&payload).map(str::to_string);
+        let event = next_event(
+            &OperationEvent {
+                id: &id,
+                payload: Some(payload),
+            },
+            event_id.as_deref(),
+        );
+        match event {
+            Ok(event) if sender.send(event).await.is_ok() => {}
+            _ => return streams.complete(&token, &id),
+        }
+    }
+    if let Ok(event) = complete_event(Some(&OperationEvent {
+        id: &id,
+        payload: None,
+    })) {
+        let _ = sender.send(event).await;
+    }
+    streams.complete(&token, &id);
+}
@@ -1170,7 +1170,7 @@ async fn it_errors_on_bad_accept_header() -> Result<(), ApolloRouterError> {
     );
     assert_eq!(
         response.text().await.unwrap(),
-        r#"{"errors":[{"message":"'accept' header must be one of: \\\"*/*\\\", \"application/json\", \"application/graphql-response+json\", \"multipart/mixed;subscriptionSpec=1.0\" or \"multipart/mixed;deferSpec=20220824\"","extensions":{"code":"INVALID_ACCEPT_HEADER"}}]}"#
+        r#"{"errors":[{"message":"'accept' header must be one of: \\\"*/*\\\", \"application/json\", \"application/graphql-response+json\", \"multipart/mixed;subscriptionSpec=1.0\", \"multipart/mixed;deferSpec=20220824\" or \"text/event-stream\"","extensions":{"code":"INVALID_ACCEPT_HEADER"}}]}"#
     );
 
     server.shutdown().await
@@ -2387,3 +2387,644 @@ async fn test_supergraph_timeout() {
     let body = response.bytes().await.unwrap();
     assert_eq!(std::str::from_utf8(&body).unwrap(), "request timed out");
 }
+
+async fn init_with_client_websocket(
+    router_service: impl Service<
+            router::Request,
+            Response = router::Response,
+            Error = BoxError,
+            Future = BoxFuture<'static, router::ServiceResult>,
+        > + Send
+        + 'static,
+    client_websocket: serde_json::Value,
+) -> HttpServerHandle {
+    let conf = Configuration::fake_builder()
+        .apollo_plugins(
+            json!({
+                "subscription": {
+                    "enabled": true,
+                    "client_websocket": client_websocket
+                }
+            })
+            .as_object()
+            .unwrap()
+            .clone(),
+        )
+        .build()
+        .unwrap();
+    let (server, _client) = init_with_config(router_service, Arc::new(conf), MultiMap::new())
+        .await
+        .unwrap();
+    server
+}
+
+async fn init_with_client_event_stream(
+    router_service: impl Service<
+            router::Request,
+            Response = router::Response,
+            Error = BoxError,
+            Future = BoxFuture<'static, router::ServiceResult>,
+        > + Send
+        + 'static,
+    client_event_stream: serde_json::Value,
+) -> (HttpServerHandle, Client) {
+    let conf = Configuration::fake_builder()
+        .apollo_plugins(
+            json!({
+                "subscription": {
+                    "enabled": true,
+                    "client_event_stream": client_event_stream
+                }
+            })
+            .as_object()
+            .unwrap()
+            .clone(),
+        )
+        .build()
+        .unwrap();
+    init_with_config(router_service, Arc::new(conf), MultiMap::new())
+        .await
+        .unwrap()
+}
+
+async fn connect_websocket(
+    server: &HttpServerHandle,
+    subprotocol: &'static str,
+) -> tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>> {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    let url = server
+        .graphql_listen_address()
+        .as_ref()
+        .unwrap()
+        .to_string()
+        .replacen("http", "ws", 1);
+    let mut request = url.into_client_request().unwrap();
+    request.headers_mut().insert(
+        header::SEC_WEBSOCKET_PROTOCOL,
+        HeaderValue::from_static(subprotocol),
+    );
+    let (socket, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+    assert_eq!(
+        response.headers().get(header::SEC_WEBSOCKET_PROTOCOL),
+        Some(&HeaderValue::from_static(subprotocol))
+    );
+    socket
+}
+
+async fn websocket_send(
+    socket: &mut tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >,
+    message: serde_json::Value,
+) {
+    use futures::SinkExt;
+
+    socket
+        .send(tokio_tungstenite::tungstenite::Message::Text(
+            message.to_string(),
+        ))
+        .await
+        .unwrap();
+}
+
+async fn websocket_next(
+    socket: &mut tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >,
+) -> serde_json::Value {
+    let message = socket.next().await.unwrap().unwrap();
+    serde_json::from_str(message.to_text().unwrap()).unwrap()
+}
+
+#[test(tokio::test)]
+async fn websocket_subscription_graphql_transport_ws() {
+    let router_service = router::service::from_supergraph_mock_callback(|req| {
+        let authorization = req
+            .supergraph_request
+            .headers()
+            .get("authorization")
+            .map(|value| value.to_str().unwrap().to_string());
+        let client_name = req
+            .supergraph_request
+            .headers()
+            .get("apollographql-client-name")
+            .map(|value| value.to_str().unwrap().to_string());
+        let connection_params = req
+            .context
+            .get_json_value(crate::plugins::subscription::SUBSCRIPTION_CLIENT_WS_CONNECTION_PARAMS);
+        let body = stream::iter(vec![
+            // The primary response of a subscription is not sent to clients
+            graphql::Response::builder().subscribed(true).build(),
+            graphql::Response::builder()
+                .data(json!({
+                    "authorization": authorization,
+                    "clientName": client_name,
+                    "connectionParams": connection_params,
+                }))
+                .subscribed(true)
+                .build(),
+            graphql::Response::builder()
+                .data(json!({ "event": 2 }))
+                .subscribed(true)
+                .build(),
+        ])
+        .boxed();
+        Ok(SupergraphResponse::new_from_response(
+            http::Response::builder().status(200).body(body).unwrap(),
+            req.context,
+        ))
+    })
+    .await;
+    let server = init_with_client_websocket(
+        router_service,
+        json!({ "enabled": true, "connection_params_headers": ["authorization"] }),
+    )
+    .await;
+    let mut socket = connect_websocket(&server, "graphql-transport-ws").await;
+
+    websocket_send(
+        &mut socket,
+        json!({
+            "type": "connection_init",
+            "payload": {
+                "Authorization": "Bearer token",
+                "apollographql-client-name": "spoofed"
+            }
+        }),
+    )
+    .await;
+    assert_eq!(
+        websocket_next(&mut socket).await,
+        json!({ "type": "connection_ack" })
+    );
+
+    websocket_send(
+        &mut socket,
+        json!({
+            "type": "subscribe",
+            "id": "1",
+            "payload": { "query": "subscription { event }" }
+        }),
+    )
+    .await;
+    assert_eq!(
+        websocket_next(&mut socket).await,
+        json!({
+            "type": "next",
+            "id": "1",
+            "payload": {
+                "data": {
+                    "authorization": "Bearer token",
+                    // Only the configured parameters are copied to the headers
+                    "clientName": null,
+                    "connectionParams": {
+                        "Authorization": "Bearer token",
+                        "apollographql-client-name": "spoofed"
+                    }
+                }
+            }
+        })
+    );
+    assert_eq!(
+        websocket_next(&mut socket).await,
+        json!({ "type": "next", "id": "1", "payload": { "data": { "event": 2 } } })
+    );
+    assert_eq!(
+        websocket_next(&mut socket).await,
+        json!({ "type": "complete", "id": "1" })
+    );
+
+    websocket_send(&mut socket, json!({ "type": "ping" })).await;
+    assert_eq!(
+        websocket_next(&mut socket).await,
+        json!({ "type": "pong", "payload": null })
+    );
+
+    // Operations can't be sent before the connection is initialized
+    let mut socket = connect_websocket(&server, "graphql-transport-ws").await;
+    websocket_send(
+        &mut socket,
+        json!({
+            "type": "subscribe",
+            "id": "1",
+            "payload": { "query": "subscription { event }" }
+        }),
+    )
+    .await;
+    match socket.next().await.unwrap().unwrap() {
+        tokio_tungstenite::tungstenite::Message::Close(Some(frame)) => {
+            assert_eq!(u16::from(frame.code), 4401)
+        }
+        message => panic!("expected a close frame, got {message:?}"),
+    }
+
+    server.shutdown().await.unwrap();
+}
+
+#[test(tokio::test)]
+async fn websocket_query_graphql_ws() {
+    let router_service = router::service::from_supergraph_mock_callback(|req| {
+        Ok(SupergraphResponse::new_from_graphql_response(
+            graphql::Response::builder()
+                .data(json!({ "me": "name" }))
+                .build(),
+            req.context,
+        ))
+    })
+    .await;
+    let server = init_with_client_websocket(router_service, json!({ "enabled": true })).await;
+    let mut socket = connect_websocket(&server, "graphql-ws").await;
+
+    websocket_send(&mut socket, json!({ "type": "connection_init" })).await;
+    assert_eq!(
+        websocket_next(&mut socket).await,
+        json!({ "type": "connection_ack" })
+    );
+    assert_eq!(websocket_next(&mut socket).await, json!({ "type": "ka" }));
+
+    websocket_send(
+        &mut socket,
+        json!({
+            "type": "start",
+            "id": "1",
+            "payload": { "query": "{ me }" }
+        }),
+    )
+    .await;
+    assert_eq!(
+        websocket_next(&mut socket).await,
+        json!({ "type": "data", "id": "1", "payload": { "data": { "me": "name" } } })
+    );
+    assert_eq!(
+        websocket_next(&mut socket).await,
+        json!({ "type": "complete", "id": "1" })
+    );
+
+    server.shutdown().await.unwrap();
+}
+
+#[test(tokio::test)]
+async fn websocket_operations_per_connection_and_shutdown() {
+    let router_service = router::service::from_supergraph_mock_callback(|req| {
+        // The subscription never ends on its own
+        let body = stream::iter(vec![graphql::Response::builder().subscribed(true).build()])
+            .chain(stream::pending())
+            .boxed();
+        Ok(SupergraphResponse::new_from_response(
+            http::Response::builder().status(200).body(body).unwrap(),
+            req.context,
+        ))
+    })
+    .await;
+    let server = init_with_client_websocket(
+        router_service,
+        json!({ "enabled": true, "max_operations_per_connection": 1 }),
+    )
+    .await;
+    let mut socket = connect_websocket(&server, "graphql-transport-ws").await;
+
+    websocket_send(&mut socket, json!({ "type": "connection_init" })).await;
+    assert_eq!(
+        websocket_next(&mut socket).await,
+        json!({ "type": "connection_ack" })
+    );
+    for id in ["1", "2"] {
+        websocket_send(
+            &mut socket,
+            json!({
+                "type": "subscribe",
+                "id": id,
+                "payload": { "query": "subscription { event }" }
+            }),
+        )
+        .await;
+    }
+    assert_eq!(
+        websocket_next(&mut socket).await,
+        json!({
+            "type": "error",
+            "id": "2",
+            "payload": [{
+                "message": "too many operations on this connection",
+                "extensions": { "code": "WEBSOCKET_OPERATION_ERROR" }
+            }]
+        })
+    );
+
+    // Live connections are closed so that clients reconnect to the new server
+    let shutdown = tokio::spawn(server.shutdown());
+    match socket.next().await.unwrap().unwrap() {
+        tokio_tungstenite::tungstenite::Message::Close(Some(frame)) => {
+            assert_eq!(u16::from(frame.code), 1012)
+        }
+        message => panic!("expected a close frame, got {message:?}"),
+    }
+    shutdown.await.unwrap().unwrap();
+}
+
+fn event_stream_events(body: &str) -> Vec<&str> {
+    body.split_terminator("\n\n")
+        .filter(|event| *event != ":")
+        .collect()
+}
+
+async fn next_event_stream_event(response: &mut reqwest::Response, buffer: &mut String) -> String {
+    loop {
+        if let Some(end) = buffer.find("\n\n") {
+            let event: String = buffer.drain(..end + 2).collect();
+            if event != ":\n\n" {
+                return event;
+            }
+            continue;
+        }
+        let chunk = response
+            .chunk()
+            .await
+            .unwrap()
+            .expect("the event stream was closed");
+        buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+}
+
+#[test(tokio::test)]
+async fn event_stream_subscription_distinct_connections() {
+    let router_service = router::service::from_supergraph_mock_callback(|req| {
+        let body = stream::iter(vec![
+            // The primary response of a subscription is not sent to clients
+            graphql::Response::builder().subscribed(true).build(),
+            graphql::Response::builder()
+                .data(json!({ "event": 1 }))
+                .subscribed(true)
+                .build(),
+            graphql::Response::builder()
+                .data(json!({ "event": 2 }))
+                .subscribed(true)
+                .build(),
+        ])
+        .boxed();
+        Ok(SupergraphResponse::new_from_response(
+            http::Response::builder().status(200).body(body).unwrap(),
+            req.context,
+        ))
+    })
+    .await;
+    let (server, client) = init(router_service).await;
+
+    let response = client
+        .post(format!(
+            "{}/",
+            server.graphql_listen_address().as_ref().unwrap()
+        ))
+        .header(ACCEPT, "text/event-stream")
+        .json(&json!({ "query": "subscription { event }" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        "text/event-stream"
+    );
+    let body = response.text().await.unwrap();
+    assert_eq!(
+        event_stream_events(&body),
+        vec![
+            "event: next\ndata: {\"data\":{\"event\":1}}",
+            "event: next\ndata: {\"data\":{\"event\":2}}",
+            "event: complete\ndata:",
+        ]
+    );
+
+    server.shutdown().await.unwrap();
+}
+
+#[test(tokio::test)]
+async fn event_stream_single_connection() {
+    let router_service = router::service::from_supergraph_mock_callback(|req| {
+        let authorization = req
+            .supergraph_request
+            .headers()
+            .get("authorization")
+            .map(|value| value.to_str().unwrap().to_string());
+        Ok(SupergraphResponse::new_from_graphql_response(
+            graphql::Response::builder()
+                .data(json!({ "authorization": authorization }))
+                .build(),
+            req.context,
+        ))
+    })
+    .await;
+    let (server, client) =
+        init_with_client_event_stream(router_service, json!({ "enabled": true })).await;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let response = client.put(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let token = response.text().await.unwrap();
+
+    // Operations can't be sent before the stream is opened
+    let response = client
+        .post(&url)
+        .header("x-graphql-event-stream-token", &token)
+        .json(&json!({ "query": "{ me }", "extensions": { "operationId": "1" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let mut stream = client
+        .get(&url)
+        .header(ACCEPT, "text/event-stream")
+        .header("x-graphql-event-stream-token", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stream.status(), StatusCode::OK);
+    assert_eq!(
+        stream.headers().get(CONTENT_TYPE).unwrap(),
+        "text/event-stream"
+    );
+
+    // A stream can only be opened once
+    let response = client
+        .get(format!("{url}?token={token}"))
+        .header(ACCEPT, "text/event-stream")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = client
+        .post(&url)
+        .header("x-graphql-event-stream-token", &token)
+        .header("authorization", "Bearer token")
+        .json(&json!({ "query": "{ me }", "extensions": { "operationId": "1" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    let mut buffer = String::new();
+    assert_eq!(
+        next_event_stream_event(&mut stream, &mut buffer).await,
+        "event: next\ndata: {\"id\":\"1\",\"payload\":{\"data\":{\"authorization\":\"Bearer token\"}}}\n\n"
+    );
+    assert_eq!(
+        next_event_stream_event(&mut stream, &mut buffer).await,
+        "event: complete\ndata: {\"id\":\"1\"}\n\n"
+    );
+
+    // Operations need an ID
+    let response = client
+        .post(&url)
+        .header("x-graphql-event-stream-token", &token)
+        .json(&json!({ "query": "{ me }" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = client
+        .delete(format!("{url}?operationId=1"))
+        .header("x-graphql-event-stream-token", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .post(&url)
+        .header("x-graphql-event-stream-token", "unknown")
+        .json(&json!({ "query": "{ me }", "extensions": { "operationId": "1" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    drop(stream);
+    server.shutdown().await.unwrap();
+}
+
+#[test(tokio::test)]
+async fn event_stream_single_connection_is_disabled_by_default() {
+    let router_service = router::service::from_supergraph_mock_callback(|req| {
+        Ok(SupergraphResponse::new_from_graphql_response(
+            graphql::Response::builder()
+                .data(json!({ "me": "name" }))
+                .build(),
+            req.context,
+        ))
+    })
+    .await;
+    let (server, client) = init(router_service).await;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let response = client.put(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    // The token header is ignored, the operation is answered directly
+    let response = client
+        .post(&url)
+        .header("x-graphql-event-stream-token", "unknown")
+        .json(&json!({ "query": "{ me }", "extensions": { "operationId": "1" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    server.shutdown().await.unwrap();
+}
+
+#[test(tokio::test)]
+async fn event_stream_single_connection_limits() {
+    let router_service = router::service::from_supergraph_mock_callback(|req| {
+        // Operations never complete
+        Ok(SupergraphResponse::new_from_response(
+            http::Response::builder()
+                .status(200)
+                .body(stream::pending().boxed())
+                .unwrap(),
+            req.context,
+        ))
+    })
+    .await;
+    let (server, client) = init_with_client_event_stream(
+        router_service,
+        json!({
+            "enabled": true,
+            "max_streams": 1,
+            "max_operations_per_stream": 1,
+            "reservation_timeout": "100ms"
+        }),
+    )
+    .await;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let response = client.put(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let token = response.text().await.unwrap();
+    let response = client.put(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    // Reservations that are not opened in time are dropped
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let response = client
+        .get(&url)
+        .header(ACCEPT, "text/event-stream")
+        .header("x-graphql-event-stream-token", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = client.put(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let token = response.text().await.unwrap();
+    let stream = client
+        .get(&url)
+        .header(ACCEPT, "text/event-stream")
+        .header("x-graphql-event-stream-token", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stream.status(), StatusCode::OK);
+
+    let response = client
+        .post(&url)
+        .header("x-graphql-event-stream-token", &token)
+        .json(&json!({ "query": "subscription { event }", "extensions": { "operationId": "1" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let response = client
+        .post(&url)
+        .header("x-graphql-event-stream-token", &token)
+        .json(&json!({ "query": "subscription { event }", "extensions": { "operationId": "2" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // Opened streams are not expired
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let response = client
+        .delete(format!("{url}?operationId=1"))
+        .header("x-graphql-event-stream-token", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .post(&url)
+        .header("x-graphql-event-stream-token", &token)
+        .json(&json!({ "query": "subscription { event }", "extensions": { "operationId": "2" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    drop(stream);
+    server.shutdown().await.unwrap();
+}
@@ -0,0 +1,506 @@
+//! WebSocket transport for clients.
+//!
+//! Upgrades on the GraphQL endpoint are accepted for the `graphql-transport-ws` subprotocol and
+//! the legacy `graphql-ws` one (subscriptions-transport-ws). Every operation started on a
+//! connection goes through the router service like an HTTP request carrying the headers of the
+//! upgrade request, so it sees the same plugins, coprocessors and layers.
+//!
+//! Browsers can't set headers on WebSocket connections, so clients send their credentials in
+//! the connection init payload instead. The string values of the parameters listed in
+//! `connection_params_headers` are added to the headers of each operation when the upgrade
+//! request doesn't already have them, which lets authentication plugins find them, and the whole
+//! payload is available in the context.
+//!
+//! Upgraded connections are not tracked by the HTTP server anymore: when the server stops, on
+//! shutdown or when the router reloads, its connections are closed with the `1012` (Service
+//! Restart) code so that clients reconnect to the router serving the new configuration.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use axum::extract::ws::CloseFrame;
+use axum::extract::ws::Message;
+use axum::extract::ws::WebSocket;
+use axum::extract::FromRequestParts;
+use axum::extract::WebSocketUpgrade;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use futures::stream::SplitSink;
+use futures::stream::SplitStream;
+use futures::SinkExt;
+use futures::StreamExt;
+use http::header::CONNECTION;
+use http::header::CONTENT_LENGTH;
+use http::header::SEC_WEBSOCKET_EXTENSIONS;
+use http::header::SEC_WEBSOCKET_KEY;
+use http::header::SEC_WEBSOCKET_PROTOCOL;
+use http::header::SEC_WEBSOCKET_VERSION;
+use http::header::UPGRADE;
+use http::HeaderMap;
+use http::HeaderName;
+use http::HeaderValue;
+use http::Request;
+use http::StatusCode;
+use http::Uri;
+use hyper::Body;
+use tokio::sync::mpsc;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Interval;
+use tower::BoxError;
+use tower::ServiceExt;
+use tower_http::decompression::DecompressionBody;
+use tracing::instrument::WithSubscriber;
+
+use super::operations;
+use super::operations::OperationResponse;
+use crate::graphql;
+use crate::plugins::subscription::ClientWebSocketConfig;
+use crate::plugins::subscription::SUBSCRIPTION_CLIENT_WS_CONNECTION_PARAMS;
+use crate::protocols::websocket::ClientMessage;
+use crate::protocols::websocket::ServerError;
+use crate::protocols::websocket::ServerMessage;
+use crate::protocols::websocket::WebSocketProtocol;
+use crate::router_factory::RouterFactory;
+use crate::services::router;
+
+// Close codes from https://github.com/enisdenjo/graphql-ws/blob/master/PROTOCOL.md
+const CLOSE_INVALID_MESSAGE: u16 = 4400;
+const CLOSE_UNAUTHORIZED: u16 = 4401;
+const CLOSE_INIT_TIMEOUT: u16 = 4408;
+const CLOSE_SUBSCRIBER_EXISTS: u16 = 4409;
+const CLOSE_TOO_MANY_INIT: u16 = 4429;
+// Close code from https://www.rfc-editor.org/rfc/rfc6455#section-7.4, clients reconnect
+const CLOSE_SERVICE_RESTART: u16 = 1012;
+
+const OUTGOING_QUEUE_CAPACITY: usize = 32;
+
+/// Returns true if the client asks to switch the connection to WebSocket
+pub(crate) fn is_upgrade_request<B>(request: &Request<B>) -> bool {
+    request
+        .headers()
+        .get(UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Upgrades the connection, and serves the operations of the client on it
+pub(crate) async fn handle_upgrade<RF>(
+    service_factory: RF,
+    config: ClientWebSocketConfig,
+    shutdown: watch::Receiver<bool>,
+    request: Request<DecompressionBody<Body>>,
+) -> Response
+where
+    RF: RouterFactory,
+{
+    let Some((protocol, subprotocol)) = negotiate_protocol(request.headers()) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "the websocket subprotocol must be one of: \"graphql-transport-ws\" or \"graphql-ws\"",
+        )
+            .into_response();
+    };
+
+    let (mut parts, _body) = request.into_parts();
+    let upgrade = match WebSocketUpgrade::from_request_parts(&mut parts, &()).await {
+        Ok(upgrade) => upgrade,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let mut headers = parts.headers;
+    for name in [
+        CONNECTION,
+        UPGRADE,
+        CONTENT_LENGTH,
+        SEC_WEBSOCKET_KEY,
+        SEC_WEBSOCKET_VERSION,
+        SEC_WEBSOCKET_PROTOCOL,
+        SEC_WEBSOCKET_EXTENSIONS,
+    ] {
+        headers.remove(name);
+    }
+
+    let connection = Connection {
+        service_factory,
+        protocol,
+        config,
+        shutdown,
+        uri: parts.uri,
+        headers,
+        connection_params: None,
+        operations: HashMap::new(),
+    };
+    let dispatch = tracing::dispatcher::get_default(|dispatch| dispatch.clone());
+    upgrade
+        .protocols([subprotocol])
+        .on_upgrade(move |socket| connection.run(socket).with_subscriber(dispatch))
+}
+
+fn negotiate_protocol(headers: &HeaderMap) -> Option<(WebSocketProtocol, String)> {
+    headers
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .find_map(|subprotocol| {
+            WebSocketProtocol::from_subprotocol(subprotocol)
+                .map(|protocol| (protocol, subprotocol.to_string()))
+        })
+}
+
+struct Connection<RF> {
+    service_factory: RF,
+    protocol: WebSocketProtocol,
+    config: ClientWebSocketConfig,
+    shutdown: watch::Receiver<bool>,
+    uri: Uri,
+    headers: HeaderMap,
+    connection_params: Option<serde_json_bytes::Value>,
+    operations: HashMap<String, JoinHandle<()>>,
+}
+
+impl<RF> Connection<RF>
+where
+    RF: RouterFactory,
+{
+    async fn run(mut self, socket: WebSocket) {
+        let (mut sink, mut stream) = socket.split();
+
+        let init = tokio::time::timeout(
+            self.config.connection_init_timeout,
+            next_client_message(&mut stream),
+        )
+        .await;
+        match init {
+            Ok(Some(Ok(ClientMessage::ConnectionInit { payload }))) => self.init(payload),
+            Ok(Some(Ok(_))) => {
+                return close(&mut sink, CLOSE_UNAUTHORIZED, "Unauthorized").await;
+            }
+            Ok(Some(Err(_))) => {
+                return close(&mut sink, CLOSE_INVALID_MESSAGE, "Invalid message received").await;
+            }
+            Ok(None) => return,
+            Err(_) => {
+                return close(
+                    &mut sink,
+                    CLOSE_INIT_TIMEOUT,
+                    "Connection initialisation timeout",
+                )
+                .await;
+            }
+        }
+
+        if self
+            .send(&mut sink, &ServerMessage::ConnectionAck)
+            .await
+            .is_err()
+        {
+            return;
+        }
+        if self.protocol == WebSocketProtocol::SubscriptionsTransportWs
+            && self
+                .send(&mut sink, &ServerMessage::KeepAlive)
+                .await
+                .is_err()
+        {
+            return;
+        }
+
+        let mut heartbeat = self
+            .config
+            .heartbeat_interval
+            .into_option()
+            .map(|duration| {
+                let mut interval =
+                    tokio::time::interval_at(tokio::time::Instant::now() + duration, duration);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+                interval
+            });
+        let (tx, mut rx) = mpsc::channel(OUTGOING_QUEUE_CAPACITY);
+        let mut shutdown = self.shutdown.clone();
+
+        loop {
+            tokio::select! {
+                message = next_client_message(&mut stream) => match message {
+                    None | Some(Ok(ClientMessage::ConnectionTerminate)) => break,
+                    Some(Err(_)) => {
+                        close(&mut sink, CLOSE_INVALID_MESSAGE, "Invalid message received").await;
+                        break;
+                    }
+                    Some(Ok(ClientMessage::ConnectionInit { .. })) => {
+                        close(
+                            &mut sink,
+                            CLOSE_TOO_MANY_INIT,
+                            "Too many initialisation requests",
+                        )
+                        .await;
+                        break;
+                    }
+                    Some(Ok(ClientMessage::Subscribe { id, payload }))
+                    | Some(Ok(ClientMessage::OldStart { id, payload })) => {
+                        if self.operations.contains_key(&id) {
+                            close(
+                                &mut sink,
+                                CLOSE_SUBSCRIBER_EXISTS,
+                                format!("Subscriber for {id} already exists"),
+                            )
+                            .await;
+                            break;
+                        }
+                        if self.operations.len() >= self.config.max_operations_per_connection {
+                            let message = error_message(
+                                id,
+                                "too many operations on this connection".to_string(),
+                            );
+                            if self.send(&mut sink, &message).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                        match self.router_request(&payload) {
+                            Ok(request) => {
+                                let service = self.service_factory.create().boxed();
+                                let operation = execute(service, id.clone(), request, tx.clone());
+                                self.operations.insert(
+                                    id,
+                                    tokio::task::spawn(operation.with_current_subscriber()),
+                                );
+                            }
+                            Err(err) => {
+                                let message =
+                                    error_message(id, format!("invalid GraphQL request: {err}"));
+                                if self.send(&mut sink, &message).await.is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    Some(Ok(ClientMessage::Complete { id }))
+                    | Some(Ok(ClientMessage::OldStop { id })) => {
+                        if let Some(operation) = self.operations.remove(&id) {
+                            operation.abort();
+                        }
+                    }
+                    Some(Ok(ClientMessage::Ping { payload })) => {
+                        let pong = ServerMessage::Pong {
+                            payload: payload
+                                .and_then(|payload| serde_json::to_value(payload).ok()),
+                        };
+                        if self.send(&mut sink, &pong).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(ClientMessage::Pong { .. })) => {}
+                },
+                Some(message) = rx.recv() => {
+                    // Operations completed by the client don't send anything anymore
+                    let Some(id) = message.id() else { continue };
+                    if !self.operations.contains_key(&id) {
+                        continue;
+                    }
+                    if matches!(
+                        message,
+                        ServerMessage::Complete { .. } | ServerMessage::Error { .. }
+                    ) {
+                        self.operations.remove(&id);
+                    }
+                    if self.send(&mut sink, &message).await.is_err() {
+                        break;
+                    }
+                }
+                _ = stopped(&mut shutdown) => {
+                    close(&mut sink, CLOSE_SERVICE_RESTART, "Service restart").await;
+                    break;
+                }
+                _ = tick(&mut heartbeat) => {
+                    let message = match self.protocol {
+                        WebSocketProtocol::GraphqlWs => ServerMessage::Ping { payload: None },
+                        WebSocketProtocol::SubscriptionsTransportWs => ServerMessage::KeepAlive,
+                    };
+                    if self.send(&mut sink, &message).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        for (_, operation) in self.operations.drain() {
+            operation.abort();
+        }
+    }
+
+    fn init(&mut self, payload: Option<serde_json_bytes::Value>) {
+        if let Some(serde_json_bytes::Value::Object(params)) = &payload {
+            for (name, value) in params.iter() {
+                // Clients can put anything in the payload: any other parameter could impersonate
+                // a header set by a proxy or trusted by the router
+                if !self
+                    .config
+                    .connection_params_headers
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(name.as_str()))
+                {
+                    continue;
+                }
+                let (Ok(name), Some(value)) = (HeaderName::try_from(name.as_str()), value.as_str())
+                else {
+                    continue;
+                };
+                if self.headers.contains_key(&name) {
+                    continue;
+                }
+                if let Ok(value) = HeaderValue::from_str(value) {
+                    self.headers.insert(name, value);
+                }
+            }
+        }
+        self.connection_params = payload;
+    }
+
+    fn router_request(&self, payload: &graphql::Request) -> Result<router::Request, BoxError> {
+        let request = operations::router_request(&self.uri, &self.headers, payload)?;
+        if let Some(connection_params) = &self.connection_params {
+            request.context.insert_json_value(
+                SUBSCRIPTION_CLIENT_WS_CONNECTION_PARAMS,
+                connection_params.clone(),
+            );
+        }
+        Ok(request)
+    }
+
+    async fn send(
+        &self,
+        sink: &mut SplitSink<WebSocket, Message>,
+        message: &ServerMessage,
+    ) -> Result<(), BoxError> {
+        let text = self.protocol.server_message(message)?;
+        sink.send(Message::Text(text)).await?;
+        Ok(())
+    }
+}
+
+/// Runs an operation through the router service, and sends its responses to the connection
+async fn execute(
+    service: router::BoxService,
+    id: String,
+    request: router::Request,
+    tx: mpsc::Sender<ServerMessage>,
+) {
+    let response = match service.oneshot(request).await {
+        Ok(response) => response,
+        Err(err) => {
+            let _ = tx
+                .send(error_message(
+                    id,
+                    format!("router service call failed: {err}"),
+                ))
+                .await;
+            return;
+        }
+    };
+
+    let messages = match operations::read_response(response).await {
+        // The operation was rejected before execution
+        Ok(OperationResponse::Single(response))
+            if response.data.is_none() && !response.errors.is_empty() =>
+        {
+            vec![ServerMessage::Error {
+                id,
+                payload: ServerError::Errors(response.errors),
+            }]
+        }
+        Ok(OperationResponse::Single(payload)) => vec![
+            ServerMessage::Next {
+                id: id.clone(),
+                payload,
+            },
+            ServerMessage::Complete { id },
+        ],
+        Ok(OperationResponse::Stream(mut stream)) => {
+            while let Some(payload) = stream.next().await {
+                let message = match payload {
+                    Ok(payload) => ServerMessage::Next {
+                        id: id.clone(),
+                        payload,
+                    },
+                    Err(err) => {
+                        let _ = tx
+                            .send(error_message(id, format!("cannot read response: {err}")))
+                            .await;
+                        return;
+                    }
+                };
+                if tx.send(message).await.is_err() {
+                    return;
+                }
+            }
+            vec![ServerMessage::Complete { id }]
+        }
+        Err(err) => vec![error_message(id, format!("cannot read response: {err}"))],
+    };
+    for message in messages {
+        if tx.send(message).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn error_message(id: String, message: String) -> ServerMessage {
+    ServerMessage::Error {
+        id,
+        payload: ServerError::Errors(vec![graphql::Error::builder()
+            .message(message)
+            .extension_code("WEBSOCKET_OPERATION_ERROR")
+            .build()]),
+    }
+}
+
+async fn next_client_message(
+    stream: &mut SplitStream<WebSocket>,
+) -> Option<Result<ClientMessage, BoxError>> {
+    loop {
+        let message = match stream.next().await? {
+            Ok(message) => message,
+            Err(err) => return Some(Err(err.into())),
+        };
+        return Some(match message {
+            Message::Text(text) => serde_json::from_str(&text).map_err(BoxError::from),
+            Message::Binary(bytes) => serde_json::from_slice(&bytes).map_err(BoxError::from),
+            // WebSocket level pings are answered by the WebSocket implementation
+            Message::Ping(_) | Message::Pong(_) => continue,
+            Message::Close(_) => return None,
+        });
+    }
+}
+
+async fn tick(heartbeat: &mut Option<Interval>) {
+    match heartbeat {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => futures::future::pending().await,
+    }
+}
+
+/// Resolves once the server serving the connection stops
+async fn stopped(shutdown: &mut watch::Receiver<bool>) {
+    // Without a server lifecycle, as in tests, nothing stops the connection
+    if shutdown.wait_for(|stopped| *stopped).await.is_err() {
+        futures::future::pending().await
+    }
+}
+
+async fn close(
+    sink: &mut SplitSink<WebSocket, Message>,
+    code: u16,
+    reason: impl Into<Cow<'static, str>>,
+) {
+    let _ = sink
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.into(),
+        })))
+        .await;
+}
@@ -141,15 +141,28 @@ where
 
 impl RedisCacheStorage {
     pub(crate) async fn new(config: RedisCache) -> Result<Self, BoxError> {
-        let url = Self::preprocess_urls(config.urls)?;
+        let (client, is_cluster) = Self::connect(&config).await?;
+
+        Ok(Self {
+            inner: Arc::new(client),
+            namespace: config.namespace.map(Arc::new),
+            ttl: config.ttl,
+            is_cluster,
+            reset_ttl: config.reset_ttl,
+        })
+    }
+
+    /// Creates a client connected to Redis, and returns whether it's connected to a cluster
+    pub(crate) async fn connect(config: &RedisCache) -> Result<(RedisClient, bool), BoxError> {
+        let url = Self::preprocess_urls(config.urls.clone())?;
         let mut client_config = RedisConfig::from_url(url.as_str())?;
         let is_cluster = url.scheme() == "redis-cluster" || url.scheme() == "rediss-cluster";
 
-        if let Some(username) = config.username {
+        if let Some(username) = config.username.clone() {
             client_config.username = Some(username);
         }
 
-        if let Some(password) = config.password {
+        if let Some(password) = config.password.clone() {
             client_config.password = Some(password);
         }
 
@@ -199,13 +212,7 @@ impl RedisCacheStorage {
             })??;
 
         tracing::trace!("redis connection established");
-        Ok(Self {
-            inner: Arc::new(client),
-            namespace: config.namespace.map(Arc::new),
-            ttl: config.ttl,
-            is_cluster,
-            reset_ttl: config.reset_ttl,
-        })
+        Ok((client, is_cluster))
     }
 
     #[cfg(test)]
@@ -180,19 +180,20 @@ impl InstrumentData {
         populate_config_instrument!(
             apollo.router.config.coprocessor,
             "$.coprocessor",
+            // `..` matches both a single coprocessor and the `coprocessors` list
             opt.router.request,
-            "$.router.request",
+            "$..router.request",
             opt.router.response,
-            "$.router.response",
+            "$..router.response",
             // Note that supergraph is not supported yet so these will always be empty
             opt.supergraph.request,
-            "$.supergraph.response",
+            "$..supergraph.response",
             opt.supergraph.response,
-            "$.supergraph.request",
+            "$..supergraph.request",
             opt.subgraph.request,
-            "$.subgraph..request",
+            "$..subgraph..request",
             opt.subgraph.response,
-            "$.subgraph..response"
+            "$..subgraph..response"
         );
         populate_config_instrument!(
             apollo.router.config.persisted_queries,
@@ -217,7 +218,9 @@ impl InstrumentData {
             opt.max_opened,
             "$[?(@.max_opened_subscriptions)]",
             opt.queue_capacity,
-            "$[?(@.queue_capacity)]"
+            "$[?(@.queue_capacity)]",
+            opt.client_websocket,
+            "$[?(@.client_websocket.enabled == true)]"
         );
 
         populate_config_instrument!(
@@ -231,8 +234,20 @@ impl InstrumentData {
             "$[?(@.max_height)]",
             opt.operation.max_root_fields,
             "$[?(@.max_root_fields)]",
+            opt.operation.max_variables,
+            "$[?(@.max_variables)]",
+            opt.operation.max_directives,
+            "$[?(@.max_directives)]",
+            opt.operation.max_fragments,
+            "$[?(@.max_fragments)]",
+            opt.operation.max_input_depth,
+            "$[?(@.max_input_depth)]",
+            opt.operation.max_list_length,
+            "$[?(@.max_list_length)]",
             opt.operation.warn_only,
             "$[?(@.warn_only)]",
+            opt.operation.profiles,
+            "$[?(@.profiles)]",
             opt.parser.max_recursion,
             "$[?(@.parser_max_recursion)]",
             opt.parser.max_tokens,
@@ -1,4 +1,5 @@
 //! Logic for loading configuration in to an object model
+use std::collections::HashMap;
 use std::fmt;
 use std::io;
 use std::io::BufReader;
@@ -15,6 +16,7 @@ use displaydoc::Display;
 use itertools::Itertools;
 use once_cell::sync::Lazy;
 pub(crate) use persisted_queries::PersistedQueries;
+pub(crate) use persisted_queries::PersistedQueriesAudit;
 #[cfg(test)]
 pub(crate) use persisted_queries::PersistedQueriesSafelist;
 use regex::Regex;
@@ -316,17 +318,21 @@ impl Configuration {
         experimental_apollo_metrics_generation_mode: Option<ApolloMetricsGenerationMode>,
     ) -> Result<Self, ConfigurationError> {
         #[cfg(not(test))]
-        let notify_queue_cap = match apollo_plugins.get(APOLLO_SUBSCRIPTION_PLUGIN_NAME) {
-            Some(plugin_conf) => {
-                let conf = serde_json::from_value::<SubscriptionConfig>(plugin_conf.clone())
-                    .map_err(|err| ConfigurationError::PluginConfiguration {
-                        plugin: APOLLO_SUBSCRIPTION_PLUGIN.to_string(),
-                        error: format!("{err:?}"),
-                    })?;
-                conf.queue_capacity
-            }
-            None => None,
-        };
+        let (notify_queue_cap, notify_replay) =
+            match apollo_plugins.get(APOLLO_SUBSCRIPTION_PLUGIN_NAME) {
+                Some(plugin_conf) => {
+                    let conf = serde_json::from_value::<SubscriptionConfig>(plugin_conf.clone())
+                        .map_err(|err| ConfigurationError::PluginConfiguration {
+                            plugin: APOLLO_SUBSCRIPTION_PLUGIN.to_string(),
+                            error: format!("{err:?}"),
+                        })?;
+                    (
+                        conf.queue_capacity,
+                        conf.replay.enabled.then_some(conf.replay),
+                    )
+                }
+                None => (None, None),
+            };
 
         let conf = Self {
             validated_yaml: Default::default(),
@@ -353,8 +359,8 @@ impl Configuration {
             #[cfg(test)]
             notify: notify.unwrap_or_default(),
             #[cfg(not(test))]
-            notify: notify.map(|n| n.set_queue_size(notify_queue_cap))
-                .unwrap_or_else(|| Notify::builder().and_queue_size(notify_queue_cap).ttl(Duration::from_secs(HEARTBEAT_TIMEOUT_DURATION_SECONDS)).router_broadcasts(Arc::new(RouterBroadcasts::new())).heartbeat_error_message(graphql::Response::builder().errors(vec![graphql::Error::builder().message("the connection has been closed because it hasn't heartbeat for a while").extension_code("SUBSCRIPTION_HEARTBEAT_ERROR").build()]).build()).build()),
+            notify: notify.map(|n| n.set_queue_size(notify_queue_cap).set_replay(notify_replay.clone()))
+                .unwrap_or_else(|| Notify::builder().and_queue_size(notify_queue_cap).and_replay(notify_replay).ttl(Duration::from_secs(HEARTBEAT_TIMEOUT_DURATION_SECONDS)).router_broadcasts(Arc::new(RouterBroadcasts::new())).heartbeat_error_message(graphql::Response::builder().errors(vec![graphql::Error::builder().message("the connection has been closed because it hasn't heartbeat for a while").extension_code("SUBSCRIPTION_HEARTBEAT_ERROR").build()]).build()).build()),
         };
 
         conf.validate()
@@ -815,6 +821,45 @@ pub(crate) struct Limits {
     /// `"extensions": {"code": "MAX_ALIASES_LIMIT"}`
     pub(crate) max_aliases: Option<u32>,
 
+    /// If set, requests with operations declaring more variables than this maximum
+    /// are rejected with a HTTP 400 Bad Request response and GraphQL error with
+    /// `"extensions": {"code": "MAX_VARIABLES_LIMIT"}`
+    pub(crate) max_variables: Option<u32>,
+
+    /// If set, requests with operations using more directives than this maximum
+    /// are rejected with a HTTP 400 Bad Request response and GraphQL error with
+    /// `"extensions": {"code": "MAX_DIRECTIVES_LIMIT"}`
+    ///
+    /// This limit counts directives on the operation, its variables, fields,
+    /// fragment spreads, inline fragments and the fragments it uses.
+    pub(crate) max_directives: Option<u32>,
+
+    /// If set, requests with documents defining more fragments than this maximum
+    /// are rejected with a HTTP 400 Bad Request response and GraphQL error with
+    /// `"extensions": {"code": "MAX_FRAGMENTS_LIMIT"}`
+    pub(crate) max_fragments: Option<u32>,
+
+    /// If set, requests with input objects nested deeper than this maximum
+    /// are rejected with a HTTP 400 Bad Request response and GraphQL error with
+    /// `"extensions": {"code": "MAX_INPUT_DEPTH_LIMIT"}`
+    ///
+    /// This limit applies to argument values written in the operation
+    /// as well as to the variables of the request.
+    /// The following argument value has a depth of 2:
+    ///
+    /// ```graphql
+    /// { products(filter: { price: { lt: 10 } }) { id } }
+    /// ```
+    pub(crate) max_input_depth: Option<u32>,
+
+    /// If set, requests with input lists longer than this maximum
+    /// are rejected with a HTTP 400 Bad Request response and GraphQL error with
+    /// `"extensions": {"code": "MAX_LIST_LENGTH_LIMIT"}`
+    ///
+    /// This limit applies to argument values written in the operation
+    /// as well as to the variables of the request.
+    pub(crate) max_list_length: Option<u32>,
+
     /// If set to true (which is the default is dev mode),
     /// requests that exceed a `max_*` limit are *not* rejected.
     /// Instead they are executed normally, and a warning is logged.
@@ -830,6 +875,54 @@ pub(crate) struct Limits {
     /// Limit the size of incoming HTTP requests read from the network,
     /// to protect against running out of memory. Default: 2000000 (2 MB)
     pub(crate) http_max_request_bytes: usize,
+
+    /// Named sets of limits replacing the ones above for the requests they are applied to
+    pub(crate) profiles: HashMap<String, LimitProfile>,
+
+    /// How the profile applied to a request is selected
+    pub(crate) profile_selection: LimitProfileSelection,
+}
+
+/// Limits replacing the global ones for the requests a profile is applied to.
+/// Limits that are not set in a profile keep their global value.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct LimitProfile {
+    /// Replaces `max_depth` for the requests the profile is applied to
+    pub(crate) max_depth: Option<u32>,
+
+    /// Replaces `max_height` for the requests the profile is applied to
+    pub(crate) max_height: Option<u32>,
+
+    /// Replaces `max_root_fields` for the requests the profile is applied to
+    pub(crate) max_root_fields: Option<u32>,
+
+    /// Replaces `max_aliases` for the requests the profile is applied to
+    pub(crate) max_aliases: Option<u32>,
+
+    /// Replaces `http_max_request_bytes` for the requests the profile is applied to
+    pub(crate) http_max_request_bytes: Option<usize>,
+}
+
+/// Selection of the limit profile applied to a request.
+///
+/// These rules are evaluated in order, and the first one naming a defined profile selects it.
+/// Requests without a profile use the global limits.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct LimitProfileSelection {
+    /// A request context entry holding the name of the profile, set by a plugin or a script
+    pub(crate) context_key: Option<String>,
+
+    /// A claim of the JWT used to authenticate the request, holding the name of the profile
+    pub(crate) claim: Option<String>,
+
+    /// The profile applied to each client name. The client name is read from the same header
+    /// as the one used for Apollo Studio reporting.
+    pub(crate) client_names: HashMap<String, String>,
+
+    /// The profile applied to requests that were not authenticated with a JWT
+    pub(crate) unauthenticated: Option<String>,
 }
 
 impl Default for Limits {
@@ -840,6 +933,11 @@ impl Default for Limits {
             max_height: None,
             max_root_fields: None,
             max_aliases: None,
+            max_variables: None,
+            max_directives: None,
+            max_fragments: None,
+            max_input_depth: None,
+            max_list_length: None,
             warn_only: false,
             http_max_request_bytes: 2_000_000,
             parser_max_tokens: 15_000,
@@ -848,6 +946,9 @@ impl Default for Limits {
             // but is still very high for "reasonable" queries.
             // https://github.com/apollographql/apollo-rs/blob/apollo-parser%400.7.3/crates/apollo-parser/src/parser/mod.rs#L93-L104
             parser_max_recursion: 500,
+
+            profiles: HashMap::new(),
+            profile_selection: LimitProfileSelection::default(),
         }
     }
 }
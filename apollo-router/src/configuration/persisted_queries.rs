@@ -1,7 +1,14 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 
+use super::ListenAddr;
+
 /// Persisted Queries (PQ) configuration
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, default)]
@@ -14,6 +21,18 @@ pub struct PersistedQueries {
 
     /// Restricts execution of operations that are not found in the Persisted Query List
     pub safelist: PersistedQueriesSafelist,
+
+    /// Paths to persisted query manifest files, used instead of the Persisted Query List from GraphOS.
+    /// Files use the same JSON format as the manifest chunks fetched from GraphOS,
+    /// and operations of all files are merged.
+    pub local_manifests: Option<Vec<PathBuf>>,
+
+    /// Reloads the local manifests when any of the files changes (disabled by default)
+    pub hot_reload: bool,
+
+    /// Aggregates the freeform operations that are not in the persisted query list,
+    /// to find the ones to register before enabling the safelist
+    pub audit: PersistedQueriesAudit,
 }
 
 #[cfg(test)]
@@ -24,11 +43,68 @@ impl PersistedQueries {
         enabled: Option<bool>,
         log_unknown: Option<bool>,
         safelist: Option<PersistedQueriesSafelist>,
+        local_manifests: Option<Vec<PathBuf>>,
+        hot_reload: Option<bool>,
+        audit: Option<PersistedQueriesAudit>,
     ) -> Self {
         Self {
             enabled: enabled.unwrap_or_else(default_pq),
             safelist: safelist.unwrap_or_default(),
             log_unknown: log_unknown.unwrap_or_else(default_log_unknown),
+            local_manifests,
+            hot_reload: hot_reload.unwrap_or_else(default_hot_reload),
+            audit: audit.unwrap_or_default(),
+        }
+    }
+}
+
+/// Audit of the operations that are not in the persisted query list
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub struct PersistedQueriesAudit {
+    /// Enables the audit of unknown operations (disabled by default)
+    pub enabled: bool,
+
+    /// The socket address and port to listen on for the audit endpoint. It has no authentication,
+    /// only expose it on a private network
+    /// Defaults to 127.0.0.1:8089
+    pub listen: ListenAddr,
+
+    /// The path of the audit endpoint
+    /// Defaults to /persisted-queries/unknown
+    pub path: String,
+
+    /// Maximum number of distinct operations kept in the audit (defaults to 1000)
+    pub max_operations: usize,
+
+    /// Periodically writes the audit report to this file
+    pub dump_path: Option<PathBuf>,
+
+    /// Interval between two writes of the audit report (defaults to 1m)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String", default = "default_audit_dump_interval")]
+    pub dump_interval: Duration,
+}
+
+#[cfg(test)]
+#[buildstructor::buildstructor]
+impl PersistedQueriesAudit {
+    #[builder]
+    pub(crate) fn new(
+        enabled: Option<bool>,
+        listen: Option<ListenAddr>,
+        path: Option<String>,
+        max_operations: Option<usize>,
+        dump_path: Option<PathBuf>,
+        dump_interval: Option<Duration>,
+    ) -> Self {
+        Self {
+            enabled: enabled.unwrap_or_default(),
+            listen: listen.unwrap_or_else(default_audit_listen),
+            path: path.unwrap_or_else(default_audit_path),
+            max_operations: max_operations.unwrap_or_else(default_audit_max_operations),
+            dump_path,
+            dump_interval: dump_interval.unwrap_or_else(default_audit_dump_interval),
         }
     }
 }
@@ -62,6 +138,22 @@ impl Default for PersistedQueries {
             enabled: default_pq(),
             safelist: PersistedQueriesSafelist::default(),
             log_unknown: default_log_unknown(),
+            local_manifests: None,
+            hot_reload: default_hot_reload(),
+            audit: PersistedQueriesAudit::default(),
+        }
+    }
+}
+
+impl Default for PersistedQueriesAudit {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: default_audit_listen(),
+            path: default_audit_path(),
+            max_operations: default_audit_max_operations(),
+            dump_path: None,
+            dump_interval: default_audit_dump_interval(),
         }
     }
 }
@@ -90,3 +182,23 @@ const fn default_require_id() -> bool {
 const fn default_log_unknown() -> bool {
     false
 }
+
+const fn default_hot_reload() -> bool {
+    false
+}
+
+fn default_audit_listen() -> ListenAddr {
+    SocketAddr::from_str("127.0.0.1:8089").unwrap().into()
+}
+
+fn default_audit_path() -> String {
+    "/persisted-queries/unknown".to_string()
+}
+
+const fn default_audit_max_operations() -> usize {
+    1000
+}
+
+const fn default_audit_dump_interval() -> Duration {
+    Duration::from_secs(60)
+}
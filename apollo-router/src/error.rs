@@ -369,6 +369,11 @@ impl IntoGraphQLErrors for QueryPlannerError {
                 height,
                 root_fields,
                 aliases,
+                variables,
+                directives,
+                fragments,
+                input_depth,
+                list_length,
             }) => {
                 let mut errors = Vec::new();
                 let mut build = |exceeded, code, message| {
@@ -401,6 +406,31 @@ impl IntoGraphQLErrors for QueryPlannerError {
                     "MAX_ALIASES_LIMIT",
                     "Maximum aliases limit exceeded in this operation",
                 );
+                build(
+                    variables,
+                    "MAX_VARIABLES_LIMIT",
+                    "Maximum variables limit exceeded in this operation",
+                );
+                build(
+                    directives,
+                    "MAX_DIRECTIVES_LIMIT",
+                    "Maximum directives limit exceeded in this operation",
+                );
+                build(
+                    fragments,
+                    "MAX_FRAGMENTS_LIMIT",
+                    "Maximum fragments limit exceeded in this operation",
+                );
+                build(
+                    input_depth,
+                    "MAX_INPUT_DEPTH_LIMIT",
+                    "Maximum input depth limit exceeded in this operation",
+                );
+                build(
+                    list_length,
+                    "MAX_LIST_LENGTH_LIMIT",
+                    "Maximum list length limit exceeded in this operation",
+                );
                 Ok(errors)
             }
             err => Err(err),
@@ -36,6 +36,9 @@ use crate::router::ConfigurationSource;
 use crate::router::RouterHttpServer;
 use crate::router::SchemaSource;
 use crate::router::ShutdownSource;
+use crate::services::layers::persisted_queries::generate_manifest;
+use crate::services::layers::persisted_queries::ManifestHash;
+use crate::services::layers::persisted_queries::ManifestSource;
 use crate::uplink::Endpoints;
 use crate::uplink::UplinkConfig;
 use crate::LicenseSource;
@@ -116,6 +119,8 @@ extern "C" fn drop_ad_hoc_profiler() {
 enum Commands {
     /// Configuration subcommands.
     Config(ConfigSubcommandArgs),
+    /// Persisted queries subcommands.
+    PersistedQueries(PersistedQueriesSubcommandArgs),
 }
 
 #[derive(Args, Debug)]
@@ -146,6 +151,44 @@ enum ConfigSubcommand {
     Preview,
 }
 
+#[derive(Args, Debug)]
+struct PersistedQueriesSubcommandArgs {
+    /// Subcommands
+    #[clap(subcommand)]
+    command: PersistedQueriesSubcommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum PersistedQueriesSubcommand {
+    /// Generate a persisted query manifest from recorded traffic.
+    GenerateManifest {
+        /// The directory of recordings written by the `experimental_record` plugin.
+        #[clap(long, required_unless_present = "audit", conflicts_with = "audit")]
+        recordings: Option<PathBuf>,
+
+        /// The unknown operations report of the persisted queries audit.
+        #[clap(long)]
+        audit: Option<PathBuf>,
+
+        /// The hash algorithm used to generate the operation IDs.
+        #[clap(long, value_enum, default_value_t)]
+        hash: ManifestHash,
+
+        /// Restrict each operation to the clients that sent it. Operations also sent without a
+        /// client name are available to all clients.
+        #[clap(long)]
+        with_clients: bool,
+
+        /// The header holding the client name of recorded requests.
+        #[clap(long, default_value = "apollographql-client-name")]
+        client_name_header: String,
+
+        /// Write the manifest to this file instead of printing it.
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
 /// Options for the router
 #[derive(Parser, Debug)]
 #[clap(name = "router", about = "Apollo federation router")]
@@ -484,6 +527,41 @@ impl Executable {
                 Discussed::new().print_preview();
                 Ok(())
             }
+            Some(Commands::PersistedQueries(PersistedQueriesSubcommandArgs {
+                command:
+                    PersistedQueriesSubcommand::GenerateManifest {
+                        recordings,
+                        audit,
+                        hash,
+                        with_clients,
+                        client_name_header,
+                        output,
+                    },
+            })) => {
+                let source = match (recordings, audit) {
+                    (Some(recordings), _) => ManifestSource::Recordings {
+                        directory: recordings,
+                        client_name_header,
+                    },
+                    (None, Some(audit)) => ManifestSource::Audit(audit),
+                    (None, None) => return Err(anyhow!("--recordings or --audit is required")),
+                };
+                let manifest =
+                    generate_manifest(source, *hash, *with_clients).map_err(|e| anyhow!(e))?;
+                let contents = serde_json::to_string_pretty(&manifest)?;
+                match output {
+                    Some(output) => {
+                        std::fs::write(output, contents)?;
+                        eprintln!(
+                            "Wrote {} operations to {}",
+                            manifest.operations.len(),
+                            output.display()
+                        );
+                    }
+                    None => println!("{contents}"),
+                }
+                Ok(())
+            }
             None => Self::inner_start(shutdown, schema, config, license, opt).await,
         };
 
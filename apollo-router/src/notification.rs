@@ -1,18 +1,24 @@
 //! Internal pub/sub facility for subscription
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::pin::Pin;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Weak;
 use std::task::Context;
 use std::task::Poll;
 use std::time::Duration;
 use std::time::Instant;
+use std::time::SystemTime;
 
+use arc_swap::ArcSwapOption;
 use futures::Sink;
 use futures::Stream;
 use futures::StreamExt;
+use parking_lot::Mutex;
 use pin_project_lite::pin_project;
 use thiserror::Error;
 use tokio::sync::broadcast;
@@ -26,12 +32,24 @@ use tokio_stream::wrappers::BroadcastStream;
 use tokio_stream::wrappers::IntervalStream;
 use tokio_stream::wrappers::ReceiverStream;
 
+use self::redis::RelayState;
+use self::replay::ReplayBuffer;
+use crate::configuration::RedisCache;
 use crate::graphql;
+use crate::plugins::subscription::ReplayConfig;
 use crate::spec::Schema;
 use crate::Configuration;
 
+mod redis;
+mod replay;
+
+pub(crate) use self::redis::RedisRelay;
+pub(crate) use self::redis::RelayedEvent;
+
 static NOTIFY_CHANNEL_SIZE: usize = 1024;
 static DEFAULT_MSG_CHANNEL_SIZE: usize = 128;
+// How often topics kept for their replay buffer are checked for expiration
+const REPLAY_EXPIRATION_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Error, Debug)]
 pub(crate) enum NotifyError<V> {
@@ -41,6 +59,8 @@ pub(crate) enum NotifyError<V> {
     BroadcastSendError(#[from] broadcast::error::SendError<V>),
     #[error("this topic doesn't exist")]
     UnknownTopic,
+    #[error("cannot reach other router instances: {0}")]
+    RelayError(String),
 }
 
 impl<K, V> From<SendError<Notification<K, V>>> for NotifyError<V>
@@ -72,20 +92,88 @@ where
     }
 }
 
-type ResponseSender<V> =
-    oneshot::Sender<Option<(broadcast::Sender<Option<V>>, broadcast::Receiver<Option<V>>)>>;
+type ResponseSender<V> = oneshot::Sender<Option<Channels<V>>>;
+
+type ResponseSenderWithCreated<V> = oneshot::Sender<(Channels<V>, bool)>;
+
+type SharedReplayBuffer<V> = Arc<Mutex<ReplayBuffer<V>>>;
+
+/// Message sent on a topic, `None` closes it
+type Message<V> = Option<Event<V>>;
+
+#[derive(Clone)]
+struct Event<V> {
+    /// Position of the event in its topic, if the topic has a replay buffer
+    position: Option<u64>,
+    value: V,
+}
+
+/// What a new handle needs to send and receive on a topic
+struct Channels<V> {
+    msg_sender: broadcast::Sender<Message<V>>,
+    msg_receiver: broadcast::Receiver<Message<V>>,
+    replay: Option<SharedReplayBuffer<V>>,
+    // Events to send before the ones received from the channel, when resuming a topic
+    replayed: VecDeque<Event<V>>,
+    info: Option<Arc<TopicInfo>>,
+}
+
+/// Description of a topic opened for a subgraph subscription
+#[derive(Debug)]
+pub(crate) struct TopicInfo {
+    /// Subgraph sending the events of the topic
+    pub(crate) subgraph_name: String,
+    /// Subscription mode used with the subgraph (callback or passthrough)
+    pub(crate) mode: &'static str,
+    pub(crate) created_at: SystemTime,
+    last_event_at: Mutex<Option<SystemTime>>,
+    /// Identifies the operation and the client that opened the topic, the only ones allowed to
+    /// resume it
+    owner: Option<String>,
+}
+
+impl TopicInfo {
+    pub(crate) fn new(subgraph_name: String, mode: &'static str) -> Self {
+        Self {
+            subgraph_name,
+            mode,
+            created_at: SystemTime::now(),
+            last_event_at: Mutex::new(None),
+            owner: None,
+        }
+    }
+
+    pub(crate) fn with_owner(mut self, owner: String) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    pub(crate) fn last_event_at(&self) -> Option<SystemTime> {
+        *self.last_event_at.lock()
+    }
+
+    fn event_sent(&self) {
+        *self.last_event_at.lock() = Some(SystemTime::now());
+    }
+}
 
-type ResponseSenderWithCreated<V> = oneshot::Sender<(
-    broadcast::Sender<Option<V>>,
-    broadcast::Receiver<Option<V>>,
-    bool,
-)>;
+/// A topic listed by [`Notify::topics`]
+#[derive(Debug)]
+pub(crate) struct TopicDetails<K> {
+    pub(crate) topic: K,
+    pub(crate) subscribers: usize,
+    pub(crate) info: Arc<TopicInfo>,
+}
 
 enum Notification<K, V> {
     CreateOrSubscribe {
         topic: K,
         // Sender connected to the original source stream
-        msg_sender: broadcast::Sender<Option<V>>,
+        msg_sender: broadcast::Sender<Message<V>>,
+        // Replay buffer of the topic if it's created
+        replay: Option<SharedReplayBuffer<V>>,
+        // Description of the topic if it's created
+        info: Option<Arc<TopicInfo>>,
         // To know if it has been created or re-used
         response_sender: ResponseSenderWithCreated<V>,
         heartbeat_enabled: bool,
@@ -100,12 +188,31 @@ enum Notification<K, V> {
         // Oneshot channel to fetch the receiver
         response_sender: ResponseSender<V>,
     },
+    Resume {
+        topic: K,
+        // Position of the last event received by the subscriber
+        position: u64,
+        // Owner of the topic, as described by its info
+        owner: String,
+        // Oneshot channel to fetch the receiver
+        response_sender: ResponseSender<V>,
+    },
     Unsubscribe {
         topic: K,
     },
     ForceDelete {
         topic: K,
     },
+    Close {
+        topic: K,
+        // Last message sent to the subscribers
+        message: V,
+        // Returns false if the topic doesn't exist
+        response_sender: oneshot::Sender<bool>,
+    },
+    Topics {
+        response_sender: oneshot::Sender<Vec<TopicDetails<K>>>,
+    },
     Exist {
         topic: K,
         response_sender: oneshot::Sender<bool>,
@@ -114,6 +221,10 @@ enum Notification<K, V> {
         topics: Vec<K>,
         response_sender: oneshot::Sender<(Vec<K>, Vec<K>)>,
     },
+    ExistingTopics {
+        topics: Vec<K>,
+        response_sender: oneshot::Sender<Vec<K>>,
+    },
     UpdateHeartbeat {
         new_ttl: Option<Duration>,
     },
@@ -138,7 +249,13 @@ pub struct Notify<K, V> {
     sender: mpsc::Sender<Notification<K, V>>,
     /// Size (number of events) of the channel to receive message
     pub(crate) queue_size: Option<usize>,
+    /// Replay buffers of the topics created from now on
+    pub(crate) replay: Option<ReplayConfig>,
     router_broadcasts: Arc<RouterBroadcasts>,
+    /// Shares topics with other router instances
+    relay: Arc<ArcSwapOption<RedisRelay>>,
+    /// Identity and shared topics of this instance, for the lifetime of the process
+    relay_state: Arc<RelayState>,
 }
 
 #[buildstructor::buildstructor]
@@ -152,6 +269,7 @@ where
         ttl: Option<Duration>,
         heartbeat_error_message: Option<V>,
         queue_size: Option<usize>,
+        replay: Option<ReplayConfig>,
         router_broadcasts: Option<Arc<RouterBroadcasts>>,
     ) -> Notify<K, V> {
         let (sender, receiver) = mpsc::channel(NOTIFY_CHANNEL_SIZE);
@@ -160,8 +278,11 @@ where
         Notify {
             sender,
             queue_size,
+            replay,
             router_broadcasts: router_broadcasts
                 .unwrap_or_else(|| Arc::new(RouterBroadcasts::new())),
+            relay: Default::default(),
+            relay_state: Default::default(),
         }
     }
 
@@ -172,7 +293,10 @@ where
         Notify {
             sender,
             queue_size: None,
+            replay: None,
             router_broadcasts: Arc::new(RouterBroadcasts::new()),
+            relay: Default::default(),
+            relay_state: Default::default(),
         }
     }
 }
@@ -194,6 +318,14 @@ impl<K, V> Notify<K, V> {
     pub(crate) fn subscribe_schema(&self) -> impl Stream<Item = Arc<Schema>> {
         self.router_broadcasts.subscribe_schema()
     }
+    /// Broadcast that the router is shutting down
+    pub(crate) fn broadcast_shutdown(&self) {
+        self.router_broadcasts.shutdown.0.send(Shutdown::default()).expect("cannot send the shutdown to the static channel. Should not happen because the receiver will always live in this struct; qed");
+    }
+    /// Receive an item when the router starts shutting down
+    pub(crate) fn subscribe_shutdown(&self) -> impl Stream<Item = Shutdown> {
+        self.router_broadcasts.subscribe_shutdown()
+    }
 }
 
 impl<K, V> Notify<K, V>
@@ -207,6 +339,12 @@ where
         self
     }
 
+    #[cfg(not(test))]
+    pub(crate) fn set_replay(mut self, replay: Option<ReplayConfig>) -> Self {
+        self.replay = replay;
+        self
+    }
+
     pub(crate) async fn set_ttl(&self, new_ttl: Option<Duration>) -> Result<(), NotifyError<V>> {
         self.sender
             .send(Notification::UpdateHeartbeat { new_ttl })
@@ -220,27 +358,51 @@ where
         &mut self,
         topic: K,
         heartbeat_enabled: bool,
+    ) -> Result<(Handle<K, V>, bool), NotifyError<V>> {
+        self.create_or_subscribe_topic(topic, heartbeat_enabled, None)
+            .await
+    }
+
+    /// Same as `create_or_subscribe`, the topic is described by `info` if it's created
+    pub(crate) async fn create_or_subscribe_with_info(
+        &mut self,
+        topic: K,
+        heartbeat_enabled: bool,
+        info: TopicInfo,
+    ) -> Result<(Handle<K, V>, bool), NotifyError<V>> {
+        self.create_or_subscribe_topic(topic, heartbeat_enabled, Some(Arc::new(info)))
+            .await
+    }
+
+    async fn create_or_subscribe_topic(
+        &mut self,
+        topic: K,
+        heartbeat_enabled: bool,
+        info: Option<Arc<TopicInfo>>,
     ) -> Result<(Handle<K, V>, bool), NotifyError<V>> {
         let (sender, _receiver) =
             broadcast::channel(self.queue_size.unwrap_or(DEFAULT_MSG_CHANNEL_SIZE));
 
+        let replay = self
+            .replay
+            .as_ref()
+            .filter(|replay| replay.enabled)
+            .map(|replay| Arc::new(Mutex::new(ReplayBuffer::new(replay))));
+
         let (tx, rx) = oneshot::channel();
         self.sender
             .send(Notification::CreateOrSubscribe {
                 topic: topic.clone(),
                 msg_sender: sender,
+                replay,
+                info,
                 response_sender: tx,
                 heartbeat_enabled,
             })
             .await?;
 
-        let (msg_sender, msg_receiver, created) = rx.await?;
-        let handle = Handle::new(
-            topic,
-            self.sender.clone(),
-            msg_sender,
-            BroadcastStream::from(msg_receiver),
-        );
+        let (channels, created) = rx.await?;
+        let handle = Handle::new(topic, self.sender.clone(), channels);
 
         Ok((handle, created))
     }
@@ -255,15 +417,10 @@ where
             })
             .await?;
 
-        let Some((msg_sender, msg_receiver)) = receiver.await? else {
+        let Some(channels) = receiver.await? else {
             return Err(NotifyError::UnknownTopic);
         };
-        let handle = Handle::new(
-            topic,
-            self.sender.clone(),
-            msg_sender,
-            BroadcastStream::from(msg_receiver),
-        );
+        let handle = Handle::new(topic, self.sender.clone(), channels);
 
         Ok(handle)
     }
@@ -281,15 +438,37 @@ where
             })
             .await?;
 
-        let Some((msg_sender, msg_receiver)) = receiver.await? else {
+        let Some(channels) = receiver.await? else {
             return Ok(None);
         };
-        let handle = Handle::new(
-            topic,
-            self.sender.clone(),
-            msg_sender,
-            BroadcastStream::from(msg_receiver),
-        );
+        let handle = Handle::new(topic, self.sender.clone(), channels);
+
+        Ok(handle.into())
+    }
+
+    /// Subscribes to a topic with a replay buffer, starting with the events after the position.
+    /// Only the owner of the topic can resume it
+    pub(crate) async fn resume(
+        &mut self,
+        topic: K,
+        position: u64,
+        owner: String,
+    ) -> Result<Option<Handle<K, V>>, NotifyError<V>> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.sender
+            .send(Notification::Resume {
+                topic: topic.clone(),
+                position,
+                owner,
+                response_sender: sender,
+            })
+            .await?;
+
+        let Some(channels) = receiver.await? else {
+            return Ok(None);
+        };
+        let handle = Handle::new(topic, self.sender.clone(), channels);
 
         Ok(handle.into())
     }
@@ -329,6 +508,25 @@ where
         Ok(resp)
     }
 
+    /// Given a list of topics, returns the ones that exist, without heartbeating them
+    pub(crate) async fn existing_topics(
+        &mut self,
+        topics: Vec<K>,
+    ) -> Result<Vec<K>, NotifyError<V>> {
+        let (response_tx, response_rx) = oneshot::channel();
+
+        self.sender
+            .send(Notification::ExistingTopics {
+                topics,
+                response_sender: response_tx,
+            })
+            .await?;
+
+        let resp = response_rx.await?;
+
+        Ok(resp)
+    }
+
     /// Delete the topic even if several subscribers are still listening
     pub(crate) async fn force_delete(&mut self, topic: K) -> Result<(), NotifyError<V>> {
         // if disconnected, we don't care (the task was stopped)
@@ -338,6 +536,34 @@ where
             .map_err(std::convert::Into::into)
     }
 
+    /// Sends a last message to the subscribers of a topic and deletes it
+    pub(crate) async fn close(&mut self, topic: K, message: V) -> Result<bool, NotifyError<V>> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.sender
+            .send(Notification::Close {
+                topic,
+                message,
+                response_sender: sender,
+            })
+            .await?;
+
+        Ok(receiver.await?)
+    }
+
+    /// Lists the topics opened for subgraph subscriptions
+    pub(crate) async fn topics(&mut self) -> Result<Vec<TopicDetails<K>>, NotifyError<V>> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.sender
+            .send(Notification::Topics {
+                response_sender: sender,
+            })
+            .await?;
+
+        Ok(receiver.await?)
+    }
+
     /// Delete the topic if and only if one or zero subscriber is still listening
     /// This function is not async to allow it to be used in a Drop impl
     #[cfg(test)]
@@ -369,6 +595,106 @@ where
     }
 }
 
+impl Notify<String, graphql::Response> {
+    /// Shares the topics held by this instance with other router instances, or stops sharing them
+    pub(crate) fn set_relay(&self, relay: Option<Arc<RedisRelay>>) {
+        self.relay.store(relay);
+    }
+
+    /// Returns the current relay if it was created with this configuration, so that reloads keep it
+    pub(crate) fn relay_with_config(&self, config: &RedisCache) -> Option<Arc<RedisRelay>> {
+        self.relay
+            .load_full()
+            .filter(|relay| relay.has_config(config))
+    }
+
+    pub(crate) fn relay_state(&self) -> Arc<RelayState> {
+        self.relay_state.clone()
+    }
+
+    /// Returns the topic of a callback subscription, specific to this instance when topics are
+    /// shared, so that callbacks for the same deduplicated subscription on several instances
+    /// don't compete for the same topic
+    pub(crate) fn callback_topic(&self, topic: String) -> String {
+        if self.relay.load().is_some() {
+            format!("{topic}-{}", self.relay_state.instance_id)
+        } else {
+            topic
+        }
+    }
+
+    /// Records that this instance holds the topic, so that other instances forward its events here
+    pub(crate) async fn share(&self, topic: &str) -> Result<(), NotifyError<graphql::Response>> {
+        match self.relay.load_full() {
+            Some(relay) => relay
+                .register(topic)
+                .await
+                .map_err(|err| NotifyError::RelayError(err.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Forwards an event to the instance holding its topic, returns false if no other instance holds it
+    pub(crate) async fn forward(
+        &self,
+        event: RelayedEvent,
+    ) -> Result<bool, NotifyError<graphql::Response>> {
+        match self.relay.load_full() {
+            Some(relay) => relay
+                .forward(event)
+                .await
+                .map_err(|err| NotifyError::RelayError(err.to_string())),
+            None => Ok(false),
+        }
+    }
+
+    /// Given a list of topics, returns the ones held by other instances
+    pub(crate) async fn remote_topics(
+        &self,
+        topics: Vec<String>,
+    ) -> Result<Vec<String>, NotifyError<graphql::Response>> {
+        match self.relay.load_full() {
+            Some(relay) => relay
+                .remote_topics(topics)
+                .await
+                .map_err(|err| NotifyError::RelayError(err.to_string())),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Applies an event forwarded by another instance to the topics of this one
+    pub(crate) async fn apply(
+        &mut self,
+        event: RelayedEvent,
+    ) -> Result<(), NotifyError<graphql::Response>> {
+        match event {
+            RelayedEvent::Next { id, payload } => {
+                if let Some(handle) = self.subscribe_if_exist(id).await? {
+                    handle.into_sink().send_sync(payload)?;
+                }
+            }
+            RelayedEvent::Heartbeat { id } => {
+                // Checking the topic heartbeats it
+                self.exist(id).await?;
+            }
+            RelayedEvent::Complete { id, errors } => {
+                if let Some(errors) = errors {
+                    match self.subscribe(id.clone()).await {
+                        Ok(handle) => handle
+                            .into_sink()
+                            .send_sync(graphql::Response::builder().errors(errors).build())?,
+                        Err(NotifyError::UnknownTopic) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                self.force_delete(id).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 impl<K, V> Default for Notify<K, V>
 where
@@ -428,9 +754,12 @@ where
 {
     handle_guard: HandleGuard<K, V>,
     #[pin]
-    msg_sender: broadcast::Sender<Option<V>>,
+    msg_sender: broadcast::Sender<Message<V>>,
     #[pin]
-    msg_receiver: BroadcastStream<Option<V>>,
+    msg_receiver: BroadcastStream<Message<V>>,
+    replay: Option<SharedReplayBuffer<V>>,
+    replayed: VecDeque<Event<V>>,
+    info: Option<Arc<TopicInfo>>,
 }
 }
 
@@ -444,6 +773,9 @@ where
             handle_guard: self.handle_guard.clone(),
             msg_receiver: BroadcastStream::new(self.msg_sender.subscribe()),
             msg_sender: self.msg_sender.clone(),
+            replay: self.replay.clone(),
+            replayed: VecDeque::new(),
+            info: self.info.clone(),
         }
     }
 }
@@ -455,16 +787,18 @@ where
     fn new(
         topic: K,
         pubsub_sender: mpsc::Sender<Notification<K, V>>,
-        msg_sender: broadcast::Sender<Option<V>>,
-        msg_receiver: BroadcastStream<Option<V>>,
+        channels: Channels<V>,
     ) -> Self {
         Self {
             handle_guard: HandleGuard {
                 topic: Arc::new(topic),
                 pubsub_sender,
             },
-            msg_sender,
-            msg_receiver,
+            msg_sender: channels.msg_sender,
+            msg_receiver: BroadcastStream::from(channels.msg_receiver),
+            replay: channels.replay,
+            replayed: channels.replayed,
+            info: channels.info,
         }
     }
 
@@ -472,6 +806,7 @@ where
         HandleStream {
             handle_guard: self.handle_guard,
             msg_receiver: self.msg_receiver,
+            replayed: self.replayed,
         }
     }
 
@@ -479,6 +814,8 @@ where
         HandleSink {
             handle_guard: self.handle_guard,
             msg_sender: self.msg_sender,
+            replay: self.replay,
+            info: self.info,
         }
     }
 
@@ -488,10 +825,13 @@ where
             HandleSink {
                 handle_guard: self.handle_guard.clone(),
                 msg_sender: self.msg_sender,
+                replay: self.replay,
+                info: self.info,
             },
             HandleStream {
                 handle_guard: self.handle_guard,
                 msg_receiver: self.msg_receiver,
+                replayed: self.replayed,
             },
         )
     }
@@ -504,31 +844,55 @@ where
 {
     handle_guard: HandleGuard<K, V>,
     #[pin]
-    msg_receiver: BroadcastStream<Option<V>>,
+    msg_receiver: BroadcastStream<Message<V>>,
+    replayed: VecDeque<Event<V>>,
 }
 }
 
-impl<K, V> Stream for HandleStream<K, V>
+impl<K, V> HandleStream<K, V>
 where
     K: Clone,
     V: Clone + 'static + Send,
 {
-    type Item = V;
-
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    fn poll_next_event(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event<V>>> {
         let mut this = self.as_mut().project();
+        if let Some(event) = this.replayed.pop_front() {
+            return Poll::Ready(Some(event));
+        }
 
         match Pin::new(&mut this.msg_receiver).poll_next(cx) {
             Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(_)))) => {
                 tracing::info!(monotonic_counter.apollo_router_skipped_event_count = 1u64,);
-                self.poll_next(cx)
+                self.poll_next_event(cx)
             }
             Poll::Ready(None) => Poll::Ready(None),
-            Poll::Ready(Some(Ok(Some(val)))) => Poll::Ready(Some(val)),
+            Poll::Ready(Some(Ok(Some(event)))) => Poll::Ready(Some(event)),
             Poll::Ready(Some(Ok(None))) => Poll::Ready(None),
             Poll::Pending => Poll::Pending,
         }
     }
+
+    /// Returns the values with their position in the topic, if the topic has a replay buffer
+    pub(crate) fn with_positions(mut self) -> impl Stream<Item = (Option<u64>, V)> {
+        futures::stream::poll_fn(move |cx| {
+            Pin::new(&mut self)
+                .poll_next_event(cx)
+                .map(|event| event.map(|event| (event.position, event.value)))
+        })
+    }
+}
+
+impl<K, V> Stream for HandleStream<K, V>
+where
+    K: Clone,
+    V: Clone + 'static + Send,
+{
+    type Item = V;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_next_event(cx)
+            .map(|event| event.map(|event| event.value))
+    }
 }
 
 pin_project! {
@@ -538,7 +902,9 @@ where
 {
     handle_guard: HandleGuard<K, V>,
     #[pin]
-    msg_sender: broadcast::Sender<Option<V>>,
+    msg_sender: broadcast::Sender<Message<V>>,
+    replay: Option<SharedReplayBuffer<V>>,
+    info: Option<Arc<TopicInfo>>,
 }
 }
 
@@ -549,11 +915,61 @@ where
 {
     /// Send data to the subscribed topic
     pub(crate) fn send_sync(&mut self, data: V) -> Result<(), NotifyError<V>> {
-        self.msg_sender.send(data.into()).map_err(|err| {
-            NotifyError::BroadcastSendError(broadcast::error::SendError(err.0.unwrap()))
-        })?;
+        self.send_event(data)
+            .map_err(|err| NotifyError::BroadcastSendError(broadcast::error::SendError(err)))
+    }
 
-        Ok(())
+    /// Send a message from the router itself to the current subscribers of the topic. It's not
+    /// an event of the topic, so it's not kept in the replay buffer
+    pub(crate) fn send_notice(&mut self, data: V) -> Result<(), NotifyError<V>> {
+        if let Some(replay) = &self.replay {
+            if replay.lock().is_closed() {
+                return Err(NotifyError::BroadcastSendError(
+                    broadcast::error::SendError(data),
+                ));
+            }
+        }
+        match self.msg_sender.send(Some(Event {
+            position: None,
+            value: data,
+        })) {
+            // Topics with a replay buffer are kept while nobody listens
+            Err(err) if self.replay.is_none() => Err(NotifyError::BroadcastSendError(
+                broadcast::error::SendError(err.0.expect("we sent a value").value),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn send_event(&self, value: V) -> Result<(), V> {
+        if let Some(info) = &self.info {
+            info.event_sent();
+        }
+        match &self.replay {
+            Some(replay) => {
+                // Sending while holding the lock keeps the events of the buffer and the ones
+                // received by a resumed subscriber consistent
+                let mut replay = replay.lock();
+                if replay.is_closed() {
+                    return Err(value);
+                }
+                let position = replay.push(value.clone());
+                // The topic is kept while nobody listens, the events are replayed later
+                let _ = self.msg_sender.send(Some(Event {
+                    position: Some(position),
+                    value,
+                }));
+                Ok(())
+            }
+            None => self
+                .msg_sender
+                .send(Some(Event {
+                    position: None,
+                    value,
+                }))
+                .map(|_| ())
+                .map_err(|err| err.0.expect("we sent a value").value),
+        }
     }
 }
 
@@ -569,7 +985,7 @@ where
     }
 
     fn start_send(self: Pin<&mut Self>, item: V) -> Result<(), Self::Error> {
-        self.msg_sender.send(Some(item)).map_err(|_err| {
+        self.send_event(item).map_err(|_err| {
             graphql::Error::builder()
                 .message("cannot send payload through pubsub")
                 .extension_code("NOTIFICATION_HANDLE_SEND_ERROR")
@@ -608,9 +1024,14 @@ async fn task<K, V>(
         Some(ttl) => Box::new(IntervalStream::new(tokio::time::interval(ttl))),
         None => Box::new(tokio_stream::pending()),
     };
+    let mut replay_expiration = tokio::time::interval(REPLAY_EXPIRATION_INTERVAL);
+    replay_expiration.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
     loop {
         tokio::select! {
+            _ = replay_expiration.tick() => {
+                pubsub.delete_expired_topics();
+            }
             _ = ttl_fut.next() => {
                 let heartbeat_error_message = heartbeat_error_message.clone();
                 pubsub.kill_dead_topics(heartbeat_error_message).await;
@@ -626,8 +1047,14 @@ async fn task<K, V>(
                         match message {
                             Notification::Unsubscribe { topic } => pubsub.unsubscribe(topic),
                             Notification::ForceDelete { topic } => pubsub.force_delete(topic),
-                            Notification::CreateOrSubscribe { topic,  msg_sender, response_sender, heartbeat_enabled } => {
-                                pubsub.subscribe_or_create(topic, msg_sender, response_sender, heartbeat_enabled);
+                            Notification::CreateOrSubscribe { topic,  msg_sender, replay, info, response_sender, heartbeat_enabled } => {
+                                pubsub.subscribe_or_create(topic, msg_sender, replay, info, response_sender, heartbeat_enabled);
+                            }
+                            Notification::Close { topic, message, response_sender } => {
+                                let _ = response_sender.send(pubsub.close(topic, message));
+                            }
+                            Notification::Topics { response_sender } => {
+                                let _ = response_sender.send(pubsub.topics());
                             }
                             Notification::Subscribe {
                                 topic,
@@ -646,6 +1073,14 @@ async fn task<K, V>(
                                     let _ = response_sender.send(None);
                                 }
                             }
+                            Notification::Resume {
+                                topic,
+                                position,
+                                owner,
+                                response_sender,
+                            } => {
+                                pubsub.resume(topic, position, owner, response_sender);
+                            }
                             Notification::InvalidIds {
                                 topics,
                                 response_sender,
@@ -653,6 +1088,13 @@ async fn task<K, V>(
                                 let invalid_topics = pubsub.invalid_topics(topics);
                                 let _ = response_sender.send(invalid_topics);
                             }
+                            Notification::ExistingTopics {
+                                topics,
+                                response_sender,
+                            } => {
+                                let existing_topics = topics.into_iter().filter(|topic| pubsub.exist(topic)).collect();
+                                let _ = response_sender.send(existing_topics);
+                            }
                             Notification::UpdateHeartbeat {
                                 mut new_ttl
                             } => {
@@ -703,15 +1145,41 @@ async fn task<K, V>(
 
 #[derive(Debug)]
 struct Subscription<V> {
-    msg_sender: broadcast::Sender<Option<V>>,
+    msg_sender: broadcast::Sender<Message<V>>,
+    replay: Option<SharedReplayBuffer<V>>,
+    info: Option<Arc<TopicInfo>>,
     heartbeat_enabled: bool,
     updated_at: Instant,
 }
 
-impl<V> Subscription<V> {
-    fn new(msg_sender: broadcast::Sender<Option<V>>, heartbeat_enabled: bool) -> Self {
+impl<V> Subscription<V>
+where
+    V: Clone,
+{
+    fn new(
+        msg_sender: broadcast::Sender<Message<V>>,
+        replay: Option<SharedReplayBuffer<V>>,
+        info: Option<Arc<TopicInfo>>,
+        heartbeat_enabled: bool,
+    ) -> Self {
+        if let Some(info) = &info {
+            u64_counter!(
+                "apollo.router.subscriptions.opened",
+                "Number of subscriptions opened with subgraphs",
+                1,
+                "subgraph.name" = info.subgraph_name.clone()
+            );
+            i64_up_down_counter!(
+                "apollo.router.subscriptions.active",
+                "Number of subscriptions currently opened with subgraphs",
+                1,
+                "subgraph.name" = info.subgraph_name.clone()
+            );
+        }
         Self {
             msg_sender,
+            replay,
+            info,
             heartbeat_enabled,
             updated_at: Instant::now(),
         }
@@ -720,6 +1188,62 @@ impl<V> Subscription<V> {
     fn touch(&mut self) {
         self.updated_at = Instant::now();
     }
+
+    fn channels(&self) -> Channels<V> {
+        Channels {
+            msg_sender: self.msg_sender.clone(),
+            msg_receiver: self.msg_sender.subscribe(),
+            replay: self.replay.clone(),
+            replayed: VecDeque::new(),
+            info: self.info.clone(),
+        }
+    }
+
+    /// Subscribers left, but the topic is kept for its replay buffer
+    fn is_retained(&self) -> bool {
+        self.replay
+            .as_ref()
+            .map_or(false, |replay| !replay.lock().is_expired())
+    }
+
+    /// Closes the topic, its sinks can't send events anymore
+    fn close(&self) {
+        if let Some(replay) = &self.replay {
+            replay.lock().close();
+        }
+        let _ = self.msg_sender.send(None);
+    }
+
+    /// The router closed the topic before its end
+    fn dropped(&self) {
+        if let Some(info) = &self.info {
+            u64_counter!(
+                "apollo.router.subscriptions.dropped",
+                "Number of subscriptions closed by the router before their end",
+                1,
+                "subgraph.name" = info.subgraph_name.clone()
+            );
+        }
+    }
+}
+
+impl<V> Drop for Subscription<V> {
+    fn drop(&mut self) {
+        if let Some(info) = &self.info {
+            u64_counter!(
+                "apollo.router.subscriptions.closed",
+                "Number of subscriptions closed with subgraphs",
+                1,
+                "subgraph.name" = info.subgraph_name.clone()
+            );
+            i64_up_down_counter!(
+                "apollo.router.subscriptions.active",
+                "Number of subscriptions currently opened with subgraphs",
+                -1,
+                "subgraph.name" = info.subgraph_name.clone()
+            );
+        }
+    }
 }
 
 struct PubSub<K, V>
@@ -758,20 +1282,21 @@ where
     fn create_topic(
         &mut self,
         topic: K,
-        sender: broadcast::Sender<Option<V>>,
+        sender: broadcast::Sender<Message<V>>,
+        replay: Option<SharedReplayBuffer<V>>,
+        info: Option<Arc<TopicInfo>>,
         heartbeat_enabled: bool,
     ) {
-        self.subscriptions
-            .insert(topic, Subscription::new(sender, heartbeat_enabled));
+        self.subscriptions.insert(
+            topic,
+            Subscription::new(sender, replay, info, heartbeat_enabled),
+        );
     }
 
     fn subscribe(&mut self, topic: K, sender: ResponseSender<V>) {
         match self.subscriptions.get_mut(&topic) {
             Some(subscription) => {
-                let _ = sender.send(Some((
-                    subscription.msg_sender.clone(),
-                    subscription.msg_sender.subscribe(),
-                )));
+                let _ = sender.send(Some(subscription.channels()));
             }
             None => {
                 let _ = sender.send(None);
@@ -782,31 +1307,83 @@ where
     fn subscribe_or_create(
         &mut self,
         topic: K,
-        msg_sender: broadcast::Sender<Option<V>>,
+        msg_sender: broadcast::Sender<Message<V>>,
+        replay: Option<SharedReplayBuffer<V>>,
+        info: Option<Arc<TopicInfo>>,
         sender: ResponseSenderWithCreated<V>,
         heartbeat_enabled: bool,
     ) {
         match self.subscriptions.get(&topic) {
             Some(subscription) => {
-                let _ = sender.send((
-                    subscription.msg_sender.clone(),
-                    subscription.msg_sender.subscribe(),
-                    false,
-                ));
+                if let Some(replay) = &subscription.replay {
+                    replay.lock().attach();
+                }
+                if let Some(info) = &subscription.info {
+                    u64_counter!(
+                        "apollo.router.subscriptions.deduplicated",
+                        "Number of subscriptions sharing a subscription already opened with a subgraph",
+                        1,
+                        "subgraph.name" = info.subgraph_name.clone()
+                    );
+                }
+                let _ = sender.send((subscription.channels(), false));
             }
             None => {
-                self.create_topic(topic, msg_sender.clone(), heartbeat_enabled);
-
-                let _ = sender.send((msg_sender.clone(), msg_sender.subscribe(), true));
+                self.create_topic(topic.clone(), msg_sender, replay, info, heartbeat_enabled);
+
+                let channels = self
+                    .subscriptions
+                    .get(&topic)
+                    .expect("the topic has just been created; qed")
+                    .channels();
+                let _ = sender.send((channels, true));
             }
         }
     }
 
+    /// Subscribe to a topic with a replay buffer, starting with the events following the position
+    fn resume(&mut self, topic: K, position: u64, owner: String, sender: ResponseSender<V>) {
+        let channels = self.subscriptions.get(&topic).and_then(|subscription| {
+            let info = subscription.info.as_ref()?;
+            if info.owner.as_ref() != Some(&owner) {
+                return None;
+            }
+            let replay = subscription.replay.as_ref()?;
+            // Subscribing while holding the lock, no event is missed or received twice
+            let mut buffer = replay.lock();
+            if buffer.is_closed() {
+                return None;
+            }
+            buffer.attach();
+            Some(Channels {
+                msg_sender: subscription.msg_sender.clone(),
+                msg_receiver: subscription.msg_sender.subscribe(),
+                replay: Some(replay.clone()),
+                replayed: buffer
+                    .events_after(position)
+                    .into_iter()
+                    .map(|(position, value)| Event {
+                        position: Some(position),
+                        value,
+                    })
+                    .collect(),
+                info: subscription.info.clone(),
+            })
+        });
+        let _ = sender.send(channels);
+    }
+
     fn unsubscribe(&mut self, topic: K) {
         let mut topic_to_delete = false;
         match self.subscriptions.get(&topic) {
             Some(subscription) => {
-                topic_to_delete = subscription.msg_sender.receiver_count() == 0;
+                if subscription.msg_sender.receiver_count() == 0 {
+                    match &subscription.replay {
+                        // Kept until its events are too old to be replayed
+                        Some(replay) => replay.lock().detach(),
+                        None => topic_to_delete = true,
+                    }
+                }
             }
             None => tracing::trace!("Cannot find the subscription to unsubscribe"),
         }
@@ -819,10 +1396,25 @@ where
     fn is_used(&self, topic: &K) -> bool {
         self.subscriptions
             .get(topic)
-            .map(|s| s.msg_sender.receiver_count() > 0)
+            .map(|s| s.msg_sender.receiver_count() > 0 || s.is_retained())
             .unwrap_or_default()
     }
 
+    /// Delete the topics kept for their replay buffer once their events are too old
+    fn delete_expired_topics(&mut self) {
+        self.subscriptions.retain(|_, subscription| {
+            let expired = subscription.msg_sender.receiver_count() == 0
+                && subscription
+                    .replay
+                    .as_ref()
+                    .map_or(false, |replay| replay.lock().is_expired());
+            if expired {
+                subscription.close();
+            }
+            !expired
+        });
+    }
+
     /// Update the heartbeat
     fn touch(&mut self, topic: &K) {
         if let Some(sub) = self.subscriptions.get_mut(topic) {
@@ -864,7 +1456,7 @@ where
                 (HashMap::new(), HashMap::new()),
                 |(mut acc, mut acc_error), (topic, sub)| {
                     if (!sub.heartbeat_enabled || sub.updated_at.elapsed() <= ttl)
-                        && sub.msg_sender.receiver_count() > 0
+                        && (sub.msg_sender.receiver_count() > 0 || sub.is_retained())
                     {
                         acc.insert(topic, sub);
                     } else {
@@ -878,10 +1470,17 @@ where
 
             // Send error message to all killed connections
             for (_subscriber_id, subscription) in closed_subs {
+                if subscription.heartbeat_enabled && subscription.updated_at.elapsed() > ttl {
+                    subscription.dropped();
+                }
+                if let Some(replay) = &subscription.replay {
+                    replay.lock().close();
+                }
                 if let Some(heartbeat_error_message) = &heartbeat_error_message {
-                    let _ = subscription
-                        .msg_sender
-                        .send(heartbeat_error_message.clone().into());
+                    let _ = subscription.msg_sender.send(Some(Event {
+                        position: None,
+                        value: heartbeat_error_message.clone(),
+                    }));
                     let _ = subscription.msg_sender.send(None);
                 }
             }
@@ -899,11 +1498,39 @@ where
         self.force_delete(topic);
     }
 
+    fn close(&mut self, topic: K, message: V) -> bool {
+        match self.subscriptions.remove(&topic) {
+            Some(sub) => {
+                sub.dropped();
+                let _ = sub.msg_sender.send(Some(Event {
+                    position: None,
+                    value: message,
+                }));
+                sub.close();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn topics(&self) -> Vec<TopicDetails<K>> {
+        self.subscriptions
+            .iter()
+            .filter_map(|(topic, sub)| {
+                Some(TopicDetails {
+                    topic: topic.clone(),
+                    subscribers: sub.msg_sender.receiver_count(),
+                    info: sub.info.clone()?,
+                })
+            })
+            .collect()
+    }
+
     fn force_delete(&mut self, topic: K) {
         tracing::trace!("deleting subscription");
         let sub = self.subscriptions.remove(&topic);
         if let Some(sub) = sub {
-            let _ = sub.msg_sender.send(None);
+            sub.close();
         }
     }
 
@@ -918,7 +1545,10 @@ where
             let sub_id = sub_id.clone();
             fut.push(
                 sub.msg_sender
-                    .send(cloned_value.into())
+                    .send(Some(Event {
+                        position: None,
+                        value: cloned_value,
+                    }))
                     .is_err()
                     .then_some(sub_id),
             );
@@ -941,6 +1571,21 @@ pub(crate) struct RouterBroadcasts {
         broadcast::Sender<Arc<Schema>>,
         broadcast::Receiver<Arc<Schema>>,
     ),
+    shutdown: (broadcast::Sender<Shutdown>, broadcast::Receiver<Shutdown>),
+}
+
+/// A shutdown of the router, shared by the subscriptions it closes
+#[derive(Clone, Default)]
+pub(crate) struct Shutdown {
+    drained_subscriptions: Arc<AtomicUsize>,
+}
+
+impl Shutdown {
+    /// Counts a subscription closed by this shutdown, and returns the number of subscriptions it
+    /// closed before, to spread them over the shutdown batches
+    pub(crate) fn drain_subscription(&self) -> usize {
+        self.drained_subscriptions.fetch_add(1, Ordering::Relaxed)
+    }
 }
 
 impl RouterBroadcasts {
@@ -948,6 +1593,7 @@ impl RouterBroadcasts {
         Self {
             configuration: broadcast::channel(1),
             schema: broadcast::channel(1),
+            shutdown: broadcast::channel(1),
         }
     }
 
@@ -960,6 +1606,11 @@ impl RouterBroadcasts {
         BroadcastStream::new(self.schema.0.subscribe())
             .filter_map(|schema| futures::future::ready(schema.ok()))
     }
+
+    pub(crate) fn subscribe_shutdown(&self) -> impl Stream<Item = Shutdown> {
+        BroadcastStream::new(self.shutdown.0.subscribe())
+            .filter_map(|shutdown| futures::future::ready(shutdown.ok()))
+    }
 }
 
 #[cfg(test)]
@@ -1053,6 +1704,66 @@ mod tests {
         assert_eq!(subscriptions_nb, 0);
     }
 
+    #[tokio::test]
+    async fn it_lists_and_closes_topics() {
+        let mut notify = Notify::builder().build();
+        let topic_1 = Uuid::new_v4();
+        let topic_2 = Uuid::new_v4();
+
+        let (handle1, created) = notify
+            .create_or_subscribe_with_info(
+                topic_1,
+                true,
+                TopicInfo::new("accounts".to_string(), "callback"),
+            )
+            .await
+            .unwrap();
+        assert!(created);
+        let (_handle1_bis, created) = notify
+            .create_or_subscribe_with_info(
+                topic_1,
+                true,
+                TopicInfo::new("accounts".to_string(), "callback"),
+            )
+            .await
+            .unwrap();
+        assert!(!created);
+        // Topics without description are not listed
+        let (_handle2, _) = notify.create_or_subscribe(topic_2, false).await.unwrap();
+
+        let (mut sink, stream) = handle1.split();
+        sink.send_sync(serde_json_bytes::json!({"test": "ok"}))
+            .unwrap();
+
+        let topics = notify.topics().await.unwrap();
+        assert_eq!(topics.len(), 1);
+        assert_eq!(topics[0].topic, topic_1);
+        assert_eq!(topics[0].subscribers, 2);
+        assert_eq!(topics[0].info.subgraph_name, "accounts");
+        assert_eq!(topics[0].info.mode, "callback");
+        assert!(topics[0].info.last_event_at().is_some());
+
+        assert!(notify
+            .close(topic_1, serde_json_bytes::json!({"closed": true}))
+            .await
+            .unwrap());
+        assert!(!notify
+            .close(topic_1, serde_json_bytes::json!({"closed": true}))
+            .await
+            .unwrap());
+
+        let messages: Vec<_> = stream.collect().await;
+        assert_eq!(
+            messages,
+            vec![
+                serde_json_bytes::json!({"test": "ok"}),
+                serde_json_bytes::json!({"closed": true})
+            ]
+        );
+        assert!(notify.topics().await.unwrap().is_empty());
+        assert!(notify.exist(topic_2).await.unwrap());
+    }
+
     #[tokio::test]
     async fn it_test_ttl() {
         let mut notify = Notify::builder()
@@ -1116,4 +1827,146 @@ mod tests {
         let subscriptions_nb = notify.debug().await.unwrap();
         assert_eq!(subscriptions_nb, 0);
     }
+
+    #[tokio::test]
+    async fn it_counts_drained_subscriptions_per_shutdown() {
+        let notify: Notify<Uuid, serde_json_bytes::Value> = Notify::builder().build();
+        let mut shutdown_rx = Box::pin(notify.subscribe_shutdown());
+
+        notify.broadcast_shutdown();
+        let shutdown = shutdown_rx.next().await.unwrap();
+        assert_eq!(shutdown.drain_subscription(), 0);
+        assert_eq!(shutdown.clone().drain_subscription(), 1);
+
+        // A new shutdown spreads its subscriptions from the first batch again
+        notify.broadcast_shutdown();
+        let shutdown = shutdown_rx.next().await.unwrap();
+        assert_eq!(shutdown.drain_subscription(), 0);
+    }
+
+    #[tokio::test]
+    async fn it_resumes_topics_with_replay() {
+        let mut notify = Notify::builder()
+            .replay(ReplayConfig {
+                enabled: true,
+                max_events: 2,
+                max_age: Duration::from_secs(60),
+            })
+            .build();
+        let topic = Uuid::new_v4();
+        let owner = "request hash".to_string();
+
+        let (handle, created) = notify
+            .create_or_subscribe_with_info(
+                topic,
+                false,
+                TopicInfo::new("accounts".to_string(), "passthrough").with_owner(owner.clone()),
+            )
+            .await
+            .unwrap();
+        assert!(created);
+        let (mut sink, stream) = handle.split();
+        // The topic is kept after its last subscriber left
+        drop(stream);
+        for event in 1..=3 {
+            sink.send_sync(serde_json_bytes::json!({ "event": event }))
+                .unwrap();
+            // Notices are not events of the topic
+            sink.send_notice(serde_json_bytes::json!({ "notice": event }))
+                .unwrap();
+        }
+
+        // Only the owner of the topic can resume it
+        assert!(notify
+            .resume(topic, 1, "other request hash".to_string())
+            .await
+            .unwrap()
+            .is_none());
+
+        // Only the latest events are replayed
+        let handle = notify
+            .resume(topic, 1, owner.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut stream = handle.into_stream().with_positions();
+        assert_eq!(
+            stream.next().await.unwrap(),
+            (Some(2), serde_json_bytes::json!({ "event": 2 }))
+        );
+        assert_eq!(
+            stream.next().await.unwrap(),
+            (Some(3), serde_json_bytes::json!({ "event": 3 }))
+        );
+        sink.send_sync(serde_json_bytes::json!({ "event": 4 }))
+            .unwrap();
+        assert_eq!(
+            stream.next().await.unwrap(),
+            (Some(4), serde_json_bytes::json!({ "event": 4 }))
+        );
+
+        // Topics without replay buffer can't be resumed
+        let mut notify: Notify<Uuid, serde_json_bytes::Value> = Notify::builder().build();
+        let (_handle, _) = notify
+            .create_or_subscribe_with_info(
+                topic,
+                false,
+                TopicInfo::new("accounts".to_string(), "passthrough").with_owner(owner.clone()),
+            )
+            .await
+            .unwrap();
+        assert!(notify.resume(topic, 1, owner).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn it_applies_relayed_events() {
+        let mut notify: Notify<String, graphql::Response> = Notify::builder().build();
+        let topic = Uuid::new_v4().to_string();
+        let unknown_topic = Uuid::new_v4().to_string();
+
+        let (handle, created) = notify
+            .create_or_subscribe(topic.clone(), true)
+            .await
+            .unwrap();
+        assert!(created);
+        assert_eq!(
+            notify
+                .existing_topics(vec![topic.clone(), unknown_topic.clone()])
+                .await
+                .unwrap(),
+            vec![topic.clone()]
+        );
+
+        notify
+            .apply(RelayedEvent::Next {
+                id: topic.clone(),
+                payload: graphql::Response::builder().subscribed(true).build(),
+            })
+            .await
+            .unwrap();
+        // Events for topics this instance doesn't hold are ignored
+        notify
+            .apply(RelayedEvent::Next {
+                id: unknown_topic,
+                payload: graphql::Response::builder().build(),
+            })
+            .await
+            .unwrap();
+        notify
+            .apply(RelayedEvent::Complete {
+                id: topic.clone(),
+                errors: Some(vec![graphql::Error::builder()
+                    .message("subgraph closed the subscription")
+                    .extension_code("TEST")
+                    .build()]),
+            })
+            .await
+            .unwrap();
+
+        let mut handle = handle.into_stream();
+        assert_eq!(handle.next().await.unwrap().subscribed, Some(true));
+        assert_eq!(handle.next().await.unwrap().errors.len(), 1);
+        assert!(handle.next().await.is_none());
+        assert!(!notify.exist(topic).await.unwrap());
+    }
 }
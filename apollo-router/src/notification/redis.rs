@@ -0,0 +1,303 @@
+//! Shares subscription topics between router instances through Redis.
+//!
+//! In callback mode, subgraphs send events to whichever router instance the load balancer picks,
+//! which is not necessarily the one holding the client connection. Each instance records the
+//! topics it holds in Redis, with its own ID as value, and listens on a pub/sub channel named
+//! after that ID. When a callback is received for a topic held by another instance, it is
+//! published on the channel of that instance, which applies it to its own topics.
+//!
+//! The ID and the topics of an instance are kept by [`Notify`] for the lifetime of the process, so
+//! that a relay created on a reload takes over the topics of the previous one. Topics of callback
+//! subscriptions include the instance ID, so that instances deduplicating the same subscription
+//! each receive their own callbacks.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::time::Duration;
+
+use fred::interfaces::EventInterface;
+use fred::interfaces::KeysInterface;
+use fred::interfaces::PubsubInterface;
+use fred::prelude::RedisClient;
+use fred::types::Expiration;
+use fred::types::Message;
+use futures::future::join_all;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tower::BoxError;
+
+use super::Notify;
+use crate::cache::redis::RedisCacheStorage;
+use crate::configuration::RedisCache;
+use crate::graphql;
+
+// How long a topic is recorded in Redis without being refreshed, when the configuration has no TTL
+const DEFAULT_TOPIC_TTL: Duration = Duration::from_secs(60);
+
+/// Callback received by an instance for a topic held by another one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(crate) enum RelayedEvent {
+    Next {
+        id: String,
+        payload: graphql::Response,
+    },
+    Heartbeat {
+        id: String,
+    },
+    Complete {
+        id: String,
+        errors: Option<Vec<graphql::Error>>,
+    },
+}
+
+impl RelayedEvent {
+    fn id(&self) -> &str {
+        match self {
+            RelayedEvent::Next { id, .. }
+            | RelayedEvent::Heartbeat { id }
+            | RelayedEvent::Complete { id, .. } => id,
+        }
+    }
+}
+
+/// Identity of this instance, and the topics it shares, kept across reloads
+pub(crate) struct RelayState {
+    pub(crate) instance_id: String,
+    // Topics recorded in Redis for this instance
+    topics: Mutex<HashSet<String>>,
+}
+
+impl Default for RelayState {
+    fn default() -> Self {
+        Self {
+            instance_id: uuid::Uuid::new_v4().to_string(),
+            topics: Default::default(),
+        }
+    }
+}
+
+/// Redis connection sharing the topics of this instance
+pub(crate) struct RedisRelay {
+    client: RedisClient,
+    config: RedisCache,
+    state: Arc<RelayState>,
+    ttl: Duration,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl RedisRelay {
+    pub(crate) async fn new(
+        config: RedisCache,
+        notify: Notify<String, graphql::Response>,
+    ) -> Result<Arc<Self>, BoxError> {
+        let (client, _) = RedisCacheStorage::connect(&config).await?;
+        // A connection subscribed to channels can't send other commands
+        let (subscriber, _) = RedisCacheStorage::connect(&config).await?;
+
+        let state = notify.relay_state();
+        let channel = namespaced(
+            &config.namespace,
+            &format!("subscription:instance:{}", state.instance_id),
+        );
+        subscriber.subscribe(channel.clone()).await?;
+        let listener = tokio::task::spawn(listen(subscriber, channel, notify.clone()));
+
+        let ttl = config.ttl.unwrap_or(DEFAULT_TOPIC_TTL);
+        Ok(Arc::new_cyclic(|relay| {
+            // The first refresh records right away the topics registered by previous relays
+            let refresher = tokio::task::spawn(refresh(relay.clone(), notify, ttl / 3));
+            RedisRelay {
+                client,
+                config,
+                state,
+                ttl,
+                tasks: vec![listener, refresher],
+            }
+        }))
+    }
+
+    /// Returns true if the relay was created with this configuration
+    pub(crate) fn has_config(&self, config: &RedisCache) -> bool {
+        match (
+            serde_json::to_value(&self.config),
+            serde_json::to_value(config),
+        ) {
+            (Ok(current), Ok(config)) => current == config,
+            _ => false,
+        }
+    }
+
+    /// Records that this instance holds the topic
+    pub(crate) async fn register(&self, topic: &str) -> Result<(), BoxError> {
+        self.state.topics.lock().insert(topic.to_string());
+        self.record(topic).await
+    }
+
+    /// Sends the event to the instance holding its topic, returns false if no other one holds it
+    pub(crate) async fn forward(&self, event: RelayedEvent) -> Result<bool, BoxError> {
+        let Some(instance_id) = self.owner(event.id()).await? else {
+            return Ok(false);
+        };
+        if instance_id == self.state.instance_id {
+            return Ok(false);
+        }
+
+        let channel = self.key(&format!("subscription:instance:{instance_id}"));
+        let receivers: i64 = self
+            .client
+            .publish(channel, serde_json::to_string(&event)?)
+            .await?;
+        Ok(receivers > 0)
+    }
+
+    /// Returns the topics held by other instances
+    pub(crate) async fn remote_topics(&self, topics: Vec<String>) -> Result<Vec<String>, BoxError> {
+        let owners = join_all(topics.iter().map(|topic| self.owner(topic))).await;
+        let mut remote_topics = Vec::new();
+        for (topic, owner) in topics.into_iter().zip(owners) {
+            if matches!(owner?, Some(instance_id) if instance_id != self.state.instance_id) {
+                remote_topics.push(topic);
+            }
+        }
+        Ok(remote_topics)
+    }
+
+    async fn owner(&self, topic: &str) -> Result<Option<String>, BoxError> {
+        Ok(self
+            .client
+            .get(self.key(&format!("subscription:topic:{topic}")))
+            .await?)
+    }
+
+    async fn record(&self, topic: &str) -> Result<(), BoxError> {
+        let _: () = self
+            .client
+            .set(
+                self.key(&format!("subscription:topic:{topic}")),
+                self.state.instance_id.clone(),
+                Some(Expiration::EX(self.ttl.as_secs().max(1) as i64)),
+                None,
+                false,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, topic: &str) -> Result<(), BoxError> {
+        let _: i64 = self
+            .client
+            .del(self.key(&format!("subscription:topic:{topic}")))
+            .await?;
+        Ok(())
+    }
+
+    fn key(&self, key: &str) -> String {
+        namespaced(&self.config.namespace, key)
+    }
+}
+
+impl Drop for RedisRelay {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+fn namespaced(namespace: &Option<String>, key: &str) -> String {
+    match namespace {
+        Some(namespace) => format!("{namespace}:{key}"),
+        None => key.to_string(),
+    }
+}
+
+/// Applies the events forwarded by other instances
+async fn listen(
+    subscriber: RedisClient,
+    channel: String,
+    mut notify: Notify<String, graphql::Response>,
+) {
+    let mut messages = subscriber.message_rx();
+    let mut reconnections = subscriber.reconnect_rx();
+    loop {
+        tokio::select! {
+            message = messages.recv() => match message {
+                Ok(message) => apply(&mut notify, message).await,
+                Err(RecvError::Lagged(count)) => {
+                    tracing::error!(
+                        "{count} subscription events from other router instances were dropped"
+                    );
+                }
+                Err(RecvError::Closed) => break,
+            },
+            reconnection = reconnections.recv() => {
+                if reconnection.is_err() {
+                    break;
+                }
+                // Subscriptions don't survive reconnections
+                if let Err(err) = subscriber.subscribe(channel.clone()).await {
+                    tracing::error!(
+                        "cannot receive subscription events from other router instances: {err}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+async fn apply(notify: &mut Notify<String, graphql::Response>, message: Message) {
+    let event = message
+        .value
+        .as_string()
+        .and_then(|value| serde_json::from_str::<RelayedEvent>(&value).ok());
+    match event {
+        Some(event) => {
+            if let Err(err) = notify.apply(event).await {
+                tracing::debug!(
+                    "cannot apply a subscription event forwarded by another router instance: {err}"
+                );
+            }
+        }
+        None => {
+            tracing::error!("invalid subscription event forwarded by another router instance")
+        }
+    }
+}
+
+/// Keeps the topics of this instance recorded in Redis while they exist
+async fn refresh(
+    relay: Weak<RedisRelay>,
+    mut notify: Notify<String, graphql::Response>,
+    period: Duration,
+) {
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let Some(relay) = relay.upgrade() else {
+            break;
+        };
+        let topics: Vec<String> = relay.state.topics.lock().iter().cloned().collect();
+        let Ok(existing_topics) = notify.existing_topics(topics.clone()).await else {
+            break;
+        };
+        for topic in topics {
+            let result = if existing_topics.contains(&topic) {
+                relay.record(&topic).await
+            } else {
+                relay.state.topics.lock().remove(&topic);
+                relay.remove(&topic).await
+            };
+            if let Err(err) = result {
+                tracing::error!(
+                    "cannot share subscription topics with other router instances: {err}"
+                );
+            }
+        }
+    }
+}
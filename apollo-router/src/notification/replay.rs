@@ -0,0 +1,95 @@
+//! Latest events of a topic, kept so that subscribers can resume it after a disconnection.
+//!
+//! Events get consecutive positions in their topic. A topic with a replay buffer stays opened
+//! after its last subscriber leaves, until its events are too old to be replayed, so that a
+//! subscriber reconnecting in the meantime gets the events it missed.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::plugins::subscription::ReplayConfig;
+
+#[derive(Debug)]
+pub(crate) struct ReplayBuffer<V> {
+    max_events: usize,
+    max_age: Duration,
+    next_position: u64,
+    events: VecDeque<(u64, Instant, V)>,
+    // Set when the last subscriber of the topic left
+    detached_at: Option<Instant>,
+    // Set when the topic is deleted, no events can be added anymore
+    closed: bool,
+}
+
+impl<V> ReplayBuffer<V>
+where
+    V: Clone,
+{
+    pub(crate) fn new(config: &ReplayConfig) -> Self {
+        Self {
+            max_events: config.max_events,
+            max_age: config.max_age,
+            next_position: 1,
+            events: VecDeque::new(),
+            detached_at: None,
+            closed: false,
+        }
+    }
+
+    /// Records an event, and returns its position
+    pub(crate) fn push(&mut self, value: V) -> u64 {
+        let position = self.next_position;
+        self.next_position += 1;
+        self.events.push_back((position, Instant::now(), value));
+        self.evict();
+
+        position
+    }
+
+    /// Returns the events following the given position
+    pub(crate) fn events_after(&mut self, position: u64) -> Vec<(u64, V)> {
+        self.evict();
+        self.events
+            .iter()
+            .filter(|(event_position, _, _)| *event_position > position)
+            .map(|(event_position, _, value)| (*event_position, value.clone()))
+            .collect()
+    }
+
+    fn evict(&mut self) {
+        while self.events.len() > self.max_events {
+            self.events.pop_front();
+        }
+        while self.events.front().map_or(false, |(_, created_at, _)| {
+            created_at.elapsed() > self.max_age
+        }) {
+            self.events.pop_front();
+        }
+    }
+
+    /// The topic has no subscribers anymore
+    pub(crate) fn detach(&mut self) {
+        self.detached_at.get_or_insert_with(Instant::now);
+    }
+
+    /// A subscriber resumed the topic
+    pub(crate) fn attach(&mut self) {
+        self.detached_at = None;
+    }
+
+    /// A topic without subscribers is deleted once its events are too old to be replayed
+    pub(crate) fn is_expired(&self) -> bool {
+        self.detached_at
+            .map_or(false, |detached_at| detached_at.elapsed() > self.max_age)
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.closed = true;
+        self.events.clear();
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
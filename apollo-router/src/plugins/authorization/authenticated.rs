@@ -1667,6 +1667,7 @@ mod tests {
             multipart_subscription: true,
             json: true,
             wildcard: true,
+            event_stream: false,
         });
         let request = supergraph::Request::fake_builder()
             .query("query { orga(id: 1) { id creatorUser { id } ... @defer { nonNullId } } }")
@@ -0,0 +1,121 @@
+//! Coprocessor calls made in the background, for stages in `async` mode
+//!
+//! Each coprocessor has a bounded queue of calls, sent by a task that runs a limited number of
+//! them at the same time. When the queue is full, calls are dropped.
+
+use std::num::NonZeroUsize;
+
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio_stream::wrappers::ReceiverStream;
+use tower::BoxError;
+
+/// Settings of the calls made by stages in `async` mode
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(super) struct AsyncCallsConf {
+    /// The maximum number of calls waiting to be sent, calls are dropped when the queue is full
+    pub(super) queue_size: NonZeroUsize,
+    /// The maximum number of calls sent at the same time
+    pub(super) concurrency: NonZeroUsize,
+}
+
+impl Default for AsyncCallsConf {
+    fn default() -> Self {
+        Self {
+            queue_size: NonZeroUsize::new(1000).expect("1000 is not zero"),
+            concurrency: NonZeroUsize::new(16).expect("16 is not zero"),
+        }
+    }
+}
+
+/// The queue of background calls to a coprocessor
+///
+/// It belongs to the plugin instance: once that instance and its services are dropped, the calls
+/// already queued are still sent and the task sending them ends.
+#[derive(Clone, Debug)]
+pub(super) struct Queue {
+    sender: mpsc::Sender<BoxFuture<'static, ()>>,
+}
+
+impl Queue {
+    pub(super) fn new(configuration: &AsyncCallsConf) -> Self {
+        let (sender, receiver) = mpsc::channel(configuration.queue_size.get());
+        let concurrency = configuration.concurrency.get();
+        // The task ends once every sender is dropped and the queue is drained
+        tokio::spawn(ReceiverStream::new(receiver).for_each_concurrent(concurrency, |call| call));
+        Self { sender }
+    }
+
+    /// Queues a call, fails if the queue is full
+    pub(super) fn send(&self, call: BoxFuture<'static, ()>) -> Result<(), BoxError> {
+        self.sender.try_send(call).map_err(|error| match error {
+            TrySendError::Full(_) => "the queue of asynchronous calls is full".into(),
+            TrySendError::Closed(_) => "the queue of asynchronous calls is closed".into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_sends_queued_calls() {
+        let queue = Queue::new(&Default::default());
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let calls = calls.clone();
+            queue
+                .send(Box::pin(async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                }))
+                .unwrap();
+        }
+
+        for _ in 0..100 {
+            if calls.load(Ordering::SeqCst) == 3 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("the queued calls were not sent");
+    }
+
+    #[tokio::test]
+    async fn it_drops_calls_when_the_queue_is_full() {
+        let queue = Queue::new(&AsyncCallsConf {
+            queue_size: NonZeroUsize::new(1).unwrap(),
+            concurrency: NonZeroUsize::new(1).unwrap(),
+        });
+
+        // The test runs on a single thread: the queue is not drained until the test yields
+        assert!(queue.send(Box::pin(async {})).is_ok());
+        assert!(queue.send(Box::pin(async {})).is_err());
+    }
+
+    #[tokio::test]
+    async fn queues_are_independent() {
+        let full = Queue::new(&AsyncCallsConf {
+            queue_size: NonZeroUsize::new(1).unwrap(),
+            concurrency: NonZeroUsize::new(1).unwrap(),
+        });
+        let other = Queue::new(&Default::default());
+
+        assert!(full.send(Box::pin(async {})).is_ok());
+        assert!(full.send(Box::pin(async {})).is_err());
+        // Another coprocessor, or the same one after a reload, has its own queue
+        assert!(other.send(Box::pin(async {})).is_ok());
+    }
+}
@@ -1,5 +1,7 @@
+use std::future::Future;
 use std::ops::ControlFlow;
 use std::sync::Arc;
+use std::task::Poll;
 
 use futures::future;
 use futures::stream;
@@ -7,6 +9,7 @@ use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 use tower::BoxError;
+use tower::Layer;
 use tower::ServiceBuilder;
 use tower_service::Service;
 
@@ -16,11 +19,14 @@ use crate::graphql;
 use crate::layers::async_checkpoint::OneShotAsyncCheckpointLayer;
 use crate::layers::ServiceBuilderExt;
 use crate::plugins::coprocessor::EXTERNAL_SPAN_NAME;
+use crate::plugins::telemetry::config_new::selectors::SupergraphSelector;
 use crate::response;
 use crate::services::execution;
+use crate::services::supergraph;
 
 /// What information is passed to a router request/response stage
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[derive(Clone, Debug, Default, Deserialize, Derivative, Serialize, JsonSchema)]
+#[derivative(PartialEq)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct ExecutionRequestConf {
     /// Send the headers
@@ -35,10 +41,24 @@ pub(super) struct ExecutionRequestConf {
     pub(super) method: bool,
     /// Send the query plan
     pub(super) query_plan: bool,
+    /// Only call the coprocessor when this condition matches, evaluated with the supergraph
+    /// selectors
+    #[serde(skip_serializing)]
+    #[derivative(PartialEq = "ignore")]
+    #[schemars(schema_with = "schema_ref::<Condition<SupergraphSelector>>")]
+    pub(super) condition: Condition<SupergraphSelector>,
+    /// What happens when the coprocessor call fails
+    #[derivative(PartialEq = "ignore")]
+    pub(super) failure_policy: FailurePolicy,
+    /// Whether the stage waits for the coprocessor (`sync`) or calls it in the background
+    /// (`async`)
+    #[derivative(PartialEq = "ignore")]
+    pub(super) mode: CallMode,
 }
 
 /// What information is passed to a router request/response stage
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[derive(Clone, Debug, Default, Deserialize, Derivative, Serialize, JsonSchema)]
+#[derivative(PartialEq)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct ExecutionResponseConf {
     /// Send the headers
@@ -51,6 +71,19 @@ pub(super) struct ExecutionResponseConf {
     pub(super) sdl: bool,
     /// Send the HTTP status
     pub(super) status_code: bool,
+    /// Only call the coprocessor when this condition matches, evaluated with the supergraph
+    /// selectors
+    #[serde(skip_serializing)]
+    #[derivative(PartialEq = "ignore")]
+    #[schemars(schema_with = "schema_ref::<Condition<SupergraphSelector>>")]
+    pub(super) condition: Condition<SupergraphSelector>,
+    /// What happens when the coprocessor call fails
+    #[derivative(PartialEq = "ignore")]
+    pub(super) failure_policy: FailurePolicy,
+    /// Whether the stage waits for the coprocessor (`sync`) or calls it in the background
+    /// (`async`)
+    #[derivative(PartialEq = "ignore")]
+    pub(super) mode: CallMode,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
@@ -67,7 +100,7 @@ impl ExecutionStage {
         &self,
         http_client: C,
         service: execution::BoxService,
-        coprocessor_url: String,
+        endpoint: Endpoint,
         sdl: Arc<String>,
     ) -> execution::BoxService
     where
@@ -80,21 +113,25 @@ impl ExecutionStage {
     {
         let request_layer = (self.request != Default::default()).then_some({
             let request_config = self.request.clone();
-            let coprocessor_url = coprocessor_url.clone();
+            let endpoint = endpoint.clone();
             let http_client = http_client.clone();
             let sdl = sdl.clone();
 
-            OneShotAsyncCheckpointLayer::new(move |request: execution::Request| {
-                let request_config = request_config.clone();
-                let coprocessor_url = coprocessor_url.clone();
+            OneShotAsyncCheckpointLayer::new(move |mut request: execution::Request| {
+                let mut request_config = request_config.clone();
+                let endpoint = endpoint.clone();
                 let http_client = http_client.clone();
                 let sdl = sdl.clone();
 
                 async move {
+                    if evaluate_request(&mut request_config.condition, &mut request) != Some(true) {
+                        return Ok(ControlFlow::Continue(request));
+                    }
+
                     let mut succeeded = true;
                     let result = process_execution_request_stage(
                         http_client,
-                        coprocessor_url,
+                        endpoint,
                         sdl,
                         request,
                         request_config,
@@ -123,42 +160,51 @@ impl ExecutionStage {
         let response_layer = (self.response != Default::default()).then_some({
             let response_config = self.response.clone();
 
-            MapFutureLayer::new(move |fut| {
-                let coprocessor_url = coprocessor_url.clone();
-                let sdl: Arc<String> = sdl.clone();
-                let http_client = http_client.clone();
-                let response_config = response_config.clone();
-
-                async move {
-                    let response: execution::Response = fut.await?;
-
-                    let mut succeeded = true;
-                    let result = process_execution_response_stage(
-                        http_client,
-                        coprocessor_url,
-                        sdl,
-                        response,
-                        response_config,
-                    )
-                    .await
-                    .map_err(|error| {
-                        succeeded = false;
-                        tracing::error!(
-                            "external extensibility: execution response stage error: {error}"
+            ConditionLayer {
+                condition: self.response.condition.clone(),
+                map_fn: move |condition: Option<Condition<SupergraphSelector>>, fut| {
+                    let endpoint = endpoint.clone();
+                    let sdl: Arc<String> = sdl.clone();
+                    let http_client = http_client.clone();
+                    let response_config = response_config.clone();
+
+                    async move {
+                        let response: execution::Response = fut.await?;
+
+                        if !condition
+                            .is_some_and(|condition| condition.evaluate_response(&response))
+                        {
+                            return Ok(response);
+                        }
+
+                        let mut succeeded = true;
+                        let result = process_execution_response_stage(
+                            http_client,
+                            endpoint,
+                            sdl,
+                            response,
+                            response_config,
+                        )
+                        .await
+                        .map_err(|error| {
+                            succeeded = false;
+                            tracing::error!(
+                                "external extensibility: execution response stage error: {error}"
+                            );
+                            error
+                        });
+
+                        u64_counter!(
+                            "apollo.router.operations.coprocessor",
+                            "Total operations with co-processors enabled",
+                            1,
+                            "coprocessor.stage" = PipelineStep::ExecutionResponse,
+                            "coprocessor.succeeded" = succeeded
                         );
-                        error
-                    });
-
-                    u64_counter!(
-                        "apollo.router.operations.coprocessor",
-                        "Total operations with co-processors enabled",
-                        1,
-                        "coprocessor.stage" = PipelineStep::ExecutionResponse,
-                        "coprocessor.succeeded" = succeeded
-                    );
-                    result
-                }
-            })
+                        result
+                    }
+                },
+            }
         });
 
         fn external_service_span() -> impl Fn(&execution::Request) -> tracing::Span + Clone {
@@ -180,9 +226,76 @@ impl ExecutionStage {
     }
 }
 
+/// Evaluates the request selectors of a condition on the supergraph request the execution
+/// request was planned from. It's lent to the selectors rather than copied
+fn evaluate_request(
+    condition: &mut Condition<SupergraphSelector>,
+    request: &mut execution::Request,
+) -> Option<bool> {
+    let supergraph_request = supergraph::Request {
+        supergraph_request: std::mem::take(&mut request.supergraph_request),
+        context: request.context.clone(),
+    };
+    let matches = condition.evaluate_request(&supergraph_request);
+    request.supergraph_request = supergraph_request.supergraph_request;
+    matches
+}
+
+/// Maps the future of the response stage with its condition, evaluated on the request. Unlike
+/// [`MapFutureWithRequestDataLayer`](crate::layers::map_future_with_request_data::MapFutureWithRequestDataLayer),
+/// the request is lent mutably to [`evaluate_request`]
+#[derive(Clone)]
+struct ConditionLayer<MF> {
+    condition: Condition<SupergraphSelector>,
+    map_fn: MF,
+}
+
+impl<S, MF> Layer<S> for ConditionLayer<MF>
+where
+    MF: Clone,
+{
+    type Service = ConditionService<S, MF>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConditionService {
+            inner,
+            condition: self.condition.clone(),
+            map_fn: self.map_fn.clone(),
+        }
+    }
+}
+
+struct ConditionService<S, MF> {
+    inner: S,
+    condition: Condition<SupergraphSelector>,
+    map_fn: MF,
+}
+
+impl<S, MF, Fut> Service<execution::Request> for ConditionService<S, MF>
+where
+    S: Service<execution::Request, Error = BoxError>,
+    MF: FnMut(Option<Condition<SupergraphSelector>>, S::Future) -> Fut,
+    Fut: Future<Output = Result<execution::Response, BoxError>>,
+{
+    type Response = execution::Response;
+    type Error = BoxError;
+    type Future = Fut;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: execution::Request) -> Self::Future {
+        let mut condition = self.condition.clone();
+        let condition =
+            (evaluate_request(&mut condition, &mut request) != Some(false)).then_some(condition);
+        (self.map_fn)(condition, self.inner.call(request))
+    }
+}
+
 async fn process_execution_request_stage<C>(
     http_client: C,
-    coprocessor_url: String,
+    endpoint: Endpoint,
     sdl: Arc<String>,
     mut request: execution::Request,
     request_config: ExecutionRequestConf,
@@ -229,19 +342,16 @@ where
         .and_query_plan(query_plan)
         .build();
 
-    tracing::debug!(?payload, "externalized output");
-    let guard = request.context.enter_active_request();
-    let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
-    let duration = start.elapsed().as_secs_f64();
-    drop(guard);
-    tracing::info!(
-        histogram.apollo.router.operations.coprocessor.duration = duration,
-        coprocessor.stage = %PipelineStep::ExecutionRequest,
-    );
-
-    tracing::debug!(?co_processor_result, "co-processor returned");
-    let co_processor_output = co_processor_result?;
+    let co_processor_output = call_coprocessor(
+        http_client,
+        &endpoint,
+        PipelineStep::ExecutionRequest,
+        payload,
+        &request.context,
+        &request_config.failure_policy,
+        request_config.mode,
+    )
+    .await?;
     validate_coprocessor_output(&co_processor_output, PipelineStep::ExecutionRequest)?;
     // unwrap is safe here because validate_coprocessor_output made sure control is available
     let control = co_processor_output.control.expect("validated above; qed");
@@ -324,7 +434,7 @@ where
 
 async fn process_execution_response_stage<C>(
     http_client: C,
-    coprocessor_url: String,
+    endpoint: Endpoint,
     sdl: Arc<String>,
     response: execution::Response,
     response_config: ExecutionResponseConf,
@@ -375,19 +485,16 @@ where
         .build();
 
     // Second, call our co-processor and get a reply.
-    tracing::debug!(?payload, "externalized output");
-    let guard = response.context.enter_active_request();
-    let start = Instant::now();
-    let co_processor_result = payload.call(http_client.clone(), &coprocessor_url).await;
-    let duration = start.elapsed().as_secs_f64();
-    drop(guard);
-    tracing::info!(
-        histogram.apollo.router.operations.coprocessor.duration = duration,
-        coprocessor.stage = %PipelineStep::ExecutionResponse,
-    );
-
-    tracing::debug!(?co_processor_result, "co-processor returned");
-    let co_processor_output = co_processor_result?;
+    let co_processor_output = call_coprocessor(
+        http_client.clone(),
+        &endpoint,
+        PipelineStep::ExecutionResponse,
+        payload,
+        &response.context,
+        &response_config.failure_policy,
+        response_config.mode,
+    )
+    .await?;
 
     validate_coprocessor_output(&co_processor_output, PipelineStep::ExecutionResponse)?;
 
@@ -424,10 +531,11 @@ where
     let mapped_stream = rest
         .then(move |deferred_response| {
             let generator_client = http_client.clone();
-            let generator_coprocessor_url = coprocessor_url.clone();
+            let generator_endpoint = endpoint.clone();
             let generator_map_context = map_context.clone();
             let generator_sdl_to_send = sdl_to_send.clone();
             let generator_id = map_context.id.clone();
+            let generator_failure_policy = response_config.failure_policy.clone();
 
             async move {
                 let body_to_send = response_config.body.then(|| {
@@ -450,14 +558,16 @@ where
                     .build();
 
                 // Second, call our co-processor and get a reply.
-                tracing::debug!(?payload, "externalized output");
-                let guard = generator_map_context.enter_active_request();
-                let co_processor_result = payload
-                    .call(generator_client, &generator_coprocessor_url)
-                    .await;
-                drop(guard);
-                tracing::debug!(?co_processor_result, "co-processor returned");
-                let co_processor_output = co_processor_result?;
+                let co_processor_output = call_coprocessor(
+                    generator_client,
+                    &generator_endpoint,
+                    PipelineStep::ExecutionResponse,
+                    payload,
+                    &generator_map_context,
+                    &generator_failure_policy,
+                    response_config.mode,
+                )
+                .await?;
 
                 validate_coprocessor_output(&co_processor_output, PipelineStep::ExecutionResponse)?;
 
@@ -579,6 +689,9 @@ mod tests {
                 sdl: false,
                 method: false,
                 query_plan: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -681,7 +794,7 @@ mod tests {
         let service = execution_stage.as_service(
             mock_http_client,
             mock_execution_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -713,6 +826,9 @@ mod tests {
                 sdl: false,
                 method: false,
                 query_plan: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -750,7 +866,7 @@ mod tests {
         let service = execution_stage.as_service(
             mock_http_client,
             mock_execution_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -784,6 +900,9 @@ mod tests {
                 body: true,
                 sdl: true,
                 status_code: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             request: Default::default(),
         };
@@ -875,7 +994,7 @@ mod tests {
         let service = execution_stage.as_service(
             mock_http_client,
             mock_execution_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -906,6 +1025,67 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn external_plugin_execution_condition_skips_the_coprocessor() {
+        let execution_stage: ExecutionStage = serde_json::from_value(json!({
+            "request": {
+                "body": true,
+                "condition": {
+                    "eq": [{ "request_header": "x-call-coprocessor" }, "true"]
+                }
+            },
+            "response": {
+                "body": true,
+                "condition": {
+                    "eq": [{ "request_header": "x-call-coprocessor" }, "true"]
+                }
+            }
+        }))
+        .unwrap();
+
+        let mut mock_execution_service = MockExecutionService::new();
+
+        mock_execution_service
+            .expect_call()
+            .returning(|req: execution::Request| {
+                Ok(execution::Response::builder()
+                    .data(json!({ "test": 1234_u32 }))
+                    .errors(Vec::new())
+                    .extensions(crate::json_ext::Object::new())
+                    .context(req.context)
+                    .build()
+                    .unwrap())
+            });
+
+        let mock_http_client = mock_with_callback(move |_: hyper::Request<Body>| {
+            unreachable!("the condition does not match, the coprocessor must not be called")
+        });
+
+        let service = execution_stage.as_service(
+            mock_http_client,
+            mock_execution_service.boxed(),
+            Endpoint::fake("http://test"),
+            Arc::new("".to_string()),
+        );
+
+        let request = execution::Request::fake_builder().build();
+
+        assert_eq!(
+            serde_json_bytes::json!({ "test": 1234_u32 }),
+            service
+                .oneshot(request)
+                .await
+                .unwrap()
+                .response
+                .into_body()
+                .next()
+                .await
+                .unwrap()
+                .data
+                .unwrap()
+        );
+    }
+
     #[tokio::test]
     async fn multi_part() {
         let execution_stage = ExecutionStage {
@@ -915,6 +1095,9 @@ mod tests {
                 body: true,
                 sdl: true,
                 status_code: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             request: Default::default(),
         };
@@ -986,7 +1169,7 @@ mod tests {
         let service = execution_stage.as_service(
             mock_http_client,
             mock_execution_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -1,6 +1,9 @@
 //! Externalization plugin
 
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::num::NonZeroUsize;
 use std::ops::ControlFlow;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -8,6 +11,7 @@ use std::time::Duration;
 use std::time::Instant;
 
 use bytes::Bytes;
+use derivative::Derivative;
 use futures::future::ready;
 use futures::stream::once;
 use futures::StreamExt;
@@ -20,24 +24,47 @@ use hyper::client::HttpConnector;
 use hyper::Body;
 use hyper_rustls::ConfigBuilderExt;
 use hyper_rustls::HttpsConnector;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::ArrayValidation;
+use schemars::schema::InstanceType;
+use schemars::schema::Metadata;
+use schemars::schema::ObjectValidation;
+use schemars::schema::Schema;
+use schemars::schema::SchemaObject;
 use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::de::Error as _;
 use serde::Deserialize;
+use serde::Deserializer;
 use serde::Serialize;
 use tower::timeout::TimeoutLayer;
-use tower::util::MapFutureLayer;
+use tower::util::Either;
 use tower::BoxError;
 use tower::Service;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
+use tracing::Instrument;
 
+use self::background::AsyncCallsConf;
+use self::resilience::CircuitBreakerConf;
+use self::resilience::ResilienceLayer;
+use self::resilience::RetryConf;
 use crate::error::Error;
+use crate::graphql;
 use crate::layers::async_checkpoint::OneShotAsyncCheckpointLayer;
+use crate::layers::map_future_with_request_data::MapFutureWithRequestDataLayer;
 use crate::layers::ServiceBuilderExt;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugins::telemetry::config_new::conditions::Condition;
+use crate::plugins::telemetry::config_new::selectors::RouterSelector;
+use crate::plugins::telemetry::config_new::selectors::SubgraphSelector;
+use crate::plugins::telemetry::config_new::Stage;
 use crate::register_plugin;
 use crate::services;
 use crate::services::external::externalize_header_map;
+use crate::services::external::grpc;
+use crate::services::external::grpc::GrpcClient;
 use crate::services::external::Control;
 use crate::services::external::Externalizable;
 use crate::services::external::PipelineStep;
@@ -47,20 +74,28 @@ use crate::services::router;
 use crate::services::subgraph;
 use crate::services::trust_dns_connector::new_async_http_connector;
 use crate::services::trust_dns_connector::AsyncHyperResolver;
+use crate::Context;
 
 #[cfg(test)]
 mod test;
 
+mod background;
 mod execution;
+mod resilience;
+mod subscription;
 mod supergraph;
 
 pub(crate) const EXTERNAL_SPAN_NAME: &str = "external_plugin";
 const POOL_IDLE_TIMEOUT_DURATION: Option<Duration> = Some(Duration::from_secs(5));
 const COPROCESSOR_ERROR_EXTENSION: &str = "ERROR";
 const COPROCESSOR_DESERIALIZATION_ERROR_EXTENSION: &str = "EXTERNAL_DESERIALIZATION_ERROR";
+const COPROCESSOR_UNAVAILABLE_EXTENSION: &str = "COPROCESSOR_UNAVAILABLE";
 
-type HTTPClientService =
-    tower::timeout::Timeout<hyper::Client<HttpsConnector<HttpConnector<AsyncHyperResolver>>, Body>>;
+type HTTPClientService = resilience::Resilient<
+    tower::timeout::Timeout<
+        Either<hyper::Client<HttpsConnector<HttpConnector<AsyncHyperResolver>>, Body>, GrpcClient>,
+    >,
+>;
 
 #[async_trait::async_trait]
 impl Plugin for CoprocessorPlugin<HTTPClientService> {
@@ -84,15 +119,44 @@ impl Plugin for CoprocessorPlugin<HTTPClientService> {
             .enable_http2()
             .wrap_connector(http_connector);
 
-        let http_client = ServiceBuilder::new()
-            .layer(TimeoutLayer::new(init.config.timeout))
-            .service(
-                hyper::Client::builder()
-                    .pool_idle_timeout(POOL_IDLE_TIMEOUT_DURATION)
-                    .build(connector),
-            );
+        let client = hyper::Client::builder()
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT_DURATION)
+            .build(connector);
+
+        let coprocessors = init
+            .config
+            .into_coprocessors()?
+            .into_iter()
+            .map(|configuration| {
+                // gRPC coprocessors have their own connections, HTTP ones share the pool
+                let transport = if grpc::is_grpc_url(&configuration.url) {
+                    Either::B(GrpcClient::new(
+                        &configuration.url,
+                        configuration.timeout,
+                        configuration.grpc.pool_size.get(),
+                    )?)
+                } else {
+                    Either::A(client.clone())
+                };
+
+                // Each coprocessor has its own timeout, retries and circuit breaker, whatever
+                // its transport
+                let http_client = ServiceBuilder::new()
+                    .layer(ResilienceLayer::new(
+                        &configuration.retry,
+                        &configuration.circuit_breaker,
+                    ))
+                    .layer(TimeoutLayer::new(configuration.timeout))
+                    .service(transport);
+                Ok(Coprocessor {
+                    http_client,
+                    queue: background::Queue::new(&configuration.async_calls),
+                    configuration,
+                })
+            })
+            .collect::<Result<Vec<_>, BoxError>>()?;
 
-        CoprocessorPlugin::new(http_client, init.config, init.supergraph_sdl)
+        CoprocessorPlugin::new(coprocessors, init.supergraph_sdl)
     }
 
     fn router_service(&self, service: router::BoxService) -> router::BoxService {
@@ -143,13 +207,48 @@ where
         + Send
         + Sync
         + 'static,
-    <C as tower::Service<http::Request<Body>>>::Future: Send + Sync + 'static,
+    <C as tower::Service<http::Request<Body>>>::Future: Send + 'static,
 {
-    http_client: C,
-    configuration: Conf,
+    coprocessors: Vec<Coprocessor<C>>,
     sdl: Arc<String>,
 }
 
+/// A configured coprocessor, the client used to call it and the queue of its `async` calls
+#[derive(Debug)]
+struct Coprocessor<C> {
+    http_client: C,
+    queue: background::Queue,
+    configuration: CoprocessorConf,
+}
+
+impl<C> Coprocessor<C> {
+    fn endpoint(&self) -> Endpoint {
+        Endpoint {
+            url: self.configuration.url.clone(),
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+/// Where the stages of a coprocessor send their calls
+#[derive(Clone, Debug)]
+pub(super) struct Endpoint {
+    /// The url of the coprocessor
+    pub(super) url: String,
+    /// The queue of the calls made by stages in `async` mode
+    pub(super) queue: background::Queue,
+}
+
+#[cfg(test)]
+impl Endpoint {
+    pub(super) fn fake(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            queue: background::Queue::new(&Default::default()),
+        }
+    }
+}
+
 impl<C> CoprocessorPlugin<C>
 where
     C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
@@ -157,60 +256,86 @@ where
         + Send
         + Sync
         + 'static,
-    <C as tower::Service<http::Request<Body>>>::Future: Send + Sync + 'static,
+    <C as tower::Service<http::Request<Body>>>::Future: Send + 'static,
 {
-    fn new(http_client: C, configuration: Conf, sdl: Arc<String>) -> Result<Self, BoxError> {
-        Ok(Self {
-            http_client,
-            configuration,
-            sdl,
-        })
+    fn new(coprocessors: Vec<Coprocessor<C>>, sdl: Arc<String>) -> Result<Self, BoxError> {
+        Ok(Self { coprocessors, sdl })
     }
 
+    // Like plugins, the first coprocessor is the outermost layer: request stages are called in
+    // the configured order and response stages in the reverse order.
+
     fn router_service(&self, service: router::BoxService) -> router::BoxService {
-        self.configuration.router.as_service(
-            self.http_client.clone(),
-            service,
-            self.configuration.url.clone(),
-            self.sdl.clone(),
-        )
+        self.coprocessors
+            .iter()
+            .rev()
+            .fold(service, |service, coprocessor| {
+                coprocessor.configuration.router.as_service(
+                    coprocessor.http_client.clone(),
+                    service,
+                    coprocessor.endpoint(),
+                    self.sdl.clone(),
+                )
+            })
     }
 
     fn supergraph_service(
         &self,
         service: services::supergraph::BoxService,
     ) -> services::supergraph::BoxService {
-        self.configuration.supergraph.as_service(
-            self.http_client.clone(),
-            service,
-            self.configuration.url.clone(),
-            self.sdl.clone(),
-        )
+        self.coprocessors
+            .iter()
+            .rev()
+            .fold(service, |service, coprocessor| {
+                let service = coprocessor.configuration.subscription_event.as_service(
+                    coprocessor.http_client.clone(),
+                    service,
+                    coprocessor.endpoint(),
+                    self.sdl.clone(),
+                );
+                coprocessor.configuration.supergraph.as_service(
+                    coprocessor.http_client.clone(),
+                    service,
+                    coprocessor.endpoint(),
+                    self.sdl.clone(),
+                )
+            })
     }
 
     fn execution_service(
         &self,
         service: services::execution::BoxService,
     ) -> services::execution::BoxService {
-        self.configuration.execution.as_service(
-            self.http_client.clone(),
-            service,
-            self.configuration.url.clone(),
-            self.sdl.clone(),
-        )
+        self.coprocessors
+            .iter()
+            .rev()
+            .fold(service, |service, coprocessor| {
+                coprocessor.configuration.execution.as_service(
+                    coprocessor.http_client.clone(),
+                    service,
+                    coprocessor.endpoint(),
+                    self.sdl.clone(),
+                )
+            })
     }
 
     fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
-        self.configuration.subgraph.all.as_service(
-            self.http_client.clone(),
-            service,
-            self.configuration.url.clone(),
-            name.to_string(),
-        )
+        self.coprocessors
+            .iter()
+            .rev()
+            .fold(service, |service, coprocessor| {
+                coprocessor.configuration.subgraph.all.as_service(
+                    coprocessor.http_client.clone(),
+                    service,
+                    coprocessor.endpoint(),
+                    name.to_string(),
+                )
+            })
     }
 }
 /// What information is passed to a router request/response stage
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[derive(Clone, Debug, Default, Deserialize, Derivative, Serialize, JsonSchema)]
+#[derivative(PartialEq)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct RouterRequestConf {
     /// Send the headers
@@ -225,10 +350,23 @@ pub(super) struct RouterRequestConf {
     pub(super) path: bool,
     /// Send the method
     pub(super) method: bool,
+    /// Only call the coprocessor when this condition matches
+    #[serde(skip_serializing)]
+    #[derivative(PartialEq = "ignore")]
+    #[schemars(schema_with = "schema_ref::<Condition<RouterSelector>>")]
+    pub(super) condition: Condition<RouterSelector>,
+    /// What happens when the coprocessor call fails
+    #[derivative(PartialEq = "ignore")]
+    pub(super) failure_policy: FailurePolicy,
+    /// Whether the stage waits for the coprocessor (`sync`) or calls it in the background
+    /// (`async`)
+    #[derivative(PartialEq = "ignore")]
+    pub(super) mode: CallMode,
 }
 
 /// What information is passed to a router request/response stage
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[derive(Clone, Debug, Default, Deserialize, Derivative, Serialize, JsonSchema)]
+#[derivative(PartialEq)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct RouterResponseConf {
     /// Send the headers
@@ -241,9 +379,22 @@ pub(super) struct RouterResponseConf {
     pub(super) sdl: bool,
     /// Send the HTTP status
     pub(super) status_code: bool,
+    /// Only call the coprocessor when this condition matches
+    #[serde(skip_serializing)]
+    #[derivative(PartialEq = "ignore")]
+    #[schemars(schema_with = "schema_ref::<Condition<RouterSelector>>")]
+    pub(super) condition: Condition<RouterSelector>,
+    /// What happens when the coprocessor call fails
+    #[derivative(PartialEq = "ignore")]
+    pub(super) failure_policy: FailurePolicy,
+    /// Whether the stage waits for the coprocessor (`sync`) or calls it in the background
+    /// (`async`)
+    #[derivative(PartialEq = "ignore")]
+    pub(super) mode: CallMode,
 }
 /// What information is passed to a subgraph request/response stage
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[derive(Clone, Debug, Default, Deserialize, Derivative, Serialize, JsonSchema)]
+#[derivative(PartialEq)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct SubgraphRequestConf {
     /// Send the headers
@@ -258,10 +409,23 @@ pub(super) struct SubgraphRequestConf {
     pub(super) method: bool,
     /// Send the service name
     pub(super) service_name: bool,
+    /// Only call the coprocessor when this condition matches
+    #[serde(skip_serializing)]
+    #[derivative(PartialEq = "ignore")]
+    #[schemars(schema_with = "schema_ref::<Condition<SubgraphSelector>>")]
+    pub(super) condition: Condition<SubgraphSelector>,
+    /// What happens when the coprocessor call fails
+    #[derivative(PartialEq = "ignore")]
+    pub(super) failure_policy: FailurePolicy,
+    /// Whether the stage waits for the coprocessor (`sync`) or calls it in the background
+    /// (`async`)
+    #[derivative(PartialEq = "ignore")]
+    pub(super) mode: CallMode,
 }
 
 /// What information is passed to a subgraph request/response stage
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[derive(Clone, Debug, Default, Deserialize, Derivative, Serialize, JsonSchema)]
+#[derivative(PartialEq)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct SubgraphResponseConf {
     /// Send the headers
@@ -274,12 +438,200 @@ pub(super) struct SubgraphResponseConf {
     pub(super) service_name: bool,
     /// Send the http status
     pub(super) status_code: bool,
+    /// Only call the coprocessor when this condition matches
+    #[serde(skip_serializing)]
+    #[derivative(PartialEq = "ignore")]
+    #[schemars(schema_with = "schema_ref::<Condition<SubgraphSelector>>")]
+    pub(super) condition: Condition<SubgraphSelector>,
+    /// What happens when the coprocessor call fails
+    #[derivative(PartialEq = "ignore")]
+    pub(super) failure_policy: FailurePolicy,
+    /// Whether the stage waits for the coprocessor (`sync`) or calls it in the background
+    /// (`async`)
+    #[derivative(PartialEq = "ignore")]
+    pub(super) mode: CallMode,
+}
+
+/// What happens when the coprocessor call of a stage fails
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(super) enum FailurePolicy {
+    /// The request fails
+    #[default]
+    FailClosed,
+    /// The stage continues as if the coprocessor had returned what it was sent, unmodified
+    FailOpen,
+    /// The stage stops as if the coprocessor had answered with `Break` and a GraphQL error,
+    /// only available in request stages and subscription events
+    StaticResponse(StaticResponseConf),
+}
+
+impl FailurePolicy {
+    fn name(&self) -> &'static str {
+        match self {
+            FailurePolicy::FailClosed => "fail_closed",
+            FailurePolicy::FailOpen => "fail_open",
+            FailurePolicy::StaticResponse(_) => "static_response",
+        }
+    }
+}
+
+/// The response of a stage whose coprocessor call failed
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(super) struct StaticResponseConf {
+    /// The HTTP status code
+    #[serde(default = "default_static_response_status_code")]
+    pub(super) status_code: u16,
+    /// The message of the GraphQL error
+    pub(super) message: String,
+}
+
+fn default_static_response_status_code() -> u16 {
+    503
+}
+
+/// How a stage calls the coprocessor
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum CallMode {
+    /// The stage waits for the reply of the coprocessor and applies it
+    #[default]
+    Sync,
+    /// The stage sends the data in the background and continues right away, the reply of the
+    /// coprocessor is ignored
+    Async,
 }
 
 /// Configures the externalization plugin
+#[derive(Clone, Debug)]
+enum Conf {
+    /// A single coprocessor
+    Single(Box<CoprocessorConf>),
+    /// Coprocessors called one after the other: request stages are called in the order of
+    /// the list, response stages in the reverse order
+    Multiple(Vec<CoprocessorConf>),
+}
+
+// The shape is picked from the `coprocessors` key rather than by trying each one, so that the
+// errors are the ones of the configuration that was meant
+impl<'de> Deserialize<'de> for Conf {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut conf = serde_json::Map::<String, serde_json::Value>::deserialize(deserializer)?;
+        match conf.remove("coprocessors") {
+            Some(coprocessors) => {
+                if let Some(field) = conf.keys().next() {
+                    return Err(D::Error::custom(format!(
+                        "`{field}` can't be set next to `coprocessors`, it must be set on the coprocessors of the list"
+                    )));
+                }
+                Vec::deserialize(coprocessors)
+                    .map(Conf::Multiple)
+                    .map_err(D::Error::custom)
+            }
+            None => CoprocessorConf::deserialize(serde_json::Value::Object(conf))
+                .map(|coprocessor| Conf::Single(Box::new(coprocessor)))
+                .map_err(D::Error::custom),
+        }
+    }
+}
+
+// A single object: the fields of a single coprocessor and the `coprocessors` list can't be mixed
+impl JsonSchema for Conf {
+    fn schema_name() -> String {
+        "Conf".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let coprocessors = SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some("Coprocessors called one after the other, instead of a single one: request stages are called in the order of the list, response stages in the reverse order".to_string()),
+                ..Default::default()
+            })),
+            instance_type: Some(InstanceType::Array.into()),
+            array: Some(Box::new(ArrayValidation {
+                items: Some(CoprocessorConf::json_schema(gen).into()),
+                min_items: Some(1),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let required = |field: &str| SchemaObject {
+            object: Some(Box::new(ObjectValidation {
+                required: [field.to_string()].into(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        let mut schema = CoprocessorConf::json_schema(gen).into_object();
+        schema.metadata().description = Some("Configures the externalization plugin".to_string());
+        let object = schema.object();
+        object
+            .properties
+            .insert("coprocessors".to_string(), coprocessors.into());
+        // The url is only required by a single coprocessor
+        object.required.remove("url");
+        let subschemas = schema.subschemas();
+        subschemas.if_schema = Some(Box::new(required("coprocessors").into()));
+        subschemas.then_schema = Some(Box::new(
+            SchemaObject {
+                object: Some(Box::new(ObjectValidation {
+                    max_properties: Some(1),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }
+            .into(),
+        ));
+        subschemas.else_schema = Some(Box::new(required("url").into()));
+        schema.into()
+    }
+}
+
+impl Conf {
+    fn into_coprocessors(self) -> Result<Vec<CoprocessorConf>, BoxError> {
+        let coprocessors = match self {
+            Conf::Single(coprocessor) => vec![*coprocessor],
+            Conf::Multiple(coprocessors) => {
+                if coprocessors.is_empty() {
+                    return Err("at least one coprocessor must be configured".into());
+                }
+                let mut names = HashSet::new();
+                for coprocessor in &coprocessors {
+                    let name = coprocessor.name.as_deref().ok_or_else(|| {
+                        format!(
+                            "the coprocessor at '{}' must have a name when several coprocessors are configured",
+                            coprocessor.url
+                        )
+                    })?;
+                    if !names.insert(name) {
+                        return Err(format!(
+                            "the coprocessor '{name}' is configured more than once"
+                        )
+                        .into());
+                    }
+                }
+                coprocessors
+            }
+        };
+        for coprocessor in &coprocessors {
+            coprocessor.validate()?;
+        }
+        Ok(coprocessors)
+    }
+}
+
+/// Configures a coprocessor
 #[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
-struct Conf {
+struct CoprocessorConf {
+    /// The name of the coprocessor, required when several coprocessors are configured
+    #[serde(default)]
+    name: Option<String>,
     /// The url you'd like to offload processing to
     url: String,
     /// The timeout for external requests
@@ -289,16 +641,117 @@ struct Conf {
     timeout: Duration,
     /// The router stage request/response configuration
     #[serde(default)]
+    #[schemars(schema_with = "schema_ref::<RouterStage>")]
     router: RouterStage,
     /// The supergraph stage request/response configuration
     #[serde(default)]
+    #[schemars(schema_with = "schema_ref::<supergraph::SupergraphStage>")]
     supergraph: supergraph::SupergraphStage,
     /// The execution stage request/response configuration
     #[serde(default)]
+    #[schemars(schema_with = "schema_ref::<execution::ExecutionStage>")]
     execution: execution::ExecutionStage,
     /// The subgraph stage request/response configuration
     #[serde(default)]
+    #[schemars(schema_with = "schema_ref::<SubgraphStages>")]
     subgraph: SubgraphStages,
+    /// The subscription event configuration, called on each event of a subscription
+    #[serde(default)]
+    #[schemars(schema_with = "schema_ref::<subscription::SubscriptionEventConf>")]
+    subscription_event: subscription::SubscriptionEventConf,
+    /// Connection settings used when the url has a `grpc://` or `grpcs://` scheme
+    #[serde(default)]
+    grpc: GrpcConf,
+    /// Retries of failed calls to the coprocessor
+    #[serde(default)]
+    retry: RetryConf,
+    /// Stops calling the coprocessor while it keeps failing
+    #[serde(default)]
+    circuit_breaker: CircuitBreakerConf,
+    /// Settings of the calls made by stages in `async` mode
+    #[serde(default)]
+    async_calls: AsyncCallsConf,
+}
+
+impl CoprocessorConf {
+    fn validate(&self) -> Result<(), BoxError> {
+        // A response has already started when a response stage is called, and the following
+        // `@defer` chunks can't be stopped: the static response can only end request stages
+        let response_failure_policies = [
+            ("router", &self.router.response.failure_policy),
+            ("supergraph", &self.supergraph.response.failure_policy),
+            ("execution", &self.execution.response.failure_policy),
+            ("subgraph", &self.subgraph.all.response.failure_policy),
+        ];
+        for (stage, failure_policy) in response_failure_policies {
+            if matches!(failure_policy, FailurePolicy::StaticResponse(_)) {
+                return Err(format!(
+                    "the {stage} response stage of the coprocessor at '{}' can't use the `static_response` failure policy, it is only available in request stages and subscription events",
+                    self.url
+                )
+                .into());
+            }
+        }
+        // Response selectors select nothing on requests, such a condition would never match
+        let request_conditions = [
+            (
+                "router",
+                self.router.request.condition.is_active(Stage::Request),
+            ),
+            (
+                "supergraph",
+                self.supergraph.request.condition.is_active(Stage::Request),
+            ),
+            (
+                "execution",
+                self.execution.request.condition.is_active(Stage::Request),
+            ),
+            (
+                "subgraph",
+                self.subgraph
+                    .all
+                    .request
+                    .condition
+                    .is_active(Stage::Request),
+            ),
+        ];
+        for (stage, is_active) in request_conditions {
+            if !is_active {
+                return Err(format!(
+                    "the condition of the {stage} request stage of the coprocessor at '{}' uses response selectors, it can't be evaluated on requests",
+                    self.url
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Connection settings for coprocessors called through gRPC
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct GrpcConf {
+    /// The number of HTTP/2 connections opened to the coprocessor, requests are spread over them
+    pool_size: NonZeroUsize,
+}
+
+impl Default for GrpcConf {
+    fn default() -> Self {
+        Self {
+            pool_size: NonZeroUsize::new(1).expect("1 is not zero"),
+        }
+    }
+}
+
+/// Refers to a schema defined once instead of inlining it in each stage and each coprocessor
+fn schema_ref<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    // Generating the schema of a recursive type, like conditions, defines it
+    let schema = gen.subschema_for::<T>();
+    let name = T::schema_name();
+    let reference = format!("{}{name}", gen.settings().definitions_path);
+    gen.definitions_mut().entry(name).or_insert(schema);
+    Schema::new_ref(reference)
 }
 
 fn default_timeout() -> Duration {
@@ -319,7 +772,7 @@ impl RouterStage {
         &self,
         http_client: C,
         service: router::BoxService,
-        coprocessor_url: String,
+        endpoint: Endpoint,
         sdl: Arc<String>,
     ) -> router::BoxService
     where
@@ -332,21 +785,25 @@ impl RouterStage {
     {
         let request_layer = (self.request != Default::default()).then_some({
             let request_config = self.request.clone();
-            let coprocessor_url = coprocessor_url.clone();
+            let endpoint = endpoint.clone();
             let http_client = http_client.clone();
             let sdl = sdl.clone();
 
             OneShotAsyncCheckpointLayer::new(move |request: router::Request| {
-                let request_config = request_config.clone();
-                let coprocessor_url = coprocessor_url.clone();
+                let mut request_config = request_config.clone();
+                let endpoint = endpoint.clone();
                 let http_client = http_client.clone();
                 let sdl = sdl.clone();
 
                 async move {
+                    if request_config.condition.evaluate_request(&request) != Some(true) {
+                        return Ok(ControlFlow::Continue(request));
+                    }
+
                     let mut succeeded = true;
                     let result = process_router_request_stage(
                         http_client,
-                        coprocessor_url,
+                        endpoint,
                         sdl,
                         request,
                         request_config,
@@ -373,41 +830,54 @@ impl RouterStage {
 
         let response_layer = (self.response != Default::default()).then_some({
             let response_config = self.response.clone();
-            MapFutureLayer::new(move |fut| {
-                let sdl = sdl.clone();
-                let coprocessor_url = coprocessor_url.clone();
-                let http_client = http_client.clone();
-                let response_config = response_config.clone();
-
-                async move {
-                    let response: router::Response = fut.await?;
-
-                    let mut succeeded = true;
-                    let result = process_router_response_stage(
-                        http_client,
-                        coprocessor_url,
-                        sdl,
-                        response,
-                        response_config,
-                    )
-                    .await
-                    .map_err(|error| {
-                        succeeded = false;
-                        tracing::error!(
-                            "external extensibility: router response stage error: {error}"
+            let condition = self.response.condition.clone();
+            MapFutureWithRequestDataLayer::new(
+                move |request: &router::Request| {
+                    let mut condition = condition.clone();
+                    (condition.evaluate_request(request) != Some(false)).then_some(condition)
+                },
+                move |condition: Option<Condition<RouterSelector>>, fut| {
+                    let sdl = sdl.clone();
+                    let endpoint = endpoint.clone();
+                    let http_client = http_client.clone();
+                    let response_config = response_config.clone();
+
+                    async move {
+                        let response: router::Response = fut.await?;
+
+                        if !condition
+                            .is_some_and(|condition| condition.evaluate_response(&response))
+                        {
+                            return Ok(response);
+                        }
+
+                        let mut succeeded = true;
+                        let result = process_router_response_stage(
+                            http_client,
+                            endpoint,
+                            sdl,
+                            response,
+                            response_config,
+                        )
+                        .await
+                        .map_err(|error| {
+                            succeeded = false;
+                            tracing::error!(
+                                "external extensibility: router response stage error: {error}"
+                            );
+                            error
+                        });
+                        u64_counter!(
+                            "apollo.router.operations.coprocessor",
+                            "Total operations with co-processors enabled",
+                            1,
+                            "coprocessor.stage" = PipelineStep::RouterResponse,
+                            "coprocessor.succeeded" = succeeded
                         );
-                        error
-                    });
-                    u64_counter!(
-                        "apollo.router.operations.coprocessor",
-                        "Total operations with co-processors enabled",
-                        1,
-                        "coprocessor.stage" = PipelineStep::RouterResponse,
-                        "coprocessor.succeeded" = succeeded
-                    );
-                    result
-                }
-            })
+                        result
+                    }
+                },
+            )
         });
 
         fn external_service_span() -> impl Fn(&router::Request) -> tracing::Span + Clone {
@@ -454,7 +924,7 @@ impl SubgraphStage {
         &self,
         http_client: C,
         service: subgraph::BoxService,
-        coprocessor_url: String,
+        endpoint: Endpoint,
         service_name: String,
     ) -> subgraph::BoxService
     where
@@ -468,19 +938,23 @@ impl SubgraphStage {
         let request_layer = (self.request != Default::default()).then_some({
             let request_config = self.request.clone();
             let http_client = http_client.clone();
-            let coprocessor_url = coprocessor_url.clone();
+            let endpoint = endpoint.clone();
             let service_name = service_name.clone();
             OneShotAsyncCheckpointLayer::new(move |request: subgraph::Request| {
                 let http_client = http_client.clone();
-                let coprocessor_url = coprocessor_url.clone();
+                let endpoint = endpoint.clone();
                 let service_name = service_name.clone();
-                let request_config = request_config.clone();
+                let mut request_config = request_config.clone();
 
                 async move {
+                    if request_config.condition.evaluate_request(&request) != Some(true) {
+                        return Ok(ControlFlow::Continue(request));
+                    }
+
                     let mut succeeded = true;
                     let result = process_subgraph_request_stage(
                         http_client,
-                        coprocessor_url,
+                        endpoint,
                         service_name,
                         request,
                         request_config,
@@ -508,41 +982,54 @@ impl SubgraphStage {
         let response_layer = (self.response != Default::default()).then_some({
             let response_config = self.response.clone();
 
-            MapFutureLayer::new(move |fut| {
-                let http_client = http_client.clone();
-                let coprocessor_url = coprocessor_url.clone();
-                let response_config = response_config.clone();
-                let service_name = service_name.clone();
-
-                async move {
-                    let response: subgraph::Response = fut.await?;
-
-                    let mut succeeded = true;
-                    let result = process_subgraph_response_stage(
-                        http_client,
-                        coprocessor_url,
-                        service_name,
-                        response,
-                        response_config,
-                    )
-                    .await
-                    .map_err(|error| {
-                        succeeded = false;
-                        tracing::error!(
-                            "external extensibility: subgraph response stage error: {error}"
+            let condition = self.response.condition.clone();
+            MapFutureWithRequestDataLayer::new(
+                move |request: &subgraph::Request| {
+                    let mut condition = condition.clone();
+                    (condition.evaluate_request(request) != Some(false)).then_some(condition)
+                },
+                move |condition: Option<Condition<SubgraphSelector>>, fut| {
+                    let http_client = http_client.clone();
+                    let endpoint = endpoint.clone();
+                    let response_config = response_config.clone();
+                    let service_name = service_name.clone();
+
+                    async move {
+                        let response: subgraph::Response = fut.await?;
+
+                        if !condition
+                            .is_some_and(|condition| condition.evaluate_response(&response))
+                        {
+                            return Ok(response);
+                        }
+
+                        let mut succeeded = true;
+                        let result = process_subgraph_response_stage(
+                            http_client,
+                            endpoint,
+                            service_name,
+                            response,
+                            response_config,
+                        )
+                        .await
+                        .map_err(|error| {
+                            succeeded = false;
+                            tracing::error!(
+                                "external extensibility: subgraph response stage error: {error}"
+                            );
+                            error
+                        });
+                        u64_counter!(
+                            "apollo.router.operations.coprocessor",
+                            "Total operations with co-processors enabled",
+                            1,
+                            "coprocessor.stage" = PipelineStep::SubgraphResponse,
+                            "coprocessor.succeeded" = succeeded
                         );
-                        error
-                    });
-                    u64_counter!(
-                        "apollo.router.operations.coprocessor",
-                        "Total operations with co-processors enabled",
-                        1,
-                        "coprocessor.stage" = PipelineStep::SubgraphResponse,
-                        "coprocessor.succeeded" = succeeded
-                    );
-                    result
-                }
-            })
+                        result
+                    }
+                },
+            )
         });
 
         fn external_service_span() -> impl Fn(&subgraph::Request) -> tracing::Span + Clone {
@@ -567,7 +1054,7 @@ impl SubgraphStage {
 // -----------------------------------------------------------------------------------------
 async fn process_router_request_stage<C>(
     http_client: C,
-    coprocessor_url: String,
+    endpoint: Endpoint,
     sdl: Arc<String>,
     mut request: router::Request,
     request_config: RouterRequestConf,
@@ -615,19 +1102,16 @@ where
         .method(parts.method.to_string())
         .build();
 
-    tracing::debug!(?payload, "externalized output");
-    let guard = request.context.enter_active_request();
-    let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
-    let duration = start.elapsed().as_secs_f64();
-    drop(guard);
-    tracing::info!(
-        histogram.apollo.router.operations.coprocessor.duration = duration,
-        coprocessor.stage = %PipelineStep::RouterRequest,
-    );
-
-    tracing::debug!(?co_processor_result, "co-processor returned");
-    let mut co_processor_output = co_processor_result?;
+    let mut co_processor_output = call_coprocessor(
+        http_client,
+        &endpoint,
+        PipelineStep::RouterRequest,
+        payload,
+        &request.context,
+        &request_config.failure_policy,
+        request_config.mode,
+    )
+    .await?;
 
     validate_coprocessor_output(&co_processor_output, PipelineStep::RouterRequest)?;
     // unwrap is safe here because validate_coprocessor_output made sure control is available
@@ -720,7 +1204,7 @@ where
 
 async fn process_router_response_stage<C>(
     http_client: C,
-    coprocessor_url: String,
+    endpoint: Endpoint,
     sdl: Arc<String>,
     mut response: router::Response,
     response_config: RouterResponseConf,
@@ -779,19 +1263,16 @@ where
         .build();
 
     // Second, call our co-processor and get a reply.
-    tracing::debug!(?payload, "externalized output");
-    let guard = response.context.enter_active_request();
-    let start = Instant::now();
-    let co_processor_result = payload.call(http_client.clone(), &coprocessor_url).await;
-    let duration = start.elapsed().as_secs_f64();
-    drop(guard);
-    tracing::info!(
-        histogram.apollo.router.operations.coprocessor.duration = duration,
-        coprocessor.stage = %PipelineStep::RouterResponse,
-    );
-
-    tracing::debug!(?co_processor_result, "co-processor returned");
-    let co_processor_output = co_processor_result?;
+    let co_processor_output = call_coprocessor(
+        http_client.clone(),
+        &endpoint,
+        PipelineStep::RouterResponse,
+        payload,
+        &response.context,
+        &response_config.failure_policy,
+        response_config.mode,
+    )
+    .await?;
 
     validate_coprocessor_output(&co_processor_output, PipelineStep::RouterResponse)?;
 
@@ -835,10 +1316,11 @@ where
         .map_err(BoxError::from)
         .and_then(move |deferred_response| {
             let generator_client = http_client.clone();
-            let generator_coprocessor_url = coprocessor_url.clone();
+            let generator_endpoint = endpoint.clone();
             let generator_map_context = map_context.clone();
             let generator_sdl_to_send = sdl_to_send.clone();
             let generator_id = map_context.id.clone();
+            let generator_failure_policy = response_config.failure_policy.clone();
 
             async move {
                 let bytes = deferred_response.to_vec();
@@ -862,14 +1344,16 @@ where
                     .build();
 
                 // Second, call our co-processor and get a reply.
-                tracing::debug!(?payload, "externalized output");
-                let guard = generator_map_context.enter_active_request();
-                let co_processor_result = payload
-                    .call(generator_client, &generator_coprocessor_url)
-                    .await;
-                drop(guard);
-                tracing::debug!(?co_processor_result, "co-processor returned");
-                let co_processor_output = co_processor_result?;
+                let co_processor_output = call_coprocessor(
+                    generator_client,
+                    &generator_endpoint,
+                    PipelineStep::RouterResponse,
+                    payload,
+                    &generator_map_context,
+                    &generator_failure_policy,
+                    response_config.mode,
+                )
+                .await?;
 
                 validate_coprocessor_output(&co_processor_output, PipelineStep::RouterResponse)?;
 
@@ -908,7 +1392,7 @@ where
 
 async fn process_subgraph_request_stage<C>(
     http_client: C,
-    coprocessor_url: String,
+    endpoint: Endpoint,
     service_name: String,
     mut request: subgraph::Request,
     request_config: SubgraphRequestConf,
@@ -951,19 +1435,16 @@ where
         .and_uri(uri)
         .build();
 
-    tracing::debug!(?payload, "externalized output");
-    let guard = request.context.enter_active_request();
-    let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
-    let duration = start.elapsed().as_secs_f64();
-    drop(guard);
-    tracing::info!(
-        histogram.apollo.router.operations.coprocessor.duration = duration,
-        coprocessor.stage = %PipelineStep::SubgraphRequest,
-    );
-
-    tracing::debug!(?co_processor_result, "co-processor returned");
-    let co_processor_output = co_processor_result?;
+    let co_processor_output = call_coprocessor(
+        http_client,
+        &endpoint,
+        PipelineStep::SubgraphRequest,
+        payload,
+        &request.context,
+        &request_config.failure_policy,
+        request_config.mode,
+    )
+    .await?;
     validate_coprocessor_output(&co_processor_output, PipelineStep::SubgraphRequest)?;
     // unwrap is safe here because validate_coprocessor_output made sure control is available
     let control = co_processor_output.control.expect("validated above; qed");
@@ -1053,7 +1534,7 @@ where
 
 async fn process_subgraph_response_stage<C>(
     http_client: C,
-    coprocessor_url: String,
+    endpoint: Endpoint,
     service_name: String,
     mut response: subgraph::Response,
     response_config: SubgraphResponseConf,
@@ -1096,19 +1577,16 @@ where
         .and_service_name(service_name)
         .build();
 
-    tracing::debug!(?payload, "externalized output");
-    let guard = response.context.enter_active_request();
-    let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
-    let duration = start.elapsed().as_secs_f64();
-    drop(guard);
-    tracing::info!(
-        histogram.apollo.router.operations.coprocessor.duration = duration,
-        coprocessor.stage = %PipelineStep::SubgraphResponse,
-    );
-
-    tracing::debug!(?co_processor_result, "co-processor returned");
-    let co_processor_output = co_processor_result?;
+    let co_processor_output = call_coprocessor(
+        http_client,
+        &endpoint,
+        PipelineStep::SubgraphResponse,
+        payload,
+        &response.context,
+        &response_config.failure_policy,
+        response_config.mode,
+    )
+    .await?;
 
     validate_coprocessor_output(&co_processor_output, PipelineStep::SubgraphResponse)?;
 
@@ -1170,6 +1648,151 @@ fn validate_coprocessor_output<T>(
     Ok(())
 }
 
+/// Body types sent to coprocessors
+trait CoprocessorBody: Sized {
+    fn from_graphql_response(response: &graphql::Response) -> Result<Self, BoxError>;
+}
+
+impl CoprocessorBody for String {
+    fn from_graphql_response(response: &graphql::Response) -> Result<Self, BoxError> {
+        Ok(serde_json::to_string(response)?)
+    }
+}
+
+impl CoprocessorBody for serde_json::Value {
+    fn from_graphql_response(response: &graphql::Response) -> Result<Self, BoxError> {
+        Ok(serde_json::to_value(response)?)
+    }
+}
+
+/// Calls the coprocessor and records how long it took
+///
+/// When the call fails, the failure policy of the stage decides whether the error is returned
+/// or replaced by the reply of a coprocessor that changes nothing or breaks.
+///
+/// In `async` mode, the call is queued and the reply of a coprocessor that changes nothing is
+/// returned right away.
+async fn call_coprocessor<C, T>(
+    http_client: C,
+    endpoint: &Endpoint,
+    stage: PipelineStep,
+    payload: Externalizable<T>,
+    context: &Context,
+    failure_policy: &FailurePolicy,
+    mode: CallMode,
+) -> Result<Externalizable<T>, BoxError>
+where
+    C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    <C as tower::Service<http::Request<Body>>>::Future: Send + 'static,
+    T: CoprocessorBody + Debug + DeserializeOwned + Serialize + Send + Sync + 'static,
+{
+    if mode == CallMode::Async {
+        let unchanged = payload.unchanged();
+        let url = endpoint.url.clone();
+        let call_stage = stage.clone();
+        let call = async move {
+            tracing::debug!(?payload, "externalized output");
+            let start = Instant::now();
+            let co_processor_result = payload.call(http_client, &url).await;
+            let duration = start.elapsed().as_secs_f64();
+            tracing::info!(
+                histogram.apollo.router.operations.coprocessor.duration = duration,
+                coprocessor.stage = %call_stage,
+            );
+            // The reply is ignored
+            if let Err(error) = co_processor_result {
+                tracing::warn!(
+                    "external extensibility: {call_stage} asynchronous coprocessor call failed: {error}"
+                );
+                u64_counter!(
+                    "apollo.router.operations.coprocessor.failures",
+                    "Number of failed calls to co-processors",
+                    1,
+                    "coprocessor.stage" = call_stage,
+                    "coprocessor.failure_policy" = "async"
+                );
+            }
+        };
+        // The call can outlive the client request: its span is a new root, linked to the request's
+        // span rather than a child keeping it open
+        let span = tracing::info_span!(
+            parent: None,
+            EXTERNAL_SPAN_NAME,
+            "external service" = "async coprocessor call",
+            "coprocessor.stage" = %stage,
+            "otel.kind" = "INTERNAL"
+        );
+        span.follows_from(tracing::Span::current());
+        if let Err(error) = endpoint.queue.send(Box::pin(call.instrument(span))) {
+            tracing::debug!(
+                "external extensibility: {stage} asynchronous coprocessor call dropped: {error}"
+            );
+            u64_counter!(
+                "apollo.router.operations.coprocessor.dropped",
+                "Number of asynchronous co-processor calls dropped because their queue was full",
+                1,
+                "coprocessor.stage" = stage
+            );
+        }
+        return Ok(unchanged);
+    }
+
+    tracing::debug!(?payload, "externalized output");
+    let mut unchanged = payload.unchanged();
+    let guard = context.enter_active_request();
+    let start = Instant::now();
+    let co_processor_result = payload.call(http_client, &endpoint.url).await;
+    let duration = start.elapsed().as_secs_f64();
+    drop(guard);
+    tracing::info!(
+        histogram.apollo.router.operations.coprocessor.duration = duration,
+        coprocessor.stage = %stage,
+    );
+    tracing::debug!(?co_processor_result, "co-processor returned");
+
+    let error = match co_processor_result {
+        Ok(co_processor_output) => return Ok(co_processor_output),
+        Err(error) => error,
+    };
+    u64_counter!(
+        "apollo.router.operations.coprocessor.failures",
+        "Number of failed calls to co-processors",
+        1,
+        "coprocessor.stage" = stage.clone(),
+        "coprocessor.failure_policy" = failure_policy.name()
+    );
+
+    match failure_policy {
+        FailurePolicy::FailClosed => Err(error),
+        FailurePolicy::FailOpen => {
+            tracing::warn!(
+                "external extensibility: {stage} coprocessor call failed, continuing without it: {error}"
+            );
+            Ok(unchanged)
+        }
+        FailurePolicy::StaticResponse(response) => {
+            tracing::warn!(
+                "external extensibility: {stage} coprocessor call failed, sending the static response: {error}"
+            );
+            let body = graphql::Response::builder()
+                .error(
+                    Error::builder()
+                        .message(response.message.clone())
+                        .extension_code(COPROCESSOR_UNAVAILABLE_EXTENSION)
+                        .build(),
+                )
+                .build();
+            unchanged.control = Some(Control::Break(response.status_code));
+            unchanged.body = Some(T::from_graphql_response(&body)?);
+            Ok(unchanged)
+        }
+    }
+}
+
 /// Convert a HashMap into a HeaderMap
 pub(super) fn internalize_header_map(
     input: HashMap<String, Vec<String>>,
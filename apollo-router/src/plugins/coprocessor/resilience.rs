@@ -0,0 +1,337 @@
+//! Retries and circuit breaking for coprocessor calls
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use hyper::Body;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+use tower::ServiceExt;
+
+/// Retries of failed coprocessor calls
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(super) struct RetryConf {
+    /// The number of times a failed call is retried
+    pub(super) attempts: usize,
+    /// The delay before the first retry, doubled for each following retry
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String", default = "default_backoff")]
+    pub(super) backoff: Duration,
+}
+
+impl Default for RetryConf {
+    fn default() -> Self {
+        Self {
+            attempts: 0,
+            backoff: default_backoff(),
+        }
+    }
+}
+
+fn default_backoff() -> Duration {
+    Duration::from_millis(50)
+}
+
+/// Stops calling a coprocessor that keeps failing
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(super) struct CircuitBreakerConf {
+    /// Enable the circuit breaker
+    pub(super) enabled: bool,
+    /// The number of consecutive failed calls that opens the circuit
+    pub(super) failure_threshold: NonZeroUsize,
+    /// How long calls fail without reaching the coprocessor once the circuit is open. A single
+    /// call is then let through: the circuit closes if it succeeds and opens again if it fails
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String", default = "default_open_duration")]
+    pub(super) open_duration: Duration,
+}
+
+impl Default for CircuitBreakerConf {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            failure_threshold: NonZeroUsize::new(5).expect("5 is not zero"),
+            open_duration: default_open_duration(),
+        }
+    }
+}
+
+fn default_open_duration() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// Adds retries and a circuit breaker to a coprocessor HTTP client
+///
+/// A call fails when the client returns an error, including timeouts, or when the coprocessor
+/// answers with a server error status.
+#[derive(Clone, Debug)]
+pub(super) struct ResilienceLayer {
+    retry: RetryConf,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+}
+
+impl ResilienceLayer {
+    pub(super) fn new(retry: &RetryConf, circuit_breaker: &CircuitBreakerConf) -> Self {
+        Self {
+            retry: retry.clone(),
+            circuit_breaker: circuit_breaker
+                .enabled
+                .then(|| Arc::new(CircuitBreaker::new(circuit_breaker))),
+        }
+    }
+}
+
+impl<S> Layer<S> for ResilienceLayer {
+    type Service = Resilient<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Resilient {
+            inner,
+            retry: self.retry.clone(),
+            circuit_breaker: self.circuit_breaker.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Resilient<S> {
+    inner: S,
+    retry: RetryConf,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+}
+
+impl<S> Service<hyper::Request<Body>> for Resilient<S>
+where
+    S: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = hyper::Response<Body>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: hyper::Request<Body>) -> Self::Future {
+        // The inner service was driven to readiness, keep it and leave a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        if self.retry.attempts == 0 && self.circuit_breaker.is_none() {
+            return Box::pin(inner.call(request));
+        }
+
+        let retry = self.retry.clone();
+        let circuit_breaker = self.circuit_breaker.clone();
+        Box::pin(async move {
+            // The body is sent again on retries, so it is buffered first
+            let (parts, body) = request.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+
+            let mut attempt = 0;
+            loop {
+                if let Some(circuit_breaker) = &circuit_breaker {
+                    if !circuit_breaker.try_acquire() {
+                        return Err("the coprocessor circuit breaker is open".into());
+                    }
+                }
+
+                let mut request = hyper::Request::new(Body::from(body.clone()));
+                *request.method_mut() = parts.method.clone();
+                *request.uri_mut() = parts.uri.clone();
+                *request.version_mut() = parts.version;
+                *request.headers_mut() = parts.headers.clone();
+
+                let result = inner.ready().await?.call(request).await;
+                let failed = match &result {
+                    Ok(response) => response.status().is_server_error(),
+                    Err(_) => true,
+                };
+                if let Some(circuit_breaker) = &circuit_breaker {
+                    circuit_breaker.record(!failed);
+                }
+                if !failed || attempt >= retry.attempts {
+                    return result;
+                }
+
+                tokio::time::sleep(retry.backoff.saturating_mul(1 << attempt.min(16))).await;
+                attempt += 1;
+            }
+        })
+    }
+}
+
+/// Shared by all the clients of a coprocessor
+#[derive(Debug)]
+struct CircuitBreaker {
+    failure_threshold: usize,
+    open_duration: Duration,
+    state: Mutex<CircuitBreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct CircuitBreakerState {
+    consecutive_failures: usize,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn new(configuration: &CircuitBreakerConf) -> Self {
+        Self {
+            failure_threshold: configuration.failure_threshold.get(),
+            open_duration: configuration.open_duration,
+            state: Default::default(),
+        }
+    }
+
+    /// Whether a call can be made
+    fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock();
+        match state.opened_at {
+            None => true,
+            Some(opened_at) if opened_at.elapsed() >= self.open_duration => {
+                // Let a single call through. Restarting the period means another call is let
+                // through later even if this one never completes.
+                state.opened_at = Some(Instant::now());
+                true
+            }
+            Some(_) => false,
+        }
+    }
+
+    fn record(&self, succeeded: bool) {
+        let mut state = self.state.lock();
+        if succeeded {
+            if state.opened_at.is_some() {
+                tracing::info!("coprocessor circuit breaker closed");
+            }
+            *state = Default::default();
+            return;
+        }
+
+        state.consecutive_failures += 1;
+        if state.opened_at.is_some() || state.consecutive_failures >= self.failure_threshold {
+            if state.opened_at.is_none() {
+                tracing::warn!(
+                    "coprocessor circuit breaker opened after {} consecutive failures",
+                    state.consecutive_failures
+                );
+            }
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use http::StatusCode;
+    use tower::util::BoxCloneService;
+
+    use super::*;
+
+    fn failing_service(
+        calls: Arc<AtomicUsize>,
+        failures: usize,
+    ) -> BoxCloneService<hyper::Request<Body>, hyper::Response<Body>, BoxError> {
+        BoxCloneService::new(tower::service_fn(move |request: hyper::Request<Body>| {
+            let calls = calls.clone();
+            async move {
+                let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                assert_eq!(body, "payload");
+                if calls.fetch_add(1, Ordering::SeqCst) < failures {
+                    return Err("unavailable".into());
+                }
+                Ok::<_, BoxError>(hyper::Response::new(Body::empty()))
+            }
+        }))
+    }
+
+    fn request() -> hyper::Request<Body> {
+        hyper::Request::post("http://coprocessor")
+            .body(Body::from("payload"))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_retries_failed_calls() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let retry = RetryConf {
+            attempts: 2,
+            backoff: Duration::from_millis(1),
+        };
+        let service = ResilienceLayer::new(&retry, &Default::default())
+            .layer(failing_service(calls.clone(), 2));
+
+        let response = service.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn it_gives_up_after_the_last_attempt() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let retry = RetryConf {
+            attempts: 1,
+            backoff: Duration::from_millis(1),
+        };
+        let service = ResilienceLayer::new(&retry, &Default::default())
+            .layer(failing_service(calls.clone(), 5));
+
+        assert!(service.clone().oneshot(request()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_opens_the_circuit_after_consecutive_failures() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let circuit_breaker = CircuitBreakerConf {
+            enabled: true,
+            failure_threshold: NonZeroUsize::new(2).unwrap(),
+            open_duration: Duration::from_millis(50),
+        };
+        let service = ResilienceLayer::new(&Default::default(), &circuit_breaker)
+            .layer(failing_service(calls.clone(), 3));
+
+        for _ in 0..2 {
+            assert!(service.clone().oneshot(request()).await.is_err());
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // The circuit is open: the coprocessor is not called
+        let error = service.clone().oneshot(request()).await.unwrap_err();
+        assert_eq!(error.to_string(), "the coprocessor circuit breaker is open");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // After the open duration, a failing call opens the circuit again
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(service.clone().oneshot(request()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(service.clone().oneshot(request()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // And a successful one closes it
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(service.clone().oneshot(request()).await.is_ok());
+        assert!(service.clone().oneshot(request()).await.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+    }
+}
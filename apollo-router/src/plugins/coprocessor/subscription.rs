@@ -0,0 +1,362 @@
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower_service::Service;
+
+use super::*;
+use crate::graphql;
+use crate::plugins::subscription::map_subscription_events;
+use crate::plugins::subscription::SubscriptionEventAction;
+use crate::services::supergraph;
+use crate::Context;
+
+/// What information is passed to a subscription event stage
+#[derive(Clone, Debug, Default, Deserialize, Derivative, Serialize, JsonSchema)]
+#[derivative(PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct SubscriptionEventConf {
+    /// Send the context
+    pub(super) context: bool,
+    /// Send the body
+    pub(super) body: bool,
+    /// Send the SDL
+    pub(super) sdl: bool,
+    /// What happens when the coprocessor call fails
+    #[derivative(PartialEq = "ignore")]
+    pub(super) failure_policy: FailurePolicy,
+    /// Whether the stage waits for the coprocessor (`sync`) or calls it in the background
+    /// (`async`)
+    #[derivative(PartialEq = "ignore")]
+    pub(super) mode: CallMode,
+}
+
+impl SubscriptionEventConf {
+    pub(crate) fn as_service<C>(
+        &self,
+        http_client: C,
+        service: supergraph::BoxService,
+        endpoint: Endpoint,
+        sdl: Arc<String>,
+    ) -> supergraph::BoxService
+    where
+        C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        <C as tower::Service<http::Request<Body>>>::Future: Send + 'static,
+    {
+        if *self == Default::default() {
+            return service;
+        }
+
+        let event_config = self.clone();
+        service
+            .map_response(move |response: supergraph::Response| {
+                let event_config = event_config.clone();
+                let endpoint = endpoint.clone();
+                let http_client = http_client.clone();
+                let sdl = sdl.clone();
+
+                map_subscription_events(response, move |context, event| {
+                    let event_config = event_config.clone();
+                    let endpoint = endpoint.clone();
+                    let http_client = http_client.clone();
+                    let sdl = sdl.clone();
+
+                    async move {
+                        let mut succeeded = true;
+                        let action = process_subscription_event_stage(
+                            http_client,
+                            endpoint,
+                            sdl,
+                            context,
+                            event,
+                            event_config,
+                        )
+                        .await
+                        .unwrap_or_else(|error| {
+                            succeeded = false;
+                            tracing::error!(
+                                "external extensibility: subscription event stage error: {error}"
+                            );
+                            SubscriptionEventAction::Continue(
+                                graphql::Response::builder()
+                                    .error(
+                                        Error::builder()
+                                            .message("Internal error handling subscription event")
+                                            .extension_code("INTERNAL_ERROR")
+                                            .build(),
+                                    )
+                                    .build(),
+                            )
+                        });
+                        u64_counter!(
+                            "apollo.router.operations.coprocessor",
+                            "Total operations with co-processors enabled",
+                            1,
+                            "coprocessor.stage" = PipelineStep::SubscriptionEvent,
+                            "coprocessor.succeeded" = succeeded
+                        );
+                        action
+                    }
+                })
+            })
+            .boxed()
+    }
+}
+
+async fn process_subscription_event_stage<C>(
+    http_client: C,
+    endpoint: Endpoint,
+    sdl: Arc<String>,
+    context: Context,
+    event: graphql::Response,
+    event_config: SubscriptionEventConf,
+) -> Result<SubscriptionEventAction, BoxError>
+where
+    C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    <C as tower::Service<http::Request<Body>>>::Future: Send + 'static,
+{
+    // First, encode the event with the data the coprocessor asked for.
+    // Headers and status code are not sent, they were already sent with the first response.
+    let body_to_send = event_config
+        .body
+        .then(|| serde_json::to_value(&event).expect("serialization will not fail"));
+    let context_to_send = event_config.context.then(|| context.clone());
+    let sdl_to_send = event_config.sdl.then(|| sdl.to_string());
+
+    let payload = Externalizable::supergraph_builder()
+        .stage(PipelineStep::SubscriptionEvent)
+        .id(context.id.clone())
+        .and_body(body_to_send)
+        .and_context(context_to_send)
+        .and_sdl(sdl_to_send)
+        .build();
+
+    // Second, call our co-processor and get a reply.
+    let co_processor_output = call_coprocessor(
+        http_client,
+        &endpoint,
+        PipelineStep::SubscriptionEvent,
+        payload,
+        &context,
+        &event_config.failure_policy,
+        event_config.mode,
+    )
+    .await?;
+
+    validate_coprocessor_output(&co_processor_output, PipelineStep::SubscriptionEvent)?;
+
+    // Third, process our reply and act on the contents. The context is updated whatever the
+    // decision is, then the event is sent, dropped or the subscription is closed.
+    if let Some(new_context) = co_processor_output.context {
+        for (key, value) in new_context.try_into_iter()? {
+            context.upsert_json_value(key, move |_current| value);
+        }
+    }
+
+    let new_event = co_processor_output
+        .body
+        .map(serde_json::from_value::<graphql::Response>)
+        .transpose()?;
+
+    Ok(match co_processor_output.control.unwrap_or_default() {
+        Control::Continue => SubscriptionEventAction::Continue(new_event.unwrap_or(event)),
+        Control::Drop => SubscriptionEventAction::Drop,
+        // When breaking, only a body coming from the coprocessor is sent before closing
+        Control::Break(_) => SubscriptionEventAction::End(new_event),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::future::BoxFuture;
+    use futures::StreamExt;
+    use hyper::Body;
+    use serde_json::json;
+    use tower::BoxError;
+    use tower::ServiceExt;
+
+    use super::super::*;
+    use super::*;
+    use crate::context::OPERATION_KIND;
+    use crate::plugin::test::MockHttpClientService;
+    use crate::plugin::test::MockSupergraphService;
+    use crate::query_planner::OperationKind;
+    use crate::services::supergraph;
+
+    #[allow(clippy::type_complexity)]
+    fn mock_with_callback(
+        callback: fn(
+            hyper::Request<Body>,
+        ) -> BoxFuture<'static, Result<hyper::Response<Body>, BoxError>>,
+    ) -> MockHttpClientService {
+        let mut mock_http_client = MockHttpClientService::new();
+        mock_http_client.expect_clone().returning(move || {
+            let mut mock_http_client = MockHttpClientService::new();
+            mock_http_client.expect_clone().returning(move || {
+                let mut mock_http_client = MockHttpClientService::new();
+                mock_http_client.expect_call().returning(callback);
+                mock_http_client
+            });
+            mock_http_client
+        });
+
+        mock_http_client
+    }
+
+    fn mock_subscription_service(operation_kind: OperationKind) -> MockSupergraphService {
+        let mut mock_supergraph_service = MockSupergraphService::new();
+        mock_supergraph_service
+            .expect_call()
+            .returning(move |req: supergraph::Request| {
+                req.context.insert(OPERATION_KIND, operation_kind).unwrap();
+                Ok(supergraph::Response::fake_stream_builder()
+                    .response(graphql::Response::builder().subscribed(true).build())
+                    .response(
+                        graphql::Response::builder()
+                            .data(json!({ "event": 1, "secret": "s1" }))
+                            .build(),
+                    )
+                    .response(
+                        graphql::Response::builder()
+                            .data(json!({ "event": 2, "secret": "s2" }))
+                            .build(),
+                    )
+                    .response(
+                        graphql::Response::builder()
+                            .data(json!({ "event": 3, "secret": "s3" }))
+                            .build(),
+                    )
+                    .response(
+                        graphql::Response::builder()
+                            .data(json!({ "event": 4, "secret": "s4" }))
+                            .build(),
+                    )
+                    .context(req.context)
+                    .build()
+                    .unwrap())
+            });
+
+        mock_supergraph_service
+    }
+
+    #[tokio::test]
+    async fn external_plugin_subscription_event() {
+        let event_conf = SubscriptionEventConf {
+            context: true,
+            body: true,
+            sdl: false,
+            failure_policy: Default::default(),
+            mode: Default::default(),
+        };
+
+        let mock_http_client = mock_with_callback(move |req: hyper::Request<Body>| {
+            Box::pin(async {
+                let deserialized_request: Externalizable<serde_json::Value> =
+                    serde_json::from_slice(&hyper::body::to_bytes(req.into_body()).await.unwrap())
+                        .unwrap();
+
+                assert_eq!(EXTERNALIZABLE_VERSION, deserialized_request.version);
+                assert_eq!(
+                    PipelineStep::SubscriptionEvent.to_string(),
+                    deserialized_request.stage
+                );
+                assert!(deserialized_request.sdl.is_none());
+
+                let body = deserialized_request.body.unwrap();
+                let event = body["data"]["event"].as_u64().unwrap();
+                // Redact the first event, drop the second one and close the subscription on the
+                // third one
+                let output = match event {
+                    1 => json!({
+                        "version": 1,
+                        "stage": "SubscriptionEvent",
+                        "control": "continue",
+                        "body": { "data": { "event": 1, "secret": null } },
+                        "context": { "entries": { "seen-events": 1 } }
+                    }),
+                    2 => json!({
+                        "version": 1,
+                        "stage": "SubscriptionEvent",
+                        "control": "drop"
+                    }),
+                    _ => json!({
+                        "version": 1,
+                        "stage": "SubscriptionEvent",
+                        "control": { "break": 403 },
+                        "body": { "errors": [{ "message": "not allowed anymore" }] }
+                    }),
+                };
+                Ok(hyper::Response::builder()
+                    .body(Body::from(serde_json::to_string(&output).unwrap()))
+                    .unwrap())
+            })
+        });
+
+        let service = event_conf.as_service(
+            mock_http_client,
+            mock_subscription_service(OperationKind::Subscription).boxed(),
+            Endpoint::fake("http://test"),
+            Arc::new("".to_string()),
+        );
+
+        let request = supergraph::Request::canned_builder().build().unwrap();
+        let mut res = service.oneshot(request).await.unwrap();
+
+        let initial = res.response.body_mut().next().await.unwrap();
+        assert_eq!(initial.subscribed, Some(true));
+
+        let event = res.response.body_mut().next().await.unwrap();
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({ "data": { "event": 1, "secret": null } }),
+        );
+        assert_eq!(res.context.get::<_, u8>("seen-events").unwrap(), Some(1));
+
+        let last = res.response.body_mut().next().await.unwrap();
+        assert_eq!(last.errors[0].message, "not allowed anymore");
+        assert_eq!(last.subscribed, Some(false));
+
+        assert!(res.response.body_mut().next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn external_plugin_subscription_event_ignores_queries() {
+        let event_conf = SubscriptionEventConf {
+            context: false,
+            body: true,
+            sdl: false,
+            failure_policy: Default::default(),
+            mode: Default::default(),
+        };
+
+        // The coprocessor must not be called
+        let mut mock_http_client = MockHttpClientService::new();
+        mock_http_client
+            .expect_clone()
+            .returning(MockHttpClientService::new);
+
+        let service = event_conf.as_service(
+            mock_http_client,
+            mock_subscription_service(OperationKind::Query).boxed(),
+            Endpoint::fake("http://test"),
+            Arc::new("".to_string()),
+        );
+
+        let request = supergraph::Request::canned_builder().build().unwrap();
+        let res = service.oneshot(request).await.unwrap();
+
+        assert_eq!(res.response.into_body().count().await, 5);
+    }
+}
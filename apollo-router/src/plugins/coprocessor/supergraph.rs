@@ -16,11 +16,13 @@ use crate::graphql;
 use crate::layers::async_checkpoint::OneShotAsyncCheckpointLayer;
 use crate::layers::ServiceBuilderExt;
 use crate::plugins::coprocessor::EXTERNAL_SPAN_NAME;
+use crate::plugins::telemetry::config_new::selectors::SupergraphSelector;
 use crate::response;
 use crate::services::supergraph;
 
 /// What information is passed to a router request/response stage
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[derive(Clone, Debug, Default, Deserialize, Derivative, Serialize, JsonSchema)]
+#[derivative(PartialEq)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct SupergraphRequestConf {
     /// Send the headers
@@ -33,10 +35,23 @@ pub(super) struct SupergraphRequestConf {
     pub(super) sdl: bool,
     /// Send the method
     pub(super) method: bool,
+    /// Only call the coprocessor when this condition matches
+    #[serde(skip_serializing)]
+    #[derivative(PartialEq = "ignore")]
+    #[schemars(schema_with = "schema_ref::<Condition<SupergraphSelector>>")]
+    pub(super) condition: Condition<SupergraphSelector>,
+    /// What happens when the coprocessor call fails
+    #[derivative(PartialEq = "ignore")]
+    pub(super) failure_policy: FailurePolicy,
+    /// Whether the stage waits for the coprocessor (`sync`) or calls it in the background
+    /// (`async`)
+    #[derivative(PartialEq = "ignore")]
+    pub(super) mode: CallMode,
 }
 
 /// What information is passed to a router request/response stage
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[derive(Clone, Debug, Default, Deserialize, Derivative, Serialize, JsonSchema)]
+#[derivative(PartialEq)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct SupergraphResponseConf {
     /// Send the headers
@@ -49,6 +64,18 @@ pub(super) struct SupergraphResponseConf {
     pub(super) sdl: bool,
     /// Send the HTTP status
     pub(super) status_code: bool,
+    /// Only call the coprocessor when this condition matches
+    #[serde(skip_serializing)]
+    #[derivative(PartialEq = "ignore")]
+    #[schemars(schema_with = "schema_ref::<Condition<SupergraphSelector>>")]
+    pub(super) condition: Condition<SupergraphSelector>,
+    /// What happens when the coprocessor call fails
+    #[derivative(PartialEq = "ignore")]
+    pub(super) failure_policy: FailurePolicy,
+    /// Whether the stage waits for the coprocessor (`sync`) or calls it in the background
+    /// (`async`)
+    #[derivative(PartialEq = "ignore")]
+    pub(super) mode: CallMode,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
@@ -65,7 +92,7 @@ impl SupergraphStage {
         &self,
         http_client: C,
         service: supergraph::BoxService,
-        coprocessor_url: String,
+        endpoint: Endpoint,
         sdl: Arc<String>,
     ) -> supergraph::BoxService
     where
@@ -78,21 +105,25 @@ impl SupergraphStage {
     {
         let request_layer = (self.request != Default::default()).then_some({
             let request_config = self.request.clone();
-            let coprocessor_url = coprocessor_url.clone();
+            let endpoint = endpoint.clone();
             let http_client = http_client.clone();
             let sdl = sdl.clone();
 
             OneShotAsyncCheckpointLayer::new(move |request: supergraph::Request| {
-                let request_config = request_config.clone();
-                let coprocessor_url = coprocessor_url.clone();
+                let mut request_config = request_config.clone();
+                let endpoint = endpoint.clone();
                 let http_client = http_client.clone();
                 let sdl = sdl.clone();
 
                 async move {
+                    if request_config.condition.evaluate_request(&request) != Some(true) {
+                        return Ok(ControlFlow::Continue(request));
+                    }
+
                     let mut succeeded = true;
                     let result = process_supergraph_request_stage(
                         http_client,
-                        coprocessor_url,
+                        endpoint,
                         sdl,
                         request,
                         request_config,
@@ -120,41 +151,54 @@ impl SupergraphStage {
         let response_layer = (self.response != Default::default()).then_some({
             let response_config = self.response.clone();
 
-            MapFutureLayer::new(move |fut| {
-                let coprocessor_url = coprocessor_url.clone();
-                let sdl: Arc<String> = sdl.clone();
-                let http_client = http_client.clone();
-                let response_config = response_config.clone();
-
-                async move {
-                    let response: supergraph::Response = fut.await?;
-
-                    let mut succeeded = true;
-                    let result = process_supergraph_response_stage(
-                        http_client,
-                        coprocessor_url,
-                        sdl,
-                        response,
-                        response_config,
-                    )
-                    .await
-                    .map_err(|error| {
-                        succeeded = false;
-                        tracing::error!(
-                            "external extensibility: supergraph response stage error: {error}"
+            let condition = self.response.condition.clone();
+            MapFutureWithRequestDataLayer::new(
+                move |request: &supergraph::Request| {
+                    let mut condition = condition.clone();
+                    (condition.evaluate_request(request) != Some(false)).then_some(condition)
+                },
+                move |condition: Option<Condition<SupergraphSelector>>, fut| {
+                    let endpoint = endpoint.clone();
+                    let sdl: Arc<String> = sdl.clone();
+                    let http_client = http_client.clone();
+                    let response_config = response_config.clone();
+
+                    async move {
+                        let response: supergraph::Response = fut.await?;
+
+                        if !condition
+                            .is_some_and(|condition| condition.evaluate_response(&response))
+                        {
+                            return Ok(response);
+                        }
+
+                        let mut succeeded = true;
+                        let result = process_supergraph_response_stage(
+                            http_client,
+                            endpoint,
+                            sdl,
+                            response,
+                            response_config,
+                        )
+                        .await
+                        .map_err(|error| {
+                            succeeded = false;
+                            tracing::error!(
+                                "external extensibility: supergraph response stage error: {error}"
+                            );
+                            error
+                        });
+                        u64_counter!(
+                            "apollo.router.operations.coprocessor",
+                            "Total operations with co-processors enabled",
+                            1,
+                            "coprocessor.stage" = PipelineStep::SupergraphResponse,
+                            "coprocessor.succeeded" = succeeded
                         );
-                        error
-                    });
-                    u64_counter!(
-                        "apollo.router.operations.coprocessor",
-                        "Total operations with co-processors enabled",
-                        1,
-                        "coprocessor.stage" = PipelineStep::SupergraphResponse,
-                        "coprocessor.succeeded" = succeeded
-                    );
-                    result
-                }
-            })
+                        result
+                    }
+                },
+            )
         });
 
         fn external_service_span() -> impl Fn(&supergraph::Request) -> tracing::Span + Clone {
@@ -178,7 +222,7 @@ impl SupergraphStage {
 
 async fn process_supergraph_request_stage<C>(
     http_client: C,
-    coprocessor_url: String,
+    endpoint: Endpoint,
     sdl: Arc<String>,
     mut request: supergraph::Request,
     request_config: SupergraphRequestConf,
@@ -221,19 +265,16 @@ where
         .and_sdl(sdl_to_send)
         .build();
 
-    tracing::debug!(?payload, "externalized output");
-    let guard = request.context.enter_active_request();
-    let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
-    let duration = start.elapsed().as_secs_f64();
-    drop(guard);
-    tracing::info!(
-        histogram.apollo.router.operations.coprocessor.duration = duration,
-        coprocessor.stage = %PipelineStep::SupergraphRequest,
-    );
-
-    tracing::debug!(?co_processor_result, "co-processor returned");
-    let co_processor_output = co_processor_result?;
+    let co_processor_output = call_coprocessor(
+        http_client,
+        &endpoint,
+        PipelineStep::SupergraphRequest,
+        payload,
+        &request.context,
+        &request_config.failure_policy,
+        request_config.mode,
+    )
+    .await?;
     validate_coprocessor_output(&co_processor_output, PipelineStep::SupergraphRequest)?;
     // unwrap is safe here because validate_coprocessor_output made sure control is available
     let control = co_processor_output.control.expect("validated above; qed");
@@ -316,7 +357,7 @@ where
 
 async fn process_supergraph_response_stage<C>(
     http_client: C,
-    coprocessor_url: String,
+    endpoint: Endpoint,
     sdl: Arc<String>,
     response: supergraph::Response,
     response_config: SupergraphResponseConf,
@@ -367,19 +408,16 @@ where
         .build();
 
     // Second, call our co-processor and get a reply.
-    tracing::debug!(?payload, "externalized output");
-    let guard = response.context.enter_active_request();
-    let start = Instant::now();
-    let co_processor_result = payload.call(http_client.clone(), &coprocessor_url).await;
-    let duration = start.elapsed().as_secs_f64();
-    drop(guard);
-    tracing::info!(
-        histogram.apollo.router.operations.coprocessor.duration = duration,
-        coprocessor.stage = %PipelineStep::SupergraphResponse,
-    );
-
-    tracing::debug!(?co_processor_result, "co-processor returned");
-    let co_processor_output = co_processor_result?;
+    let co_processor_output = call_coprocessor(
+        http_client.clone(),
+        &endpoint,
+        PipelineStep::SupergraphResponse,
+        payload,
+        &response.context,
+        &response_config.failure_policy,
+        response_config.mode,
+    )
+    .await?;
 
     validate_coprocessor_output(&co_processor_output, PipelineStep::SupergraphResponse)?;
 
@@ -416,10 +454,11 @@ where
     let mapped_stream = rest
         .then(move |deferred_response| {
             let generator_client = http_client.clone();
-            let generator_coprocessor_url = coprocessor_url.clone();
+            let generator_endpoint = endpoint.clone();
             let generator_map_context = map_context.clone();
             let generator_sdl_to_send = sdl_to_send.clone();
             let generator_id = map_context.id.clone();
+            let generator_failure_policy = response_config.failure_policy.clone();
 
             async move {
                 let body_to_send = response_config.body.then(|| {
@@ -442,14 +481,16 @@ where
                     .build();
 
                 // Second, call our co-processor and get a reply.
-                tracing::debug!(?payload, "externalized output");
-                let guard = generator_map_context.enter_active_request();
-                let co_processor_result = payload
-                    .call(generator_client, &generator_coprocessor_url)
-                    .await;
-                drop(guard);
-                tracing::debug!(?co_processor_result, "co-processor returned");
-                let co_processor_output = co_processor_result?;
+                let co_processor_output = call_coprocessor(
+                    generator_client,
+                    &generator_endpoint,
+                    PipelineStep::SupergraphResponse,
+                    payload,
+                    &generator_map_context,
+                    &generator_failure_policy,
+                    response_config.mode,
+                )
+                .await?;
 
                 validate_coprocessor_output(
                     &co_processor_output,
@@ -573,6 +614,9 @@ mod tests {
                 body: true,
                 sdl: false,
                 method: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -675,7 +719,7 @@ mod tests {
         let service = supergraph_stage.as_service(
             mock_http_client,
             mock_supergraph_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -706,6 +750,9 @@ mod tests {
                 body: true,
                 sdl: false,
                 method: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -743,7 +790,7 @@ mod tests {
         let service = supergraph_stage.as_service(
             mock_http_client,
             mock_supergraph_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -777,6 +824,9 @@ mod tests {
                 body: true,
                 sdl: true,
                 status_code: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             request: Default::default(),
         };
@@ -868,7 +918,7 @@ mod tests {
         let service = supergraph_stage.as_service(
             mock_http_client,
             mock_supergraph_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -908,6 +958,9 @@ mod tests {
                 body: true,
                 sdl: true,
                 status_code: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             request: Default::default(),
         };
@@ -979,7 +1032,7 @@ mod tests {
         let service = supergraph_stage.as_service(
             mock_http_client,
             mock_supergraph_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
     use std::sync::Arc;
 
     use futures::future::BoxFuture;
@@ -18,6 +20,7 @@ mod tests {
     use tower::ServiceExt;
 
     use super::super::*;
+    use crate::metrics::FutureMetricsExt;
     use crate::plugin::test::MockHttpClientService;
     use crate::plugin::test::MockRouterService;
     use crate::plugin::test::MockSubgraphService;
@@ -99,6 +102,9 @@ mod tests {
                 sdl: true,
                 path: false,
                 method: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -132,7 +138,7 @@ mod tests {
         let service = router_stage.as_service(
             mock_http_client,
             mock_router_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -158,6 +164,9 @@ mod tests {
                 sdl: true,
                 path: false,
                 method: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -191,7 +200,7 @@ mod tests {
         let service = router_stage.as_service(
             mock_http_client,
             mock_router_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -217,6 +226,9 @@ mod tests {
                 sdl: true,
                 path: false,
                 method: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -249,7 +261,7 @@ mod tests {
         let service = router_stage.as_service(
             mock_http_client,
             mock_router_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -275,6 +287,9 @@ mod tests {
                 uri: false,
                 method: false,
                 service_name: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -307,7 +322,7 @@ mod tests {
         let service = subgraph_stage.as_service(
             mock_http_client,
             mock_subgraph_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             "my_subgraph_service_name".to_string(),
         );
 
@@ -337,6 +352,9 @@ mod tests {
                 uri: false,
                 method: false,
                 service_name: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -437,7 +455,7 @@ mod tests {
         let service = subgraph_stage.as_service(
             mock_http_client,
             mock_subgraph_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             "my_subgraph_service_name".to_string(),
         );
 
@@ -456,6 +474,290 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn external_plugin_subgraph_condition_skips_the_coprocessor() {
+        let subgraph_stage: SubgraphStage = serde_json::from_value(json!({
+            "request": {
+                "body": true,
+                "condition": {
+                    "eq": [{ "subgraph_name": true }, "accounts"]
+                }
+            },
+            "response": {
+                "body": true,
+                "condition": {
+                    "eq": [{ "subgraph_name": true }, "accounts"]
+                }
+            }
+        }))
+        .unwrap();
+
+        let mut mock_subgraph_service = MockSubgraphService::new();
+
+        mock_subgraph_service
+            .expect_call()
+            .returning(|req: subgraph::Request| {
+                Ok(subgraph::Response::builder()
+                    .data(json!({ "test": 1234_u32 }))
+                    .errors(Vec::new())
+                    .extensions(crate::json_ext::Object::new())
+                    .context(req.context)
+                    .build())
+            });
+
+        let mock_http_client = mock_with_callback(move |_: hyper::Request<Body>| {
+            unreachable!("the condition does not match, the coprocessor must not be called")
+        });
+
+        let service = subgraph_stage.as_service(
+            mock_http_client,
+            mock_subgraph_service.boxed(),
+            Endpoint::fake("http://test"),
+            "products".to_string(),
+        );
+
+        let request = subgraph::Request::fake_builder()
+            .subgraph_name("products".to_string())
+            .build();
+
+        assert_eq!(
+            serde_json_bytes::json!({ "test": 1234_u32 }),
+            service
+                .oneshot(request)
+                .await
+                .unwrap()
+                .response
+                .into_body()
+                .data
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn external_plugin_subgraph_fail_open_continues_without_the_coprocessor() {
+        async {
+            let subgraph_stage: SubgraphStage = serde_json::from_value(json!({
+                "request": {
+                    "body": true,
+                    "failure_policy": "fail_open"
+                }
+            }))
+            .unwrap();
+
+            let mut mock_subgraph_service = MockSubgraphService::new();
+
+            mock_subgraph_service
+                .expect_call()
+                .returning(|req: subgraph::Request| {
+                    // The request was not modified
+                    assert_eq!(
+                        "query { me { name } }",
+                        req.subgraph_request.into_body().query.unwrap()
+                    );
+
+                    Ok(subgraph::Response::builder()
+                        .data(json!({ "test": 1234_u32 }))
+                        .errors(Vec::new())
+                        .extensions(crate::json_ext::Object::new())
+                        .context(req.context)
+                        .build())
+                });
+
+            let mock_http_client = mock_with_callback(move |_: hyper::Request<Body>| {
+                Box::pin(async { Err("connection refused".into()) })
+            });
+
+            let service = subgraph_stage.as_service(
+                mock_http_client,
+                mock_subgraph_service.boxed(),
+                Endpoint::fake("http://test"),
+                "products".to_string(),
+            );
+
+            let request = subgraph::Request::fake_builder()
+                .subgraph_request(
+                    http::Request::builder()
+                        .body(
+                            crate::graphql::Request::builder()
+                                .query("query { me { name } }")
+                                .build(),
+                        )
+                        .unwrap(),
+                )
+                .build();
+
+            assert_eq!(
+                serde_json_bytes::json!({ "test": 1234_u32 }),
+                service
+                    .oneshot(request)
+                    .await
+                    .unwrap()
+                    .response
+                    .into_body()
+                    .data
+                    .unwrap()
+            );
+
+            assert_counter!(
+                "apollo.router.operations.coprocessor.failures",
+                1,
+                "coprocessor.stage" = "SubgraphRequest",
+                "coprocessor.failure_policy" = "fail_open"
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn external_plugin_router_static_response_when_the_coprocessor_fails() {
+        let router_stage: RouterStage = serde_json::from_value(json!({
+            "request": {
+                "headers": true,
+                "failure_policy": {
+                    "static_response": {
+                        "status_code": 503,
+                        "message": "the coprocessor is unavailable"
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        // This will never be called because the static response is sent instead
+        let mock_router_service = MockRouterService::new();
+
+        let mock_http_client = mock_with_callback(move |_: hyper::Request<Body>| {
+            Box::pin(async { Err("request timed out".into()) })
+        });
+
+        let service = router_stage.as_service(
+            mock_http_client,
+            mock_router_service.boxed(),
+            Endpoint::fake("http://test"),
+            Arc::new("".to_string()),
+        );
+
+        let request = supergraph::Request::canned_builder().build().unwrap();
+
+        let response = service
+            .oneshot(request.try_into().unwrap())
+            .await
+            .unwrap()
+            .response;
+
+        assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+        let actual_response = serde_json::from_slice::<serde_json::Value>(
+            &hyper::body::to_bytes(response.into_body()).await.unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            json!({
+                "errors": [{
+                   "message": "the coprocessor is unavailable",
+                   "extensions": {
+                       "code": "COPROCESSOR_UNAVAILABLE"
+                   }
+                }]
+            }),
+            actual_response
+        );
+    }
+
+    #[tokio::test]
+    async fn external_plugin_subgraph_async_stage_ignores_the_reply() {
+        static COPROCESSOR_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let subgraph_stage: SubgraphStage = serde_json::from_value(json!({
+            "request": {
+                "body": true,
+                "mode": "async"
+            }
+        }))
+        .unwrap();
+
+        let mut mock_subgraph_service = MockSubgraphService::new();
+
+        mock_subgraph_service
+            .expect_call()
+            .returning(|req: subgraph::Request| {
+                // The request was not modified
+                assert_eq!(
+                    "query { me { name } }",
+                    req.subgraph_request.into_body().query.unwrap()
+                );
+
+                Ok(subgraph::Response::builder()
+                    .data(json!({ "test": 1234_u32 }))
+                    .errors(Vec::new())
+                    .extensions(crate::json_ext::Object::new())
+                    .context(req.context)
+                    .build())
+            });
+
+        let mock_http_client = mock_with_callback(move |_: hyper::Request<Body>| {
+            Box::pin(async {
+                COPROCESSOR_CALLS.fetch_add(1, Ordering::SeqCst);
+                // Breaking has no effect on an async stage
+                Ok(hyper::Response::builder()
+                    .body(Body::from(
+                        r#"{
+                                "version": 1,
+                                "stage": "SubgraphRequest",
+                                "control": {
+                                    "break": 401
+                                },
+                                "body": {
+                                    "errors": [{ "message": "ignored" }]
+                                }
+                            }"#,
+                    ))
+                    .unwrap())
+            })
+        });
+
+        let service = subgraph_stage.as_service(
+            mock_http_client,
+            mock_subgraph_service.boxed(),
+            Endpoint::fake("http://async-test"),
+            "products".to_string(),
+        );
+
+        let request = subgraph::Request::fake_builder()
+            .subgraph_request(
+                http::Request::builder()
+                    .body(
+                        crate::graphql::Request::builder()
+                            .query("query { me { name } }")
+                            .build(),
+                    )
+                    .unwrap(),
+            )
+            .build();
+
+        assert_eq!(
+            serde_json_bytes::json!({ "test": 1234_u32 }),
+            service
+                .oneshot(request)
+                .await
+                .unwrap()
+                .response
+                .into_body()
+                .data
+                .unwrap()
+        );
+
+        // The coprocessor is called in the background
+        for _ in 0..100 {
+            if COPROCESSOR_CALLS.load(Ordering::SeqCst) == 1 {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("the coprocessor was not called");
+    }
+
     #[tokio::test]
     async fn external_plugin_subgraph_request_controlflow_break() {
         let subgraph_stage = SubgraphStage {
@@ -466,6 +768,9 @@ mod tests {
                 uri: false,
                 method: false,
                 service_name: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -503,7 +808,7 @@ mod tests {
         let service = subgraph_stage.as_service(
             mock_http_client,
             mock_subgraph_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             "my_subgraph_service_name".to_string(),
         );
 
@@ -534,6 +839,9 @@ mod tests {
                 uri: false,
                 method: false,
                 service_name: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -561,7 +869,7 @@ mod tests {
         let service = subgraph_stage.as_service(
             mock_http_client,
             mock_subgraph_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             "my_subgraph_service_name".to_string(),
         );
 
@@ -597,6 +905,9 @@ mod tests {
                 body: true,
                 service_name: false,
                 status_code: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
         };
 
@@ -669,7 +980,7 @@ mod tests {
         let service = subgraph_stage.as_service(
             mock_http_client,
             mock_subgraph_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             "my_subgraph_service_name".to_string(),
         );
 
@@ -708,6 +1019,9 @@ mod tests {
                 sdl: true,
                 path: true,
                 method: true,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -807,7 +1121,7 @@ mod tests {
         let service = router_stage.as_service(
             mock_http_client,
             mock_router_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -826,6 +1140,9 @@ mod tests {
                 sdl: true,
                 path: true,
                 method: true,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -932,7 +1249,7 @@ mod tests {
         let service = router_stage.as_service(
             mock_http_client,
             mock_router_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -954,6 +1271,9 @@ mod tests {
                 sdl: true,
                 path: true,
                 method: true,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -1002,7 +1322,7 @@ mod tests {
         let service = router_stage.as_service(
             mock_http_client,
             mock_router_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -1042,6 +1362,9 @@ mod tests {
                 sdl: true,
                 path: true,
                 method: true,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             response: Default::default(),
         };
@@ -1080,7 +1403,7 @@ mod tests {
         let service = router_stage.as_service(
             mock_http_client,
             mock_router_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -1120,6 +1443,9 @@ mod tests {
                 body: true,
                 sdl: true,
                 status_code: false,
+                condition: Default::default(),
+                failure_policy: Default::default(),
+                mode: Default::default(),
             },
             request: Default::default(),
         };
@@ -1206,7 +1532,7 @@ mod tests {
         let service = router_stage.as_service(
             mock_http_client,
             mock_router_service.boxed(),
-            "http://test".to_string(),
+            Endpoint::fake("http://test"),
             Arc::new("".to_string()),
         );
 
@@ -1308,6 +1634,270 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn several_coprocessors_must_be_named() {
+        let config: Conf = serde_json::from_value(json!({
+            "coprocessors": [
+                { "name": "auth", "url": "http://127.0.0.1:8081" },
+                { "url": "http://127.0.0.1:8082" }
+            ]
+        }))
+        .unwrap();
+        assert!(config.into_coprocessors().is_err());
+
+        let config: Conf = serde_json::from_value(json!({
+            "coprocessors": [
+                { "name": "auth", "url": "http://127.0.0.1:8081" },
+                { "name": "auth", "url": "http://127.0.0.1:8082" }
+            ]
+        }))
+        .unwrap();
+        assert!(config.into_coprocessors().is_err());
+
+        let config: Conf = serde_json::from_value(json!({ "coprocessors": [] })).unwrap();
+        assert!(config.into_coprocessors().is_err());
+    }
+
+    #[test]
+    fn configuration_errors_are_specific() {
+        // The error comes from the shape that was meant, not from a failed guess
+        let error = serde_json::from_value::<Conf>(json!({
+            "url": "http://127.0.0.1:8081",
+            "routr": { "request": { "headers": true } }
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("unknown field `routr`"));
+
+        let error = serde_json::from_value::<Conf>(json!({
+            "coprocessors": [{ "name": "auth", "url": "http://127.0.0.1:8081", "timout": "1s" }]
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("unknown field `timout`"));
+
+        // The fields of a single coprocessor and the list can't be mixed
+        let error = serde_json::from_value::<Conf>(json!({
+            "url": "http://127.0.0.1:8081",
+            "coprocessors": [{ "name": "auth", "url": "http://127.0.0.1:8082" }]
+        }))
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("`url` can't be set next to `coprocessors`"));
+    }
+
+    #[test]
+    fn static_response_is_rejected_on_response_stages() {
+        let static_response = json!({
+            "static_response": { "message": "the coprocessor is unavailable" }
+        });
+
+        // The status of a response that already started can't be changed
+        let config: Conf = serde_json::from_value(json!({
+            "url": "http://127.0.0.1:8081",
+            "router": { "response": { "body": true, "failure_policy": static_response } }
+        }))
+        .unwrap();
+        assert!(config.into_coprocessors().is_err());
+
+        let config: Conf = serde_json::from_value(json!({
+            "url": "http://127.0.0.1:8081",
+            "subgraph": {
+                "all": { "response": { "body": true, "failure_policy": static_response } }
+            }
+        }))
+        .unwrap();
+        assert!(config.into_coprocessors().is_err());
+
+        // Request stages and subscription events can stop with it
+        let config: Conf = serde_json::from_value(json!({
+            "url": "http://127.0.0.1:8081",
+            "router": { "request": { "body": true, "failure_policy": static_response } },
+            "subgraph": {
+                "all": { "request": { "body": true, "failure_policy": static_response } }
+            },
+            "subscription_event": { "body": true, "failure_policy": static_response }
+        }))
+        .unwrap();
+        assert!(config.into_coprocessors().is_ok());
+    }
+
+    #[test]
+    fn response_selectors_are_rejected_in_request_conditions() {
+        let condition = json!({ "exists": { "response_header": "x-cache" } });
+
+        // A response header never exists on a request, the stage would never be called
+        let config: Conf = serde_json::from_value(json!({
+            "url": "http://127.0.0.1:8081",
+            "router": { "request": { "headers": true, "condition": condition } }
+        }))
+        .unwrap();
+        assert!(config.into_coprocessors().is_err());
+
+        let config: Conf = serde_json::from_value(json!({
+            "url": "http://127.0.0.1:8081",
+            "execution": {
+                "request": {
+                    "headers": true,
+                    "condition": { "not": { "eq": [{ "response_status": "code" }, 200] } }
+                }
+            }
+        }))
+        .unwrap();
+        assert!(config.into_coprocessors().is_err());
+
+        // Response stages evaluate them once the response is there
+        let config: Conf = serde_json::from_value(json!({
+            "url": "http://127.0.0.1:8081",
+            "router": { "response": { "headers": true, "condition": condition } },
+            "execution": {
+                "request": {
+                    "headers": true,
+                    "condition": { "eq": [{ "request_header": "x-cache" }, "miss"] }
+                }
+            }
+        }))
+        .unwrap();
+        assert!(config.into_coprocessors().is_ok());
+    }
+
+    #[test]
+    fn static_response_is_rejected_on_deferred_response_stages() {
+        let static_response = json!({
+            "static_response": { "message": "the coprocessor is unavailable" }
+        });
+
+        // Every `@defer` chunk of the response would be replaced by the static response
+        let config: Conf = serde_json::from_value(json!({
+            "coprocessors": [{
+                "name": "auth",
+                "url": "http://127.0.0.1:8081",
+                "supergraph": { "response": { "body": true, "failure_policy": static_response } }
+            }]
+        }))
+        .unwrap();
+        assert!(config.into_coprocessors().is_err());
+
+        let config: Conf = serde_json::from_value(json!({
+            "coprocessors": [{
+                "name": "auth",
+                "url": "http://127.0.0.1:8081",
+                "execution": { "response": { "body": true, "failure_policy": static_response } }
+            }]
+        }))
+        .unwrap();
+        assert!(config.into_coprocessors().is_err());
+
+        let config: Conf = serde_json::from_value(json!({
+            "coprocessors": [{
+                "name": "auth",
+                "url": "http://127.0.0.1:8081",
+                "supergraph": { "request": { "body": true, "failure_policy": static_response } },
+                "execution": { "request": { "body": true, "failure_policy": static_response } }
+            }]
+        }))
+        .unwrap();
+        assert!(config.into_coprocessors().is_ok());
+    }
+
+    #[tokio::test]
+    async fn several_coprocessors_share_the_context() {
+        let config: Conf = serde_json::from_value(json!({
+            "coprocessors": [
+                {
+                    "name": "auth",
+                    "url": "http://auth",
+                    "router": { "request": { "context": true } }
+                },
+                {
+                    "name": "audit",
+                    "url": "http://audit",
+                    "router": { "request": { "context": true } }
+                }
+            ]
+        }))
+        .unwrap();
+        let mut configurations = config.into_coprocessors().unwrap().into_iter();
+
+        // The first coprocessor adds a context entry
+        let auth = cloneable_mock_with_callback(move |_: hyper::Request<Body>| {
+            Box::pin(async {
+                let output = json!({
+                    "version": 1,
+                    "stage": "RouterRequest",
+                    "control": "continue",
+                    "context": {
+                        "entries": {
+                            "authenticated": true
+                        }
+                    }
+                });
+                Ok(hyper::Response::builder()
+                    .body(Body::from(serde_json::to_string(&output).unwrap()))
+                    .unwrap())
+            })
+        });
+
+        // The second one is called after it and sees that entry
+        let audit = cloneable_mock_with_callback(move |req: hyper::Request<Body>| {
+            Box::pin(async {
+                let deserialized_request: Externalizable<serde_json::Value> =
+                    serde_json::from_slice(&hyper::body::to_bytes(req.into_body()).await.unwrap())
+                        .unwrap();
+                let context = deserialized_request.context.unwrap();
+                assert_eq!(context.get::<_, bool>("authenticated").unwrap(), Some(true));
+                context.insert("audited", true).unwrap();
+
+                let output = json!({
+                    "version": 1,
+                    "stage": "RouterRequest",
+                    "control": "continue",
+                    "context": context
+                });
+                Ok(hyper::Response::builder()
+                    .body(Body::from(serde_json::to_string(&output).unwrap()))
+                    .unwrap())
+            })
+        });
+
+        let plugin = CoprocessorPlugin::new(
+            vec![
+                Coprocessor {
+                    http_client: auth,
+                    queue: background::Queue::new(&Default::default()),
+                    configuration: configurations.next().unwrap(),
+                },
+                Coprocessor {
+                    http_client: audit,
+                    queue: background::Queue::new(&Default::default()),
+                    configuration: configurations.next().unwrap(),
+                },
+            ],
+            Arc::new("".to_string()),
+        )
+        .unwrap();
+
+        let mock_router_service = router::service::from_supergraph_mock_callback(move |req| {
+            assert_eq!(
+                req.context.get::<_, bool>("authenticated").unwrap(),
+                Some(true)
+            );
+            assert_eq!(req.context.get::<_, bool>("audited").unwrap(), Some(true));
+
+            Ok(supergraph::Response::builder()
+                .data(json!({ "test": 1234_u32 }))
+                .context(req.context)
+                .build()
+                .unwrap())
+        })
+        .await;
+
+        let service = plugin.router_service(mock_router_service.boxed());
+
+        let request = supergraph::Request::canned_builder().build().unwrap();
+
+        service.oneshot(request.try_into().unwrap()).await.unwrap();
+    }
+
     #[allow(clippy::type_complexity)]
     fn mock_with_callback(
         callback: fn(
@@ -1328,6 +1918,21 @@ mod tests {
         mock_http_client
     }
 
+    // The plugin clones its clients more times than a single stage does
+    #[allow(clippy::type_complexity)]
+    fn cloneable_mock_with_callback(
+        callback: fn(
+            hyper::Request<Body>,
+        ) -> BoxFuture<'static, Result<hyper::Response<Body>, BoxError>>,
+    ) -> MockHttpClientService {
+        let mut mock_http_client = MockHttpClientService::new();
+        mock_http_client
+            .expect_clone()
+            .returning(move || cloneable_mock_with_callback(callback));
+        mock_http_client.expect_call().returning(callback);
+        mock_http_client
+    }
+
     #[allow(clippy::type_complexity)]
     fn mock_with_deferred_callback(
         callback: fn(
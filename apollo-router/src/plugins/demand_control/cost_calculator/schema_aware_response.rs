@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use apollo_compiler::executable::Field;
+use apollo_compiler::executable::Operation;
 use apollo_compiler::executable::Selection;
 use apollo_compiler::executable::SelectionSet;
 use apollo_compiler::ExecutableDocument;
@@ -10,6 +11,8 @@ use serde_json_bytes::Value;
 
 use super::DemandControlError;
 use crate::graphql::Response;
+use crate::json_ext::Path;
+use crate::json_ext::PathElement;
 
 pub(crate) struct SchemaAwareResponse<'a> {
     pub(crate) value: TypedValue<'a>,
@@ -47,6 +50,78 @@ impl<'a> SchemaAwareResponse<'a> {
             ))
         }
     }
+
+    /// Zips the data of an incremental payload, such as a `@defer` increment, with the
+    /// selections of the executed operation found at the payload's path.
+    pub(crate) fn incremental(
+        request: &'a ExecutableDocument,
+        operation: &'a Operation,
+        path: &Path,
+        data: &'a Value,
+    ) -> Result<Self, DemandControlError> {
+        let Value::Object(children) = data else {
+            return Err(DemandControlError::ResponseTypingFailure(
+                "Can't zip an incremental payload with a non-object!".to_string(),
+            ));
+        };
+
+        let mut selection_sets: Vec<&'a SelectionSet> = vec![&operation.selection_set];
+        for element in path.iter() {
+            if let PathElement::Key(key) = element {
+                let mut nested = Vec::new();
+                for selection_set in selection_sets {
+                    collect_field_selections(request, selection_set, key, &mut nested);
+                }
+                selection_sets = nested;
+            }
+        }
+        if selection_sets.is_empty() {
+            return Err(DemandControlError::ResponseTypingFailure(format!(
+                "The incremental path {} does not match any selection in the query.",
+                path
+            )));
+        }
+
+        let mut typed_children: HashMap<String, TypedValue<'a>> = HashMap::new();
+        for selection_set in selection_sets {
+            typed_children.extend(TypedValue::zip_selections(
+                request,
+                selection_set,
+                children,
+            )?);
+        }
+
+        Ok(Self {
+            value: TypedValue::Root(typed_children),
+        })
+    }
+}
+
+/// Collects the selection sets of every field in `selection_set` whose response key is `key`,
+/// looking through fragments.
+fn collect_field_selections<'a>(
+    request: &'a ExecutableDocument,
+    selection_set: &'a SelectionSet,
+    key: &str,
+    found: &mut Vec<&'a SelectionSet>,
+) {
+    for selection in &selection_set.selections {
+        match selection {
+            Selection::Field(field) => {
+                if field.alias.as_ref().unwrap_or(&field.name).as_str() == key {
+                    found.push(&field.selection_set);
+                }
+            }
+            Selection::FragmentSpread(fragment_spread) => {
+                if let Some(fragment) = fragment_spread.fragment_def(request) {
+                    collect_field_selections(request, &fragment.selection_set, key, found);
+                }
+            }
+            Selection::InlineFragment(inline_fragment) => {
+                collect_field_selections(request, &inline_fragment.selection_set, key, found);
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -318,10 +318,22 @@ impl StaticCostCalculator {
     pub(crate) fn actual(
         &self,
         request: &ExecutableDocument,
+        operation: &Operation,
         response: &Response,
     ) -> Result<f64, DemandControlError> {
-        let schema_aware_response = SchemaAwareResponse::new(request, response)?;
-        Self::score_json(&schema_aware_response.value)
+        let mut cost = 0.0;
+        if response.data.is_some() {
+            let schema_aware_response = SchemaAwareResponse::new(request, response)?;
+            cost += Self::score_json(&schema_aware_response.value)?;
+        }
+        for incremental in &response.incremental {
+            if let (Some(path), Some(data)) = (&incremental.path, &incremental.data) {
+                let schema_aware_response =
+                    SchemaAwareResponse::incremental(request, operation, path, data)?;
+                cost += Self::score_json(&schema_aware_response.value)?;
+            }
+        }
+        Ok(cost)
     }
 }
 
@@ -408,8 +420,9 @@ mod tests {
         let (_schema, query) =
             parse_schema_and_operation(schema_str, query_str, &Default::default());
         let response = Response::from_bytes("test", Bytes::from(response_bytes)).unwrap();
+        let operation = query.executable.get_operation(None).unwrap();
         StaticCostCalculator::new(Default::default())
-            .actual(&query.executable, &response)
+            .actual(&query.executable, operation, &response)
             .unwrap()
     }
 
@@ -546,4 +559,52 @@ mod tests {
         assert_eq!(planned_cost(schema, query).await, 10400.0);
         assert_eq!(actual_cost(schema, query, response), 2.0);
     }
+
+    #[test]
+    fn federated_query_with_defer_increment() {
+        let schema = include_str!("./fixtures/federated_ships_schema.graphql");
+        let query = include_str!("./fixtures/federated_ships_deferred_owner_query.graphql");
+        let response =
+            include_bytes!("./fixtures/federated_ships_deferred_increment_response.json");
+
+        let (_schema, query) = parse_schema_and_operation(schema, query, &Default::default());
+        let response: Response = serde_json::from_slice(response).unwrap();
+        let operation = query.executable.get_operation(None).unwrap();
+        let cost = StaticCostCalculator::new(Default::default())
+            .actual(&query.executable, operation, &response)
+            .unwrap();
+
+        assert_eq!(cost, 2.0);
+    }
+
+    #[test]
+    fn federated_query_with_defer_increment_in_a_multi_operation_document() {
+        let schema = include_str!("./fixtures/federated_ships_schema.graphql");
+        let query = r#"
+            query Names { ships { name } }
+            query Owners { ships { name ... @defer { owner { name } } } }
+        "#;
+        let response =
+            include_bytes!("./fixtures/federated_ships_deferred_increment_response.json");
+
+        let (_schema, query) = parse_schema_and_operation(schema, query, &Default::default());
+        let response: Response = serde_json::from_slice(response).unwrap();
+        let calculator = StaticCostCalculator::new(Default::default());
+
+        // The increments are only typed against the operation that was executed
+        let owners = query.executable.get_operation(Some("Owners")).unwrap();
+        assert_eq!(
+            calculator
+                .actual(&query.executable, owners, &response)
+                .unwrap(),
+            2.0
+        );
+        let names = query.executable.get_operation(Some("Names")).unwrap();
+        assert_eq!(
+            calculator
+                .actual(&query.executable, names, &response)
+                .unwrap(),
+            0.0
+        );
+    }
 }
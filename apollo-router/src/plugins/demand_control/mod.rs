@@ -12,25 +12,34 @@ use displaydoc::Display;
 use futures::future::Either;
 use futures::stream;
 use futures::StreamExt;
+use http::HeaderValue;
 use schemars::JsonSchema;
 use serde::Deserialize;
+use serde_json_bytes::json;
+use serde_json_bytes::Value;
 use thiserror::Error;
 use tower::BoxError;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 
+use crate::context::OPERATION_NAME;
 use crate::error::Error;
 use crate::graphql;
 use crate::graphql::IntoGraphQLErrors;
+use crate::json_ext::Object;
 use crate::layers::ServiceBuilderExt;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
 use crate::plugins::demand_control::strategy::Strategy;
 use crate::plugins::demand_control::strategy::StrategyFactory;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::plugins::telemetry::CLIENT_VERSION;
 use crate::register_plugin;
 use crate::services::execution;
 use crate::services::execution::BoxService;
+use crate::services::layers::persisted_queries::UsedQueryIdFromManifest;
 use crate::services::subgraph;
+use crate::Context;
 
 pub(crate) mod cost_calculator;
 pub(crate) mod strategy;
@@ -55,6 +64,11 @@ pub(crate) enum StrategyConfig {
     StaticEstimated {
         /// The maximum cost of a query
         max: f64,
+        /// The maximum actual cost of a response.
+        /// The actual cost accumulates over the primary response, `@defer` increments and subscription events.
+        /// In enforce mode, no further payloads are delivered once it exceeds this value.
+        #[serde(default)]
+        actual_max: Option<f64>,
     },
 
     #[cfg(test)]
@@ -83,6 +97,109 @@ pub(crate) struct DemandControlConfig {
     mode: Mode,
     /// The strategy used to reject requests.
     strategy: StrategyConfig,
+    /// Exposing the cost of operations to clients.
+    #[serde(default)]
+    expose: ExposeConfig,
+    /// Limits and modes applied instead of the defaults to the requests matching a set of conditions.
+    /// The first override that matches a request is applied.
+    #[serde(default)]
+    overrides: Vec<OverrideConfig>,
+}
+
+/// Demand control limits applied to the requests matching a set of conditions
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct OverrideConfig {
+    /// The conditions that a request must match. Every condition that is set must match.
+    #[serde(rename = "match")]
+    matches: OverrideMatch,
+    /// The maximum cost of matching requests. Defaults to the maximum of the strategy.
+    #[serde(default)]
+    max: Option<f64>,
+    /// The mode for matching requests. Defaults to the mode of the plugin.
+    #[serde(default)]
+    mode: Option<Mode>,
+}
+
+/// Conditions matched against a request
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct OverrideMatch {
+    /// The client name, read from the header used for Apollo Studio reporting
+    client_name: Option<String>,
+    /// The client version, read from the header used for Apollo Studio reporting
+    client_version: Option<String>,
+    /// The ID of the persisted query the operation was loaded from
+    persisted_query_id: Option<String>,
+    /// The name of the operation
+    operation_name: Option<String>,
+    /// An entry of the request context
+    context: Option<ContextMatch>,
+}
+
+/// An entry of the request context to match
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ContextMatch {
+    /// The context key
+    key: String,
+    /// The expected value. String values are compared as is, other values through their JSON representation.
+    value: String,
+}
+
+impl OverrideMatch {
+    fn matches(&self, context: &Context) -> bool {
+        let context_string = |key: &str| context.get::<_, String>(key).ok().flatten();
+        let matches = |expected: &Option<String>, actual: Option<String>| match expected {
+            Some(expected) => actual.as_ref() == Some(expected),
+            None => true,
+        };
+
+        matches(&self.client_name, context_string(CLIENT_NAME))
+            && matches(&self.client_version, context_string(CLIENT_VERSION))
+            && matches(&self.operation_name, context_string(OPERATION_NAME))
+            && matches(
+                &self.persisted_query_id,
+                context
+                    .extensions()
+                    .lock()
+                    .get::<UsedQueryIdFromManifest>()
+                    .map(|used| used.0.clone()),
+            )
+            && self.context.as_ref().map_or(true, |expected| {
+                let actual =
+                    context
+                        .get_json_value(expected.key.as_str())
+                        .map(|value| match value {
+                            Value::String(value) => value.as_str().to_string(),
+                            value => value.to_string(),
+                        });
+                actual.as_ref() == Some(&expected.value)
+            })
+    }
+}
+
+/// Exposing the cost of operations to clients
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ExposeConfig {
+    /// The names of the clients that receive the estimated and actual cost of their operations, along with the applied maximum, in the `cost` response extension.
+    /// The client name is read from the same header as the one used for Apollo Studio reporting.
+    client_names: Vec<String>,
+    /// Allow these clients to send the `Apollo-Cost-Dry-Run: true` header, to get the estimated cost of an operation without executing it.
+    dry_run: bool,
+}
+
+impl ExposeConfig {
+    fn is_exposed_to(&self, context: &Context) -> bool {
+        !self.client_names.is_empty()
+            && context
+                .get::<_, String>(CLIENT_NAME)
+                .ok()
+                .flatten()
+                .map(|client_name| self.client_names.contains(&client_name))
+                .unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Display, Error)]
@@ -90,7 +207,6 @@ pub(crate) enum DemandControlError {
     /// Query estimated cost exceeded configured maximum
     EstimatedCostTooExpensive,
     /// Query actual cost exceeded configured maximum
-    #[allow(dead_code)]
     ActualCostTooExpensive,
     /// Query could not be parsed: {0}
     QueryParseFailure(String),
@@ -98,38 +214,97 @@ pub(crate) enum DemandControlError {
     ResponseTypingFailure(String),
 }
 
-impl IntoGraphQLErrors for DemandControlError {
-    fn into_graphql_errors(self) -> Result<Vec<Error>, Self> {
+impl DemandControlError {
+    pub(crate) fn code(&self) -> &'static str {
         match self {
-            DemandControlError::EstimatedCostTooExpensive => Ok(vec![graphql::Error::builder()
-                .extension_code("COST_ESTIMATED_TOO_EXPENSIVE")
-                .message(self.to_string())
-                .build()]),
-            DemandControlError::ActualCostTooExpensive => Ok(vec![graphql::Error::builder()
-                .extension_code("COST_ACTUAL_TOO_EXPENSIVE")
-                .message(self.to_string())
-                .build()]),
-            DemandControlError::QueryParseFailure(_) => Ok(vec![graphql::Error::builder()
-                .extension_code("COST_QUERY_PARSE_FAILURE")
-                .message(self.to_string())
-                .build()]),
-            DemandControlError::ResponseTypingFailure(_) => Ok(vec![graphql::Error::builder()
-                .extension_code("COST_RESPONSE_TYPING_FAILURE")
-                .message(self.to_string())
-                .build()]),
+            DemandControlError::EstimatedCostTooExpensive => "COST_ESTIMATED_TOO_EXPENSIVE",
+            DemandControlError::ActualCostTooExpensive => "COST_ACTUAL_TOO_EXPENSIVE",
+            DemandControlError::QueryParseFailure(_) => "COST_QUERY_PARSE_FAILURE",
+            DemandControlError::ResponseTypingFailure(_) => "COST_RESPONSE_TYPING_FAILURE",
         }
     }
 }
 
+impl IntoGraphQLErrors for DemandControlError {
+    fn into_graphql_errors(self) -> Result<Vec<Error>, Self> {
+        Ok(vec![graphql::Error::builder()
+            .extension_code(self.code())
+            .message(self.to_string())
+            .build()])
+    }
+}
+
 impl<T> From<WithErrors<T>> for DemandControlError {
     fn from(value: WithErrors<T>) -> Self {
         DemandControlError::QueryParseFailure(format!("{}", value))
     }
 }
 
+/// The cost of the current request, as measured by the demand control plugin.
+/// It is stored in the context extensions so that it can be read after the response has been delivered.
+#[derive(Clone, Debug)]
+pub(crate) struct CostContext {
+    /// The estimated cost, computed from the query plan before execution
+    pub(crate) estimated: f64,
+    /// The actual cost, accumulated over every payload of the response
+    pub(crate) actual: f64,
+    /// The outcome of the cost checks: `COST_OK` or the code of the demand control error
+    pub(crate) result: &'static str,
+    /// The maximum cost applied by the strategy, if it has one
+    pub(crate) max: Option<f64>,
+}
+
+impl Default for CostContext {
+    fn default() -> Self {
+        Self {
+            estimated: 0.0,
+            actual: 0.0,
+            result: COST_OK,
+            max: None,
+        }
+    }
+}
+
+impl CostContext {
+    pub(crate) fn delta(&self) -> f64 {
+        self.estimated - self.actual
+    }
+
+    /// The value of the `cost` response extension.
+    fn to_extension(&self) -> Value {
+        let mut extension = json!({
+            "estimated": self.estimated,
+            "actual": self.actual,
+            "result": self.result,
+        });
+        if let (Some(max), Some(extension)) = (self.max, extension.as_object_mut()) {
+            extension.insert("max", json!(max));
+        }
+        extension
+    }
+
+    /// Updates the cost context of a request, creating it if it does not exist yet.
+    pub(crate) fn update<R>(context: &Context, f: impl FnOnce(&mut CostContext) -> R) -> R {
+        let mut extensions = context.extensions().lock();
+        match extensions.get_mut::<CostContext>() {
+            Some(cost_context) => f(cost_context),
+            None => {
+                let mut cost_context = CostContext::default();
+                let result = f(&mut cost_context);
+                extensions.insert(cost_context);
+                result
+            }
+        }
+    }
+}
+
+pub(crate) const COST_OK: &str = "COST_OK";
+const COST_EXTENSION_KEY: &str = "cost";
+const DRY_RUN_HEADER_NAME: &str = "Apollo-Cost-Dry-Run";
+
 pub(crate) struct DemandControl {
     config: DemandControlConfig,
-    strategy_factory: StrategyFactory,
+    strategy_factory: Arc<StrategyFactory>,
 }
 
 #[async_trait::async_trait]
@@ -138,11 +313,11 @@ impl Plugin for DemandControl {
 
     async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
         Ok(DemandControl {
-            strategy_factory: StrategyFactory::new(
+            strategy_factory: Arc::new(StrategyFactory::new(
                 init.config.clone(),
                 init.supergraph_schema.clone(),
                 init.subgraph_schemas.clone(),
-            ),
+            )),
             config: init.config,
         })
     }
@@ -151,26 +326,44 @@ impl Plugin for DemandControl {
         if !self.config.enabled {
             service
         } else {
-            let strategy = self.strategy_factory.create();
+            let strategy_factory = self.strategy_factory.clone();
+            let expose = Arc::new(self.config.expose.clone());
+            let response_expose = expose.clone();
             ServiceBuilder::new()
                 .checkpoint(move |req: execution::Request| {
+                    let strategy = strategy_factory.create_for_request(&req.context);
                     req.context.extensions().lock().insert(strategy.clone());
+                    let exposed = expose.is_exposed_to(&req.context);
+                    let dry_run = exposed
+                        && expose.dry_run
+                        && req.supergraph_request.headers().get(DRY_RUN_HEADER_NAME)
+                            == Some(&HeaderValue::from_static("true"));
                     // On the request path we need to check for estimates, checkpoint is used to do this, short-circuiting the request if it's too expensive.
-                    Ok(match strategy.on_execution_request(&req) {
-                        Ok(_) => ControlFlow::Continue(req),
-                        Err(err) => ControlFlow::Break(
-                            execution::Response::builder()
-                                .errors(
-                                    err.into_graphql_errors()
-                                        .expect("must be able to convert to graphql error"),
-                                )
-                                .context(req.context.clone())
-                                .build()
-                                .expect("Must be able to build response"),
-                        ),
-                    })
+                    // A dry run stops here as well, returning the estimate without executing the operation.
+                    let errors = match strategy.on_execution_request(&req) {
+                        Ok(_) if !dry_run => return Ok(ControlFlow::Continue(req)),
+                        Ok(_) => Vec::new(),
+                        Err(err) => {
+                            // The operation is not executed: its estimated cost is all there is to record
+                            record_cost_metrics(&req.context);
+                            err.into_graphql_errors()
+                                .expect("must be able to convert to graphql error")
+                        }
+                    };
+                    let mut extensions = Object::new();
+                    if exposed {
+                        extensions.insert(COST_EXTENSION_KEY, cost_extension(&req.context));
+                    }
+                    Ok(ControlFlow::Break(
+                        execution::Response::builder()
+                            .errors(errors)
+                            .extensions(extensions)
+                            .context(req.context.clone())
+                            .build()
+                            .expect("Must be able to build response"),
+                    ))
                 })
-                .map_response(|mut resp: execution::Response| {
+                .map_response(move |mut resp: execution::Response| {
                     let req = resp
                         .context
                         .unsupported_executable_document()
@@ -182,13 +375,27 @@ impl Plugin for DemandControl {
                         .get::<Strategy>()
                         .expect("must have strategy")
                         .clone();
+                    let context = resp.context.clone();
+                    let exposed = response_expose.is_exposed_to(&context);
                     resp.response = resp.response.map(move |resp| {
+                        // Owned by the stream, so that the metrics are recorded when it ends, or when it is dropped early
+                        let metrics = CostMetricsGuard(context.clone());
                         // Here we are going to abort the stream if the cost is too high
                         // First we map based on cost, then we use take while to abort the stream if an error is emitted.
                         // When we terminate the stream we still want to emit a graphql error, so the error response is emitted first before a termination error.
-                        resp.flat_map(move |resp| {
-                            match strategy.on_execution_response(req.as_ref(), &resp) {
-                                Ok(_) => Either::Left(stream::once(future::ready(Ok(resp)))),
+                        resp.flat_map(move |mut resp| {
+                            let context = &metrics.0;
+                            let result =
+                                strategy.on_execution_response(context, req.as_ref(), &resp);
+                            let mut extensions = Object::new();
+                            if exposed {
+                                extensions.insert(COST_EXTENSION_KEY, cost_extension(context));
+                            }
+                            match result {
+                                Ok(_) => {
+                                    resp.extensions.extend(extensions);
+                                    Either::Left(stream::once(future::ready(Ok(resp))))
+                                }
                                 Err(err) => Either::Right(stream::iter(vec![
                                     // This is the error we are returning to the user
                                     Ok(graphql::Response::builder()
@@ -196,7 +403,9 @@ impl Plugin for DemandControl {
                                             err.into_graphql_errors()
                                                 .expect("must be able to convert to graphql error"),
                                         )
-                                        .extensions(crate::json_ext::Object::new())
+                                        .extensions(extensions)
+                                        // Deferred and subscription streams must be told that nothing else is coming
+                                        .and_has_next(resp.has_next.map(|_| false))
                                         .build()),
                                     // This will terminate the stream
                                     Err(()),
@@ -244,7 +453,7 @@ impl Plugin for DemandControl {
                                         .expect("must be able to convert to graphql error"),
                                 )
                                 .context(req.context.clone())
-                                .extensions(crate::json_ext::Object::new())
+                                .extensions(Object::new())
                                 .build(),
                         ),
                     })
@@ -270,7 +479,7 @@ impl Plugin for DemandControl {
                                         .expect("must be able to convert to graphql error"),
                                 )
                                 .context(resp.context.clone())
-                                .extensions(crate::json_ext::Object::new())
+                                .extensions(Object::new())
                                 .build(),
                         })
                     },
@@ -281,6 +490,48 @@ impl Plugin for DemandControl {
     }
 }
 
+fn cost_extension(context: &Context) -> Value {
+    context
+        .extensions()
+        .lock()
+        .get::<CostContext>()
+        .map(CostContext::to_extension)
+        .unwrap_or_else(|| CostContext::default().to_extension())
+}
+
+/// Records the cost metrics of a request when dropped, once its actual cost is final
+struct CostMetricsGuard(Context);
+
+impl Drop for CostMetricsGuard {
+    fn drop(&mut self) {
+        record_cost_metrics(&self.0);
+    }
+}
+
+fn record_cost_metrics(context: &Context) {
+    let Some(cost_context) = context.extensions().lock().get::<CostContext>().cloned() else {
+        return;
+    };
+    f64_histogram!(
+        "apollo.router.operations.demand_control.estimated",
+        "Estimated cost of the operation",
+        cost_context.estimated,
+        "demand_control.result" = cost_context.result
+    );
+    f64_histogram!(
+        "apollo.router.operations.demand_control.actual",
+        "Actual cost of the operation",
+        cost_context.actual,
+        "demand_control.result" = cost_context.result
+    );
+    f64_histogram!(
+        "apollo.router.operations.demand_control.delta",
+        "Difference between the estimated and actual cost of the operation",
+        cost_context.delta(),
+        "demand_control.result" = cost_context.result
+    );
+}
+
 register_plugin!("apollo", "experimental_demand_control", DemandControl);
 
 #[cfg(test)]
@@ -290,20 +541,34 @@ mod test {
     use apollo_compiler::ast;
     use apollo_compiler::validation::Valid;
     use apollo_compiler::ExecutableDocument;
+    use bytes::Bytes;
     use futures::StreamExt;
     use schemars::JsonSchema;
     use serde::Deserialize;
+    use tower::Service;
 
+    use crate::context::OPERATION_NAME;
     use crate::graphql;
     use crate::graphql::Response;
+    use crate::metrics::FutureMetricsExt;
     use crate::plugins::demand_control::DemandControl;
     use crate::plugins::demand_control::DemandControlError;
+    use crate::plugins::demand_control::OverrideMatch;
+    use crate::plugins::demand_control::DRY_RUN_HEADER_NAME;
+    use crate::plugins::telemetry::CLIENT_NAME;
     use crate::plugins::test::PluginTestHarness;
     use crate::query_planner::fetch::QueryHash;
+    use crate::query_planner::BridgeQueryPlanner;
     use crate::services::execution;
+    use crate::services::layers::persisted_queries::UsedQueryIdFromManifest;
     use crate::services::layers::query_analysis::ParsedDocument;
     use crate::services::layers::query_analysis::ParsedDocumentInner;
     use crate::services::subgraph;
+    use crate::services::QueryPlannerContent;
+    use crate::services::QueryPlannerRequest;
+    use crate::spec;
+    use crate::spec::Query;
+    use crate::Configuration;
     use crate::Context;
 
     #[tokio::test]
@@ -342,6 +607,268 @@ mod test {
         insta::assert_yaml_snapshot!(body);
     }
 
+    #[tokio::test]
+    async fn test_enforce_on_deferred_execution_response() {
+        let plugin = PluginTestHarness::<DemandControl>::builder()
+            .config(include_str!(
+                "fixtures/enforce_on_execution_response.router.yaml"
+            ))
+            .build()
+            .await;
+
+        let resp = plugin
+            .call_execution(
+                execution::Request::fake_builder()
+                    .context(context())
+                    .build(),
+                |req| {
+                    execution::Response::fake_stream_builder()
+                        .response(graphql::Response::builder().has_next(true).build())
+                        .response(graphql::Response::builder().has_next(false).build())
+                        .context(req.context)
+                        .build()
+                        .unwrap()
+                },
+            )
+            .await
+            .unwrap();
+
+        let body = resp
+            .response
+            .into_body()
+            .collect::<Vec<graphql::Response>>()
+            .await;
+        insta::assert_yaml_snapshot!(body);
+    }
+
+    #[tokio::test]
+    async fn test_records_metrics_when_the_estimate_is_rejected() {
+        async {
+            test_on_execution(include_str!(
+                "fixtures/enforce_on_execution_request.router.yaml"
+            ))
+            .await;
+            assert_histogram_exists!(
+                "apollo.router.operations.demand_control.estimated",
+                f64,
+                "demand_control.result" = "COST_ESTIMATED_TOO_EXPENSIVE"
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_records_metrics_when_the_response_stream_is_dropped() {
+        async {
+            let plugin = PluginTestHarness::<DemandControl>::builder()
+                .config(include_str!(
+                    "fixtures/measure_on_execution_request.router.yaml"
+                ))
+                .build()
+                .await;
+
+            let resp = plugin
+                .call_execution(
+                    execution::Request::fake_builder()
+                        .context(context())
+                        .build(),
+                    |req| {
+                        execution::Response::fake_stream_builder()
+                            .response(graphql::Response::builder().has_next(true).build())
+                            .response(graphql::Response::builder().has_next(false).build())
+                            .context(req.context)
+                            .build()
+                            .unwrap()
+                    },
+                )
+                .await
+                .unwrap();
+
+            // The client disconnects after the first payload
+            let mut body = resp.response.into_body();
+            body.next().await.unwrap();
+            assert_histogram_not_exists!(
+                "apollo.router.operations.demand_control.actual",
+                f64,
+                "demand_control.result" = "COST_ESTIMATED_TOO_EXPENSIVE"
+            );
+            drop(body);
+            assert_histogram_exists!(
+                "apollo.router.operations.demand_control.actual",
+                f64,
+                "demand_control.result" = "COST_ESTIMATED_TOO_EXPENSIVE"
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_expose_cost() {
+        let body = test_expose(false).await;
+        insta::assert_yaml_snapshot!(body);
+    }
+
+    #[tokio::test]
+    async fn test_expose_cost_dry_run() {
+        let body = test_expose(true).await;
+        insta::assert_yaml_snapshot!(body);
+    }
+
+    async fn test_expose(dry_run: bool) -> Vec<Response> {
+        let schema = include_str!("cost_calculator/fixtures/federated_ships_schema.graphql");
+        let query = include_str!("cost_calculator/fixtures/federated_ships_fragment_query.graphql");
+
+        let plugin = PluginTestHarness::<DemandControl>::builder()
+            .config(include_str!("fixtures/expose_cost.router.yaml"))
+            .schema(schema)
+            .build()
+            .await;
+
+        // The strategy estimates the cost from the query plan and measures it on the response
+        let config: Arc<Configuration> = Default::default();
+        let parsed_schema = spec::Schema::parse_test(schema, &config).unwrap();
+        let document = Query::parse_document(query, None, &parsed_schema, &config).unwrap();
+        let ctx = Context::new();
+        ctx.extensions().lock().insert::<ParsedDocument>(document);
+        ctx.insert(CLIENT_NAME, "test-client".to_string()).unwrap();
+        let mut planner = BridgeQueryPlanner::new(schema.to_string(), config)
+            .await
+            .unwrap();
+        let query_plan = match planner
+            .call(QueryPlannerRequest::new(
+                query.to_string(),
+                None,
+                ctx.clone(),
+            ))
+            .await
+            .unwrap()
+            .content
+            .unwrap()
+        {
+            QueryPlannerContent::Plan { plan } => plan,
+            _ => panic!("Query planner returned unexpected non-plan content"),
+        };
+        let mut supergraph_request = http::Request::builder();
+        if dry_run {
+            supergraph_request = supergraph_request.header(DRY_RUN_HEADER_NAME, "true");
+        }
+
+        let resp = plugin
+            .call_execution(
+                execution::Request::builder()
+                    .supergraph_request(
+                        supergraph_request
+                            .body(graphql::Request::default())
+                            .unwrap(),
+                    )
+                    .query_plan(query_plan)
+                    .context(ctx)
+                    .build(),
+                |req| {
+                    let response = Response::from_bytes(
+                        "test",
+                        Bytes::from_static(include_bytes!(
+                            "cost_calculator/fixtures/federated_ships_fragment_response.json"
+                        )),
+                    )
+                    .unwrap();
+                    execution::Response::fake_builder()
+                        .data(response.data.unwrap())
+                        .context(req.context)
+                        .build()
+                        .unwrap()
+                },
+            )
+            .await
+            .unwrap();
+
+        resp.response
+            .into_body()
+            .collect::<Vec<graphql::Response>>()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_override_applied_to_matching_request() {
+        let ctx = context();
+        ctx.insert(CLIENT_NAME, "test-client".to_string()).unwrap();
+        ctx.insert(OPERATION_NAME, "MyQuery".to_string()).unwrap();
+        let body = test_overrides(ctx).await;
+        assert_eq!(body.len(), 1);
+        assert_eq!(
+            body[0].errors[0].extensions.get("code"),
+            Some(&serde_json_bytes::json!("COST_ESTIMATED_TOO_EXPENSIVE"))
+        );
+        assert_eq!(body[0].data, None);
+    }
+
+    #[tokio::test]
+    async fn test_override_not_applied_to_other_requests() {
+        let ctx = context();
+        ctx.insert(CLIENT_NAME, "test-client".to_string()).unwrap();
+        ctx.insert(OPERATION_NAME, "OtherQuery".to_string())
+            .unwrap();
+        let body = test_overrides(ctx).await;
+        assert_eq!(body.len(), 1);
+        assert!(body[0].errors.is_empty());
+        assert_eq!(
+            body[0].data,
+            Some(serde_json_bytes::json!({ "executed": true }))
+        );
+    }
+
+    #[test]
+    fn test_override_match() {
+        let matcher: OverrideMatch = serde_json::from_value(serde_json::json!({
+            "persisted_query_id": "abc",
+            "context": { "key": "tier", "value": "3" }
+        }))
+        .unwrap();
+
+        let ctx = Context::new();
+        assert!(!matcher.matches(&ctx));
+        ctx.insert("tier", 3).unwrap();
+        assert!(!matcher.matches(&ctx));
+        ctx.extensions()
+            .lock()
+            .insert(UsedQueryIdFromManifest("abc".to_string()));
+        assert!(matcher.matches(&ctx));
+        ctx.insert("tier", "3".to_string()).unwrap();
+        assert!(matcher.matches(&ctx));
+        ctx.insert("tier", "4".to_string()).unwrap();
+        assert!(!matcher.matches(&ctx));
+
+        assert!(OverrideMatch::default().matches(&Context::new()));
+    }
+
+    async fn test_overrides(ctx: Context) -> Vec<Response> {
+        let plugin = PluginTestHarness::<DemandControl>::builder()
+            .config(include_str!("fixtures/overrides.router.yaml"))
+            .build()
+            .await;
+
+        let resp = plugin
+            .call_execution(
+                execution::Request::fake_builder().context(ctx).build(),
+                |req| {
+                    execution::Response::fake_builder()
+                        .data(serde_json_bytes::json!({ "executed": true }))
+                        .context(req.context)
+                        .build()
+                        .unwrap()
+                },
+            )
+            .await
+            .unwrap();
+
+        resp.response
+            .into_body()
+            .collect::<Vec<graphql::Response>>()
+            .await
+    }
+
     #[tokio::test]
     async fn test_measure_on_subgraph_request() {
         let body = test_on_subgraph(include_str!(
@@ -8,12 +8,15 @@ use apollo_compiler::Schema;
 use crate::graphql;
 use crate::plugins::demand_control::cost_calculator::static_cost::StaticCostCalculator;
 use crate::plugins::demand_control::strategy::static_estimated::StaticEstimated;
+use crate::plugins::demand_control::CostContext;
 use crate::plugins::demand_control::DemandControlConfig;
 use crate::plugins::demand_control::DemandControlError;
 use crate::plugins::demand_control::Mode;
 use crate::plugins::demand_control::StrategyConfig;
 use crate::services::execution;
+use crate::services::layers::persisted_queries::OperationMetadata;
 use crate::services::subgraph;
+use crate::Context;
 
 mod static_estimated;
 #[cfg(test)]
@@ -32,19 +35,15 @@ impl Strategy {
         &self,
         request: &execution::Request,
     ) -> Result<(), DemandControlError> {
-        match self.inner.on_execution_request(request) {
-            Err(e) if self.mode == Mode::Enforce => Err(e),
-            _ => Ok(()),
-        }
+        let result = self.inner.on_execution_request(request);
+        self.apply_mode(&request.context, result)
     }
     pub(crate) fn on_subgraph_request(
         &self,
         request: &subgraph::Request,
     ) -> Result<(), DemandControlError> {
-        match self.inner.on_subgraph_request(request) {
-            Err(e) if self.mode == Mode::Enforce => Err(e),
-            _ => Ok(()),
-        }
+        let result = self.inner.on_subgraph_request(request);
+        self.apply_mode(&request.context, result)
     }
 
     pub(crate) fn on_subgraph_response(
@@ -52,19 +51,35 @@ impl Strategy {
         request: &ExecutableDocument,
         response: &subgraph::Response,
     ) -> Result<(), DemandControlError> {
-        match self.inner.on_subgraph_response(request, response) {
-            Err(e) if self.mode == Mode::Enforce => Err(e),
-            _ => Ok(()),
-        }
+        let result = self.inner.on_subgraph_response(request, response);
+        self.apply_mode(&response.context, result)
     }
     pub(crate) fn on_execution_response(
         &self,
+        context: &Context,
         request: &ExecutableDocument,
         response: &graphql::Response,
     ) -> Result<(), DemandControlError> {
-        match self.inner.on_execution_response(request, response) {
-            Err(e) if self.mode == Mode::Enforce => Err(e),
-            _ => Ok(()),
+        let result = self.inner.on_execution_response(context, request, response);
+        self.apply_mode(context, result)
+    }
+
+    /// Records the outcome of a check in the cost context, and only surfaces the error when enforcing.
+    fn apply_mode(
+        &self,
+        context: &Context,
+        result: Result<(), DemandControlError>,
+    ) -> Result<(), DemandControlError> {
+        match result {
+            Err(e) => {
+                CostContext::update(context, |cost_context| cost_context.result = e.code());
+                if self.mode == Mode::Enforce {
+                    Err(e)
+                } else {
+                    Ok(())
+                }
+            }
+            Ok(()) => Ok(()),
         }
     }
 }
@@ -90,9 +105,33 @@ impl StrategyFactory {
     }
 
     pub(crate) fn create(&self) -> Strategy {
+        self.create_with(None, self.config.mode)
+    }
+
+    /// Creates the strategy for a request, applying the first override matching the request context.
+    /// The cost allowance of a persisted query's metadata takes precedence over the overrides.
+    pub(crate) fn create_for_request(&self, context: &Context) -> Strategy {
+        let max_cost = context
+            .extensions()
+            .lock()
+            .get::<OperationMetadata>()
+            .and_then(|metadata| metadata.max_cost);
+        match self
+            .config
+            .overrides
+            .iter()
+            .find(|o| o.matches.matches(context))
+        {
+            Some(o) => self.create_with(max_cost.or(o.max), o.mode.unwrap_or(self.config.mode)),
+            None => self.create_with(max_cost, self.config.mode),
+        }
+    }
+
+    fn create_with(&self, max_override: Option<f64>, mode: Mode) -> Strategy {
         let strategy: Arc<dyn StrategyImpl> = match &self.config.strategy {
-            StrategyConfig::StaticEstimated { max } => Arc::new(StaticEstimated {
-                max: *max,
+            StrategyConfig::StaticEstimated { max, actual_max } => Arc::new(StaticEstimated {
+                max: max_override.unwrap_or(*max),
+                actual_max: *actual_max,
                 cost_calculator: StaticCostCalculator::new(self.subgraph_schemas.clone()),
             }),
             #[cfg(test)]
@@ -102,7 +141,7 @@ impl StrategyFactory {
             }),
         };
         Strategy {
-            mode: self.config.mode,
+            mode,
             inner: strategy,
         }
     }
@@ -119,6 +158,7 @@ pub(crate) trait StrategyImpl: Send + Sync {
     ) -> Result<(), DemandControlError>;
     fn on_execution_response(
         &self,
+        context: &Context,
         request: &ExecutableDocument,
         response: &graphql::Response,
     ) -> Result<(), DemandControlError>;
@@ -1,16 +1,23 @@
 use apollo_compiler::ExecutableDocument;
 
+use crate::context::OPERATION_NAME;
 use crate::graphql;
 use crate::plugins::demand_control::cost_calculator::static_cost::StaticCostCalculator;
 use crate::plugins::demand_control::strategy::StrategyImpl;
+use crate::plugins::demand_control::CostContext;
 use crate::plugins::demand_control::DemandControlError;
 use crate::services::execution;
 use crate::services::subgraph;
+use crate::Context;
 
 /// This strategy will reject requests if the estimated cost of the request exceeds the maximum cost.
+/// The actual cost of the response is measured as it is delivered, and if an actual maximum is configured
+/// the response stream is cut short once it is exceeded.
 pub(crate) struct StaticEstimated {
     // The estimated value of the demand
     pub(crate) max: f64,
+    // The maximum actual cost of the response
+    pub(crate) actual_max: Option<f64>,
     pub(crate) cost_calculator: StaticCostCalculator,
 }
 
@@ -19,6 +26,10 @@ impl StrategyImpl for StaticEstimated {
         self.cost_calculator
             .planned(&request.query_plan)
             .and_then(|cost| {
+                CostContext::update(&request.context, |cost_context| {
+                    cost_context.estimated = cost;
+                    cost_context.max = Some(self.max);
+                });
                 if cost > self.max {
                     Err(DemandControlError::EstimatedCostTooExpensive)
                 } else {
@@ -41,12 +52,29 @@ impl StrategyImpl for StaticEstimated {
 
     fn on_execution_response(
         &self,
+        context: &Context,
         request: &ExecutableDocument,
         response: &graphql::Response,
     ) -> Result<(), DemandControlError> {
-        if response.data.is_some() {
-            let _cost = self.cost_calculator.actual(request, response)?;
-            // Todo metrics
+        if response.data.is_some() || !response.incremental.is_empty() {
+            let operation_name = context.get::<_, String>(OPERATION_NAME).ok().flatten();
+            let operation = request
+                .get_operation(operation_name.as_deref())
+                .map_err(|_| {
+                    DemandControlError::ResponseTypingFailure(
+                        "The executed operation was not found in the query.".to_string(),
+                    )
+                })?;
+            let cost = self.cost_calculator.actual(request, operation, response)?;
+            let actual = CostContext::update(context, |cost_context| {
+                cost_context.actual += cost;
+                cost_context.actual
+            });
+            if let Some(actual_max) = self.actual_max {
+                if actual > actual_max {
+                    return Err(DemandControlError::ActualCostTooExpensive);
+                }
+            }
         }
         Ok(())
     }
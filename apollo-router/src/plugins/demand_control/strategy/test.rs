@@ -6,6 +6,7 @@ use crate::plugins::demand_control::test::TestStage;
 use crate::plugins::demand_control::DemandControlError;
 use crate::services::execution::Request;
 use crate::services::subgraph::Response;
+use crate::Context;
 
 /// Test strategy for demand control.
 /// Can be configured to fail at different stages of the request processing.
@@ -54,6 +55,7 @@ impl StrategyImpl for Test {
 
     fn on_execution_response(
         &self,
+        _context: &Context,
         _request: &ExecutableDocument,
         _response: &crate::graphql::Response,
     ) -> Result<(), DemandControlError> {
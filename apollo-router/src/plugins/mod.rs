@@ -33,7 +33,7 @@ mod headers;
 mod include_subgraph_errors;
 pub(crate) mod override_url;
 pub(crate) mod progressive_override;
-mod record_replay;
+pub(crate) mod record_replay;
 pub(crate) mod rhai;
 pub(crate) mod subscription;
 pub(crate) mod telemetry;
@@ -1,3 +1,3 @@
 mod record;
-mod recording;
+pub(crate) mod recording;
 mod replay;
@@ -45,6 +45,7 @@ use crate::graphql::Response;
 use crate::http_ext;
 use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
 use crate::plugins::cache::entity::CONTEXT_CACHE_KEY;
+use crate::plugins::subscription::SUBSCRIPTION_CLIENT_WS_CONNECTION_PARAMS;
 use crate::plugins::subscription::SUBSCRIPTION_WS_CUSTOM_CONNECTION_PARAMS;
 use crate::Context;
 
@@ -932,6 +933,16 @@ mod router_plugin {
             .map_response(rhai_service.clone(), callback)
     }
 
+    #[rhai_fn(return_raw)]
+    pub(crate) fn map_event(
+        rhai_service: &mut RhaiService,
+        callback: FnPtr,
+    ) -> Result<(), Box<EvalAltResult>> {
+        rhai_service
+            .service
+            .map_event(rhai_service.clone(), callback)
+    }
+
     // Register urlencode/decode functions
     #[rhai_fn(pure)]
     pub(crate) fn urlencode(x: &mut ImmutableString) -> String {
@@ -1705,6 +1716,10 @@ impl Rhai {
             "APOLLO_SUBSCRIPTION_WS_CUSTOM_CONNECTION_PARAMS".into(),
             SUBSCRIPTION_WS_CUSTOM_CONNECTION_PARAMS.to_string().into(),
         );
+        global_variables.insert(
+            "APOLLO_SUBSCRIPTION_CLIENT_WS_CONNECTION_PARAMS".into(),
+            SUBSCRIPTION_CLIENT_WS_CONNECTION_PARAMS.to_string().into(),
+        );
         global_variables.insert("APOLLO_ENTITY_CACHE_KEY".into(), CONTEXT_CACHE_KEY.into());
 
         let shared_globals = Arc::new(global_variables);
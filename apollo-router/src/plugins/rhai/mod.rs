@@ -45,7 +45,10 @@ use crate::layers::ServiceBuilderExt;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
 use crate::plugins::rhai::engine::OptionDance;
+use crate::plugins::subscription::map_subscription_events;
+use crate::plugins::subscription::SubscriptionEventAction;
 use crate::register_plugin;
+use crate::Context;
 
 mod engine;
 
@@ -736,6 +739,72 @@ impl ServiceStep {
             }
         }
     }
+
+    fn map_event(
+        &mut self,
+        rhai_service: RhaiService,
+        callback: FnPtr,
+    ) -> Result<(), Box<EvalAltResult>> {
+        let ServiceStep::Supergraph(service) = self else {
+            return Err("map_event is only available in supergraph_service".into());
+        };
+        service.replace(|service| {
+            service
+                .map_response(move |response: supergraph::Response| {
+                    let rhai_service = rhai_service.clone();
+                    let callback = callback.clone();
+                    map_subscription_events(response, move |context, event| {
+                        ready(map_subscription_event(
+                            &rhai_service,
+                            &callback,
+                            context,
+                            event,
+                        ))
+                    })
+                })
+                .boxed()
+        });
+        Ok(())
+    }
+}
+
+/// Calls a `map_event` callback on a subscription event
+///
+/// Returning `false` from the callback drops the event, throwing an error closes the subscription.
+fn map_subscription_event(
+    rhai_service: &RhaiService,
+    callback: &FnPtr,
+    context: Context,
+    event: crate::graphql::Response,
+) -> SubscriptionEventAction {
+    let shared_event = Shared::new(Mutex::new(Some(supergraph::DeferredResponse {
+        context,
+        response: event,
+    })));
+
+    let result = execute(rhai_service, callback, (shared_event.clone(),));
+    let supergraph::DeferredResponse { response, .. } = shared_event
+        .lock()
+        .unwrap()
+        .take()
+        .expect("the event is taken once");
+    match result {
+        Ok(keep) if keep.as_bool() == Ok(false) => SubscriptionEventAction::Drop,
+        Ok(_) => SubscriptionEventAction::Continue(response),
+        Err(error) => {
+            tracing::error!("map_event callback failed: {error}");
+            let error_details = process_error(error);
+            let last_event = error_details.body.unwrap_or_else(|| {
+                crate::graphql::Response::builder()
+                    .error(Error {
+                        message: error_details.message.unwrap_or_default(),
+                        ..Default::default()
+                    })
+                    .build()
+            });
+            SubscriptionEventAction::End(Some(last_event))
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
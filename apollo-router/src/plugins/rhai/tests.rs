@@ -22,6 +22,7 @@ use super::process_error;
 use super::subgraph;
 use super::PathBuf;
 use super::Rhai;
+use crate::context::OPERATION_KIND;
 use crate::graphql::Error;
 use crate::graphql::Request;
 use crate::http_ext;
@@ -32,6 +33,7 @@ use crate::plugins::rhai::engine::RhaiExecutionDeferredResponse;
 use crate::plugins::rhai::engine::RhaiExecutionResponse;
 use crate::plugins::rhai::engine::RhaiSupergraphDeferredResponse;
 use crate::plugins::rhai::engine::RhaiSupergraphResponse;
+use crate::query_planner::OperationKind;
 use crate::services::ExecutionRequest;
 use crate::services::SubgraphRequest;
 use crate::services::SupergraphRequest;
@@ -92,6 +94,83 @@ async fn rhai_plugin_supergraph_service() -> Result<(), BoxError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn rhai_plugin_supergraph_service_map_event() -> Result<(), BoxError> {
+    let mut mock_service = MockSupergraphService::new();
+    mock_service
+        .expect_call()
+        .times(1)
+        .returning(move |req: SupergraphRequest| {
+            req.context
+                .insert(OPERATION_KIND, OperationKind::Subscription)
+                .unwrap();
+            Ok(SupergraphResponse::fake_stream_builder()
+                .response(crate::graphql::Response::builder().subscribed(true).build())
+                .response(
+                    crate::graphql::Response::builder()
+                        .data(serde_json::json!({ "count": 1, "secret": "s" }))
+                        .build(),
+                )
+                .response(
+                    crate::graphql::Response::builder()
+                        .data(serde_json::json!({ "count": 2, "secret": "s" }))
+                        .build(),
+                )
+                .response(
+                    crate::graphql::Response::builder()
+                        .data(serde_json::json!({ "count": 3, "secret": "s" }))
+                        .build(),
+                )
+                .response(
+                    crate::graphql::Response::builder()
+                        .data(serde_json::json!({ "count": 4, "secret": "s" }))
+                        .build(),
+                )
+                .context(req.context)
+                .build()
+                .unwrap())
+        });
+
+    let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+        .find(|factory| factory.name == "apollo.rhai")
+        .expect("Plugin not found")
+        .create_instance_without_schema(
+            &Value::from_str(r#"{"scripts":"tests/fixtures", "main":"subscription_events.rhai"}"#)
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let mut router_service = dyn_plugin.supergraph_service(BoxService::new(mock_service));
+    let supergraph_req = SupergraphRequest::fake_builder().build()?;
+
+    let mut supergraph_resp = router_service.ready().await?.call(supergraph_req).await?;
+
+    // The initial response is not an event
+    let initial = supergraph_resp.next_response().await.unwrap();
+    assert_eq!(initial.subscribed, Some(true));
+
+    let event = supergraph_resp.next_response().await.unwrap();
+    assert_eq!(
+        serde_json::to_value(&event).unwrap(),
+        serde_json::json!({ "data": { "count": 1, "secret": null } })
+    );
+    assert_eq!(
+        supergraph_resp
+            .context
+            .get::<_, i64>("seen_events")
+            .unwrap()
+            .unwrap(),
+        1
+    );
+
+    // The second event is dropped and the third one closes the subscription
+    let last = supergraph_resp.next_response().await.unwrap();
+    assert_eq!(last.errors[0].message, "not allowed anymore");
+    assert_eq!(last.subscribed, Some(false));
+    assert!(supergraph_resp.next_response().await.is_none());
+    Ok(())
+}
+
 #[tokio::test]
 async fn rhai_plugin_execution_service_error() -> Result<(), BoxError> {
     let mut mock_service = MockExecutionService::new();
@@ -1,11 +1,16 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::num::NonZeroUsize;
 use std::ops::ControlFlow;
+use std::sync::Arc;
 use std::task::Poll;
 use std::time::Duration;
 
 use bytes::Buf;
 use futures::future::BoxFuture;
+use futures::stream;
+use futures::Future;
+use futures::StreamExt;
 use hmac::Hmac;
 use hmac::Mac;
 use http::HeaderName;
@@ -26,20 +31,27 @@ use tower::ServiceExt;
 use tracing_futures::Instrument;
 use uuid::Uuid;
 
+use crate::configuration::RedisCache;
 use crate::context::Context;
+use crate::context::OPERATION_KIND;
 use crate::graphql;
 use crate::graphql::Response;
 use crate::json_ext::Object;
 use crate::layers::ServiceBuilderExt;
 use crate::notification::Notify;
 use crate::notification::NotifyError;
+use crate::notification::RedisRelay;
+use crate::notification::RelayedEvent;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::plugins::telemetry::CLIENT_NAME;
 use crate::protocols::websocket::WebSocketProtocol;
 use crate::query_planner::OperationKind;
 use crate::register_plugin;
 use crate::services::router;
 use crate::services::subgraph;
+use crate::services::supergraph;
 use crate::Endpoint;
 use crate::ListenAddr;
 
@@ -50,6 +62,16 @@ pub(crate) const APOLLO_SUBSCRIPTION_PLUGIN_NAME: &str = "subscription";
 pub(crate) static SUBSCRIPTION_CALLBACK_HMAC_KEY: OnceCell<String> = OnceCell::new();
 pub(crate) const SUBSCRIPTION_WS_CUSTOM_CONNECTION_PARAMS: &str =
     "apollo.subscription.custom_connection_params";
+/// Connection init payload of the client WebSocket connection an operation was sent on
+pub(crate) const SUBSCRIPTION_CLIENT_WS_CONNECTION_PARAMS: &str =
+    "apollo.subscription.client_connection_params";
+/// Response extension carrying the ID of a subscription event, clients resume from it
+pub(crate) const SUBSCRIPTION_EVENT_ID_EXTENSION: &str = "subscriptionEventId";
+/// Request extension carrying the ID of the last event received by a client
+pub(crate) const SUBSCRIPTION_LAST_EVENT_ID_EXTENSION: &str = "lastEventId";
+pub(crate) const LAST_EVENT_ID_HEADER_NAME: &str = "last-event-id";
+/// Response extension of the events telling clients the router reconnects to a subgraph
+pub(crate) const SUBSCRIPTION_RECONNECTING_EXTENSION: &str = "reconnecting";
 const CALLBACK_SUBSCRIPTION_HEADER_NAME: &str = "subscription-protocol";
 const CALLBACK_SUBSCRIPTION_HEADER_VALUE: &str = "callback/1.0";
 
@@ -75,6 +97,28 @@ pub(crate) struct SubscriptionConfig {
     pub(crate) max_opened_subscriptions: Option<usize>,
     /// It represent the capacity of the in memory queue to know how many events we can keep in a buffer
     pub(crate) queue_capacity: Option<usize>,
+    /// Accept subscriptions from clients over WebSocket on the GraphQL endpoint
+    pub(crate) client_websocket: ClientWebSocketConfig,
+    /// Accept operations from clients in the single connection mode of GraphQL over Server-Sent Events
+    pub(crate) client_event_stream: ClientEventStreamConfig,
+    /// Share subscriptions between router instances through Redis, so that callbacks can be received by any instance
+    pub(crate) redis: Option<RedisCache>,
+    /// Keep the latest events of subscriptions, so that clients can resume them after a disconnection
+    pub(crate) replay: ReplayConfig,
+    /// Limit the number of subscriptions opened at the same time by each client. By default if it's not set there is no limit.
+    pub(crate) per_client: Option<ClientLimitConfig>,
+    /// Close subscriptions opened for longer than this duration. By default if it's not set subscriptions are never closed.
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>", default)]
+    pub(crate) max_lifetime: Option<Duration>,
+    /// Close subscriptions which didn't receive any event for this duration. By default if it's not set idle subscriptions are never closed.
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>", default)]
+    pub(crate) idle_timeout: Option<Duration>,
+    /// Endpoint listing the opened subscriptions, and closing them
+    pub(crate) admin: AdminConfig,
+    /// Closing of the opened subscriptions when the router is reloaded or shut down
+    pub(crate) drain: DrainConfig,
 }
 
 impl Default for SubscriptionConfig {
@@ -85,10 +129,230 @@ impl Default for SubscriptionConfig {
             enable_deduplication: true,
             max_opened_subscriptions: None,
             queue_capacity: None,
+            client_websocket: Default::default(),
+            client_event_stream: Default::default(),
+            redis: None,
+            replay: Default::default(),
+            per_client: None,
+            max_lifetime: None,
+            idle_timeout: None,
+            admin: Default::default(),
+            drain: Default::default(),
+        }
+    }
+}
+
+/// Closing of the opened subscriptions when the router is reloaded or shut down
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct DrainConfig {
+    /// After a schema or configuration reload, keep subscriptions running on the previous pipeline for this duration, then close them with a 'SUBSCRIPTION_RECONNECT' error. By default if it's not set subscriptions are closed right away on schema reloads, and switch to the new configuration on configuration reloads.
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>", default)]
+    pub(crate) reload_deadline: Option<Duration>,
+    /// When the router shuts down, close subscriptions with a 'SUBSCRIPTION_RECONNECT' error in batches spread over this duration. By default if it's not set all subscriptions are closed at once.
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "Option<String>", default)]
+    pub(crate) shutdown_grace_period: Option<Duration>,
+    /// Number of batches subscriptions are closed in during the shutdown grace period (default: 10)
+    pub(crate) shutdown_batches: NonZeroUsize,
+}
+
+impl Default for DrainConfig {
+    fn default() -> Self {
+        Self {
+            reload_deadline: None,
+            shutdown_grace_period: None,
+            shutdown_batches: NonZeroUsize::new(10).expect("10 is not zero"),
+        }
+    }
+}
+
+impl DrainConfig {
+    /// Delay before closing a subscription when the router shuts down, subscriptions are spread
+    /// over the batches of the grace period in the order they are drained
+    pub(crate) fn shutdown_delay(&self, drained_subscriptions: usize) -> Duration {
+        match self.shutdown_grace_period {
+            Some(grace_period) => {
+                let batches = self.shutdown_batches.get();
+                let batch = drained_subscriptions % batches;
+                let nanos = grace_period.as_nanos() * batch as u128 / batches as u128;
+                Duration::from_nanos(nanos.try_into().unwrap_or(u64::MAX))
+            }
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// Subscription admin endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct AdminConfig {
+    /// Expose the endpoint. It has no authentication, only expose it on a private network (default: false)
+    pub(crate) enabled: bool,
+    /// The socket address and port to listen on (default: 127.0.0.1:8088)
+    pub(crate) listen: ListenAddr,
+    /// Path of the endpoint (default: /subscriptions)
+    pub(crate) path: String,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: default_admin_listen_addr(),
+            path: default_admin_path(),
+        }
+    }
+}
+
+fn default_admin_listen_addr() -> ListenAddr {
+    ListenAddr::SocketAddr("127.0.0.1:8088".parse().expect("valid ListenAddr"))
+}
+
+fn default_admin_path() -> String {
+    String::from("/subscriptions")
+}
+
+/// Subscription limits applied to each client
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ClientLimitConfig {
+    /// How clients are identified (default: client_name)
+    #[serde(default)]
+    pub(crate) identified_by: ClientIdentifier,
+    /// Maximum number of subscriptions opened at the same time by a client
+    pub(crate) max_opened_subscriptions: usize,
+}
+
+/// How clients are identified for subscription limits. Subscriptions of clients which cannot be identified only count towards the global limit.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum ClientIdentifier {
+    /// The client name, read from the header configured in `telemetry.apollo.client_name_header`
+    #[default]
+    ClientName,
+    /// The value of a request header
+    Header(String),
+    /// The value of a claim of the JWT validated by the authentication plugin
+    Claim(String),
+}
+
+impl ClientIdentifier {
+    /// Identifies the client sending a request
+    pub(crate) fn client_id(&self, context: &Context, headers: &http::HeaderMap) -> Option<String> {
+        match self {
+            ClientIdentifier::ClientName => context.get::<_, String>(CLIENT_NAME).ok().flatten(),
+            ClientIdentifier::Header(name) => headers
+                .get(name.as_str())
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string()),
+            ClientIdentifier::Claim(name) => context
+                .get::<_, serde_json::Value>(APOLLO_AUTHENTICATION_JWT_CLAIMS)
+                .ok()
+                .flatten()
+                .and_then(|claims| match claims.get(name)? {
+                    serde_json::Value::String(value) => Some(value.clone()),
+                    serde_json::Value::Null => None,
+                    value => Some(value.to_string()),
+                }),
+        }
+    }
+}
+
+/// Replay buffers of subscription events
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ReplayConfig {
+    /// Keep the latest events of each subscription, and keep subscriptions opened after their clients disconnect (default: false)
+    pub(crate) enabled: bool,
+    /// Maximum number of events kept for each subscription (default: 100)
+    pub(crate) max_events: usize,
+    /// How long events are kept, and how long a subscription without clients stays opened (default: 30s)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String", default = "default_replay_max_age")]
+    pub(crate) max_age: Duration,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_events: 100,
+            max_age: default_replay_max_age(),
+        }
+    }
+}
+
+fn default_replay_max_age() -> Duration {
+    Duration::from_secs(30)
+}
+
+/// WebSocket transport for clients
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ClientWebSocketConfig {
+    /// Accept WebSocket upgrades on the GraphQL endpoint, with the 'graphql-transport-ws' or 'graphql-ws' subprotocol (default: false)
+    pub(crate) enabled: bool,
+    /// Interval of the pings (graphql-transport-ws) or keep alive messages (graphql-ws) sent to clients (default: 5secs)
+    pub(crate) heartbeat_interval: HeartbeatInterval,
+    /// How long to wait for the connection init message of a client before closing the connection (default: 3s)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String", default = "default_connection_init_timeout")]
+    pub(crate) connection_init_timeout: Duration,
+    /// Parameters of the connection init payload copied to the headers of the operations of the connection, when the upgrade request doesn't already have them (default: none). Clients can set these headers to any value, only list the ones they are expected to send, like authorization
+    pub(crate) connection_params_headers: Vec<String>,
+    /// Maximum number of operations running at the same time on a connection (default: 100)
+    pub(crate) max_operations_per_connection: usize,
+}
+
+impl Default for ClientWebSocketConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            heartbeat_interval: HeartbeatInterval::new_enabled(),
+            connection_init_timeout: default_connection_init_timeout(),
+            connection_params_headers: Vec::new(),
+            max_operations_per_connection: 100,
+        }
+    }
+}
+
+fn default_connection_init_timeout() -> Duration {
+    Duration::from_secs(3)
+}
+
+/// Single connection mode of GraphQL over Server-Sent Events
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ClientEventStreamConfig {
+    /// Accept event stream reservations with PUT requests on the GraphQL endpoint, and operations sent on reserved streams (default: false)
+    pub(crate) enabled: bool,
+    /// Maximum number of event streams reserved or opened at the same time (default: 1000)
+    pub(crate) max_streams: usize,
+    /// Maximum number of operations running at the same time on an event stream (default: 100)
+    pub(crate) max_operations_per_stream: usize,
+    /// How long a reserved event stream can wait to be opened before it is dropped (default: 30s)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String", default = "default_reservation_timeout")]
+    pub(crate) reservation_timeout: Duration,
+}
+
+impl Default for ClientEventStreamConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_streams: 1000,
+            max_operations_per_stream: 100,
+            reservation_timeout: default_reservation_timeout(),
         }
     }
 }
 
+fn default_reservation_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct SubscriptionModeConfig {
@@ -231,6 +495,66 @@ pub(crate) struct WebSocketConfiguration {
     /// Heartbeat interval for graphql-ws protocol (default: disabled)
     #[serde(default = "HeartbeatInterval::new_disabled")]
     pub(crate) heartbeat_interval: HeartbeatInterval,
+    /// Maximum number of subscriptions sent on the same WebSocket connection. Subscriptions share a connection when they have the same headers and connection parameters (default: 1, one connection per subscription)
+    #[serde(default = "default_max_operations_per_connection")]
+    pub(crate) max_operations_per_connection: NonZeroUsize,
+    /// Reconnect to the subgraph when its WebSocket connection is lost
+    #[serde(default)]
+    pub(crate) reconnect: WebSocketReconnectConfig,
+}
+
+fn default_max_operations_per_connection() -> NonZeroUsize {
+    NonZeroUsize::MIN
+}
+
+/// Reconnection to a subgraph after its WebSocket connection was lost
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct WebSocketReconnectConfig {
+    /// Open a new connection and subscribe again when the connection to the subgraph is lost, instead of ending the subscriptions (default: false)
+    pub(crate) enabled: bool,
+    /// Maximum number of consecutive reconnection attempts before ending the subscriptions (default: 5)
+    pub(crate) max_attempts: usize,
+    /// Delay before the first reconnection attempt, doubled after each failed attempt (default: 1s)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String", default = "default_reconnect_initial_delay")]
+    pub(crate) initial_delay: Duration,
+    /// Maximum delay between two reconnection attempts (default: 30s)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String", default = "default_reconnect_max_delay")]
+    pub(crate) max_delay: Duration,
+    /// Send clients an event with a 'reconnecting' extension before each reconnection attempt (default: false)
+    pub(crate) notify_clients: bool,
+}
+
+impl Default for WebSocketReconnectConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_attempts: 5,
+            initial_delay: default_reconnect_initial_delay(),
+            max_delay: default_reconnect_max_delay(),
+            notify_clients: false,
+        }
+    }
+}
+
+impl WebSocketReconnectConfig {
+    /// Delay before a reconnection attempt, starting at 1
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(u32::BITS as usize - 1) as u32;
+        self.initial_delay
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_delay)
+    }
+}
+
+fn default_reconnect_initial_delay() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_reconnect_max_delay() -> Duration {
+    Duration::from_secs(30)
 }
 
 fn default_path() -> String {
@@ -267,6 +591,28 @@ impl Plugin for Subscription {
                 .await?;
         }
 
+        // The relay lives as long as the process, it is only replaced if its configuration changed
+        let relay = match (&init.config.redis, &init.config.mode.callback) {
+            (Some(redis), Some(_)) => match init.notify.relay_with_config(redis) {
+                Some(relay) => Some(relay),
+                None => match RedisRelay::new(redis.clone(), init.notify.clone()).await {
+                    Ok(relay) => Some(relay),
+                    Err(e) => {
+                        tracing::error!(
+                            e,
+                            "could not open connection to Redis for sharing subscriptions",
+                        );
+                        if redis.required_to_start {
+                            return Err(e);
+                        }
+                        None
+                    }
+                },
+            },
+            _ => None,
+        };
+        init.notify.set_relay(relay);
+
         Ok(Subscription {
             notify: init.notify,
             callback_hmac_key,
@@ -310,10 +656,141 @@ impl Plugin for Subscription {
             map.insert(listen.clone().unwrap_or_else(default_listen_addr), endpoint);
         }
 
+        if self.config.admin.enabled {
+            let path = self.config.admin.path.trim_end_matches('/');
+            let service = AdminService::new(self.notify.clone(), path.to_string());
+            map.insert(
+                self.config.admin.listen.clone(),
+                Endpoint::from_router_service(path.to_string(), service.clone().boxed()),
+            );
+            map.insert(
+                self.config.admin.listen.clone(),
+                Endpoint::from_router_service(format!("{path}/:subscription"), service.boxed()),
+            );
+        }
+
         map
     }
 }
 
+/// Subscription listed by the admin endpoint
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct AdminSubscription {
+    /// Topic of the subscription, which can be used to close it
+    pub(crate) id: String,
+    pub(crate) subgraph: String,
+    /// Subscription mode used with the subgraph (callback or passthrough)
+    pub(crate) mode: String,
+    /// Number of client subscriptions receiving its events
+    pub(crate) subscribers: usize,
+    pub(crate) created_at: String,
+    pub(crate) age_secs: u64,
+    pub(crate) last_event_at: Option<String>,
+}
+
+/// Lists the opened subscriptions with `GET <path>`, and closes one with `DELETE <path>/<id>`
+#[derive(Clone)]
+pub(crate) struct AdminService {
+    notify: Notify<String, graphql::Response>,
+    path: String,
+}
+
+impl AdminService {
+    pub(crate) fn new(notify: Notify<String, graphql::Response>, path: String) -> Self {
+        Self { notify, path }
+    }
+}
+
+impl Service<router::Request> for AdminService {
+    type Response = router::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, req: router::Request) -> Self::Future {
+        let mut notify = self.notify.clone();
+        let path = self.path.clone();
+        Box::pin(async move {
+            let (parts, _body) = req.router_request.into_parts();
+            let sub_id = parts
+                .uri
+                .path()
+                .strip_prefix(&format!("{path}/"))
+                .filter(|sub_id| !sub_id.is_empty())
+                .map(|sub_id| sub_id.to_string());
+
+            let response = match (parts.method, sub_id) {
+                (Method::GET, None) => {
+                    let mut subscriptions: Vec<AdminSubscription> = notify
+                        .topics()
+                        .await?
+                        .into_iter()
+                        .map(|details| AdminSubscription {
+                            id: details.topic,
+                            subgraph: details.info.subgraph_name.clone(),
+                            mode: details.info.mode.to_string(),
+                            subscribers: details.subscribers,
+                            created_at: humantime::format_rfc3339_seconds(details.info.created_at)
+                                .to_string(),
+                            age_secs: details
+                                .info
+                                .created_at
+                                .elapsed()
+                                .unwrap_or_default()
+                                .as_secs(),
+                            last_event_at: details.info.last_event_at().map(|last_event_at| {
+                                humantime::format_rfc3339_seconds(last_event_at).to_string()
+                            }),
+                        })
+                        .collect();
+                    subscriptions
+                        .sort_by(|a, b| b.age_secs.cmp(&a.age_secs).then_with(|| a.id.cmp(&b.id)));
+
+                    http::Response::builder()
+                        .status(StatusCode::OK)
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .body(
+                            serde_json::to_vec(&serde_json::json!({
+                                "subscriptions": subscriptions
+                            }))?
+                            .into(),
+                        )?
+                }
+                (Method::DELETE, Some(sub_id)) => {
+                    let message = graphql::Response::builder()
+                        .error(
+                            graphql::Error::builder()
+                                .message("subscription closed by an administrator")
+                                .extension_code("SUBSCRIPTION_CLOSED_BY_ADMIN")
+                                .build(),
+                        )
+                        .build();
+                    if notify.close(sub_id, message).await? {
+                        http::Response::builder()
+                            .status(StatusCode::NO_CONTENT)
+                            .body("".into())?
+                    } else {
+                        http::Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body("subscription doesn't exist".into())?
+                    }
+                }
+                _ => http::Response::builder()
+                    .status(StatusCode::METHOD_NOT_ALLOWED)
+                    .body("".into())?,
+            };
+
+            Ok(router::Response {
+                response,
+                context: req.context,
+            })
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(tag = "kind", rename = "lowercase")]
 pub(crate) enum CallbackPayload {
@@ -497,20 +974,35 @@ impl Service<router::Request> for CallbackService {
                                 mut payload,
                                 ..
                             }) => {
-                                let mut handle = match notify.subscribe_if_exist(id).await? {
+                                // Keep the subscription to the client opened
+                                payload.subscribed = Some(true);
+                                let handle = notify.subscribe_if_exist(id.clone()).await?;
+                                let mut handle = match handle {
                                     Some(handle) => handle.into_sink(),
                                     None => {
+                                        // The subscription may be held by another router instance
+                                        let status = if notify
+                                            .forward(RelayedEvent::Next { id, payload })
+                                            .await?
+                                        {
+                                            StatusCode::OK
+                                        } else {
+                                            StatusCode::NOT_FOUND
+                                        };
+                                        let body = if status == StatusCode::OK {
+                                            ""
+                                        } else {
+                                            "suscription doesn't exist"
+                                        };
                                         return Ok(router::Response {
                                             response: http::Response::builder()
-                                                .status(StatusCode::NOT_FOUND)
-                                                .body("suscription doesn't exist".into())
+                                                .status(status)
+                                                .body(body.into())
                                                 .map_err(BoxError::from)?,
                                             context: req.context,
                                         });
                                     }
                                 };
-                                // Keep the subscription to the client opened
-                                payload.subscribed = Some(true);
                                 tracing::info!(
                                         monotonic_counter.apollo.router.operations.subscriptions.events = 1u64,
                                         subscriptions.mode="callback"
@@ -528,7 +1020,9 @@ impl Service<router::Request> for CallbackService {
                             CallbackPayload::Subscription(SubscriptionPayload::Check {
                                 ..
                             }) => {
-                                if notify.exist(id).await? {
+                                if notify.exist(id.clone()).await?
+                                    || !notify.remote_topics(vec![id]).await?.is_empty()
+                                {
                                     Ok(router::Response {
                                         response: http::Response::builder()
                                             .status(StatusCode::NO_CONTENT)
@@ -563,7 +1057,22 @@ impl Service<router::Request> for CallbackService {
                                     });
                                 }
 
-                                let (mut valid_ids, invalid_ids) = notify.invalid_ids(ids).await?;
+                                let (mut valid_ids, mut invalid_ids) =
+                                    notify.invalid_ids(ids).await?;
+                                // Heartbeat the subscriptions held by other router instances
+                                if !invalid_ids.is_empty() {
+                                    let remote_ids =
+                                        notify.remote_topics(invalid_ids.clone()).await?;
+                                    for remote_id in remote_ids {
+                                        notify
+                                            .forward(RelayedEvent::Heartbeat {
+                                                id: remote_id.clone(),
+                                            })
+                                            .await?;
+                                        invalid_ids.retain(|invalid_id| invalid_id != &remote_id);
+                                        valid_ids.push(remote_id);
+                                    }
+                                }
                                 if invalid_ids.is_empty() {
                                     Ok(router::Response {
                                         response: http::Response::builder()
@@ -612,6 +1121,23 @@ impl Service<router::Request> for CallbackService {
                                 errors,
                                 ..
                             }) => {
+                                // The subscription may be held by another router instance
+                                if !notify.exist(id.clone()).await?
+                                    && notify
+                                        .forward(RelayedEvent::Complete {
+                                            id: id.clone(),
+                                            errors: errors.clone(),
+                                        })
+                                        .await?
+                                {
+                                    return Ok(router::Response {
+                                        response: http::Response::builder()
+                                            .status(StatusCode::ACCEPTED)
+                                            .body::<hyper::Body>("".into())
+                                            .map_err(BoxError::from)?,
+                                        context: req.context,
+                                    });
+                                }
                                 if let Some(errors) = errors {
                                     let mut handle = match notify.subscribe(id.clone()).await {
                                          Ok(handle) => handle.into_sink(),
@@ -684,6 +1210,68 @@ impl Service<router::Request> for CallbackService {
     }
 }
 
+/// What a subscription event hook decided to do with an event
+pub(crate) enum SubscriptionEventAction {
+    /// Send the event, possibly modified, to the client
+    Continue(Response),
+    /// Do not send this event to the client
+    Drop,
+    /// Close the subscription, after sending a last event if there is one
+    End(Option<Response>),
+}
+
+/// Calls `hook` on each event of a subscription response stream
+///
+/// The first response of a subscription is the result of the subscription request itself, it is
+/// left as is. Responses to queries and mutations are returned unchanged.
+pub(crate) fn map_subscription_events<F, Fut>(
+    response: supergraph::Response,
+    hook: F,
+) -> supergraph::Response
+where
+    F: Fn(Context, Response) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = SubscriptionEventAction> + Send + 'static,
+{
+    let is_subscription = matches!(
+        response.context.get::<_, OperationKind>(OPERATION_KIND),
+        Ok(Some(OperationKind::Subscription))
+    );
+    if !is_subscription {
+        return response;
+    }
+
+    let context = response.context.clone();
+    let hook = Arc::new(hook);
+    response.map(move |events| {
+        stream::unfold((Some(events), true), move |(events, is_first)| {
+            let context = context.clone();
+            let hook = hook.clone();
+            async move {
+                let mut events = events?;
+                loop {
+                    let event = events.next().await?;
+                    if is_first {
+                        return Some((event, (Some(events), false)));
+                    }
+                    match hook(context.clone(), event).await {
+                        SubscriptionEventAction::Continue(event) => {
+                            return Some((event, (Some(events), false)));
+                        }
+                        SubscriptionEventAction::Drop => continue,
+                        // Dropping the events stream closes the subscription
+                        SubscriptionEventAction::End(Some(mut last_event)) => {
+                            last_event.subscribed = Some(false);
+                            return Some((last_event, (None, false)));
+                        }
+                        SubscriptionEventAction::End(None) => return None,
+                    }
+                }
+            }
+        })
+        .boxed()
+    })
+}
+
 pub(crate) fn create_verifier(sub_id: &str) -> Result<String, BoxError> {
     let callback_hmac_key = SUBSCRIPTION_CALLBACK_HMAC_KEY
         .get()
@@ -696,6 +1284,17 @@ pub(crate) fn create_verifier(sub_id: &str) -> Result<String, BoxError> {
     Ok(verifier)
 }
 
+/// Returns the ID of an event, sent to clients so that they can resume the subscription from it
+pub(crate) fn event_id(topic: &str, position: u64) -> String {
+    format!("{topic}:{position}")
+}
+
+/// Returns the topic and position of the event a client wants to resume a subscription from
+pub(crate) fn parse_event_id(event_id: &str) -> Option<(&str, u64)> {
+    let (topic, position) = event_id.rsplit_once(':')?;
+    Some((topic, position.parse().ok()?))
+}
+
 fn ensure_id_consistency(
     context: &Context,
     id_from_path: &str,
@@ -727,6 +1326,7 @@ mod tests {
     use super::*;
     use crate::graphql::Request;
     use crate::http_ext;
+    use crate::notification::TopicInfo;
     use crate::plugin::test::MockSubgraphService;
     use crate::plugin::DynPlugin;
     use crate::services::SubgraphRequest;
@@ -1170,6 +1770,64 @@ mod tests {
         assert_eq!(subgraph_response.response.body(), &graphql::Response::builder().data(serde_json_bytes::Value::Null).error(graphql::Error::builder().message("cannot execute a subscription if it's not enabled in the configuration").extension_code("SUBSCRIPTION_DISABLED").build()).extensions(Object::default()).build());
     }
 
+    #[tokio::test]
+    async fn it_test_admin_endpoint() {
+        let mut notify = Notify::builder().build();
+        let mut admin = AdminService::new(notify.clone(), "/subscriptions".to_string());
+        let sub_id = uuid::Uuid::new_v4().to_string();
+        let (handle, _created) = notify
+            .create_or_subscribe_with_info(
+                sub_id.clone(),
+                true,
+                TopicInfo::new("accounts".to_string(), "callback"),
+            )
+            .await
+            .unwrap();
+
+        let http_req = http::Request::get("http://localhost:8088/subscriptions")
+            .body(Default::default())
+            .unwrap();
+        let resp = admin.call(http_req.into()).await.unwrap();
+        assert_eq!(resp.response.status(), http::StatusCode::OK);
+        let body = hyper::body::to_bytes(resp.response.into_body())
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let subscriptions: Vec<AdminSubscription> =
+            serde_json::from_value(body["subscriptions"].clone()).unwrap();
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].id, sub_id);
+        assert_eq!(subscriptions[0].subgraph, "accounts");
+        assert_eq!(subscriptions[0].mode, "callback");
+        assert_eq!(subscriptions[0].subscribers, 1);
+        assert_eq!(subscriptions[0].last_event_at, None);
+
+        let http_req =
+            http::Request::delete(format!("http://localhost:8088/subscriptions/{sub_id}"))
+                .body(Default::default())
+                .unwrap();
+        let resp = admin.call(http_req.into()).await.unwrap();
+        assert_eq!(resp.response.status(), http::StatusCode::NO_CONTENT);
+
+        let mut stream = handle.into_stream();
+        let message = stream.next().await.unwrap();
+        assert_eq!(
+            message.errors[0]
+                .extensions
+                .get("code")
+                .and_then(|code| code.as_str()),
+            Some("SUBSCRIPTION_CLOSED_BY_ADMIN")
+        );
+        assert!(stream.next().await.is_none());
+
+        let http_req =
+            http::Request::delete(format!("http://localhost:8088/subscriptions/{sub_id}"))
+                .body(Default::default())
+                .unwrap();
+        let resp = admin.call(http_req.into()).await.unwrap();
+        assert_eq!(resp.response.status(), http::StatusCode::NOT_FOUND);
+    }
+
     #[test]
     fn it_test_subscription_config() {
         let config_with_callback: SubscriptionConfig = serde_json::from_value(serde_json::json!({
@@ -1404,6 +2062,112 @@ mod tests {
         assert!(sub_config.enable_deduplication);
         assert!(sub_config.max_opened_subscriptions.is_none());
         assert!(sub_config.queue_capacity.is_none());
+        assert!(!sub_config.client_websocket.enabled);
+        assert!(sub_config.per_client.is_none());
+        assert!(sub_config.max_lifetime.is_none());
+        assert!(sub_config.idle_timeout.is_none());
+
+        let config_with_limits: SubscriptionConfig = serde_json::from_value(serde_json::json!({
+            "per_client": {
+                "identified_by": { "header": "x-client-id" },
+                "max_opened_subscriptions": 2
+            },
+            "max_lifetime": "1h",
+            "idle_timeout": "5m"
+        }))
+        .unwrap();
+
+        assert_eq!(
+            config_with_limits.per_client,
+            Some(ClientLimitConfig {
+                identified_by: ClientIdentifier::Header("x-client-id".to_string()),
+                max_opened_subscriptions: 2,
+            })
+        );
+        assert_eq!(
+            config_with_limits.max_lifetime,
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            config_with_limits.idle_timeout,
+            Some(Duration::from_secs(300))
+        );
+    }
+
+    #[test]
+    fn it_computes_reconnection_delays() {
+        let reconnect: WebSocketReconnectConfig = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "initial_delay": "500ms",
+            "max_delay": "3s"
+        }))
+        .unwrap();
+
+        assert_eq!(reconnect.max_attempts, 5);
+        assert_eq!(reconnect.delay(1), Duration::from_millis(500));
+        assert_eq!(reconnect.delay(2), Duration::from_secs(1));
+        assert_eq!(reconnect.delay(3), Duration::from_secs(2));
+        assert_eq!(reconnect.delay(4), Duration::from_secs(3));
+        assert_eq!(reconnect.delay(100), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn it_spreads_subscriptions_over_shutdown_batches() {
+        let drain: DrainConfig = serde_json::from_value(serde_json::json!({
+            "shutdown_grace_period": "10s",
+            "shutdown_batches": 5
+        }))
+        .unwrap();
+
+        assert_eq!(drain.shutdown_delay(0), Duration::ZERO);
+        assert_eq!(drain.shutdown_delay(1), Duration::from_secs(2));
+        assert_eq!(drain.shutdown_delay(4), Duration::from_secs(8));
+        assert_eq!(drain.shutdown_delay(5), Duration::ZERO);
+        assert_eq!(drain.shutdown_delay(7), Duration::from_secs(4));
+
+        let drain = DrainConfig::default();
+        assert!(drain.reload_deadline.is_none());
+        assert_eq!(drain.shutdown_delay(3), Duration::ZERO);
+    }
+
+    #[test]
+    fn it_identifies_clients() {
+        let context = Context::new();
+        let mut headers = http::HeaderMap::new();
+        assert_eq!(
+            ClientIdentifier::ClientName.client_id(&context, &headers),
+            None
+        );
+
+        context.insert(CLIENT_NAME, "web".to_string()).unwrap();
+        context
+            .insert(
+                APOLLO_AUTHENTICATION_JWT_CLAIMS,
+                serde_json::json!({ "sub": "alice", "org": 42 }),
+            )
+            .unwrap();
+        headers.insert("x-client-id", HeaderValue::from_static("mobile"));
+
+        assert_eq!(
+            ClientIdentifier::ClientName.client_id(&context, &headers),
+            Some("web".to_string())
+        );
+        assert_eq!(
+            ClientIdentifier::Header("x-client-id".to_string()).client_id(&context, &headers),
+            Some("mobile".to_string())
+        );
+        assert_eq!(
+            ClientIdentifier::Claim("sub".to_string()).client_id(&context, &headers),
+            Some("alice".to_string())
+        );
+        assert_eq!(
+            ClientIdentifier::Claim("org".to_string()).client_id(&context, &headers),
+            Some("42".to_string())
+        );
+        assert_eq!(
+            ClientIdentifier::Claim("email".to_string()).client_id(&context, &headers),
+            None
+        );
     }
 }
 
@@ -4,6 +4,7 @@ use serde::Deserialize;
 
 use crate::plugins::telemetry::config::AttributeValue;
 use crate::plugins::telemetry::config_new::Selector;
+use crate::plugins::telemetry::config_new::Stage;
 
 #[allow(dead_code)]
 #[derive(Deserialize, JsonSchema, Clone, Debug)]
@@ -140,6 +141,19 @@ where
             Condition::False => false,
         }
     }
+
+    /// Whether all the selectors of the condition can be evaluated at this stage
+    pub(crate) fn is_active(&self, stage: Stage) -> bool {
+        match self {
+            Condition::Eq(eq) => eq.iter().all(|selector| selector.is_active(stage)),
+            Condition::Exists(exist) => exist.is_active(stage),
+            Condition::All(conditions) | Condition::Any(conditions) => conditions
+                .iter()
+                .all(|condition| condition.is_active(stage)),
+            Condition::Not(not) => not.is_active(stage),
+            Condition::True | Condition::False => true,
+        }
+    }
 }
 
 impl<T> Selector for SelectorOrValue<T>
@@ -162,6 +176,13 @@ where
             SelectorOrValue::Selector(selector) => selector.on_response(response),
         }
     }
+
+    fn is_active(&self, stage: Stage) -> bool {
+        match self {
+            SelectorOrValue::Value(_) => true,
+            SelectorOrValue::Selector(selector) => selector.is_active(stage),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -171,6 +192,7 @@ mod test {
     use crate::plugins::telemetry::config_new::conditions::Condition;
     use crate::plugins::telemetry::config_new::conditions::SelectorOrValue;
     use crate::plugins::telemetry::config_new::Selector;
+    use crate::plugins::telemetry::config_new::Stage;
 
     struct TestSelector;
     impl Selector for TestSelector {
@@ -184,6 +206,10 @@ mod test {
         fn on_response(&self, response: &Self::Response) -> Option<Value> {
             response.map(Value::I64)
         }
+
+        fn is_active(&self, _stage: Stage) -> bool {
+            true
+        }
     }
 
     enum TestSelectorReqRes {
@@ -208,6 +234,13 @@ mod test {
                 TestSelectorReqRes::Resp => response.map(Value::I64),
             }
         }
+
+        fn is_active(&self, stage: Stage) -> bool {
+            match self {
+                TestSelectorReqRes::Req => stage == Stage::Request,
+                TestSelectorReqRes::Resp => stage == Stage::Response,
+            }
+        }
     }
 
     #[test]
@@ -232,6 +265,24 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_condition_is_active() {
+        let condition = Condition::<TestSelectorReqRes>::All(vec![
+            Condition::Exists(TestSelectorReqRes::Req),
+            Condition::Not(Box::new(Condition::Eq([
+                SelectorOrValue::Selector(TestSelectorReqRes::Resp),
+                SelectorOrValue::Value(1i64.into()),
+            ]))),
+        ]);
+        assert!(!condition.is_active(Stage::Request));
+        assert!(!condition.is_active(Stage::Response));
+        assert!(
+            Condition::<TestSelectorReqRes>::Exists(TestSelectorReqRes::Req)
+                .is_active(Stage::Request)
+        );
+        assert!(Condition::<TestSelectorReqRes>::True.is_active(Stage::Request));
+    }
+
     #[test]
     fn test_condition_eq() {
         assert_eq!(
@@ -31,12 +31,22 @@ pub(crate) trait Selectors {
     fn on_error(&self, error: &BoxError) -> Vec<KeyValue>;
 }
 
+/// When a selector is evaluated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stage {
+    Request,
+    Response,
+}
+
 pub(crate) trait Selector {
     type Request;
     type Response;
 
     fn on_request(&self, request: &Self::Request) -> Option<opentelemetry::Value>;
     fn on_response(&self, response: &Self::Response) -> Option<opentelemetry::Value>;
+
+    /// Whether the selector can select a value at this stage
+    fn is_active(&self, stage: Stage) -> bool;
 }
 
 pub(crate) trait DefaultForLevel {
@@ -13,11 +13,13 @@ use crate::context::OPERATION_KIND;
 use crate::context::OPERATION_NAME;
 use crate::plugin::serde::deserialize_json_query;
 use crate::plugin::serde::deserialize_jsonpath;
+use crate::plugins::demand_control::CostContext;
 use crate::plugins::telemetry::config::AttributeValue;
 use crate::plugins::telemetry::config_new::get_baggage;
 use crate::plugins::telemetry::config_new::trace_id;
 use crate::plugins::telemetry::config_new::DatadogId;
 use crate::plugins::telemetry::config_new::Selector;
+use crate::plugins::telemetry::config_new::Stage;
 use crate::plugins::telemetry::config_new::ToOtelValue;
 use crate::services::router;
 use crate::services::subgraph;
@@ -68,6 +70,20 @@ pub(crate) enum OperationKind {
     String,
 }
 
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[cfg_attr(test, derive(Serialize))]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum CostValue {
+    /// The estimated cost of the operation, computed from the query plan.
+    Estimated,
+    /// The actual cost of the response delivered so far.
+    Actual,
+    /// The estimated cost minus the actual cost.
+    Delta,
+    /// The result of the cost checks: `COST_OK` or the code of the demand control error.
+    Result,
+}
+
 #[derive(Deserialize, JsonSchema, Clone, Debug)]
 #[serde(deny_unknown_fields, untagged)]
 pub(crate) enum RouterSelector {
@@ -141,6 +157,11 @@ pub(crate) enum RouterSelector {
         /// Optional default value.
         default: Option<String>,
     },
+    /// The cost of the operation, measured by demand control.
+    Cost {
+        /// The cost value to select.
+        cost: CostValue,
+    },
     Static(String),
 }
 
@@ -258,6 +279,10 @@ pub(crate) enum SupergraphSelector {
 #[serde(deny_unknown_fields, rename_all = "snake_case", untagged)]
 #[derivative(Debug)]
 pub(crate) enum SubgraphSelector {
+    SubgraphName {
+        /// The subgraph name
+        subgraph_name: bool,
+    },
     SubgraphOperationName {
         /// The operation name from the subgraph query.
         subgraph_operation_name: OperationName,
@@ -528,9 +553,42 @@ impl Selector for RouterSelector {
             RouterSelector::Baggage {
                 baggage, default, ..
             } => get_baggage(baggage).or_else(|| default.maybe_to_otel_value()),
+            RouterSelector::Cost { cost } => response
+                .context
+                .extensions()
+                .lock()
+                .get::<CostContext>()
+                .map(|cost_context| match cost {
+                    CostValue::Estimated => cost_context.estimated.into(),
+                    CostValue::Actual => cost_context.actual.into(),
+                    CostValue::Delta => cost_context.delta().into(),
+                    CostValue::Result => cost_context.result.into(),
+                }),
             _ => None,
         }
     }
+
+    fn is_active(&self, stage: Stage) -> bool {
+        match stage {
+            Stage::Request => matches!(
+                self,
+                RouterSelector::RequestMethod { .. }
+                    | RouterSelector::RequestHeader { .. }
+                    | RouterSelector::Env { .. }
+                    | RouterSelector::TraceId { .. }
+                    | RouterSelector::Baggage { .. }
+                    | RouterSelector::Static(_)
+            ),
+            Stage::Response => matches!(
+                self,
+                RouterSelector::ResponseHeader { .. }
+                    | RouterSelector::ResponseStatus { .. }
+                    | RouterSelector::ResponseContext { .. }
+                    | RouterSelector::Baggage { .. }
+                    | RouterSelector::Cost { .. }
+            ),
+        }
+    }
 }
 
 impl Selector for SupergraphSelector {
@@ -657,6 +715,29 @@ impl Selector for SupergraphSelector {
             _ => None,
         }
     }
+
+    fn is_active(&self, stage: Stage) -> bool {
+        match stage {
+            Stage::Request => matches!(
+                self,
+                SupergraphSelector::OperationName { .. }
+                    | SupergraphSelector::OperationKind { .. }
+                    | SupergraphSelector::Query { .. }
+                    | SupergraphSelector::RequestHeader { .. }
+                    | SupergraphSelector::QueryVariable { .. }
+                    | SupergraphSelector::RequestContext { .. }
+                    | SupergraphSelector::Baggage { .. }
+                    | SupergraphSelector::Env { .. }
+                    | SupergraphSelector::Static(_)
+            ),
+            Stage::Response => matches!(
+                self,
+                SupergraphSelector::ResponseHeader { .. }
+                    | SupergraphSelector::ResponseStatus { .. }
+                    | SupergraphSelector::ResponseContext { .. }
+            ),
+        }
+    }
 }
 
 impl Selector for SubgraphSelector {
@@ -665,6 +746,10 @@ impl Selector for SubgraphSelector {
 
     fn on_request(&self, request: &subgraph::Request) -> Option<opentelemetry::Value> {
         match self {
+            SubgraphSelector::SubgraphName { subgraph_name } if *subgraph_name => request
+                .subgraph_name
+                .clone()
+                .map(opentelemetry::Value::from),
             SubgraphSelector::SubgraphOperationName {
                 subgraph_operation_name,
                 default,
@@ -895,6 +980,38 @@ impl Selector for SubgraphSelector {
             _ => None,
         }
     }
+
+    fn is_active(&self, stage: Stage) -> bool {
+        match stage {
+            Stage::Request => matches!(
+                self,
+                SubgraphSelector::SubgraphName { .. }
+                    | SubgraphSelector::SubgraphOperationName { .. }
+                    | SubgraphSelector::SupergraphOperationName { .. }
+                    | SubgraphSelector::SubgraphOperationKind { .. }
+                    | SubgraphSelector::SupergraphOperationKind { .. }
+                    | SubgraphSelector::SupergraphQuery { .. }
+                    | SubgraphSelector::SubgraphQuery { .. }
+                    | SubgraphSelector::SubgraphQueryVariable { .. }
+                    | SubgraphSelector::SupergraphQueryVariable { .. }
+                    | SubgraphSelector::SubgraphRequestHeader { .. }
+                    | SubgraphSelector::SupergraphRequestHeader { .. }
+                    | SubgraphSelector::RequestContext { .. }
+                    | SubgraphSelector::Baggage { .. }
+                    | SubgraphSelector::Env { .. }
+                    | SubgraphSelector::Static(_)
+            ),
+            Stage::Response => matches!(
+                self,
+                SubgraphSelector::SubgraphResponseHeader { .. }
+                    | SubgraphSelector::SubgraphResponseStatus { .. }
+                    | SubgraphSelector::SubgraphResponseBody { .. }
+                    | SubgraphSelector::SubgraphResponseData { .. }
+                    | SubgraphSelector::SubgraphResponseErrors { .. }
+                    | SubgraphSelector::ResponseContext { .. }
+            ),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -922,7 +1039,9 @@ mod test {
     use crate::context::OPERATION_KIND;
     use crate::context::OPERATION_NAME;
     use crate::graphql;
+    use crate::plugins::demand_control::CostContext;
     use crate::plugins::telemetry::config::AttributeValue;
+    use crate::plugins::telemetry::config_new::selectors::CostValue;
     use crate::plugins::telemetry::config_new::selectors::OperationKind;
     use crate::plugins::telemetry::config_new::selectors::OperationName;
     use crate::plugins::telemetry::config_new::selectors::Query;
@@ -1147,6 +1266,38 @@ mod test {
         );
     }
 
+    #[test]
+    fn subgraph_name() {
+        let selector = SubgraphSelector::SubgraphName {
+            subgraph_name: true,
+        };
+        assert_eq!(
+            selector
+                .on_request(
+                    &crate::services::SubgraphRequest::fake_builder()
+                        .subgraph_name("accounts".to_string())
+                        .build()
+                )
+                .unwrap(),
+            "accounts".into()
+        );
+        assert_eq!(
+            selector.on_request(&crate::services::SubgraphRequest::fake_builder().build()),
+            None
+        );
+        assert_eq!(
+            SubgraphSelector::SubgraphName {
+                subgraph_name: false,
+            }
+            .on_request(
+                &crate::services::SubgraphRequest::fake_builder()
+                    .subgraph_name("accounts".to_string())
+                    .build()
+            ),
+            None
+        );
+    }
+
     #[test]
     fn subgraph_supergraph_request_header() {
         let selector = SubgraphSelector::SupergraphRequestHeader {
@@ -1274,6 +1425,47 @@ mod test {
         );
     }
 
+    #[test]
+    fn router_cost() {
+        let context = crate::context::Context::new();
+        context.extensions().lock().insert(CostContext {
+            estimated: 100.0,
+            actual: 40.0,
+            result: "COST_OK",
+            max: Some(1000.0),
+        });
+        let response = crate::services::RouterResponse::fake_builder()
+            .context(context)
+            .build()
+            .unwrap();
+
+        let selector = RouterSelector::Cost {
+            cost: CostValue::Estimated,
+        };
+        assert_eq!(selector.on_response(&response), Some(100.0.into()));
+        let selector = RouterSelector::Cost {
+            cost: CostValue::Actual,
+        };
+        assert_eq!(selector.on_response(&response), Some(40.0.into()));
+        let selector = RouterSelector::Cost {
+            cost: CostValue::Delta,
+        };
+        assert_eq!(selector.on_response(&response), Some(60.0.into()));
+        let selector = RouterSelector::Cost {
+            cost: CostValue::Result,
+        };
+        assert_eq!(selector.on_response(&response), Some("COST_OK".into()));
+
+        assert_eq!(
+            selector.on_response(
+                &crate::services::RouterResponse::fake_builder()
+                    .build()
+                    .unwrap()
+            ),
+            None
+        );
+    }
+
     #[test]
     fn router_response_context() {
         let selector = RouterSelector::ResponseContext {
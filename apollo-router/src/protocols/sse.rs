@@ -0,0 +1,256 @@
+//! Event stream responses following the GraphQL over Server-Sent Events protocol.
+//!
+//! See <https://github.com/enisdenjo/graphql-sse/blob/master/PROTOCOL.md>
+
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::stream::select;
+use futures::stream::StreamExt;
+use futures::Stream;
+use serde::Serialize;
+use serde_json_bytes::Value;
+use tokio_stream::once;
+use tokio_stream::wrappers::IntervalStream;
+
+use crate::graphql;
+use crate::plugins::subscription::SUBSCRIPTION_EVENT_ID_EXTENSION;
+
+#[cfg(test)]
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(10);
+#[cfg(not(test))]
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A comment line, ignored by clients but keeping proxies from closing idle connections
+pub(crate) const HEARTBEAT_EVENT: &[u8] = b":\n\n";
+const COMPLETE_EVENT: &[u8] = b"event: complete\ndata:\n\n";
+
+/// Serializes a `next` event carrying a result, with the ID clients resume a subscription from
+pub(crate) fn next_event<T: Serialize>(
+    data: &T,
+    id: Option<&str>,
+) -> Result<Bytes, serde_json::Error> {
+    let mut buf = Vec::from(&b"event: next\n"[..]);
+    // Line breaks would end the field
+    if let Some(id) = id.filter(|id| !id.contains(['\n', '\r'])) {
+        buf.extend_from_slice(b"id: ");
+        buf.extend_from_slice(id.as_bytes());
+        buf.push(b'\n');
+    }
+    buf.extend_from_slice(b"data: ");
+    serde_json::to_writer(&mut buf, data)?;
+    buf.extend_from_slice(b"\n\n");
+    Ok(buf.into())
+}
+
+/// Serializes a `complete` event, with data identifying the operation in single connection mode
+pub(crate) fn complete_event<T: Serialize>(data: Option<&T>) -> Result<Bytes, serde_json::Error> {
+    match data {
+        Some(data) => {
+            let mut buf = Vec::from(&b"event: complete\ndata: "[..]);
+            serde_json::to_writer(&mut buf, data)?;
+            buf.extend_from_slice(b"\n\n");
+            Ok(buf.into())
+        }
+        None => Ok(Bytes::from_static(COMPLETE_EVENT)),
+    }
+}
+
+/// Returns the ID of a subscription event, browsers send it back in the `Last-Event-ID` header
+/// when reconnecting
+pub(crate) fn event_id(response: &graphql::Response) -> Option<&str> {
+    response
+        .extensions
+        .get(SUBSCRIPTION_EVENT_ID_EXTENSION)
+        .and_then(|id| id.as_str())
+}
+
+enum MessageKind {
+    Heartbeat,
+    Message(graphql::Response),
+    Eof,
+}
+
+/// Response body of an operation in distinct connections mode: every response is sent in a
+/// `next` event, and a `complete` event ends the stream
+pub(crate) struct EventStream {
+    stream: Pin<Box<dyn Stream<Item = MessageKind> + Send>>,
+    is_terminated: bool,
+    is_completed: bool,
+}
+
+impl EventStream {
+    pub(crate) fn new<S>(stream: S) -> Self
+    where
+        S: Stream<Item = graphql::Response> + Send + 'static,
+    {
+        let stream = select(
+            stream
+                .map(MessageKind::Message)
+                .chain(once(MessageKind::Eof)),
+            IntervalStream::new(tokio::time::interval_at(
+                tokio::time::Instant::now() + HEARTBEAT_INTERVAL,
+                HEARTBEAT_INTERVAL,
+            ))
+            .map(|_| MessageKind::Heartbeat),
+        )
+        .boxed();
+
+        Self {
+            stream,
+            is_terminated: false,
+            is_completed: false,
+        }
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Result<Bytes, serde_json::Error>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        if self.is_terminated {
+            return Poll::Ready(None);
+        }
+        if self.is_completed {
+            self.is_terminated = true;
+            return Poll::Ready(Some(complete_event::<()>(None)));
+        }
+        match self.stream.as_mut().poll_next(cx) {
+            Poll::Ready(message) => match message {
+                Some(MessageKind::Heartbeat) => {
+                    Poll::Ready(Some(Ok(Bytes::from_static(HEARTBEAT_EVENT))))
+                }
+                Some(MessageKind::Message(response)) => {
+                    let is_still_open =
+                        response.has_next.unwrap_or(false) || response.subscribed.unwrap_or(false);
+                    if !is_still_open {
+                        self.is_completed = true;
+                        // Gracefully closed at the server side
+                        if response.has_next.is_none()
+                            && matches!(response.data, None | Some(Value::Null))
+                            && response.errors.is_empty()
+                            && response.extensions.is_empty()
+                            && response.incremental.is_empty()
+                        {
+                            self.is_terminated = true;
+                            return Poll::Ready(Some(complete_event::<()>(None)));
+                        }
+                    }
+
+                    Poll::Ready(Some(next_event(&response, event_id(&response))))
+                }
+                Some(MessageKind::Eof) | None => {
+                    self.is_terminated = true;
+                    Poll::Ready(Some(complete_event::<()>(None)))
+                }
+            },
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+    use serde_json_bytes::ByteString;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscription_events() {
+        let responses = vec![
+            graphql::Response::builder()
+                .data(serde_json_bytes::Value::String(ByteString::from(
+                    String::from("foo"),
+                )))
+                .subscribed(true)
+                .build(),
+            graphql::Response::builder()
+                .data(serde_json_bytes::Value::String(ByteString::from(
+                    String::from("bar"),
+                )))
+                .subscribed(true)
+                .build(),
+            graphql::Response::builder().build(),
+        ];
+
+        let events: Vec<String> = EventStream::new(stream::iter(responses))
+            .map(|event| String::from_utf8(event.unwrap().to_vec()).unwrap())
+            .filter(|event| futures::future::ready(event != ":\n\n"))
+            .collect()
+            .await;
+        assert_eq!(
+            events,
+            vec![
+                "event: next\ndata: {\"data\":\"foo\"}\n\n",
+                "event: next\ndata: {\"data\":\"bar\"}\n\n",
+                "event: complete\ndata:\n\n",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_defer_events() {
+        let responses = vec![
+            graphql::Response::builder()
+                .data(serde_json_bytes::Value::String(ByteString::from(
+                    String::from("foo"),
+                )))
+                .has_next(true)
+                .build(),
+            graphql::Response::builder().has_next(false).build(),
+        ];
+
+        let events: Vec<String> = EventStream::new(stream::iter(responses))
+            .map(|event| String::from_utf8(event.unwrap().to_vec()).unwrap())
+            .filter(|event| futures::future::ready(event != ":\n\n"))
+            .collect()
+            .await;
+        assert_eq!(
+            events,
+            vec![
+                "event: next\ndata: {\"data\":\"foo\",\"hasNext\":true}\n\n",
+                "event: next\ndata: {\"hasNext\":false}\n\n",
+                "event: complete\ndata:\n\n",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_event_ids() {
+        let mut response = graphql::Response::builder()
+            .data(serde_json_bytes::json!({"foo": 1}))
+            .subscribed(true)
+            .build();
+        response
+            .extensions
+            .insert(SUBSCRIPTION_EVENT_ID_EXTENSION, "topic:1".into());
+
+        let events: Vec<String> = EventStream::new(stream::iter(vec![response]))
+            .map(|event| String::from_utf8(event.unwrap().to_vec()).unwrap())
+            .filter(|event| futures::future::ready(event != ":\n\n"))
+            .collect()
+            .await;
+        assert_eq!(
+            events,
+            vec![
+                "event: next\nid: topic:1\ndata: {\"data\":{\"foo\":1},\"extensions\":{\"subscriptionEventId\":\"topic:1\"}}\n\n",
+                "event: complete\ndata:\n\n",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat() {
+        let mut events = EventStream::new(stream::pending());
+        assert_eq!(
+            events.next().await.unwrap().unwrap(),
+            Bytes::from_static(HEARTBEAT_EVENT)
+        );
+    }
+}
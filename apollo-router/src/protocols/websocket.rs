@@ -1,4 +1,9 @@
+use std::collections::HashMap;
 use std::pin::Pin;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::task::Poll;
 use std::time::Duration;
 
@@ -17,6 +22,7 @@ use serde::Serialize;
 use serde_json_bytes::Value;
 use tokio::io::AsyncRead;
 use tokio::io::AsyncWrite;
+use tokio::sync::mpsc;
 use tokio_stream::wrappers::IntervalStream;
 use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
 use tokio_tungstenite::tungstenite::protocol::CloseFrame;
@@ -26,6 +32,10 @@ use tokio_tungstenite::WebSocketStream;
 use crate::graphql;
 
 const CONNECTION_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Number of events buffered for each subscription of a multiplexed connection
+const MULTIPLEXED_SUBSCRIPTION_BUFFER_SIZE: usize = 128;
+/// Operation count of a multiplexed connection being closed, no operation can be added to it
+const CLOSING: usize = usize::MAX;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, JsonSchema, Copy)]
 #[serde(rename_all = "snake_case")]
@@ -53,6 +63,29 @@ impl From<WebSocketProtocol> for HeaderValue {
 }
 
 impl WebSocketProtocol {
+    /// Returns the protocol matching a `Sec-WebSocket-Protocol` value sent by a client
+    pub(crate) fn from_subprotocol(subprotocol: &str) -> Option<Self> {
+        match subprotocol {
+            "graphql-transport-ws" => Some(WebSocketProtocol::GraphqlWs),
+            "graphql-ws" => Some(WebSocketProtocol::SubscriptionsTransportWs),
+            _ => None,
+        }
+    }
+
+    /// Serializes a message sent by the router to a client of this protocol
+    pub(crate) fn server_message(&self, message: &ServerMessage) -> serde_json::Result<String> {
+        let mut value = serde_json::to_value(message)?;
+        if let WebSocketProtocol::SubscriptionsTransportWs = self {
+            // The old protocol uses different names for these messages
+            match message {
+                ServerMessage::Next { .. } => value["type"] = "data".into(),
+                ServerMessage::KeepAlive => value["type"] = "ka".into(),
+                _ => {}
+            }
+        }
+        serde_json::to_string(&value)
+    }
+
     fn subscribe(&self, id: String, payload: graphql::Request) -> ClientMessage {
         match self {
             // old
@@ -202,7 +235,7 @@ impl ServerMessage {
         }
     }
 
-    fn id(&self) -> Option<String> {
+    pub(crate) fn id(&self) -> Option<String> {
         match self {
             ServerMessage::ConnectionAck
             | ServerMessage::KeepAlive
@@ -320,6 +353,29 @@ where
                     .build()
             })
     }
+
+    /// Turns this connection into one shared by several subscriptions
+    ///
+    /// A slot is reserved for the first subscription, to send with
+    /// [`MultiplexedWebSocket::subscribe`].
+    pub(crate) fn into_multiplexed(
+        self,
+        heartbeat_interval: Option<tokio::time::Duration>,
+    ) -> MultiplexedWebSocket {
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let operations = Arc::new(AtomicUsize::new(1));
+        tokio::task::spawn(drive_multiplexed_connection(
+            self,
+            commands_rx,
+            operations.clone(),
+            heartbeat_interval,
+        ));
+
+        MultiplexedWebSocket {
+            commands,
+            operations,
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -366,6 +422,7 @@ where
                             graphql::Error::builder()
                                 .message(format!("websocket connection has been closed with error code '{code}' and reason '{reason}'"))
                                 .extension_code("WEBSOCKET_CLOSE_ERROR")
+                                .extension("closeCode", u16::from(code))
                                 .build(),
                         ),
                     })
@@ -388,6 +445,34 @@ where
         })
 }
 
+/// Whether this response reports that the connection to the subgraph was lost, rather than the
+/// subgraph ending the subscription
+pub(crate) fn is_connection_lost(response: &graphql::Response) -> bool {
+    response.errors.iter().any(|error| {
+        match error.extensions.get("code").and_then(|code| code.as_str()) {
+            Some("WEBSOCKET_MESSAGE_ERROR" | "WEBSOCKET_CONNECTION_ERROR") => true,
+            // Only the subgraph going away or failing, other codes are sent on purpose
+            Some("WEBSOCKET_CLOSE_ERROR") => error
+                .extensions
+                .get("closeCode")
+                .and_then(|code| code.as_u64())
+                .and_then(|code| u16::try_from(code).ok())
+                .map(|code| {
+                    matches!(
+                        CloseCode::from(code),
+                        CloseCode::Away
+                            | CloseCode::Abnormal
+                            | CloseCode::Error
+                            | CloseCode::Restart
+                            | CloseCode::Again
+                    )
+                })
+                .unwrap_or(false),
+            _ => false,
+        }
+    })
+}
+
 pub(crate) struct SubscriptionStream<S> {
     inner_stream: SplitStream<InnerStream<S>>,
     close_signal: Option<tokio::sync::oneshot::Sender<()>>,
@@ -477,6 +562,309 @@ where
     }
 }
 
+enum MultiplexCommand {
+    Subscribe {
+        id: String,
+        request: graphql::Request,
+        events: mpsc::Sender<graphql::Response>,
+    },
+    Complete {
+        id: String,
+    },
+}
+
+/// A GraphQL WebSocket connection carrying several subscriptions, told apart by their ID
+#[derive(Clone)]
+pub(crate) struct MultiplexedWebSocket {
+    commands: mpsc::UnboundedSender<MultiplexCommand>,
+    operations: Arc<AtomicUsize>,
+}
+
+impl MultiplexedWebSocket {
+    /// Whether the connection was closed by the subgraph, or because its last operation ended
+    pub(crate) fn is_closed(&self) -> bool {
+        self.commands.is_closed() || self.operations.load(Ordering::Acquire) == CLOSING
+    }
+
+    /// Reserves a slot for a new subscription if the connection carries fewer than
+    /// `max_operations`
+    pub(crate) fn try_reserve(&self, max_operations: usize) -> bool {
+        self.operations
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |operations| {
+                (operations < max_operations).then_some(operations + 1)
+            })
+            .is_ok()
+    }
+
+    /// Sends a subscription on a slot reserved with [`Self::try_reserve`]
+    pub(crate) fn subscribe(
+        &self,
+        id: String,
+        request: graphql::Request,
+    ) -> Result<MultiplexedSubscription, graphql::Error> {
+        let (events_tx, events) = mpsc::channel(MULTIPLEXED_SUBSCRIPTION_BUFFER_SIZE);
+        self.commands
+            .send(MultiplexCommand::Subscribe {
+                id: id.clone(),
+                request,
+                events: events_tx,
+            })
+            .map_err(|_err| {
+                graphql::Error::builder()
+                    .message("cannot send to websocket connection")
+                    .extension_code("WEBSOCKET_CONNECTION_ERROR")
+                    .build()
+            })?;
+
+        Ok(MultiplexedSubscription {
+            id,
+            events,
+            commands: self.commands.clone(),
+        })
+    }
+}
+
+/// A subscription sent on a [`MultiplexedWebSocket`], completed when dropped
+pub(crate) struct MultiplexedSubscription {
+    id: String,
+    events: mpsc::Receiver<graphql::Response>,
+    commands: mpsc::UnboundedSender<MultiplexCommand>,
+}
+
+impl Drop for MultiplexedSubscription {
+    fn drop(&mut self) {
+        // The connection ignores it if the subgraph already completed the subscription
+        let _ = self.commands.send(MultiplexCommand::Complete {
+            id: std::mem::take(&mut self.id),
+        });
+    }
+}
+
+impl Stream for MultiplexedSubscription {
+    type Item = graphql::Response;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+/// Sends the subscriptions of a multiplexed connection and routes their events, until the
+/// subgraph closes the connection or its last subscription ends
+async fn drive_multiplexed_connection<S>(
+    socket: GraphqlWebSocket<S>,
+    mut commands: mpsc::UnboundedReceiver<MultiplexCommand>,
+    operations: Arc<AtomicUsize>,
+    heartbeat_interval: Option<tokio::time::Duration>,
+) where
+    S: Stream<Item = serde_json::Result<ServerMessage>>
+        + Sink<ClientMessage>
+        + std::marker::Unpin
+        + std::marker::Send
+        + 'static,
+{
+    let GraphqlWebSocket {
+        stream,
+        id: connection_id,
+        protocol,
+    } = socket;
+    let (mut sink, mut stream) = stream.split();
+    let mut subscriptions: HashMap<String, mpsc::Sender<graphql::Response>> = HashMap::new();
+    let mut heartbeat = match (protocol, heartbeat_interval) {
+        (WebSocketProtocol::GraphqlWs, Some(duration)) => {
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + duration, duration);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            Some(interval)
+        }
+        _ => None,
+    };
+
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(MultiplexCommand::Subscribe { id, request, events }) => {
+                    tracing::info!(
+                        monotonic_counter.apollo.router.operations.subscriptions.events = 1u64,
+                        subscriptions.mode = "passthrough"
+                    );
+                    if sink.send(protocol.subscribe(id.clone(), request)).await.is_ok() {
+                        subscriptions.insert(id, events);
+                    } else {
+                        let _ = events
+                            .send(
+                                graphql::Response::builder()
+                                    .error(
+                                        graphql::Error::builder()
+                                            .message("cannot send to websocket connection")
+                                            .extension_code("WEBSOCKET_CONNECTION_ERROR")
+                                            .build(),
+                                    )
+                                    .build(),
+                            )
+                            .await;
+                        subscription_completed(&operations);
+                    }
+                }
+                Some(MultiplexCommand::Complete { id }) => {
+                    if subscriptions.remove(&id).is_some() {
+                        subscription_completed(&operations);
+                        if sink.send(protocol.complete(id)).await.is_err() {
+                            tracing::trace!(
+                                "cannot complete the subscription on the websocket connection"
+                            );
+                        }
+                    }
+                }
+                None => break,
+            },
+            message = stream.next() => match message {
+                Some(Ok(ServerMessage::Ping { .. })) => {
+                    if sink.send(ClientMessage::Pong { payload: None }).await.is_err() {
+                        tracing::trace!("cannot send pong on the websocket connection");
+                    }
+                }
+                Some(Ok(message)) => match message.id() {
+                    // Messages about the connection itself, like a close frame, end all its
+                    // subscriptions
+                    Some(id) if id == connection_id => {
+                        if let (Some(response), _) = message.into_graphql_response() {
+                            for events in subscriptions.values() {
+                                let _ = events.try_send(response.clone());
+                            }
+                        }
+                        break;
+                    }
+                    Some(id) => {
+                        let (response, done) = message.into_graphql_response();
+                        // Waiting for a subscription that doesn't consume its events would block
+                        // the other ones and the heartbeat, it's ended instead
+                        let lagging = match (response, subscriptions.get(&id)) {
+                            (Some(response), Some(events)) => matches!(
+                                events.try_send(response),
+                                Err(mpsc::error::TrySendError::Full(_))
+                            ),
+                            _ => false,
+                        };
+                        if (done || lagging) && subscriptions.remove(&id).is_some() {
+                            subscription_completed(&operations);
+                            if !done {
+                                tracing::warn!(
+                                    "subscription {id:?} doesn't consume its events fast enough, ending it"
+                                );
+                                if sink.send(protocol.complete(id)).await.is_err() {
+                                    tracing::trace!(
+                                        "cannot complete the subscription on the websocket connection"
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    None => {}
+                },
+                Some(Err(err)) => {
+                    tracing::error!("cannot deserialize websocket server message: {err:?}");
+                }
+                None => break,
+            },
+            _ = async { heartbeat.as_mut().expect("checked by the precondition").tick().await },
+                if heartbeat.is_some() => {
+                if sink.send(ClientMessage::Ping { payload: None }).await.is_err() {
+                    tracing::trace!("cannot send heartbeat on the websocket connection");
+                }
+            }
+        }
+
+        // Once no subscription is left and none was reserved, no other one can be added
+        if operations
+            .compare_exchange(0, CLOSING, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            break;
+        }
+    }
+
+    commands.close();
+    while let Ok(command) = commands.try_recv() {
+        if let MultiplexCommand::Subscribe { events, .. } = command {
+            let _ = events
+                .send(
+                    graphql::Response::builder()
+                        .error(
+                            graphql::Error::builder()
+                                .message("websocket connection has been closed")
+                                .extension_code("WEBSOCKET_CONNECTION_ERROR")
+                                .build(),
+                        )
+                        .build(),
+                )
+                .await;
+        }
+    }
+    // Dropping the event senders ends the remaining subscriptions
+    for _ in subscriptions.drain() {
+        subscription_completed(&operations);
+    }
+    operations.store(CLOSING, Ordering::Release);
+
+    if let WebSocketProtocol::SubscriptionsTransportWs = protocol {
+        if sink.send(ClientMessage::ConnectionTerminate).await.is_err() {
+            tracing::trace!("cannot terminate the websocket connection");
+        }
+    }
+    if sink.close().await.is_err() {
+        tracing::trace!("cannot close the websocket stream");
+    }
+}
+
+fn subscription_completed(operations: &AtomicUsize) {
+    operations.fetch_sub(1, Ordering::AcqRel);
+    tracing::info!(
+        monotonic_counter
+            .apollo
+            .router
+            .operations
+            .subscriptions
+            .events = 1u64,
+        subscriptions.mode = "passthrough",
+        subscriptions.complete = true
+    );
+}
+
+/// Multiplexed connections to subgraphs, grouped by a key identifying the subscriptions that can
+/// share them
+#[derive(Clone, Default)]
+pub(crate) struct WebSocketPool {
+    connections: Arc<Mutex<HashMap<String, Vec<MultiplexedWebSocket>>>>,
+}
+
+impl WebSocketPool {
+    /// Returns an opened connection for this key, with a slot reserved for a new subscription
+    pub(crate) fn get(&self, key: &str, max_operations: usize) -> Option<MultiplexedWebSocket> {
+        let mut connections = self.connections.lock().expect("poisoned mutex");
+        let pooled = connections.get_mut(key)?;
+        pooled.retain(|connection| !connection.is_closed());
+        let connection = pooled
+            .iter()
+            .find(|connection| connection.try_reserve(max_operations))
+            .cloned();
+        if pooled.is_empty() {
+            connections.remove(key);
+        }
+
+        connection
+    }
+
+    pub(crate) fn insert(&self, key: String, connection: MultiplexedWebSocket) {
+        let mut connections = self.connections.lock().expect("poisoned mutex");
+        let pooled = connections.entry(key).or_default();
+        pooled.retain(|connection| !connection.is_closed());
+        pooled.push(connection);
+    }
+}
+
 pin_project! {
 struct InnerStream<S> {
     #[pin]
@@ -1054,4 +1442,257 @@ mod tests {
             "It should be completed"
         );
     }
+
+    async fn emulate_multiplexing_websocket_server() -> SocketAddr {
+        let ws_handler = move |ws: WebSocketUpgrade| async move {
+            let res = ws.on_upgrade(move |mut socket| async move {
+                let init_connection = socket.recv().await.unwrap().unwrap().into_text().unwrap();
+                let init_msg: ClientMessage = serde_json::from_str(&init_connection).unwrap();
+                assert!(matches!(init_msg, ClientMessage::ConnectionInit { .. }));
+                socket
+                    .send(AxumWsMessage::Text(
+                        serde_json::to_string(&ServerMessage::ConnectionAck).unwrap(),
+                    ))
+                    .await
+                    .unwrap();
+
+                let mut ids = Vec::new();
+                for _ in 0..2 {
+                    let new_message = socket.recv().await.unwrap().unwrap().into_text().unwrap();
+                    let subscribe_msg: ClientMessage = serde_json::from_str(&new_message).unwrap();
+                    if let ClientMessage::Subscribe { id, .. } = subscribe_msg {
+                        ids.push(id);
+                    } else {
+                        panic!("we should receive a subscribe message");
+                    }
+                }
+
+                // Events are sent in reverse order, they must still reach their subscription
+                for (index, id) in ids.iter().enumerate().rev() {
+                    socket
+                        .send(AxumWsMessage::Text(
+                            serde_json::to_string(&ServerMessage::Next {
+                                id: id.clone(),
+                                payload: graphql::Response::builder()
+                                    .data(serde_json_bytes::json!({ "index": index }))
+                                    .build(),
+                            })
+                            .unwrap(),
+                        ))
+                        .await
+                        .unwrap();
+                }
+                socket
+                    .send(AxumWsMessage::Text(
+                        serde_json::to_string(&ServerMessage::Complete { id: ids[0].clone() })
+                            .unwrap(),
+                    ))
+                    .await
+                    .unwrap();
+
+                let complete_sub = socket.recv().await.unwrap().unwrap().into_text().unwrap();
+                let complete_msg: ClientMessage = serde_json::from_str(&complete_sub).unwrap();
+                assert!(matches!(complete_msg, ClientMessage::Complete { id } if id == ids[1]));
+            });
+
+            Ok::<_, Infallible>(res)
+        };
+
+        let app = Router::new().route("/ws", get(ws_handler));
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let local_addr = server.local_addr();
+        tokio::spawn(async { server.await.unwrap() });
+        local_addr
+    }
+
+    #[tokio::test]
+    async fn test_ws_connection_multiplexed() {
+        let socket_addr = emulate_multiplexing_websocket_server().await;
+        let url = url::Url::parse(format!("ws://{}/ws", socket_addr).as_str()).unwrap();
+        let mut request = url.into_client_request().unwrap();
+        request.headers_mut().insert(
+            http::header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static("graphql-transport-ws"),
+        );
+        let (ws_stream, _resp) = connect_async(request).await.unwrap();
+
+        let connection_id = Uuid::new_v4().to_string();
+        let connection = GraphqlWebSocket::new(
+            convert_websocket_stream(ws_stream, connection_id.clone()),
+            connection_id,
+            WebSocketProtocol::GraphqlWs,
+            None,
+        )
+        .await
+        .unwrap()
+        .into_multiplexed(None);
+        // A slot is already reserved for the first subscription
+        assert!(connection.try_reserve(2));
+        assert!(!connection.try_reserve(2), "the connection should be full");
+
+        let sub = graphql::Request::builder()
+            .query("subscription {\n  userWasCreated {\n    username\n  }\n}")
+            .build();
+        let mut first = connection
+            .subscribe("first".to_string(), sub.clone())
+            .unwrap();
+        let mut second = connection.subscribe("second".to_string(), sub).unwrap();
+
+        assert_eq!(
+            first.next().await.unwrap(),
+            graphql::Response::builder()
+                .subscribed(true)
+                .data(serde_json_bytes::json!({ "index": 0 }))
+                .build()
+        );
+        assert!(first.next().await.is_none(), "it should be completed");
+        assert_eq!(
+            second.next().await.unwrap(),
+            graphql::Response::builder()
+                .subscribed(true)
+                .data(serde_json_bytes::json!({ "index": 1 }))
+                .build()
+        );
+        assert!(!connection.is_closed());
+
+        // The connection is closed once its last subscription is completed
+        drop(second);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !connection.is_closed() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the connection should be closed");
+        assert!(!connection.try_reserve(2));
+    }
+
+    async fn emulate_lagging_websocket_server() -> SocketAddr {
+        let ws_handler = move |ws: WebSocketUpgrade| async move {
+            let res = ws.on_upgrade(move |mut socket| async move {
+                let init_connection = socket.recv().await.unwrap().unwrap().into_text().unwrap();
+                let init_msg: ClientMessage = serde_json::from_str(&init_connection).unwrap();
+                assert!(matches!(init_msg, ClientMessage::ConnectionInit { .. }));
+                socket
+                    .send(AxumWsMessage::Text(
+                        serde_json::to_string(&ServerMessage::ConnectionAck).unwrap(),
+                    ))
+                    .await
+                    .unwrap();
+
+                let mut ids = Vec::new();
+                for _ in 0..2 {
+                    let new_message = socket.recv().await.unwrap().unwrap().into_text().unwrap();
+                    let subscribe_msg: ClientMessage = serde_json::from_str(&new_message).unwrap();
+                    if let ClientMessage::Subscribe { id, .. } = subscribe_msg {
+                        ids.push(id);
+                    } else {
+                        panic!("we should receive a subscribe message");
+                    }
+                }
+
+                // The first subscription gets more events than it buffers, then the second one
+                // gets an event
+                let events = (0..=MULTIPLEXED_SUBSCRIPTION_BUFFER_SIZE)
+                    .map(|index| (&ids[0], index))
+                    .chain(std::iter::once((&ids[1], 0)));
+                for (id, index) in events {
+                    socket
+                        .send(AxumWsMessage::Text(
+                            serde_json::to_string(&ServerMessage::Next {
+                                id: id.clone(),
+                                payload: graphql::Response::builder()
+                                    .data(serde_json_bytes::json!({ "index": index }))
+                                    .build(),
+                            })
+                            .unwrap(),
+                        ))
+                        .await
+                        .unwrap();
+                }
+
+                // The lagging subscription is completed, the other one keeps receiving events
+                let complete_sub = socket.recv().await.unwrap().unwrap().into_text().unwrap();
+                let complete_msg: ClientMessage = serde_json::from_str(&complete_sub).unwrap();
+                assert!(matches!(complete_msg, ClientMessage::Complete { id } if id == ids[0]));
+                socket
+                    .send(AxumWsMessage::Text(
+                        serde_json::to_string(&ServerMessage::Next {
+                            id: ids[1].clone(),
+                            payload: graphql::Response::builder()
+                                .data(serde_json_bytes::json!({ "index": 1 }))
+                                .build(),
+                        })
+                        .unwrap(),
+                    ))
+                    .await
+                    .unwrap();
+                let _ = socket.recv().await;
+            });
+
+            Ok::<_, Infallible>(res)
+        };
+
+        let app = Router::new().route("/ws", get(ws_handler));
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let local_addr = server.local_addr();
+        tokio::spawn(async { server.await.unwrap() });
+        local_addr
+    }
+
+    #[tokio::test]
+    async fn test_ws_connection_multiplexed_lagging_subscription() {
+        let socket_addr = emulate_lagging_websocket_server().await;
+        let url = url::Url::parse(format!("ws://{}/ws", socket_addr).as_str()).unwrap();
+        let mut request = url.into_client_request().unwrap();
+        request.headers_mut().insert(
+            http::header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static("graphql-transport-ws"),
+        );
+        let (ws_stream, _resp) = connect_async(request).await.unwrap();
+
+        let connection_id = Uuid::new_v4().to_string();
+        let connection = GraphqlWebSocket::new(
+            convert_websocket_stream(ws_stream, connection_id.clone()),
+            connection_id,
+            WebSocketProtocol::GraphqlWs,
+            None,
+        )
+        .await
+        .unwrap()
+        .into_multiplexed(None);
+        assert!(connection.try_reserve(2));
+
+        let sub = graphql::Request::builder()
+            .query("subscription {\n  userWasCreated {\n    username\n  }\n}")
+            .build();
+        let mut slow = connection
+            .subscribe("slow".to_string(), sub.clone())
+            .unwrap();
+        let mut fast = connection.subscribe("fast".to_string(), sub).unwrap();
+
+        // The slow subscription doesn't hold up the other one
+        for index in 0..2 {
+            assert_eq!(
+                fast.next().await.unwrap(),
+                graphql::Response::builder()
+                    .subscribed(true)
+                    .data(serde_json_bytes::json!({ "index": index }))
+                    .build()
+            );
+        }
+
+        // It only gets the events it buffered
+        for index in 0..MULTIPLEXED_SUBSCRIPTION_BUFFER_SIZE {
+            assert_eq!(
+                slow.next().await.unwrap(),
+                graphql::Response::builder()
+                    .subscribed(true)
+                    .data(serde_json_bytes::json!({ "index": index }))
+                    .build()
+            );
+        }
+        assert!(slow.next().await.is_none(), "it should be completed");
+        assert!(!connection.is_closed());
+    }
 }
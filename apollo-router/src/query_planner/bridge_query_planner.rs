@@ -313,12 +313,17 @@ impl BridgeQueryPlanner {
         doc: &ParsedDocument,
     ) -> Result<Query, QueryPlannerError> {
         let executable = &doc.executable;
-        crate::spec::operation_limits::check(
-            &self.configuration,
-            &query,
-            executable,
-            operation_name,
-        )?;
+        // With limit profiles, operation limits depend on the request
+        // and are checked by the supergraph service instead
+        if self.configuration.limits.profiles.is_empty() {
+            crate::spec::operation_limits::check(
+                &self.configuration,
+                None,
+                &query,
+                executable,
+                operation_name,
+            )?;
+        }
 
         let (fragments, operations, defer_stats, schema_aware_hash) =
             Query::extract_query_information(&self.schema, executable, operation_name)?;
@@ -1,7 +1,10 @@
+use std::collections::HashMap;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 
 use futures::future;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json_bytes::Value;
@@ -26,6 +29,46 @@ use crate::services::SubscriptionTaskParams;
 
 pub(crate) const SUBSCRIPTION_EVENT_SPAN_NAME: &str = "subscription_event";
 pub(crate) static OPENED_SUBSCRIPTIONS: AtomicUsize = AtomicUsize::new(0);
+/// Opened subscriptions of each client, only tracked when a per client limit is set
+pub(crate) static OPENED_SUBSCRIPTIONS_PER_CLIENT: Lazy<Mutex<HashMap<String, usize>>> =
+    Lazy::new(Default::default);
+
+/// Subscription reserved for a client, released when dropped
+pub(crate) struct ClientSubscription {
+    client_id: String,
+}
+
+impl ClientSubscription {
+    /// Reserves a subscription for the client, unless it already opened the maximum number of
+    /// subscriptions. Checking and counting under the same lock keeps concurrent requests of the
+    /// client under the limit
+    fn reserve(client_id: String, max_opened_subscriptions: usize) -> Option<Self> {
+        let mut opened = OPENED_SUBSCRIPTIONS_PER_CLIENT.lock();
+        let count = opened.entry(client_id.clone()).or_default();
+        if *count >= max_opened_subscriptions {
+            if *count == 0 {
+                opened.remove(&client_id);
+            }
+            return None;
+        }
+        *count += 1;
+
+        Some(Self { client_id })
+    }
+}
+
+impl Drop for ClientSubscription {
+    fn drop(&mut self) {
+        let mut opened = OPENED_SUBSCRIPTIONS_PER_CLIENT.lock();
+        if let Some(count) = opened.get_mut(&self.client_id) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                opened.remove(&self.client_id);
+            }
+        }
+    }
+}
+
 pub(crate) struct SubscriptionHandle {
     pub(crate) closed_signal: broadcast::Receiver<()>,
     pub(crate) subscription_conf_tx: Option<tokio::sync::mpsc::Sender<SubscriptionTaskParams>>,
@@ -108,6 +151,32 @@ impl SubscriptionNode {
                 });
             }
         }
+        // Kept by the subscription task until the subscription is closed, released if it's
+        // never opened
+        let mut client_subscription = None;
+        if let Some(per_client) = parameters
+            .subscription_config
+            .as_ref()
+            .and_then(|s| s.per_client.as_ref())
+        {
+            let client_id = per_client
+                .identified_by
+                .client_id(parameters.context, parameters.supergraph_request.headers());
+            if let Some(client_id) = client_id {
+                client_subscription =
+                    ClientSubscription::reserve(client_id, per_client.max_opened_subscriptions);
+                if client_subscription.is_none() {
+                    let max_opened_subscriptions = per_client.max_opened_subscriptions;
+                    return Box::pin(async move {
+                        vec![Error::builder()
+                            .message("can't open new subscription, limit reached for this client")
+                            .extension_code("SUBSCRIPTION_CLIENT_MAX_LIMIT")
+                            .extension("maxOpenedSubscriptions", max_opened_subscriptions)
+                            .build()]
+                    });
+                }
+            }
+        }
         let subscription_handle = parameters
             .subscription_handle
             .as_ref()
@@ -151,6 +220,7 @@ impl SubscriptionNode {
                         subscription_config,
                         stream_rx: rx_handle.into(),
                         service_name: self.service_name.clone(),
+                        client_subscription,
                     };
 
                     if let Err(err) = subscription_conf_tx.send(subs_params).await {
@@ -271,3 +341,28 @@ impl SubscriptionNode {
         Ok(response.errors)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reserves_client_subscriptions_up_to_the_limit() {
+        let client_id = uuid::Uuid::new_v4().to_string();
+
+        let first = ClientSubscription::reserve(client_id.clone(), 2).unwrap();
+        let second = ClientSubscription::reserve(client_id.clone(), 2).unwrap();
+        assert!(ClientSubscription::reserve(client_id.clone(), 2).is_none());
+        // Other clients have their own limit
+        assert!(ClientSubscription::reserve(uuid::Uuid::new_v4().to_string(), 2).is_some());
+
+        // Subscriptions that are not opened, or closed, give their reservation back
+        drop(first);
+        let third = ClientSubscription::reserve(client_id.clone(), 2).unwrap();
+        drop(second);
+        drop(third);
+        assert!(!OPENED_SUBSCRIPTIONS_PER_CLIENT
+            .lock()
+            .contains_key(&client_id));
+    }
+}
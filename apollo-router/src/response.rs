@@ -51,6 +51,10 @@ pub struct Response {
 
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub incremental: Vec<IncrementalResponse>,
+
+    /// Set on the notices the router adds to subscription streams, which are not subgraph events
+    #[serde(skip, default)]
+    pub(crate) router_notice: bool,
 }
 
 #[buildstructor::buildstructor]
@@ -80,6 +84,7 @@ impl Response {
             subscribed,
             incremental,
             created_at,
+            router_notice: false,
         }
     }
 
@@ -187,6 +192,7 @@ impl Response {
             subscribed: None,
             incremental,
             created_at: None,
+            router_notice: false,
         })
     }
 }
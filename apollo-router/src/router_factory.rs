@@ -244,6 +244,7 @@ impl YamlRouterFactory {
         let persisted_query_layer = Arc::new(PersistedQueryLayer::new(&configuration).await?);
 
         if let Some(previous_router) = previous_router {
+            persisted_query_layer.keep_audit_of(&previous_router.persisted_query_layer);
             let previous_cache = previous_router.previous_cache();
 
             supergraph_creator
@@ -26,6 +26,8 @@ use crate::plugins::telemetry::reload::prepare_context;
 use crate::query_planner::QueryPlan;
 use crate::Context;
 
+pub(crate) mod grpc;
+
 pub(crate) const DEFAULT_EXTERNALIZATION_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// Version of our externalised data. Rev this if it changes
@@ -41,6 +43,7 @@ pub(crate) enum PipelineStep {
     ExecutionResponse,
     SubgraphRequest,
     SubgraphResponse,
+    SubscriptionEvent,
 }
 
 impl From<PipelineStep> for opentelemetry::Value {
@@ -55,6 +58,8 @@ pub(crate) enum Control {
     #[default]
     Continue,
     Break(u16),
+    /// Only valid in the `SubscriptionEvent` stage: the event is not sent to the client
+    Drop,
 }
 
 impl Control {
@@ -67,6 +72,7 @@ impl Control {
         match self {
             Control::Continue => Ok(StatusCode::OK),
             Control::Break(code) => StatusCode::from_u16(*code).map_err(|e| e.into()),
+            Control::Drop => Err("the `drop` control is only valid for subscription events".into()),
         }
     }
 }
@@ -165,7 +171,9 @@ where
     ) -> Self {
         assert!(matches!(
             stage,
-            PipelineStep::SupergraphRequest | PipelineStep::SupergraphResponse
+            PipelineStep::SupergraphRequest
+                | PipelineStep::SupergraphResponse
+                | PipelineStep::SubscriptionEvent
         ));
         Externalizable {
             version: EXTERNALIZABLE_VERSION,
@@ -265,6 +273,28 @@ where
         }
     }
 
+    /// The reply of a coprocessor that changes nothing: only the version, stage, control and
+    /// id are kept
+    pub(crate) fn unchanged(&self) -> Self {
+        Externalizable {
+            version: self.version,
+            stage: self.stage.clone(),
+            control: self.control.clone(),
+            id: self.id.clone(),
+            headers: None,
+            body: None,
+            context: None,
+            sdl: None,
+            uri: None,
+            method: None,
+            path: None,
+            service_name: None,
+            status_code: None,
+            has_next: None,
+            query_plan: None,
+        }
+    }
+
     pub(crate) async fn call<C>(self, mut client: C, uri: &str) -> Result<Self, BoxError>
     where
         C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
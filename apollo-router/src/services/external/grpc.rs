@@ -0,0 +1,774 @@
+//! gRPC transport for coprocessors
+//!
+//! Coprocessors configured with a `grpc://` or `grpcs://` url receive the same payloads as
+//! HTTP coprocessors, encoded as the protobuf messages defined in `proto/coprocessor.proto`.
+//!
+//! [`GrpcClient`] is a drop-in replacement for the HTTP client of a coprocessor: it takes the
+//! JSON payload of a call and answers with the JSON encoding of the reply, so the timeout,
+//! retries and circuit breaker of the coprocessor apply to both transports.
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use http::uri::PathAndQuery;
+use hyper::Body;
+use prost_types::value::Kind;
+use prost_types::ListValue;
+use prost_types::Struct;
+use serde_json::Map;
+use serde_json::Value;
+use tonic::codec::Codec;
+use tonic::codec::DecodeBuf;
+use tonic::codec::Decoder;
+use tonic::codec::EncodeBuf;
+use tonic::codec::Encoder;
+use tonic::metadata::MetadataMap;
+use tonic::transport::Channel;
+use tonic::transport::ClientTlsConfig;
+use tonic::transport::Endpoint;
+use tonic::Status;
+use tower::BoxError;
+use tower::Service;
+
+use super::Control;
+use super::Externalizable;
+
+#[allow(unreachable_pub)]
+pub(crate) mod proto {
+    #![allow(clippy::derive_partial_eq_without_eq)]
+    tonic::include_proto!("apollo.router.coprocessor.v1");
+}
+
+const GRPC_SCHEME: &str = "grpc://";
+const GRPCS_SCHEME: &str = "grpcs://";
+const PROCESS_PATH: &str = "/apollo.router.coprocessor.v1.Coprocessor/Process";
+const QUERY_PLAN_FIELD: &str = "queryPlan";
+
+/// Whether the coprocessor at this url is called through gRPC
+pub(crate) fn is_grpc_url(url: &str) -> bool {
+    url.starts_with(GRPC_SCHEME) || url.starts_with(GRPCS_SCHEME)
+}
+
+/// A pool of HTTP/2 connections to a coprocessor
+///
+/// Connections are opened lazily and requests are spread over them in turn. Each coprocessor
+/// of a plugin instance has its own pool, dropped with the plugin.
+#[derive(Clone, Debug)]
+pub(crate) struct GrpcClient {
+    channels: Arc<[Channel]>,
+    next: Arc<AtomicUsize>,
+}
+
+impl GrpcClient {
+    pub(crate) fn new(
+        url: &str,
+        connect_timeout: Duration,
+        pool_size: usize,
+    ) -> Result<Self, BoxError> {
+        let (uri, tls) = if let Some(rest) = url.strip_prefix(GRPCS_SCHEME) {
+            (format!("https://{rest}"), true)
+        } else if let Some(rest) = url.strip_prefix(GRPC_SCHEME) {
+            (format!("http://{rest}"), false)
+        } else {
+            return Err(format!("'{url}' is not a gRPC coprocessor url").into());
+        };
+
+        let mut endpoint = Endpoint::from_shared(uri)?
+            .connect_timeout(connect_timeout)
+            .tcp_nodelay(true)
+            .tcp_keepalive(Some(Duration::from_secs(60)));
+        if tls {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+        }
+
+        let channels: Vec<Channel> = (0..pool_size.max(1))
+            .map(|_| endpoint.connect_lazy())
+            .collect();
+        Ok(Self {
+            channels: channels.into(),
+            next: Default::default(),
+        })
+    }
+
+    fn channel(&self) -> Channel {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.channels.len();
+        self.channels[index].clone()
+    }
+}
+
+impl Service<hyper::Request<Body>> for GrpcClient {
+    type Response = hyper::Response<Body>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: hyper::Request<Body>) -> Self::Future {
+        Box::pin(process(self.channel(), request))
+    }
+}
+
+/// Sends the JSON payload of an HTTP coprocessor request over gRPC, and returns the reply as
+/// the JSON body of an HTTP response
+async fn process(
+    channel: Channel,
+    request: hyper::Request<Body>,
+) -> Result<hyper::Response<Body>, BoxError> {
+    let (parts, body) = request.into_parts();
+    let bytes = hyper::body::to_bytes(body).await?;
+    let mut payload: Map<String, Value> = serde_json::from_slice(&bytes)?;
+    // The query plan is only carried, there is no need to deserialize it
+    let query_plan = payload.remove(QUERY_PLAN_FIELD);
+    let payload: Externalizable<Value> = serde_json::from_value(Value::Object(payload))?;
+
+    let mut request = tonic::Request::new(to_proto(payload, query_plan)?);
+    // The tracing headers of the HTTP request
+    *request.metadata_mut() = MetadataMap::from_headers(parts.headers);
+
+    let mut grpc = tonic::client::Grpc::new(channel);
+    grpc.ready().await?;
+    let response = grpc
+        .unary(
+            request,
+            PathAndQuery::from_static(PROCESS_PATH),
+            ProtoCodec::default(),
+        )
+        .await?;
+
+    let (reply, query_plan) = from_proto(response.into_inner())?;
+    let mut reply = match serde_json::to_value(reply)? {
+        Value::Object(reply) => reply,
+        _ => return Err("the coprocessor reply is not an object".into()),
+    };
+    if let Some(query_plan) = query_plan {
+        reply.insert(QUERY_PLAN_FIELD.to_string(), query_plan);
+    }
+    Ok(hyper::Response::new(Body::from(serde_json::to_vec(
+        &reply,
+    )?)))
+}
+
+fn to_proto(
+    payload: Externalizable<Value>,
+    query_plan: Option<Value>,
+) -> Result<proto::Externalizable, BoxError> {
+    // Request stages are sent GraphQL requests, and get GraphQL responses when they break
+    let request_stage =
+        payload.stage.ends_with("Request") && !matches!(payload.control, Some(Control::Break(_)));
+    let body = payload
+        .body
+        .map(|body| body_to_proto(body, request_stage))
+        .transpose()?;
+    let context = payload
+        .context
+        .map(|context| -> Result<_, BoxError> {
+            let entries = match serde_json::to_value(context)? {
+                Value::Object(mut context) => match context.remove("entries") {
+                    Some(Value::Object(entries)) => entries,
+                    _ => Map::new(),
+                },
+                _ => Map::new(),
+            };
+            Ok(proto::Context {
+                entries: entries
+                    .into_iter()
+                    .map(|(key, value)| (key, to_proto_value(value)))
+                    .collect(),
+            })
+        })
+        .transpose()?;
+
+    Ok(proto::Externalizable {
+        version: payload.version.into(),
+        stage: payload.stage,
+        control: payload.control.map(|control| {
+            let (action, status_code) = match control {
+                Control::Continue => (proto::control::Action::Continue, 0),
+                Control::Break(status_code) => (proto::control::Action::Break, status_code),
+                Control::Drop => (proto::control::Action::Drop, 0),
+            };
+            proto::Control {
+                action: action.into(),
+                status_code: status_code.into(),
+            }
+        }),
+        id: payload.id,
+        headers: payload.headers.map(|headers| proto::Headers {
+            entries: headers
+                .into_iter()
+                .map(|(name, values)| (name, proto::HeaderValues { values }))
+                .collect(),
+        }),
+        body,
+        context,
+        sdl: payload.sdl,
+        uri: payload.uri,
+        method: payload.method,
+        path: payload.path,
+        service_name: payload.service_name,
+        status_code: payload.status_code.map(Into::into),
+        has_next: payload.has_next,
+        query_plan: query_plan.map(to_proto_value),
+    })
+}
+
+fn from_proto(
+    message: proto::Externalizable,
+) -> Result<(Externalizable<Value>, Option<Value>), BoxError> {
+    let context = message
+        .context
+        .map(|context| {
+            let entries: Map<String, Value> = context
+                .entries
+                .into_iter()
+                .map(|(key, value)| (key, from_proto_value(value)))
+                .collect();
+            serde_json::from_value(serde_json::json!({ "entries": entries }))
+        })
+        .transpose()?;
+
+    let payload = Externalizable {
+        version: message
+            .version
+            .try_into()
+            .map_err(|_| format!("invalid externalizable version: {}", message.version))?,
+        stage: message.stage,
+        control: message
+            .control
+            .map(|control| match control.action() {
+                proto::control::Action::Continue => Ok(Control::Continue),
+                proto::control::Action::Break => control
+                    .status_code
+                    .try_into()
+                    .map(Control::Break)
+                    .map_err(|_| format!("invalid status code: {}", control.status_code)),
+                proto::control::Action::Drop => Ok(Control::Drop),
+            })
+            .transpose()?,
+        id: message.id,
+        headers: message.headers.map(|headers| {
+            headers
+                .entries
+                .into_iter()
+                .map(|(name, values)| (name, values.values))
+                .collect()
+        }),
+        body: message.body.map(body_from_proto),
+        context,
+        sdl: message.sdl,
+        uri: message.uri,
+        method: message.method,
+        path: message.path,
+        service_name: message.service_name,
+        status_code: message
+            .status_code
+            .map(|status_code| {
+                status_code
+                    .try_into()
+                    .map_err(|_| format!("invalid status code: {status_code}"))
+            })
+            .transpose()?,
+        has_next: message.has_next,
+        query_plan: None,
+    };
+    Ok((payload, message.query_plan.map(from_proto_value)))
+}
+
+fn body_to_proto(
+    body: Value,
+    request_stage: bool,
+) -> Result<proto::externalizable::Body, BoxError> {
+    Ok(match body {
+        Value::String(body) => proto::externalizable::Body::RawBody(body),
+        Value::Object(body) if request_stage => {
+            proto::externalizable::Body::RequestBody(request_to_proto(body))
+        }
+        Value::Object(body) => proto::externalizable::Body::ResponseBody(response_to_proto(body)?),
+        _ => return Err("the body must be a string or an object".into()),
+    })
+}
+
+fn body_from_proto(body: proto::externalizable::Body) -> Value {
+    match body {
+        proto::externalizable::Body::RawBody(body) => Value::String(body),
+        proto::externalizable::Body::RequestBody(request) => request_from_proto(request),
+        proto::externalizable::Body::ResponseBody(response) => response_from_proto(response),
+    }
+}
+
+fn request_to_proto(mut request: Map<String, Value>) -> proto::GraphQlRequest {
+    let request_proto = proto::GraphQlRequest {
+        query: take_string(&mut request, "query"),
+        operation_name: take_string(&mut request, "operationName"),
+        variables: take_struct(&mut request, "variables"),
+        extensions: take_struct(&mut request, "extensions"),
+    };
+    warn_dropped_keys(&request, "GraphQL request");
+    request_proto
+}
+
+fn request_from_proto(request: proto::GraphQlRequest) -> Value {
+    let mut object = Map::new();
+    if let Some(query) = request.query {
+        object.insert("query".to_string(), Value::String(query));
+    }
+    if let Some(operation_name) = request.operation_name {
+        object.insert("operationName".to_string(), Value::String(operation_name));
+    }
+    insert_struct(&mut object, "variables", request.variables);
+    insert_struct(&mut object, "extensions", request.extensions);
+    Value::Object(object)
+}
+
+fn response_to_proto(mut response: Map<String, Value>) -> Result<proto::GraphQlResponse, BoxError> {
+    let errors = match response.remove("errors") {
+        Some(Value::Array(errors)) => errors
+            .into_iter()
+            .map(|error| match error {
+                Value::Object(error) => Ok(error_to_proto(error)),
+                _ => Err(BoxError::from("a GraphQL error must be an object")),
+            })
+            .collect::<Result<_, _>>()?,
+        _ => Vec::new(),
+    };
+    let incremental = match response.remove("incremental") {
+        Some(Value::Array(incremental)) => incremental
+            .into_iter()
+            .map(|payload| match payload {
+                Value::Object(payload) => response_to_proto(payload),
+                _ => Err(BoxError::from("an incremental payload must be an object")),
+            })
+            .collect::<Result<_, _>>()?,
+        _ => Vec::new(),
+    };
+    let response_proto = proto::GraphQlResponse {
+        label: take_string(&mut response, "label"),
+        data: response.remove("data").map(to_proto_value),
+        path: take_list(&mut response, "path"),
+        errors,
+        extensions: take_struct(&mut response, "extensions"),
+        has_next: response
+            .remove("hasNext")
+            .and_then(|has_next| has_next.as_bool()),
+        incremental,
+    };
+    warn_dropped_keys(&response, "GraphQL response");
+    Ok(response_proto)
+}
+
+fn response_from_proto(response: proto::GraphQlResponse) -> Value {
+    let mut object = Map::new();
+    if let Some(label) = response.label {
+        object.insert("label".to_string(), Value::String(label));
+    }
+    if let Some(data) = response.data {
+        object.insert("data".to_string(), from_proto_value(data));
+    }
+    insert_list(&mut object, "path", response.path);
+    if !response.errors.is_empty() {
+        object.insert(
+            "errors".to_string(),
+            Value::Array(response.errors.into_iter().map(error_from_proto).collect()),
+        );
+    }
+    insert_struct(&mut object, "extensions", response.extensions);
+    if let Some(has_next) = response.has_next {
+        object.insert("hasNext".to_string(), Value::Bool(has_next));
+    }
+    if !response.incremental.is_empty() {
+        object.insert(
+            "incremental".to_string(),
+            Value::Array(
+                response
+                    .incremental
+                    .into_iter()
+                    .map(response_from_proto)
+                    .collect(),
+            ),
+        );
+    }
+    Value::Object(object)
+}
+
+fn error_to_proto(mut error: Map<String, Value>) -> proto::GraphQlError {
+    let locations = match error.remove("locations") {
+        Some(Value::Array(locations)) => locations
+            .iter()
+            .map(|location| proto::GraphQlLocation {
+                line: location_field(location, "line"),
+                column: location_field(location, "column"),
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    proto::GraphQlError {
+        message: take_string(&mut error, "message").unwrap_or_default(),
+        locations,
+        path: take_list(&mut error, "path"),
+        extensions: take_struct(&mut error, "extensions"),
+    }
+}
+
+fn error_from_proto(error: proto::GraphQlError) -> Value {
+    let mut object = Map::new();
+    object.insert("message".to_string(), Value::String(error.message));
+    if !error.locations.is_empty() {
+        object.insert(
+            "locations".to_string(),
+            Value::Array(
+                error
+                    .locations
+                    .into_iter()
+                    .map(|location| {
+                        serde_json::json!({ "line": location.line, "column": location.column })
+                    })
+                    .collect(),
+            ),
+        );
+    }
+    insert_list(&mut object, "path", error.path);
+    insert_struct(&mut object, "extensions", error.extensions);
+    Value::Object(object)
+}
+
+fn location_field(location: &Value, name: &str) -> u32 {
+    location
+        .get(name)
+        .and_then(Value::as_u64)
+        .and_then(|value| value.try_into().ok())
+        .unwrap_or_default()
+}
+
+/// The messages only have the fields of the GraphQL specification, the keys left once they are
+/// taken are not sent to the coprocessor
+fn warn_dropped_keys(object: &Map<String, Value>, kind: &str) {
+    if !object.is_empty() {
+        tracing::warn!(
+            "the keys {:?} of the {kind} are not sent to the gRPC coprocessor",
+            object.keys().collect::<Vec<_>>()
+        );
+    }
+}
+
+fn take_string(object: &mut Map<String, Value>, name: &str) -> Option<String> {
+    match object.remove(name) {
+        Some(Value::String(value)) => Some(value),
+        _ => None,
+    }
+}
+
+fn take_struct(object: &mut Map<String, Value>, name: &str) -> Option<Struct> {
+    match object.remove(name) {
+        Some(Value::Object(value)) => Some(to_proto_struct(value)),
+        _ => None,
+    }
+}
+
+fn take_list(object: &mut Map<String, Value>, name: &str) -> Vec<prost_types::Value> {
+    match object.remove(name) {
+        Some(Value::Array(values)) => values.into_iter().map(to_proto_value).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn insert_struct(object: &mut Map<String, Value>, name: &str, value: Option<Struct>) {
+    if let Some(value) = value.filter(|value| !value.fields.is_empty()) {
+        object.insert(name.to_string(), from_proto_struct(value));
+    }
+}
+
+fn insert_list(object: &mut Map<String, Value>, name: &str, values: Vec<prost_types::Value>) {
+    if !values.is_empty() {
+        object.insert(
+            name.to_string(),
+            Value::Array(values.into_iter().map(from_proto_value).collect()),
+        );
+    }
+}
+
+// Protobuf numbers are doubles: integers beyond 2^53 lose their precision, as they do in
+// JavaScript. Coprocessors that need them exactly should receive them as strings
+fn to_proto_value(value: Value) -> prost_types::Value {
+    let kind = match value {
+        Value::Null => Kind::NullValue(prost_types::NullValue::NullValue.into()),
+        Value::Bool(value) => Kind::BoolValue(value),
+        Value::Number(value) => Kind::NumberValue(value.as_f64().unwrap_or_default()),
+        Value::String(value) => Kind::StringValue(value),
+        Value::Array(values) => Kind::ListValue(ListValue {
+            values: values.into_iter().map(to_proto_value).collect(),
+        }),
+        Value::Object(object) => Kind::StructValue(to_proto_struct(object)),
+    };
+    prost_types::Value { kind: Some(kind) }
+}
+
+fn to_proto_struct(object: Map<String, Value>) -> Struct {
+    Struct {
+        fields: object
+            .into_iter()
+            .map(|(key, value)| (key, to_proto_value(value)))
+            .collect::<BTreeMap<_, _>>(),
+    }
+}
+
+fn from_proto_value(value: prost_types::Value) -> Value {
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => Value::Null,
+        Some(Kind::BoolValue(value)) => Value::Bool(value),
+        Some(Kind::NumberValue(value)) => from_proto_number(value),
+        Some(Kind::StringValue(value)) => Value::String(value),
+        Some(Kind::ListValue(list)) => {
+            Value::Array(list.values.into_iter().map(from_proto_value).collect())
+        }
+        Some(Kind::StructValue(object)) => from_proto_struct(object),
+    }
+}
+
+fn from_proto_struct(object: Struct) -> Value {
+    Value::Object(
+        object
+            .fields
+            .into_iter()
+            .map(|(key, value)| (key, from_proto_value(value)))
+            .collect(),
+    )
+}
+
+// Protobuf numbers are doubles: integral values are turned back into integers, so that GraphQL
+// `Int` values and list indexes in paths keep their type
+fn from_proto_number(value: f64) -> Value {
+    const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+    if value.fract() == 0.0 && value.abs() <= MAX_SAFE_INTEGER {
+        Value::from(value as i64)
+    } else {
+        serde_json::Number::from_f64(value)
+            .map(Value::Number)
+            .unwrap_or(Value::Null)
+    }
+}
+
+/// Protobuf codec for the coprocessor messages
+///
+/// tonic's own codec is tied to the prost version tonic depends on, this one uses the
+/// version the generated messages derive from.
+struct ProtoCodec<E, D>(PhantomData<(E, D)>);
+
+impl<E, D> Default for ProtoCodec<E, D> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<E, D> Codec for ProtoCodec<E, D>
+where
+    E: prost::Message + Send + 'static,
+    D: prost::Message + Default + Send + 'static,
+{
+    type Encode = E;
+    type Decode = D;
+    type Encoder = ProtoEncoder<E>;
+    type Decoder = ProtoDecoder<D>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        ProtoEncoder(PhantomData)
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        ProtoDecoder(PhantomData)
+    }
+}
+
+struct ProtoEncoder<T>(PhantomData<T>);
+
+impl<T> Encoder for ProtoEncoder<T>
+where
+    T: prost::Message,
+{
+    type Item = T;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, buf: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        item.encode(buf)
+            .map_err(|error| Status::internal(error.to_string()))
+    }
+}
+
+struct ProtoDecoder<T>(PhantomData<T>);
+
+impl<T> Decoder for ProtoDecoder<T>
+where
+    T: prost::Message + Default,
+{
+    type Item = T;
+    type Error = Status;
+
+    fn decode(&mut self, buf: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        T::decode(buf)
+            .map(Some)
+            .map_err(|error| Status::internal(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::services::external::PipelineStep;
+    use crate::Context;
+
+    #[test]
+    fn it_recognizes_grpc_urls() {
+        assert!(is_grpc_url("grpc://127.0.0.1:50051"));
+        assert!(is_grpc_url("grpcs://coprocessor.example.com"));
+        assert!(!is_grpc_url("http://127.0.0.1:8081"));
+        assert!(!is_grpc_url("https://coprocessor.example.com"));
+    }
+
+    #[test]
+    fn it_converts_payloads_to_protobuf_and_back() {
+        let context = Context::new();
+        context.insert("key", "value".to_string()).unwrap();
+        let payload = Externalizable::<Value>::subgraph_builder()
+            .stage(PipelineStep::SubgraphResponse)
+            .control(Control::Break(401))
+            .id("id".to_string())
+            .headers(HashMap::from([(
+                "x-header".to_string(),
+                vec!["a".to_string(), "b".to_string()],
+            )]))
+            .body(serde_json::json!({
+                "data": { "me": { "name": "Ada", "age": 36 } },
+                "errors": [{
+                    "message": "cannot fetch the friends",
+                    "locations": [{ "line": 1, "column": 2 }],
+                    "path": ["me", "friends", 0],
+                    "extensions": { "code": "FETCH_ERROR" }
+                }]
+            }))
+            .context(context)
+            .status_code(200)
+            .service_name("accounts".to_string())
+            .build();
+
+        let message = to_proto(payload.clone(), None).unwrap();
+        assert_eq!(message.stage, "SubgraphResponse");
+        assert_eq!(
+            message.control.as_ref().map(|control| control.action()),
+            Some(proto::control::Action::Break)
+        );
+        let Some(proto::externalizable::Body::ResponseBody(body)) = &message.body else {
+            panic!("expected a GraphQL response body, got {:?}", message.body);
+        };
+        assert_eq!(body.errors[0].message, "cannot fetch the friends");
+        assert_eq!(
+            message.context.as_ref().unwrap().entries.get("key"),
+            Some(&to_proto_value(Value::String("value".to_string())))
+        );
+
+        let (back, query_plan) = from_proto(message).unwrap();
+        assert_eq!(query_plan, None);
+        assert_eq!(
+            serde_json::to_value(&back).unwrap(),
+            serde_json::to_value(&payload).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_sends_graphql_requests_to_request_stages() {
+        let payload = Externalizable::<Value>::supergraph_builder()
+            .stage(PipelineStep::SupergraphRequest)
+            .id("id".to_string())
+            .body(serde_json::json!({
+                "query": "query($id: ID!) { user(id: $id) { name } }",
+                "variables": { "id": 1, "ratio": 0.5 }
+            }))
+            .build();
+
+        let message = to_proto(payload.clone(), None).unwrap();
+        let Some(proto::externalizable::Body::RequestBody(body)) = &message.body else {
+            panic!("expected a GraphQL request body, got {:?}", message.body);
+        };
+        assert_eq!(
+            body.query.as_deref(),
+            Some("query($id: ID!) { user(id: $id) { name } }")
+        );
+
+        // Integers are still integers once back from protobuf doubles
+        let (back, _) = from_proto(message).unwrap();
+        assert_eq!(back.body, payload.body);
+    }
+
+    #[test]
+    fn it_loses_what_protobuf_values_cannot_carry() {
+        let payload = Externalizable::<Value>::supergraph_builder()
+            .stage(PipelineStep::SupergraphResponse)
+            .id("id".to_string())
+            .body(serde_json::json!({
+                "data": { "id": 9_007_199_254_740_993_u64, "big": 1e300 },
+                "unknown": true
+            }))
+            .build();
+
+        let message = to_proto(payload, None).unwrap();
+        let (back, _) = from_proto(message).unwrap();
+        // Integers beyond 2^53 are rounded to the nearest double, and keys outside of the
+        // GraphQL specification are dropped
+        assert_eq!(
+            back.body,
+            Some(serde_json::json!({
+                "data": { "id": 9_007_199_254_740_992.0, "big": 1e300 }
+            }))
+        );
+    }
+
+    #[test]
+    fn it_carries_query_plans() {
+        let payload = Externalizable::<Value>::execution_builder()
+            .stage(PipelineStep::ExecutionRequest)
+            .id("id".to_string())
+            .build();
+        let query_plan = serde_json::json!({ "root": { "kind": "Fetch", "serviceName": "users" } });
+
+        let message = to_proto(payload, Some(query_plan.clone())).unwrap();
+        assert!(message.query_plan.is_some());
+
+        let (_, back) = from_proto(message).unwrap();
+        assert_eq!(back, Some(query_plan));
+    }
+
+    #[tokio::test]
+    async fn it_fails_calls_to_unreachable_coprocessors() {
+        use tower::ServiceExt;
+
+        let payload = Externalizable::<Value>::router_builder()
+            .stage(PipelineStep::RouterRequest)
+            .id("id".to_string())
+            .build();
+        let request = hyper::Request::builder()
+            .uri("grpc://127.0.0.1:1")
+            .body(Body::from(serde_json::to_vec(&payload).unwrap()))
+            .unwrap();
+        let client = GrpcClient::new("grpc://127.0.0.1:1", Duration::from_secs(1), 1).unwrap();
+        assert!(client.oneshot(request).await.is_err());
+    }
+
+    #[test]
+    fn it_rejects_invalid_status_codes() {
+        let message = proto::Externalizable {
+            version: 1,
+            stage: "RouterResponse".to_string(),
+            status_code: Some(70000),
+            ..Default::default()
+        };
+        assert!(from_proto(message).is_err());
+    }
+}
@@ -0,0 +1,321 @@
+//! Audit of the freeform operations that are not in the persisted query list.
+//!
+//! Unknown operations are grouped by their normalized body, the same normalization
+//! used to match freeform GraphQL against the safelist, so registering the body of a
+//! group allows all the operations of the group.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use http::header::CONTENT_TYPE;
+use http::StatusCode;
+use hyper::Body;
+use multimap::MultiMap;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::service_fn;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::configuration::PersistedQueriesAudit;
+use crate::services::router;
+use crate::services::APPLICATION_JSON_HEADER_VALUE;
+use crate::Endpoint;
+use crate::ListenAddr;
+
+#[derive(Debug)]
+pub(crate) struct UnknownOperationAudit {
+    max_operations: usize,
+    operations: Mutex<HashMap<String, UnknownOperation>>,
+}
+
+/// A group of unknown operations sharing the same normalized body
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct UnknownOperation {
+    pub(crate) body: String,
+    pub(crate) count: u64,
+    pub(crate) client_names: BTreeSet<String>,
+    /// Whether some of the requests had no client name
+    #[serde(default)]
+    pub(crate) sent_without_client_name: bool,
+    #[serde(with = "humantime_serde")]
+    pub(crate) first_seen: SystemTime,
+    #[serde(with = "humantime_serde")]
+    pub(crate) last_seen: SystemTime,
+}
+
+/// The audit report, most frequent operations first
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct UnknownOperationReport {
+    pub(crate) operations: Vec<UnknownOperation>,
+}
+
+impl UnknownOperationAudit {
+    /// Creates the audit, and starts writing it periodically to the dump file if configured.
+    /// The dump stops when the audit is dropped.
+    pub(crate) fn new(config: &PersistedQueriesAudit) -> Arc<Self> {
+        let audit = Arc::new(Self {
+            max_operations: config.max_operations,
+            operations: Mutex::new(HashMap::new()),
+        });
+
+        if let Some(dump_path) = config.dump_path.clone() {
+            tokio::task::spawn(dump_periodically(
+                Arc::downgrade(&audit),
+                dump_path,
+                config.dump_interval,
+            ));
+        }
+
+        audit
+    }
+
+    pub(crate) fn record(&self, normalized_body: String, client_name: Option<String>) {
+        let now = SystemTime::now();
+        let mut operations = self
+            .operations
+            .lock()
+            .expect("could not acquire lock on unknown operations audit");
+
+        if !operations.contains_key(&normalized_body) && operations.len() >= self.max_operations {
+            tracing::debug!(
+                "unknown operation not audited: the audit already holds {} operations",
+                self.max_operations
+            );
+            return;
+        }
+
+        let operation = operations
+            .entry(normalized_body.clone())
+            .or_insert_with(|| UnknownOperation {
+                body: normalized_body,
+                count: 0,
+                client_names: BTreeSet::new(),
+                sent_without_client_name: false,
+                first_seen: now,
+                last_seen: now,
+            });
+        operation.count += 1;
+        operation.last_seen = now;
+        match client_name {
+            Some(client_name) => {
+                operation.client_names.insert(client_name);
+            }
+            None => operation.sent_without_client_name = true,
+        }
+    }
+
+    /// Takes over the operations of the audit of a previous configuration or schema, the most
+    /// frequent ones first if they don't all fit
+    pub(crate) fn keep_operations_of(&self, previous: &UnknownOperationAudit) {
+        let previous = previous.report();
+        let mut operations = self
+            .operations
+            .lock()
+            .expect("could not acquire lock on unknown operations audit");
+        for operation in previous.operations {
+            if operations.len() >= self.max_operations {
+                break;
+            }
+            operations.insert(operation.body.clone(), operation);
+        }
+    }
+
+    pub(crate) fn report(&self) -> UnknownOperationReport {
+        let mut operations: Vec<UnknownOperation> = self
+            .operations
+            .lock()
+            .expect("could not acquire lock on unknown operations audit")
+            .values()
+            .cloned()
+            .collect();
+        operations.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.body.cmp(&b.body)));
+        UnknownOperationReport { operations }
+    }
+
+    pub(crate) fn web_endpoints(
+        self: &Arc<Self>,
+        config: &PersistedQueriesAudit,
+    ) -> MultiMap<ListenAddr, Endpoint> {
+        let mut path = config.path.clone();
+        if !path.starts_with('/') {
+            path = format!("/{path}");
+        }
+        tracing::info!(
+            "Persisted queries audit exposed at {}{}",
+            config.listen,
+            path
+        );
+
+        let audit = self.clone();
+        let mut endpoints = MultiMap::new();
+        endpoints.insert(
+            config.listen.clone(),
+            Endpoint::from_router_service(
+                path,
+                service_fn(move |req: router::Request| {
+                    let report = serde_json::to_vec(&audit.report());
+                    async move {
+                        Ok::<_, BoxError>(router::Response {
+                            response: http::Response::builder()
+                                .status(StatusCode::OK)
+                                .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE.clone())
+                                .body(Body::from(report?))?,
+                            context: req.context,
+                        })
+                    }
+                })
+                .boxed(),
+            ),
+        );
+        endpoints
+    }
+}
+
+async fn dump_periodically(audit: Weak<UnknownOperationAudit>, path: PathBuf, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    // The first tick completes immediately, there is nothing to write yet
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let report = match audit.upgrade() {
+            Some(audit) => audit.report(),
+            None => break,
+        };
+        let written = match serde_json::to_vec_pretty(&report) {
+            Ok(contents) => tokio::fs::write(&path, contents)
+                .await
+                .map_err(BoxError::from),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = written {
+            tracing::error!(
+                "could not write the persisted queries audit to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn audit_groups_operations() {
+        let audit = UnknownOperationAudit::new(
+            &PersistedQueriesAudit::builder()
+                .enabled(true)
+                .max_operations(2)
+                .build(),
+        );
+        audit.record("query A { a }".to_string(), Some("web".to_string()));
+        audit.record("query A { a }".to_string(), Some("ios".to_string()));
+        audit.record("query A { a }".to_string(), None);
+        audit.record("query B { b }".to_string(), Some("web".to_string()));
+        // The audit is full
+        audit.record("query C { c }".to_string(), Some("web".to_string()));
+
+        let report = audit.report();
+        assert_eq!(report.operations.len(), 2);
+        let operation = &report.operations[0];
+        assert_eq!(operation.body, "query A { a }");
+        assert_eq!(operation.count, 3);
+        assert_eq!(
+            operation.client_names,
+            BTreeSet::from(["ios".to_string(), "web".to_string()])
+        );
+        assert!(operation.sent_without_client_name);
+        assert!(operation.first_seen <= operation.last_seen);
+        assert_eq!(report.operations[1].body, "query B { b }");
+        assert_eq!(report.operations[1].count, 1);
+    }
+
+    #[tokio::test]
+    async fn audit_keeps_the_operations_of_the_previous_audit() {
+        let previous =
+            UnknownOperationAudit::new(&PersistedQueriesAudit::builder().enabled(true).build());
+        previous.record("query A { a }".to_string(), Some("web".to_string()));
+        previous.record("query B { b }".to_string(), None);
+        previous.record("query B { b }".to_string(), None);
+
+        let audit = UnknownOperationAudit::new(
+            &PersistedQueriesAudit::builder()
+                .enabled(true)
+                .max_operations(1)
+                .build(),
+        );
+        audit.keep_operations_of(&previous);
+
+        // Only the most frequent operation fits
+        let report = audit.report();
+        assert_eq!(report.operations.len(), 1);
+        assert_eq!(report.operations[0].body, "query B { b }");
+        assert_eq!(report.operations[0].count, 2);
+    }
+
+    #[tokio::test]
+    async fn audit_is_served_over_http() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let config = PersistedQueriesAudit::builder()
+            .enabled(true)
+            .listen(ListenAddr::from(address))
+            .build();
+        let audit = UnknownOperationAudit::new(&config);
+        audit.record("query A { a }".to_string(), Some("web".to_string()));
+
+        let (listen, mut endpoints) = audit.web_endpoints(&config).into_iter().next().unwrap();
+        assert_eq!(listen, ListenAddr::from(address));
+        let router = endpoints.pop().unwrap().into_router();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service()),
+        );
+
+        let response = hyper::Client::new()
+            .get(
+                format!("http://{address}/persisted-queries/unknown")
+                    .parse()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let report: UnknownOperationReport =
+            serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await.unwrap())
+                .unwrap();
+        assert_eq!(report.operations.len(), 1);
+        assert_eq!(report.operations[0].body, "query A { a }");
+        assert_eq!(report.operations[0].count, 1);
+    }
+
+    #[tokio::test]
+    async fn audit_is_dumped_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("unknown-operations.json");
+        let audit = UnknownOperationAudit::new(
+            &PersistedQueriesAudit::builder()
+                .enabled(true)
+                .dump_path(path.clone())
+                .dump_interval(Duration::from_millis(10))
+                .build(),
+        );
+        audit.record("query A { a }".to_string(), Some("web".to_string()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let dump: serde_json::Value =
+            serde_json::from_slice(&tokio::fs::read(&path).await.unwrap()).unwrap();
+        assert_eq!(dump["operations"][0]["body"], "query A { a }");
+        assert_eq!(dump["operations"][0]["count"], 1);
+        assert_eq!(dump["operations"][0]["client_names"][0], "web");
+    }
+}
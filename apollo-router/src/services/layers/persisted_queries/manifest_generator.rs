@@ -0,0 +1,296 @@
+//! Generation of a persisted query manifest from recorded traffic, to bootstrap a
+//! safelist for clients that don't use a trusted document toolchain.
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use apollo_compiler::ast;
+use sha1::Sha1;
+use sha2::Digest;
+use sha2::Sha256;
+use tower::BoxError;
+
+use super::audit::UnknownOperationReport;
+use super::manifest_poller::FreeformGraphQLSafelist;
+use super::manifest_poller::Operation;
+use super::manifest_poller::SignedUrlChunk;
+use crate::plugins::record_replay::recording::Recording;
+
+/// Hash algorithm used to generate the IDs of the operations
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum ManifestHash {
+    #[default]
+    Sha256,
+    Sha1,
+}
+
+impl ManifestHash {
+    fn id(&self, body: &str) -> String {
+        match self {
+            ManifestHash::Sha256 => hex::encode(Sha256::digest(body.as_bytes())),
+            ManifestHash::Sha1 => hex::encode(Sha1::digest(body.as_bytes())),
+        }
+    }
+}
+
+/// Where the operations of the manifest come from
+#[derive(Debug)]
+pub(crate) enum ManifestSource<'a> {
+    /// A directory of files written by the `experimental_record` plugin, along with the
+    /// header holding the client name of the recorded requests
+    Recordings {
+        directory: &'a Path,
+        client_name_header: &'a str,
+    },
+    /// A report of the unknown operations audit, from its endpoint or its dump file
+    Audit(&'a Path),
+}
+
+/// An operation read from the source, with the client names that sent it, or `None` if it
+/// was sent without a client name
+type SourceOperation = (String, Option<BTreeSet<String>>);
+
+/// Reads the operations of the source, and returns them as a manifest. Operations are
+/// normalized like freeform GraphQL matched against the safelist, and deduplicated.
+/// Operations that can't be parsed are skipped.
+///
+/// With `with_clients`, each operation is restricted to the clients that sent it, unless it
+/// was also sent without a client name.
+pub(crate) fn generate_manifest(
+    source: ManifestSource<'_>,
+    hash: ManifestHash,
+    with_clients: bool,
+) -> Result<SignedUrlChunk, BoxError> {
+    let source_operations = match source {
+        ManifestSource::Recordings {
+            directory,
+            client_name_header,
+        } => read_recordings(directory, client_name_header)?,
+        ManifestSource::Audit(path) => {
+            let report: UnknownOperationReport = serde_json::from_slice(&std::fs::read(path)?)
+                .map_err(|e| format!("could not read audit report {}: {e}", path.display()))?;
+            report
+                .operations
+                .into_iter()
+                .map(|operation| {
+                    let clients = (!operation.sent_without_client_name
+                        && !operation.client_names.is_empty())
+                    .then_some(operation.client_names);
+                    (operation.body, clients)
+                })
+                .collect()
+        }
+    };
+
+    // Keyed by ID so the manifest is deterministic
+    let mut operations: BTreeMap<String, SourceOperation> = BTreeMap::new();
+    for (body, clients) in source_operations {
+        let Ok(document) = ast::Document::parse(body.as_str(), "") else {
+            continue;
+        };
+        let normalized_body = FreeformGraphQLSafelist::normalize_body(Ok(&document));
+        match operations.entry(hash.id(&normalized_body)) {
+            Entry::Occupied(mut entry) => {
+                let (_, known_clients) = entry.get_mut();
+                match (known_clients.as_mut(), clients) {
+                    (Some(known_clients), Some(clients)) => known_clients.extend(clients),
+                    _ => *known_clients = None,
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((normalized_body, clients));
+            }
+        }
+    }
+
+    Ok(SignedUrlChunk {
+        format: "apollo-persisted-query-manifest".to_string(),
+        version: 1,
+        operations: operations
+            .into_iter()
+            .map(|(id, (body, clients))| Operation {
+                id,
+                body,
+                clients: clients
+                    .filter(|_| with_clients)
+                    .map(|clients| clients.into_iter().collect()),
+                metadata: None,
+            })
+            .collect(),
+        clients: None,
+    })
+}
+
+fn read_recordings(
+    directory: &Path,
+    client_name_header: &str,
+) -> Result<Vec<SourceOperation>, BoxError> {
+    let mut operations = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .map_or(true, |extension| extension != "json")
+        {
+            continue;
+        }
+        let recording: Recording = serde_json::from_slice(&std::fs::read(&path)?)
+            .map_err(|e| format!("could not read recording {}: {e}", path.display()))?;
+        let client_name = recording
+            .client_request
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(client_name_header))
+            .and_then(|(_, values)| values.first().cloned());
+        if let Some(query) = recording.client_request.query {
+            operations.push((query, client_name.map(|name| BTreeSet::from([name]))));
+        }
+    }
+    Ok(operations)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::services::layers::persisted_queries::audit::UnknownOperation;
+
+    fn recording(query: Option<&str>, client_name: Option<&str>) -> serde_json::Value {
+        let headers = match client_name {
+            Some(client_name) => serde_json::json!({ "apollographql-client-name": [client_name] }),
+            None => serde_json::json!({}),
+        };
+        serde_json::json!({
+            "supergraph_sdl": "",
+            "client_request": {
+                "query": query,
+                "operation_name": null,
+                "variables": {},
+                "headers": headers,
+                "method": "POST",
+                "uri": "http://localhost:4000/"
+            },
+            "client_response": { "chunks": [], "headers": {} },
+            "formatted_query_plan": null,
+            "subgraph_fetches": null
+        })
+    }
+
+    #[test]
+    fn manifest_from_recordings() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, query) in [
+            ("a.json", Some("query A { a }")),
+            ("b.json", Some("query A {\n  a\n}")),
+            ("c.json", Some("fragment F on Query { c } query C { ...F }")),
+            ("d.json", None),
+            ("e.json", Some("not graphql {")),
+        ] {
+            std::fs::write(dir.path().join(name), recording(query, None).to_string()).unwrap();
+        }
+        std::fs::write(dir.path().join("README.md"), "not a recording").unwrap();
+
+        let manifest = generate_manifest(
+            ManifestSource::Recordings {
+                directory: dir.path(),
+                client_name_header: "apollographql-client-name",
+            },
+            ManifestHash::Sha256,
+            false,
+        )
+        .unwrap();
+        assert_eq!(manifest.format, "apollo-persisted-query-manifest");
+        assert_eq!(manifest.version, 1);
+        assert_eq!(manifest.operations.len(), 2);
+        for operation in &manifest.operations {
+            assert_eq!(operation.id, ManifestHash::Sha256.id(&operation.body));
+        }
+        assert!(manifest
+            .operations
+            .iter()
+            .any(|operation| operation.body.starts_with("query A")));
+    }
+
+    #[test]
+    fn manifest_from_recordings_with_clients() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, query, client_name) in [
+            ("a1.json", "query A { a }", Some("web")),
+            ("a2.json", "query A {\n  a\n}", Some("ios")),
+            ("b1.json", "query B { b }", Some("web")),
+            ("b2.json", "query B { b }", None),
+        ] {
+            std::fs::write(
+                dir.path().join(name),
+                recording(Some(query), client_name).to_string(),
+            )
+            .unwrap();
+        }
+
+        let generate = |with_clients| {
+            generate_manifest(
+                ManifestSource::Recordings {
+                    directory: dir.path(),
+                    client_name_header: "apollographql-client-name",
+                },
+                ManifestHash::Sha256,
+                with_clients,
+            )
+            .unwrap()
+        };
+        let clients_of = |manifest: &SignedUrlChunk, prefix: &str| {
+            manifest
+                .operations
+                .iter()
+                .find(|operation| operation.body.starts_with(prefix))
+                .unwrap()
+                .clients
+                .clone()
+        };
+
+        let manifest = generate(true);
+        assert_eq!(
+            clients_of(&manifest, "query A"),
+            Some(vec!["ios".to_string(), "web".to_string()])
+        );
+        // Sent without a client name: available to all clients
+        assert_eq!(clients_of(&manifest, "query B"), None);
+
+        let manifest = generate(false);
+        assert_eq!(clients_of(&manifest, "query A"), None);
+    }
+
+    #[test]
+    fn manifest_from_audit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.json");
+        let report = UnknownOperationReport {
+            operations: vec![UnknownOperation {
+                body: "query A { a }".to_string(),
+                count: 3,
+                client_names: BTreeSet::from(["web".to_string()]),
+                sent_without_client_name: false,
+                first_seen: SystemTime::now(),
+                last_seen: SystemTime::now(),
+            }],
+        };
+        std::fs::write(&path, serde_json::to_vec(&report).unwrap()).unwrap();
+
+        let manifest =
+            generate_manifest(ManifestSource::Audit(&path), ManifestHash::Sha1, true).unwrap();
+        assert_eq!(manifest.operations.len(), 1);
+        assert_eq!(manifest.operations[0].id.len(), 40);
+        assert_eq!(
+            manifest.operations[0].id,
+            ManifestHash::Sha1.id(&manifest.operations[0].body)
+        );
+        assert_eq!(
+            manifest.operations[0].clients,
+            Some(vec!["web".to_string()])
+        );
+    }
+}
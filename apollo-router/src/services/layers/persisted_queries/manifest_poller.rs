@@ -2,11 +2,20 @@
 
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::time::Duration;
+use std::time::SystemTime;
 
 use apollo_compiler::ast;
 use futures::prelude::*;
+use futures::stream::BoxStream;
+use http::header::HeaderName;
+use http::header::CACHE_CONTROL;
+use http::HeaderMap;
+use http::HeaderValue;
 use reqwest::Client;
 use serde::Deserialize;
 use serde::Serialize;
@@ -23,6 +32,51 @@ use crate::Configuration;
 /// An in memory cache of persisted queries.
 pub(crate) type PersistedQueryManifest = HashMap<String, String>;
 
+/// The client names that operations of the manifest are restricted to, by operation ID.
+/// Operations that aren't tagged with any client name are available to all clients.
+pub(crate) type PersistedQueryClients = HashMap<String, HashSet<String>>;
+
+/// The metadata of the operations of the manifest that have some, by operation ID.
+pub(crate) type PersistedQueryMetadata = HashMap<String, Arc<OperationMetadata>>;
+
+/// The operations loaded from one or more manifests, along with their client restrictions.
+#[derive(Debug, Default)]
+pub(crate) struct LoadedManifest {
+    operations: PersistedQueryManifest,
+    clients: PersistedQueryClients,
+    metadata: PersistedQueryMetadata,
+}
+
+impl LoadedManifest {
+    /// Adds an operation of a manifest chunk. Client names set on the operation take
+    /// precedence over the ones set on the whole chunk.
+    fn insert(&mut self, operation: Operation, chunk_clients: Option<&Vec<String>>) {
+        match operation.clients.as_ref().or(chunk_clients) {
+            Some(clients) => {
+                self.clients
+                    .insert(operation.id.clone(), clients.iter().cloned().collect());
+            }
+            None => {
+                self.clients.remove(&operation.id);
+            }
+        }
+        match operation.metadata {
+            Some(metadata) => {
+                self.metadata
+                    .insert(operation.id.clone(), Arc::new(metadata));
+            }
+            None => {
+                self.metadata.remove(&operation.id);
+            }
+        }
+        self.operations.insert(operation.id, operation.body);
+    }
+
+    fn len(&self) -> usize {
+        self.operations.len()
+    }
+}
+
 /// How the router should respond to requests that are not resolved as the IDs
 /// of an operation in the manifest. (For the most part this means "requests
 /// sent as freeform GraphQL", though it also includes requests sent as an ID
@@ -50,7 +104,10 @@ pub(crate) enum FreeformGraphQLBehavior {
 /// Describes what the router should do for a given request: allow it, deny it
 /// with an error, or allow it but log the operation as unknown.
 pub(crate) enum FreeformGraphQLAction {
-    Allow,
+    /// Holds the ID of the manifest operation with the same body, if the body was matched against the safelist
+    Allow {
+        persisted_query_id: Option<String>,
+    },
     Deny,
     AllowAndLog,
     DenyAndLog,
@@ -60,9 +117,12 @@ impl FreeformGraphQLBehavior {
     fn action_for_freeform_graphql(
         &self,
         ast: Result<&ast::Document, &str>,
+        client_name: Option<&str>,
     ) -> FreeformGraphQLAction {
         match self {
-            FreeformGraphQLBehavior::AllowAll { .. } => FreeformGraphQLAction::Allow,
+            FreeformGraphQLBehavior::AllowAll { .. } => FreeformGraphQLAction::Allow {
+                persisted_query_id: None,
+            },
             // Note that this branch doesn't get called in practice, because we catch
             // DenyAll at an earlier phase with never_allows_freeform_graphql.
             FreeformGraphQLBehavior::DenyAll { log_unknown, .. } => {
@@ -77,8 +137,10 @@ impl FreeformGraphQLBehavior {
                 log_unknown,
                 ..
             } => {
-                if safelist.is_allowed(ast) {
-                    FreeformGraphQLAction::Allow
+                if let Some(persisted_query_id) = safelist.operation_id(ast, client_name) {
+                    FreeformGraphQLAction::Allow {
+                        persisted_query_id: Some(persisted_query_id.to_string()),
+                    }
                 } else if *log_unknown {
                     FreeformGraphQLAction::DenyAndLog
                 } else {
@@ -86,8 +148,10 @@ impl FreeformGraphQLBehavior {
                 }
             }
             FreeformGraphQLBehavior::LogUnlessInSafelist { safelist, .. } => {
-                if safelist.is_allowed(ast) {
-                    FreeformGraphQLAction::Allow
+                if let Some(persisted_query_id) = safelist.operation_id(ast, client_name) {
+                    FreeformGraphQLAction::Allow {
+                        persisted_query_id: Some(persisted_query_id.to_string()),
+                    }
                 } else {
                     FreeformGraphQLAction::AllowAndLog
                 }
@@ -114,42 +178,72 @@ impl FreeformGraphQLBehavior {
 /// importantly, once we're doing any normalization at all, it's much easier to
 /// normalize to the default formatting instead of trying to preserve
 /// formatting.
+///
+/// Each normalized body maps to the IDs of the operations registered with it,
+/// along with the client names each of them is restricted to, or `None` if any
+/// client can send it.
 #[derive(Debug)]
 pub(crate) struct FreeformGraphQLSafelist {
-    normalized_bodies: HashSet<String>,
+    normalized_bodies: HashMap<String, Vec<(String, Option<HashSet<String>>)>>,
 }
 
 impl FreeformGraphQLSafelist {
-    fn new(manifest: &PersistedQueryManifest) -> Self {
+    fn new(manifest: &PersistedQueryManifest, clients: &PersistedQueryClients) -> Self {
         let mut safelist = Self {
-            normalized_bodies: HashSet::new(),
+            normalized_bodies: HashMap::new(),
         };
 
-        for body in manifest.values() {
-            safelist.insert_from_manifest(body);
+        for (id, body) in manifest {
+            safelist.insert_from_manifest(id, body, clients.get(id));
+        }
+        // The same body can be registered by several operations: the first one the
+        // client can send is the one it is matched with.
+        for operations in safelist.normalized_bodies.values_mut() {
+            operations.sort_by(|(a, _), (b, _)| a.cmp(b));
         }
 
         safelist
     }
 
-    fn insert_from_manifest(&mut self, body_from_manifest: &str) {
-        self.normalized_bodies.insert(
-            self.normalize_body(
-                ast::Document::parse(body_from_manifest, "from_manifest")
-                    .as_ref()
-                    .map_err(|_| body_from_manifest),
-            ),
+    fn insert_from_manifest(
+        &mut self,
+        id: &str,
+        body_from_manifest: &str,
+        clients: Option<&HashSet<String>>,
+    ) {
+        let normalized_body = Self::normalize_body(
+            ast::Document::parse(body_from_manifest, "from_manifest")
+                .as_ref()
+                .map_err(|_| body_from_manifest),
         );
+        self.normalized_bodies
+            .entry(normalized_body)
+            .or_default()
+            .push((id.to_string(), clients.cloned()));
     }
 
-    fn is_allowed(&self, ast: Result<&ast::Document, &str>) -> bool {
+    /// The ID of the manifest operation with the same body that the client can send, if any
+    fn operation_id(
+        &self,
+        ast: Result<&ast::Document, &str>,
+        client_name: Option<&str>,
+    ) -> Option<&str> {
         // Note: consider adding an LRU cache that caches this function's return
         // value based solely on body_from_request without needing to normalize
         // the body.
-        self.normalized_bodies.contains(&self.normalize_body(ast))
+        self.normalized_bodies
+            .get(&Self::normalize_body(ast))?
+            .iter()
+            .find(|(_, allowed_clients)| match allowed_clients {
+                None => true,
+                Some(allowed_clients) => {
+                    client_name.map_or(false, |name| allowed_clients.contains(name))
+                }
+            })
+            .map(|(id, _)| id.as_str())
     }
 
-    fn normalize_body(&self, ast: Result<&ast::Document, &str>) -> String {
+    pub(crate) fn normalize_body(ast: Result<&ast::Document, &str>) -> String {
         match ast {
             Err(body_from_request) => {
                 // If we can't parse the operation (whether from the PQ list or the
@@ -192,6 +286,8 @@ impl FreeformGraphQLSafelist {
 #[derive(Debug)]
 pub(crate) struct PersistedQueryManifestPollerState {
     persisted_query_manifest: PersistedQueryManifest,
+    persisted_query_clients: PersistedQueryClients,
+    persisted_query_metadata: PersistedQueryMetadata,
     pub(crate) freeform_graphql_behavior: FreeformGraphQLBehavior,
 }
 
@@ -207,16 +303,31 @@ impl PersistedQueryManifestPoller {
     /// Starts polling immediately and this function only returns after all chunks have been fetched
     /// and the [`PersistedQueryManifest`] has been fully populated.
     pub(crate) async fn new(config: Configuration) -> Result<Self, BoxError> {
-        if let Some(uplink_config) = config.uplink.as_ref() {
-            // Note that the contents of this Arc<RwLock> will be overwritten by poll_uplink before
-            // we return from this `new` method, so the particular choice of freeform_graphql_behavior
-            // here does not matter. (Can we improve this? We could use an Option but then we'd just
-            // end up `unwrap`ping a lot later. Perhaps MaybeUninit, but that's even worse?)
-            let state = Arc::new(RwLock::new(PersistedQueryManifestPollerState {
-                persisted_query_manifest: PersistedQueryManifest::new(),
-                freeform_graphql_behavior: FreeformGraphQLBehavior::DenyAll { log_unknown: false },
-            }));
+        // Note that the contents of this Arc<RwLock> will be overwritten by the poller before
+        // we return from this `new` method, so the particular choice of freeform_graphql_behavior
+        // here does not matter. (Can we improve this? We could use an Option but then we'd just
+        // end up `unwrap`ping a lot later. Perhaps MaybeUninit, but that's even worse?)
+        let state = Arc::new(RwLock::new(PersistedQueryManifestPollerState {
+            persisted_query_manifest: PersistedQueryManifest::new(),
+            persisted_query_clients: PersistedQueryClients::new(),
+            persisted_query_metadata: PersistedQueryMetadata::new(),
+            freeform_graphql_behavior: FreeformGraphQLBehavior::DenyAll { log_unknown: false },
+        }));
+
+        let (_drop_signal, drop_receiver) = mpsc::channel::<()>(1);
+        let (ready_sender, mut ready_receiver) = mpsc::channel::<ManifestPollResultOnStartup>(1);
 
+        if let Some(local_manifests) = config.persisted_queries.local_manifests.clone() {
+            // start reading the persisted queries from the local manifest files
+            tokio::task::spawn(poll_local_manifests(
+                local_manifests,
+                config.persisted_queries.hot_reload,
+                state.clone(),
+                config,
+                ready_sender,
+                drop_receiver,
+            ));
+        } else if let Some(uplink_config) = config.uplink.as_ref() {
             let http_client = Client::builder().timeout(uplink_config.timeout).gzip(true).build()
             .map_err(|e| -> BoxError {
                 format!(
@@ -225,10 +336,6 @@ impl PersistedQueryManifestPoller {
                 ).into()
             })?;
 
-            let (_drop_signal, drop_receiver) = mpsc::channel::<()>(1);
-            let (ready_sender, mut ready_receiver) =
-                mpsc::channel::<ManifestPollResultOnStartup>(1);
-
             // start polling uplink for persisted query chunks
             tokio::task::spawn(poll_uplink(
                 uplink_config.clone(),
@@ -238,26 +345,26 @@ impl PersistedQueryManifestPoller {
                 drop_receiver,
                 http_client,
             ));
+        } else {
+            return Err("persisted queries requires Apollo GraphOS or local manifests. ensure that you have set APOLLO_KEY and APOLLO_GRAPH_REF environment variables, or `persisted_queries.local_manifests` in the configuration".into());
+        }
 
-            // wait for the uplink poller to report its first success and continue
-            // or report the error
-            match ready_receiver.recv().await {
-                Some(startup_result) => match startup_result {
-                    ManifestPollResultOnStartup::LoadedOperations => (),
-                    ManifestPollResultOnStartup::Err(error) => return Err(error),
-                },
-                None => {
-                    return Err("could not receive ready event for persisted query layer".into());
-                }
+        // wait for the poller to report its first success and continue
+        // or report the error
+        match ready_receiver.recv().await {
+            Some(startup_result) => match startup_result {
+                ManifestPollResultOnStartup::LoadedOperations => (),
+                ManifestPollResultOnStartup::Err(error) => return Err(error),
+            },
+            None => {
+                return Err("could not receive ready event for persisted query layer".into());
             }
-
-            Ok(Self {
-                state,
-                _drop_signal,
-            })
-        } else {
-            Err("persisted queries requires Apollo GraphOS. ensure that you have set APOLLO_KEY and APOLLO_GRAPH_REF environment variables".into())
         }
+
+        Ok(Self {
+            state,
+            _drop_signal,
+        })
     }
 
     pub(crate) fn get_operation_body(&self, persisted_query_id: &str) -> Option<String> {
@@ -271,6 +378,40 @@ impl PersistedQueryManifestPoller {
             .cloned()
     }
 
+    /// Whether the operation registered with this ID can be sent by the client.
+    /// Operations that aren't restricted to any client are allowed for everyone,
+    /// including requests without a client name.
+    pub(crate) fn is_operation_allowed_for_client(
+        &self,
+        persisted_query_id: &str,
+        client_name: Option<&str>,
+    ) -> bool {
+        let state = self
+            .state
+            .read()
+            .expect("could not acquire read lock on persisted query manifest state");
+        match state.persisted_query_clients.get(persisted_query_id) {
+            None => true,
+            Some(allowed_clients) => {
+                client_name.map_or(false, |name| allowed_clients.contains(name))
+            }
+        }
+    }
+
+    pub(crate) fn get_operation_metadata(
+        &self,
+        persisted_query_id: &str,
+    ) -> Option<Arc<OperationMetadata>> {
+        let state = self
+            .state
+            .read()
+            .expect("could not acquire read lock on persisted query manifest state");
+        state
+            .persisted_query_metadata
+            .get(persisted_query_id)
+            .cloned()
+    }
+
     pub(crate) fn get_all_operations(&self) -> Vec<String> {
         let state = self
             .state
@@ -282,6 +423,7 @@ impl PersistedQueryManifestPoller {
     pub(crate) fn action_for_freeform_graphql(
         &self,
         ast: Result<&ast::Document, &str>,
+        client_name: Option<&str>,
     ) -> FreeformGraphQLAction {
         let state = self
             .state
@@ -289,7 +431,7 @@ impl PersistedQueryManifestPoller {
             .expect("could not acquire read lock on persisted query state");
         state
             .freeform_graphql_behavior
-            .action_for_freeform_graphql(ast)
+            .action_for_freeform_graphql(ast, client_name)
     }
 
     // Some(bool) means "never allows freeform GraphQL, bool is whether or not to log"
@@ -329,37 +471,85 @@ async fn poll_uplink(
     state: Arc<RwLock<PersistedQueryManifestPollerState>>,
     config: Configuration,
     ready_sender: mpsc::Sender<ManifestPollResultOnStartup>,
-    mut drop_receiver: mpsc::Receiver<()>,
+    drop_receiver: mpsc::Receiver<()>,
     http_client: Client,
 ) {
     let http_client = http_client.clone();
-    let mut uplink_executor = stream::select_all(vec![
-        stream_from_uplink_transforming_new_response::<
-            PersistedQueriesManifestQuery,
-            MaybePersistedQueriesManifestChunks,
-            Option<PersistedQueryManifest>,
-        >(uplink_config.clone(), move |response| {
-            let http_client = http_client.clone();
-            Box::new(Box::pin(async move {
-                match response {
-                    Some(chunks) => manifest_from_chunks(chunks, http_client)
-                        .await
-                        .map(Some)
-                        .map_err(|err| {
-                            format!("could not download persisted query lists: {}", err).into()
-                        }),
-                    None => Ok(None),
+    let uplink_events = stream_from_uplink_transforming_new_response::<
+        PersistedQueriesManifestQuery,
+        MaybePersistedQueriesManifestChunks,
+        Option<LoadedManifest>,
+    >(uplink_config.clone(), move |response| {
+        let http_client = http_client.clone();
+        Box::new(Box::pin(async move {
+            match response {
+                Some(chunks) => manifest_from_chunks(chunks, http_client)
+                    .await
+                    .map(Some)
+                    .map_err(|err| {
+                        format!("could not download persisted query lists: {}", err).into()
+                    }),
+                None => Ok(None),
+            }
+        }))
+    })
+    .map(|res| match res {
+        Ok(Some(new_manifest)) => ManifestPollEvent::NewManifest(new_manifest),
+        Ok(None) => ManifestPollEvent::NoPersistedQueryList {
+            graph_ref: uplink_config.apollo_graph_ref.clone(),
+        },
+        Err(e) => ManifestPollEvent::Err(e.into()),
+    })
+    .boxed();
+
+    process_manifest_poll_events(uplink_events, state, config, ready_sender, drop_receiver).await
+}
+
+async fn poll_local_manifests(
+    paths: Vec<PathBuf>,
+    hot_reload: bool,
+    state: Arc<RwLock<PersistedQueryManifestPollerState>>,
+    config: Configuration,
+    ready_sender: mpsc::Sender<ManifestPollResultOnStartup>,
+    drop_receiver: mpsc::Receiver<()>,
+) {
+    // Every watch starts with an event telling to read the file: skip it, the manifests are
+    // read once below for all the files
+    let changes = if hot_reload {
+        stream::select_all(
+            paths
+                .iter()
+                .map(|path| crate::files::watch(path).skip(1).boxed()),
+        )
+        .boxed()
+    } else {
+        stream::empty().boxed()
+    };
+    let local_events = stream::once(future::ready(()))
+        .chain(changes)
+        .then(move |_| {
+            let paths = paths.clone();
+            async move {
+                match manifest_from_files(&paths).await {
+                    Ok(new_manifest) => ManifestPollEvent::NewManifest(new_manifest),
+                    Err(e) => ManifestPollEvent::Err(e),
                 }
-            }))
-        })
-        .map(|res| match res {
-            Ok(Some(new_manifest)) => ManifestPollEvent::NewManifest(new_manifest),
-            Ok(None) => ManifestPollEvent::NoPersistedQueryList {
-                graph_ref: uplink_config.apollo_graph_ref.clone(),
-            },
-            Err(e) => ManifestPollEvent::Err(e.into()),
+            }
         })
-        .boxed(),
+        .boxed();
+
+    process_manifest_poll_events(local_events, state, config, ready_sender, drop_receiver).await
+}
+
+async fn process_manifest_poll_events(
+    events: BoxStream<'static, ManifestPollEvent>,
+    state: Arc<RwLock<PersistedQueryManifestPollerState>>,
+    config: Configuration,
+    ready_sender: mpsc::Sender<ManifestPollResultOnStartup>,
+    mut drop_receiver: mpsc::Receiver<()>,
+) {
+    let mut poll_executor = stream::select_all(vec![
+        events,
         drop_receiver
             .recv()
             .into_stream()
@@ -380,23 +570,28 @@ async fn poll_uplink(
 
     let mut ready_sender_once = Some(ready_sender);
 
-    while let Some(event) = uplink_executor.next().await {
+    while let Some(event) = poll_executor.next().await {
         match event {
-            ManifestPollEvent::NewManifest(new_manifest) => {
+            ManifestPollEvent::NewManifest(LoadedManifest {
+                operations: new_manifest,
+                clients: new_clients,
+                metadata: new_metadata,
+            }) => {
+                // Unknown operations are detected the same way for the audit as for logging
+                let log_unknown =
+                    config.persisted_queries.log_unknown || config.persisted_queries.audit.enabled;
                 let freeform_graphql_behavior = if config.persisted_queries.safelist.enabled {
                     if config.persisted_queries.safelist.require_id {
-                        FreeformGraphQLBehavior::DenyAll {
-                            log_unknown: config.persisted_queries.log_unknown,
-                        }
+                        FreeformGraphQLBehavior::DenyAll { log_unknown }
                     } else {
                         FreeformGraphQLBehavior::AllowIfInSafelist {
-                            safelist: FreeformGraphQLSafelist::new(&new_manifest),
-                            log_unknown: config.persisted_queries.log_unknown,
+                            safelist: FreeformGraphQLSafelist::new(&new_manifest, &new_clients),
+                            log_unknown,
                         }
                     }
-                } else if config.persisted_queries.log_unknown {
+                } else if log_unknown {
                     FreeformGraphQLBehavior::LogUnlessInSafelist {
-                        safelist: FreeformGraphQLSafelist::new(&new_manifest),
+                        safelist: FreeformGraphQLSafelist::new(&new_manifest, &new_clients),
                         apq_enabled: config.apq.enabled,
                     }
                 } else {
@@ -407,6 +602,8 @@ async fn poll_uplink(
 
                 let new_state = PersistedQueryManifestPollerState {
                     persisted_query_manifest: new_manifest,
+                    persisted_query_clients: new_clients,
+                    persisted_query_metadata: new_metadata,
                     freeform_graphql_behavior,
                 };
 
@@ -460,10 +657,7 @@ async fn poll_uplink(
             (None, ManifestPollResultOnStartup::Err(err)) => {
                 // We've already successfully started up, but we received some sort of error. This doesn't
                 // need to break our functional router, but we can log in case folks are interested.
-                tracing::error!(
-                    "error while polling uplink for persisted query manifests: {}",
-                    err
-                )
+                tracing::error!("error while polling for persisted query manifests: {}", err)
             }
             // Do nothing in the normal background "new manifest" case.
             (None, ManifestPollResultOnStartup::LoadedOperations) => {}
@@ -474,8 +668,8 @@ async fn poll_uplink(
 async fn manifest_from_chunks(
     new_chunks: Vec<PersistedQueriesManifestChunk>,
     http_client: Client,
-) -> Result<PersistedQueryManifest, BoxError> {
-    let mut new_persisted_query_manifest = PersistedQueryManifest::new();
+) -> Result<LoadedManifest, BoxError> {
+    let mut new_persisted_query_manifest = LoadedManifest::default();
     tracing::debug!("ingesting new persisted queries: {:?}", &new_chunks);
     // TODO: consider doing these fetches in parallel
     for new_chunk in new_chunks {
@@ -497,7 +691,7 @@ async fn manifest_from_chunks(
 
 async fn add_chunk_to_operations(
     chunk: PersistedQueriesManifestChunk,
-    operations: &mut PersistedQueryManifest,
+    manifest: &mut LoadedManifest,
     http_client: Client,
 ) -> Result<(), BoxError> {
     let mut it = chunk.urls.iter().peekable();
@@ -505,7 +699,7 @@ async fn add_chunk_to_operations(
         match fetch_chunk(http_client.clone(), chunk_url).await {
             Ok(chunk) => {
                 for operation in chunk.operations {
-                    operations.insert(operation.id, operation.body);
+                    manifest.insert(operation, chunk.clients.as_ref());
                 }
                 return Ok(());
             }
@@ -554,21 +748,42 @@ async fn fetch_chunk(http_client: Client, chunk_url: &String) -> Result<SignedUr
             .into()
         })?;
 
-    if chunk.format != "apollo-persisted-query-manifest" {
-        return Err("chunk format is not 'apollo-persisted-query-manifest'".into());
-    }
+    chunk.validate()
+}
 
-    if chunk.version != 1 {
-        return Err("persisted query manifest chunk version is not 1".into());
+async fn manifest_from_files(paths: &[PathBuf]) -> Result<LoadedManifest, BoxError> {
+    let mut new_persisted_query_manifest = LoadedManifest::default();
+    for path in paths {
+        let chunk = read_manifest_file(path).await.map_err(|e| -> BoxError {
+            format!(
+                "could not read persisted query manifest at {}: {}",
+                path.display(),
+                e
+            )
+            .into()
+        })?;
+        for operation in chunk.operations {
+            new_persisted_query_manifest.insert(operation, chunk.clients.as_ref());
+        }
     }
 
-    Ok(chunk)
+    tracing::info!(
+        "Loaded {} persisted queries from local manifests.",
+        new_persisted_query_manifest.len()
+    );
+
+    Ok(new_persisted_query_manifest)
+}
+
+async fn read_manifest_file(path: &Path) -> Result<SignedUrlChunk, BoxError> {
+    let contents = tokio::fs::read(path).await?;
+    serde_json::from_slice::<SignedUrlChunk>(&contents)?.validate()
 }
 
 /// Types of events produced by the manifest poller.
 #[derive(Debug)]
 pub(crate) enum ManifestPollEvent {
-    NewManifest(PersistedQueryManifest),
+    NewManifest(LoadedManifest),
     NoPersistedQueryList { graph_ref: String },
     Err(BoxError),
     Shutdown,
@@ -587,6 +802,23 @@ pub(crate) struct SignedUrlChunk {
     pub(crate) format: String,
     pub(crate) version: u64,
     pub(crate) operations: Vec<Operation>,
+    /// Restricts all operations of the chunk to these client names
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) clients: Option<Vec<String>>,
+}
+
+impl SignedUrlChunk {
+    fn validate(self) -> Result<Self, BoxError> {
+        if self.format != "apollo-persisted-query-manifest" {
+            return Err("chunk format is not 'apollo-persisted-query-manifest'".into());
+        }
+
+        if self.version != 1 {
+            return Err("persisted query manifest chunk version is not 1".into());
+        }
+
+        Ok(self)
+    }
 }
 
 /// A single operation containing an ID and a body,
@@ -594,6 +826,86 @@ pub(crate) struct SignedUrlChunk {
 pub(crate) struct Operation {
     pub(crate) id: String,
     pub(crate) body: String,
+    /// Restricts the operation to these client names, overriding the ones of the chunk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) clients: Option<Vec<String>>,
+    /// Policy applied when the operation is sent by ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) metadata: Option<OperationMetadata>,
+}
+
+/// Per-operation policy carried by the manifest, applied by the router when a
+/// request runs the operation by ID.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct OperationMetadata {
+    /// Maximum duration of the request before it fails with a timeout
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub(crate) timeout: Option<Duration>,
+    /// Maximum cost allowed by demand control, replacing the configured one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) max_cost: Option<f64>,
+    /// Lifetime of successful responses, sent in the `Cache-Control` header
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub(crate) cache_ttl: Option<Duration>,
+    /// Scopes that must all be in the `scope` claim of the request's JWT
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) required_scopes: Vec<String>,
+    /// Marks the operation as deprecated, sent in the `Deprecation` header
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) deprecated: bool,
+    /// RFC 3339 date after which the operation is rejected, sent in the `Sunset` header
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub(crate) sunset: Option<SystemTime>,
+}
+
+impl OperationMetadata {
+    pub(crate) fn is_sunset(&self) -> bool {
+        self.sunset
+            .map_or(false, |sunset| sunset <= SystemTime::now())
+    }
+
+    /// Adds the deprecation headers, and the caching header if the response can be cached
+    ///
+    /// Responses of authenticated requests and of scoped operations are only cached by the
+    /// client, shared caches must not serve them to other users.
+    pub(crate) fn add_response_headers(
+        &self,
+        headers: &mut HeaderMap,
+        cacheable: bool,
+        authenticated: bool,
+    ) {
+        if self.deprecated {
+            headers.insert(
+                HeaderName::from_static("deprecation"),
+                HeaderValue::from_static("true"),
+            );
+        }
+        if let Some(sunset) = self.sunset.and_then(http_date) {
+            if let Ok(value) = HeaderValue::from_str(&sunset) {
+                headers.insert(HeaderName::from_static("sunset"), value);
+            }
+        }
+        if let (Some(ttl), true) = (self.cache_ttl, cacheable) {
+            let private = if authenticated || !self.required_scopes.is_empty() {
+                "private, "
+            } else {
+                ""
+            };
+            if let Ok(value) = HeaderValue::from_str(&format!("{private}max-age={}", ttl.as_secs()))
+            {
+                headers.insert(CACHE_CONTROL, value);
+            }
+        }
+    }
+}
+
+fn http_date(date: SystemTime) -> Option<String> {
+    let format = time::format_description::parse(
+        "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT",
+    )
+    .ok()?;
+    time::OffsetDateTime::from(date).format(&format).ok()
 }
 
 #[cfg(test)]
@@ -601,6 +913,9 @@ mod tests {
     use url::Url;
 
     use super::*;
+    use crate::configuration::PersistedQueries;
+    use crate::files::tests::create_temp_file;
+    use crate::files::tests::write_and_flush;
     use crate::test_harness::mocks::persisted_queries::*;
     use crate::uplink::Endpoints;
 
@@ -652,6 +967,252 @@ mod tests {
         assert_eq!(manifest_manager.get_operation_body(&id), Some(body))
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn poller_can_read_local_manifests() {
+        let (path1, mut file1) = create_temp_file();
+        let (path2, mut file2) = create_temp_file();
+        write_and_flush(&mut file1, &local_manifest("1", "query { a }")).await;
+        write_and_flush(&mut file2, &local_manifest("2", "query { b }")).await;
+
+        let manifest_manager = PersistedQueryManifestPoller::new(
+            Configuration::fake_builder()
+                .persisted_query(
+                    PersistedQueries::builder()
+                        .enabled(true)
+                        .local_manifests(vec![path1, path2.clone()])
+                        .hot_reload(true)
+                        .build(),
+                )
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            manifest_manager.get_operation_body("1"),
+            Some("query { a }".to_string())
+        );
+        assert_eq!(
+            manifest_manager.get_operation_body("2"),
+            Some("query { b }".to_string())
+        );
+
+        write_and_flush(&mut file2, &local_manifest("3", "query { c }")).await;
+        assert_eq!(manifest_manager.get_operation_body("2"), None);
+        assert_eq!(
+            manifest_manager.get_operation_body("3"),
+            Some("query { c }".to_string())
+        );
+
+        // An invalid manifest keeps the previous operations
+        write_and_flush(&mut file2, "not a manifest").await;
+        assert_eq!(
+            manifest_manager.get_operation_body("3"),
+            Some("query { c }".to_string())
+        );
+        let _ = std::fs::remove_file(path2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn poller_wont_start_with_invalid_local_manifest() {
+        let (path, mut file) = create_temp_file();
+        write_and_flush(
+            &mut file,
+            r#"{"format":"apollo-persisted-query-manifest","version":2,"operations":[]}"#,
+        )
+        .await;
+        assert!(PersistedQueryManifestPoller::new(
+            Configuration::fake_builder()
+                .persisted_query(
+                    PersistedQueries::builder()
+                        .enabled(true)
+                        .local_manifests(vec![path])
+                        .build(),
+                )
+                .build()
+                .unwrap(),
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn poller_reads_operation_metadata() {
+        let (path, mut file) = create_temp_file();
+        write_and_flush(
+            &mut file,
+            &serde_json::json!({
+                "format": "apollo-persisted-query-manifest",
+                "version": 1,
+                "operations": [
+                    {
+                        "id": "1",
+                        "body": "query { a }",
+                        "metadata": {
+                            "timeout": "5s",
+                            "maxCost": 100.0,
+                            "cacheTtl": "1m",
+                            "requiredScopes": ["read:a"],
+                            "deprecated": true,
+                            "sunset": "2030-01-01T00:00:00Z"
+                        }
+                    },
+                    { "id": "2", "body": "query { b }" }
+                ]
+            })
+            .to_string(),
+        )
+        .await;
+
+        let manifest_manager = PersistedQueryManifestPoller::new(
+            Configuration::fake_builder()
+                .persisted_query(
+                    PersistedQueries::builder()
+                        .enabled(true)
+                        .local_manifests(vec![path])
+                        .build(),
+                )
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            manifest_manager.get_operation_metadata("1").as_deref(),
+            Some(&OperationMetadata {
+                timeout: Some(Duration::from_secs(5)),
+                max_cost: Some(100.0),
+                cache_ttl: Some(Duration::from_secs(60)),
+                required_scopes: vec!["read:a".to_string()],
+                deprecated: true,
+                sunset: Some(humantime::parse_rfc3339("2030-01-01T00:00:00Z").unwrap()),
+            })
+        );
+        assert_eq!(manifest_manager.get_operation_metadata("2"), None);
+    }
+
+    fn local_manifest(id: &str, body: &str) -> String {
+        serde_json::json!({
+            "format": "apollo-persisted-query-manifest",
+            "version": 1,
+            "operations": [{ "id": id, "body": body }]
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn manifest_operations_can_be_restricted_to_clients() {
+        let chunk: SignedUrlChunk = serde_json::from_value(serde_json::json!({
+            "format": "apollo-persisted-query-manifest",
+            "version": 1,
+            "clients": ["web"],
+            "operations": [
+                { "id": "1", "body": "query A { a }" },
+                { "id": "2", "body": "query B { b }", "clients": ["ios", "android"] }
+            ]
+        }))
+        .unwrap();
+        let mut manifest = LoadedManifest::default();
+        for operation in chunk.operations {
+            manifest.insert(operation, chunk.clients.as_ref());
+        }
+        manifest.insert(
+            Operation {
+                id: "3".to_string(),
+                body: "query A { a }".to_string(),
+                clients: Some(vec!["ios".to_string()]),
+                metadata: None,
+            },
+            None,
+        );
+        manifest.insert(
+            Operation {
+                id: "4".to_string(),
+                body: "query C { c }".to_string(),
+                clients: None,
+                metadata: None,
+            },
+            None,
+        );
+        assert_eq!(manifest.clients["1"], HashSet::from(["web".to_string()]));
+        assert_eq!(
+            manifest.clients["2"],
+            HashSet::from(["ios".to_string(), "android".to_string()])
+        );
+        assert!(!manifest.clients.contains_key("4"));
+
+        let safelist = FreeformGraphQLSafelist::new(&manifest.operations, &manifest.clients);
+        let operation_id = |body: &str, client_name: Option<&str>| -> Option<String> {
+            safelist
+                .operation_id(
+                    ast::Document::parse(body, "").as_ref().map_err(|_| body),
+                    client_name,
+                )
+                .map(str::to_string)
+        };
+        let is_allowed = |body: &str, client_name: Option<&str>| -> bool {
+            operation_id(body, client_name).is_some()
+        };
+        // Bodies registered for several clients are allowed for all of them.
+        assert!(is_allowed("query A { a }", Some("web")));
+        assert!(is_allowed("query A { a }", Some("ios")));
+        assert!(!is_allowed("query A { a }", Some("android")));
+        assert!(!is_allowed("query A { a }", None));
+        assert!(is_allowed("query C { c }", Some("android")));
+        assert!(is_allowed("query C { c }", None));
+        // Bodies are matched with an operation the client can send
+        assert_eq!(
+            operation_id("query A { a }", Some("web")).as_deref(),
+            Some("1")
+        );
+        assert_eq!(
+            operation_id("query A { a }", Some("ios")).as_deref(),
+            Some("3")
+        );
+    }
+
+    #[test]
+    fn operation_metadata_response_headers() {
+        let metadata = OperationMetadata {
+            cache_ttl: Some(Duration::from_secs(60)),
+            deprecated: true,
+            sunset: Some(humantime::parse_rfc3339("2030-01-01T00:00:00Z").unwrap()),
+            ..Default::default()
+        };
+        assert!(!metadata.is_sunset());
+
+        let mut headers = HeaderMap::new();
+        metadata.add_response_headers(&mut headers, true, false);
+        assert_eq!(headers["deprecation"], "true");
+        assert_eq!(headers["sunset"], "Tue, 01 Jan 2030 00:00:00 GMT");
+        assert_eq!(headers[CACHE_CONTROL], "max-age=60");
+
+        let mut headers = HeaderMap::new();
+        metadata.add_response_headers(&mut headers, false, false);
+        assert!(!headers.contains_key(CACHE_CONTROL));
+
+        // Responses of authenticated requests are private
+        let mut headers = HeaderMap::new();
+        metadata.add_response_headers(&mut headers, true, true);
+        assert_eq!(headers[CACHE_CONTROL], "private, max-age=60");
+
+        // And so are the responses of scoped operations
+        let scoped = OperationMetadata {
+            cache_ttl: Some(Duration::from_secs(60)),
+            required_scopes: vec!["read:orders".to_string()],
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        scoped.add_response_headers(&mut headers, true, false);
+        assert_eq!(headers[CACHE_CONTROL], "private, max-age=60");
+
+        assert!(OperationMetadata {
+            sunset: Some(humantime::parse_rfc3339("2020-01-01T00:00:00Z").unwrap()),
+            ..Default::default()
+        }
+        .is_sunset());
+    }
+
     #[test]
     fn safelist_body_normalization() {
         let safelist = FreeformGraphQLSafelist::new(&PersistedQueryManifest::from([(
@@ -661,10 +1222,15 @@ mod tests {
         ), (
             "invalid-syntax".to_string(),
             "}}}".to_string()),
-        ]));
+        ]), &PersistedQueryClients::new());
 
         let is_allowed = |body: &str| -> bool {
-            safelist.is_allowed(ast::Document::parse(body, "").as_ref().map_err(|_| body))
+            safelist
+                .operation_id(
+                    ast::Document::parse(body, "").as_ref().map_err(|_| body),
+                    None,
+                )
+                .is_some()
         };
 
         // Precise string matches.
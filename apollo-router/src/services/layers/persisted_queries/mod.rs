@@ -1,26 +1,41 @@
+mod audit;
 mod id_extractor;
+mod manifest_generator;
 mod manifest_poller;
 
-#[cfg(test)]
 use std::sync::Arc;
 
+use apollo_compiler::ast;
 use http::header::CACHE_CONTROL;
 use http::HeaderValue;
 use http::StatusCode;
 use id_extractor::PersistedQueryIdExtractor;
+pub(crate) use manifest_generator::generate_manifest;
+pub(crate) use manifest_generator::ManifestHash;
+pub(crate) use manifest_generator::ManifestSource;
+pub(crate) use manifest_poller::OperationMetadata;
 pub(crate) use manifest_poller::PersistedQueryManifestPoller;
+use multimap::MultiMap;
 use tower::BoxError;
 
+use self::audit::UnknownOperationAudit;
 use self::manifest_poller::FreeformGraphQLAction;
+use self::manifest_poller::FreeformGraphQLSafelist;
 use super::query_analysis::ParsedDocument;
+use crate::configuration::PersistedQueriesAudit;
 use crate::graphql::Error as GraphQLError;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::plugins::telemetry::CLIENT_NAME;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
 use crate::Configuration;
+use crate::Endpoint;
+use crate::ListenAddr;
 
 const DONT_CACHE_RESPONSE_VALUE: &str = "private, no-cache, must-revalidate";
 
-struct UsedQueryIdFromManifest;
+/// Marks a request whose operation was loaded from the persisted query manifest, holding the ID it was loaded with
+pub(crate) struct UsedQueryIdFromManifest(pub(crate) String);
 
 #[derive(Debug)]
 pub(crate) struct PersistedQueryLayer {
@@ -28,6 +43,10 @@ pub(crate) struct PersistedQueryLayer {
     /// value of the manifest and projected safelist. None if the layer is disabled.
     pub(crate) manifest_poller: Option<PersistedQueryManifestPoller>,
     introspection_enabled: bool,
+    log_unknown: bool,
+    /// Aggregates unknown operations if the audit is enabled
+    audit: Option<Arc<UnknownOperationAudit>>,
+    audit_config: PersistedQueriesAudit,
 }
 
 impl PersistedQueryLayer {
@@ -35,16 +54,26 @@ impl PersistedQueryLayer {
     /// and optionally, an existing persisted query manifest poller.
     pub(crate) async fn new(configuration: &Configuration) -> Result<Self, BoxError> {
         if configuration.persisted_queries.enabled {
+            let audit_config = &configuration.persisted_queries.audit;
+            let audit = audit_config
+                .enabled
+                .then(|| UnknownOperationAudit::new(audit_config));
             Ok(Self {
                 manifest_poller: Some(
                     PersistedQueryManifestPoller::new(configuration.clone()).await?,
                 ),
                 introspection_enabled: configuration.supergraph.introspection,
+                log_unknown: configuration.persisted_queries.log_unknown,
+                audit,
+                audit_config: audit_config.clone(),
             })
         } else {
             Ok(Self {
                 manifest_poller: None,
                 introspection_enabled: configuration.supergraph.introspection,
+                log_unknown: false,
+                audit: None,
+                audit_config: Default::default(),
             })
         }
     }
@@ -69,7 +98,13 @@ impl PersistedQueryLayer {
                 // If we don't have an ID and we require an ID, return an error immediately,
                 if log_unknown {
                     if let Some(operation_body) = request.supergraph_request.body().query.as_ref() {
-                        log_unknown_operation(operation_body);
+                        self.report_unknown_operation(
+                            &request,
+                            operation_body,
+                            ast::Document::parse(operation_body.as_str(), "")
+                                .as_ref()
+                                .map_err(|_| operation_body.as_str()),
+                        );
                     }
                 }
                 Err(supergraph_err_pq_id_required(request))
@@ -89,7 +124,7 @@ impl PersistedQueryLayer {
     /// Places an operation body on a [`SupergraphRequest`] if it has been persisted
     pub(crate) fn replace_query_id_with_operation_body(
         &self,
-        mut request: SupergraphRequest,
+        request: SupergraphRequest,
         manifest_poller: &PersistedQueryManifestPoller,
         persisted_query_id: &str,
     ) -> Result<SupergraphRequest, SupergraphResponse> {
@@ -113,6 +148,21 @@ impl PersistedQueryLayer {
             if let Some(persisted_query_body) =
                 manifest_poller.get_operation_body(persisted_query_id)
             {
+                if !manifest_poller.is_operation_allowed_for_client(
+                    persisted_query_id,
+                    client_name(&request).as_deref(),
+                ) {
+                    tracing::info!(
+                        monotonic_counter.apollo.router.operations.persisted_queries = 1u64,
+                        persisted_queries.client.rejected = true
+                    );
+                    return Err(supergraph_err_operation_not_allowed_for_client(
+                        request,
+                        persisted_query_id,
+                    ));
+                }
+                let mut request =
+                    apply_operation_metadata(request, manifest_poller, persisted_query_id)?;
                 let body = request.supergraph_request.body_mut();
                 body.query = Some(persisted_query_body);
                 body.extensions.remove("persistedQuery");
@@ -122,7 +172,7 @@ impl PersistedQueryLayer {
                     .context
                     .extensions()
                     .lock()
-                    .insert(UsedQueryIdFromManifest);
+                    .insert(UsedQueryIdFromManifest(persisted_query_id.to_string()));
                 tracing::info!(monotonic_counter.apollo.router.operations.persisted_queries = 1u64);
                 Ok(request)
             } else if manifest_poller.augmenting_apq_with_pre_registration_and_no_safelisting() {
@@ -204,10 +254,19 @@ impl PersistedQueryLayer {
             return Ok(request);
         }
 
-        match manifest_poller.action_for_freeform_graphql(Ok(&doc.ast)) {
-            FreeformGraphQLAction::Allow => {
+        match manifest_poller
+            .action_for_freeform_graphql(Ok(&doc.ast), client_name(&request).as_deref())
+        {
+            FreeformGraphQLAction::Allow { persisted_query_id } => {
                 tracing::info!(monotonic_counter.apollo.router.operations.persisted_queries = 1u64,);
-                Ok(request)
+                // A body matching the safelist is the operation of the manifest: its metadata
+                // applies just as if the client had sent the ID.
+                match persisted_query_id {
+                    Some(persisted_query_id) => {
+                        apply_operation_metadata(request, manifest_poller, &persisted_query_id)
+                    }
+                    None => Ok(request),
+                }
             }
             FreeformGraphQLAction::Deny => {
                 tracing::info!(
@@ -222,7 +281,7 @@ impl PersistedQueryLayer {
                     monotonic_counter.apollo.router.operations.persisted_queries = 1u64,
                     persisted_queries.logged = true
                 );
-                log_unknown_operation(operation_body);
+                self.report_unknown_operation(&request, operation_body, Ok(&doc.ast));
                 Ok(request)
             }
             FreeformGraphQLAction::DenyAndLog => {
@@ -231,12 +290,46 @@ impl PersistedQueryLayer {
                     persisted_queries.safelist.rejected.unknown = true,
                     persisted_queries.logged = true
                 );
-                log_unknown_operation(operation_body);
+                self.report_unknown_operation(&request, operation_body, Ok(&doc.ast));
                 Err(supergraph_err_operation_not_in_safelist(request))
             }
         }
     }
 
+    /// Logs the unknown operation and records it in the audit, depending on the configuration
+    fn report_unknown_operation(
+        &self,
+        request: &SupergraphRequest,
+        operation_body: &str,
+        ast: Result<&ast::Document, &str>,
+    ) {
+        if self.log_unknown {
+            log_unknown_operation(operation_body);
+        }
+        if let Some(audit) = &self.audit {
+            audit.record(
+                FreeformGraphQLSafelist::normalize_body(ast),
+                client_name(request),
+            );
+        }
+    }
+
+    /// Keeps the unknown operations audited by the layer this one replaces, so that they survive
+    /// configuration and schema reloads
+    pub(crate) fn keep_audit_of(&self, previous: &PersistedQueryLayer) {
+        if let (Some(audit), Some(previous_audit)) = (&self.audit, &previous.audit) {
+            audit.keep_operations_of(previous_audit);
+        }
+    }
+
+    /// The audit endpoint, if enabled
+    pub(crate) fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        self.audit
+            .as_ref()
+            .map(|audit| audit.web_endpoints(&self.audit_config))
+            .unwrap_or_default()
+    }
+
     pub(crate) fn all_operations(&self) -> Option<Vec<String>> {
         self.manifest_poller
             .as_ref()
@@ -244,6 +337,65 @@ impl PersistedQueryLayer {
     }
 }
 
+/// The client name detected by telemetry from the client name header, the same one used for Studio reporting
+fn client_name(request: &SupergraphRequest) -> Option<String> {
+    request.context.get::<_, String>(CLIENT_NAME).ok().flatten()
+}
+
+/// Rejects sunset operations and operations the client lacks the scopes for, and records the
+/// rest of the metadata of the operation, which is applied later in the pipeline.
+fn apply_operation_metadata(
+    request: SupergraphRequest,
+    manifest_poller: &PersistedQueryManifestPoller,
+    persisted_query_id: &str,
+) -> Result<SupergraphRequest, SupergraphResponse> {
+    if let Some(metadata) = manifest_poller.get_operation_metadata(persisted_query_id) {
+        if metadata.is_sunset() {
+            tracing::info!(
+                monotonic_counter.apollo.router.operations.persisted_queries = 1u64,
+                persisted_queries.sunset.rejected = true
+            );
+            return Err(supergraph_err_operation_sunset(request, persisted_query_id));
+        }
+        if !has_scopes(&request, &metadata.required_scopes) {
+            tracing::info!(
+                monotonic_counter.apollo.router.operations.persisted_queries = 1u64,
+                persisted_queries.scopes.rejected = true
+            );
+            return Err(supergraph_err_missing_scopes(request, persisted_query_id));
+        }
+        if metadata.deprecated {
+            tracing::warn!(persisted_query_id, "a deprecated persisted query was used");
+        }
+        request
+            .context
+            .extensions()
+            .lock()
+            .insert(OperationMetadata::clone(&metadata));
+    }
+    Ok(request)
+}
+
+/// Whether the `scope` claim of the request's JWT contains all the scopes
+fn has_scopes(request: &SupergraphRequest, required_scopes: &[String]) -> bool {
+    if required_scopes.is_empty() {
+        return true;
+    }
+    let claims = request
+        .context
+        .get_json_value(APOLLO_AUTHENTICATION_JWT_CLAIMS);
+    let scopes = claims
+        .as_ref()
+        .and_then(|claims| claims.get("scope"))
+        .and_then(|scope| scope.as_str())
+        .unwrap_or_default()
+        .split(' ')
+        .collect::<Vec<_>>();
+    required_scopes
+        .iter()
+        .all(|required| scopes.contains(&required.as_str()))
+}
+
 fn log_unknown_operation(operation_body: &str) {
     tracing::warn!(message = "unknown operation", operation_body);
 }
@@ -300,6 +452,63 @@ fn supergraph_err_operation_not_found(
     )
 }
 
+fn graphql_err_operation_not_allowed_for_client(persisted_query_id: &str) -> GraphQLError {
+    graphql_err(
+        "PERSISTED_QUERY_NOT_ALLOWED_FOR_CLIENT",
+        &format!("Persisted query '{persisted_query_id}' is not allowed for this client"),
+    )
+}
+
+fn supergraph_err_operation_not_allowed_for_client(
+    request: SupergraphRequest,
+    persisted_query_id: &str,
+) -> SupergraphResponse {
+    supergraph_err(
+        graphql_err_operation_not_allowed_for_client(persisted_query_id),
+        request,
+        ErrorCacheStrategy::DontCache,
+        StatusCode::FORBIDDEN,
+    )
+}
+
+fn graphql_err_operation_sunset(persisted_query_id: &str) -> GraphQLError {
+    graphql_err(
+        "PERSISTED_QUERY_SUNSET",
+        &format!("Persisted query '{persisted_query_id}' has reached its sunset date"),
+    )
+}
+
+fn supergraph_err_operation_sunset(
+    request: SupergraphRequest,
+    persisted_query_id: &str,
+) -> SupergraphResponse {
+    supergraph_err(
+        graphql_err_operation_sunset(persisted_query_id),
+        request,
+        ErrorCacheStrategy::DontCache,
+        StatusCode::GONE,
+    )
+}
+
+fn graphql_err_missing_scopes(persisted_query_id: &str) -> GraphQLError {
+    graphql_err(
+        "PERSISTED_QUERY_MISSING_SCOPES",
+        &format!("Persisted query '{persisted_query_id}' requires scopes the request doesn't have"),
+    )
+}
+
+fn supergraph_err_missing_scopes(
+    request: SupergraphRequest,
+    persisted_query_id: &str,
+) -> SupergraphResponse {
+    supergraph_err(
+        graphql_err_missing_scopes(persisted_query_id),
+        request,
+        ErrorCacheStrategy::DontCache,
+        StatusCode::FORBIDDEN,
+    )
+}
+
 fn graphql_err_cannot_send_id_and_body() -> GraphQLError {
     graphql_err(
         "CANNOT_SEND_PQ_ID_AND_BODY",
@@ -377,10 +586,13 @@ mod tests {
     use crate::configuration::PersistedQueries;
     use crate::configuration::PersistedQueriesSafelist;
     use crate::configuration::Supergraph;
+    use crate::files::tests::create_temp_file;
+    use crate::files::tests::write_and_flush;
     use crate::services::layers::persisted_queries::manifest_poller::FreeformGraphQLBehavior;
     use crate::services::layers::query_analysis::QueryAnalysisLayer;
     use crate::spec::Schema;
     use crate::test_harness::mocks::persisted_queries::*;
+    use crate::Context;
 
     #[tokio::test(flavor = "multi_thread")]
     async fn disabled_pq_layer_has_no_poller() {
@@ -1069,4 +1281,342 @@ mod tests {
             .expect("could not get response from pq layer");
         assert_eq!(response.errors, vec![graphql_err_cannot_send_id_and_body()]);
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pq_layer_applies_client_safelists() {
+        let (path, mut file) = create_temp_file();
+        write_and_flush(
+            &mut file,
+            &json!({
+                "format": "apollo-persisted-query-manifest",
+                "version": 1,
+                "operations": [
+                    { "id": "shared", "body": "query Shared { me { id } }" },
+                    { "id": "web", "body": "query Web { me { name } }", "clients": ["web"] }
+                ]
+            })
+            .to_string(),
+        )
+        .await;
+
+        let config = Configuration::fake_builder()
+            .persisted_query(
+                PersistedQueries::builder()
+                    .enabled(true)
+                    .local_manifests(vec![path])
+                    .safelist(PersistedQueriesSafelist::builder().enabled(true).build())
+                    .build(),
+            )
+            .apq(Apq::fake_builder().enabled(false).build())
+            .build()
+            .unwrap();
+        let pq_layer = PersistedQueryLayer::new(&config).await.unwrap();
+        let schema = Arc::new(
+            Schema::parse_test(
+                include_str!("../../../testdata/supergraph.graphql"),
+                &Default::default(),
+            )
+            .unwrap(),
+        );
+        let query_analysis_layer = QueryAnalysisLayer::new(schema, Arc::new(config)).await;
+
+        let context_for = |client_name: Option<&str>| {
+            let context = Context::new();
+            if let Some(client_name) = client_name {
+                context
+                    .insert(CLIENT_NAME, client_name.to_string())
+                    .unwrap();
+            }
+            context
+        };
+
+        // Operations sent by ID are only resolved for the clients they belong to.
+        for (id, client_name, allowed) in [
+            ("shared", None, true),
+            ("shared", Some("ios"), true),
+            ("web", Some("web"), true),
+            ("web", Some("ios"), false),
+            ("web", None, false),
+        ] {
+            let incoming_request = SupergraphRequest::fake_builder()
+                .extension("persistedQuery", json!({"version": 1, "sha256Hash": id}))
+                .context(context_for(client_name))
+                .build()
+                .unwrap();
+            match pq_layer.supergraph_request(incoming_request) {
+                Ok(_) => assert!(allowed, "{id} should not be allowed for {client_name:?}"),
+                Err(mut supergraph_response) => {
+                    assert!(!allowed, "{id} should be allowed for {client_name:?}");
+                    assert_eq!(supergraph_response.response.status(), 403);
+                    let response = supergraph_response.next_response().await.unwrap();
+                    assert_eq!(
+                        response.errors,
+                        vec![graphql_err_operation_not_allowed_for_client(id)]
+                    );
+                }
+            }
+        }
+
+        // Freeform GraphQL is checked against the caller's safelist.
+        for (body, client_name, allowed) in [
+            ("query Shared { me { id } }", Some("ios"), true),
+            ("query Web { me { name } }", Some("web"), true),
+            ("query Web { me { name } }", Some("ios"), false),
+            ("query Web { me { name } }", None, false),
+        ] {
+            let incoming_request = SupergraphRequest::fake_builder()
+                .query(body)
+                .context(context_for(client_name))
+                .build()
+                .unwrap();
+            let request = pq_layer.supergraph_request(incoming_request).ok().unwrap();
+            let request = query_analysis_layer
+                .supergraph_request(request)
+                .await
+                .ok()
+                .unwrap();
+            let result = pq_layer
+                .supergraph_request_with_analyzed_query(request)
+                .await;
+            assert_eq!(result.is_ok(), allowed, "{body} for {client_name:?}");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pq_layer_applies_operation_metadata() {
+        let (path, mut file) = create_temp_file();
+        write_and_flush(
+            &mut file,
+            &json!({
+                "format": "apollo-persisted-query-manifest",
+                "version": 1,
+                "operations": [
+                    {
+                        "id": "scoped",
+                        "body": "query Scoped { me { id } }",
+                        "metadata": { "requiredScopes": ["read:me"], "timeout": "1s" }
+                    },
+                    {
+                        "id": "sunset",
+                        "body": "query Sunset { me { id } }",
+                        "metadata": { "deprecated": true, "sunset": "2020-01-01T00:00:00Z" }
+                    }
+                ]
+            })
+            .to_string(),
+        )
+        .await;
+
+        let pq_layer = PersistedQueryLayer::new(
+            &Configuration::fake_builder()
+                .persisted_query(
+                    PersistedQueries::builder()
+                        .enabled(true)
+                        .local_manifests(vec![path])
+                        .build(),
+                )
+                .apq(Apq::fake_builder().enabled(false).build())
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let request_for = |id: &str, scope: Option<&str>| {
+            let context = Context::new();
+            if let Some(scope) = scope {
+                context
+                    .insert(APOLLO_AUTHENTICATION_JWT_CLAIMS, json!({ "scope": scope }))
+                    .unwrap();
+            }
+            SupergraphRequest::fake_builder()
+                .extension("persistedQuery", json!({"version": 1, "sha256Hash": id}))
+                .context(context)
+                .build()
+                .unwrap()
+        };
+
+        let request = pq_layer
+            .supergraph_request(request_for("scoped", Some("read:me write:me")))
+            .ok()
+            .expect("pq layer returned response instead of putting the query on the request");
+        assert_eq!(
+            request
+                .context
+                .extensions()
+                .lock()
+                .get::<OperationMetadata>()
+                .and_then(|metadata| metadata.timeout),
+            Some(Duration::from_secs(1))
+        );
+
+        let mut supergraph_response = pq_layer
+            .supergraph_request(request_for("scoped", Some("write:me")))
+            .expect_err("pq layer returned request instead of returning an error response");
+        assert_eq!(supergraph_response.response.status(), 403);
+        let response = supergraph_response.next_response().await.unwrap();
+        assert_eq!(response.errors, vec![graphql_err_missing_scopes("scoped")]);
+
+        let mut supergraph_response = pq_layer
+            .supergraph_request(request_for("sunset", None))
+            .expect_err("pq layer returned request instead of returning an error response");
+        assert_eq!(supergraph_response.response.status(), 410);
+        let response = supergraph_response.next_response().await.unwrap();
+        assert_eq!(
+            response.errors,
+            vec![graphql_err_operation_sunset("sunset")]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pq_layer_applies_operation_metadata_to_safelisted_freeform_graphql() {
+        let (path, mut file) = create_temp_file();
+        write_and_flush(
+            &mut file,
+            &json!({
+                "format": "apollo-persisted-query-manifest",
+                "version": 1,
+                "operations": [
+                    {
+                        "id": "scoped",
+                        "body": "query Scoped { me { id } }",
+                        "metadata": { "requiredScopes": ["read:me"], "timeout": "1s" }
+                    },
+                    {
+                        "id": "sunset",
+                        "body": "query Sunset { me { id } }",
+                        "metadata": { "sunset": "2020-01-01T00:00:00Z" }
+                    }
+                ]
+            })
+            .to_string(),
+        )
+        .await;
+
+        let config = Configuration::fake_builder()
+            .persisted_query(
+                PersistedQueries::builder()
+                    .enabled(true)
+                    .local_manifests(vec![path])
+                    .safelist(PersistedQueriesSafelist::builder().enabled(true).build())
+                    .build(),
+            )
+            .apq(Apq::fake_builder().enabled(false).build())
+            .build()
+            .unwrap();
+        let pq_layer = PersistedQueryLayer::new(&config).await.unwrap();
+        let schema = Arc::new(
+            Schema::parse_test(
+                include_str!("../../../testdata/supergraph.graphql"),
+                &Default::default(),
+            )
+            .unwrap(),
+        );
+        let query_analysis_layer = QueryAnalysisLayer::new(schema, Arc::new(config)).await;
+
+        let run = |body: &'static str, scope: Option<&'static str>| {
+            let pq_layer = &pq_layer;
+            let query_analysis_layer = &query_analysis_layer;
+            async move {
+                let context = Context::new();
+                if let Some(scope) = scope {
+                    context
+                        .insert(APOLLO_AUTHENTICATION_JWT_CLAIMS, json!({ "scope": scope }))
+                        .unwrap();
+                }
+                let incoming_request = SupergraphRequest::fake_builder()
+                    .query(body)
+                    .context(context)
+                    .build()
+                    .unwrap();
+                let request = pq_layer.supergraph_request(incoming_request).ok().unwrap();
+                let request = query_analysis_layer
+                    .supergraph_request(request)
+                    .await
+                    .ok()
+                    .unwrap();
+                pq_layer
+                    .supergraph_request_with_analyzed_query(request)
+                    .await
+            }
+        };
+
+        // Sending the body instead of the ID does not bypass the metadata
+        let request = run("query Scoped { me { id } }", Some("read:me"))
+            .await
+            .ok()
+            .expect("pq layer returned response instead of allowing the safelisted query");
+        assert_eq!(
+            request
+                .context
+                .extensions()
+                .lock()
+                .get::<OperationMetadata>()
+                .and_then(|metadata| metadata.timeout),
+            Some(Duration::from_secs(1))
+        );
+
+        let mut supergraph_response = run("query Scoped { me { id } }", None)
+            .await
+            .expect_err("pq layer returned request instead of returning an error response");
+        assert_eq!(supergraph_response.response.status(), 403);
+        let response = supergraph_response.next_response().await.unwrap();
+        assert_eq!(response.errors, vec![graphql_err_missing_scopes("scoped")]);
+
+        let mut supergraph_response = run("query Sunset { me { id } }", None)
+            .await
+            .expect_err("pq layer returned request instead of returning an error response");
+        assert_eq!(supergraph_response.response.status(), 410);
+        let response = supergraph_response.next_response().await.unwrap();
+        assert_eq!(
+            response.errors,
+            vec![graphql_err_operation_sunset("sunset")]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pq_layer_audits_unknown_operations() {
+        let (_id, body, manifest) = fake_manifest();
+        let (_mock_guard, uplink_config) = mock_pq_uplink(&manifest).await;
+
+        let config = Configuration::fake_builder()
+            .persisted_query(
+                PersistedQueries::builder()
+                    .enabled(true)
+                    .audit(PersistedQueriesAudit::builder().enabled(true).build())
+                    .build(),
+            )
+            .uplink(uplink_config)
+            .apq(Apq::fake_builder().enabled(false).build())
+            .build()
+            .unwrap();
+        let pq_layer = PersistedQueryLayer::new(&config).await.unwrap();
+        let schema = Arc::new(
+            Schema::parse_test(
+                include_str!("../../../testdata/supergraph.graphql"),
+                &Default::default(),
+            )
+            .unwrap(),
+        );
+        let query_analysis_layer = QueryAnalysisLayer::new(schema, Arc::new(config)).await;
+
+        // Unknown operations are allowed, and grouped regardless of formatting
+        for unknown in ["query A { me { id } }", "query A {\n  me {\n    id\n  }\n}"] {
+            allowed_by_safelist(&pq_layer, &query_analysis_layer, unknown).await;
+        }
+        allowed_by_safelist(&pq_layer, &query_analysis_layer, &body).await;
+
+        let report = pq_layer.audit.as_ref().unwrap().report();
+        assert_eq!(report.operations.len(), 1);
+        assert_eq!(report.operations[0].count, 2);
+        assert_eq!(
+            report.operations[0].body,
+            FreeformGraphQLSafelist::normalize_body(Ok(&ast::Document::parse(
+                "query A { me { id } }",
+                ""
+            )
+            .unwrap()))
+        );
+        assert_eq!(pq_layer.web_endpoints().len(), 1);
+    }
 }
@@ -82,3 +82,6 @@ pub(crate) const MULTIPART_SUBSCRIPTION_CONTENT_TYPE: &str =
     "multipart/mixed;boundary=\"graphql\";subscriptionSpec=1.0";
 pub(crate) const MULTIPART_SUBSCRIPTION_SPEC_PARAMETER: &str = "subscriptionSpec";
 pub(crate) const MULTIPART_SUBSCRIPTION_SPEC_VALUE: &str = "1.0";
+
+// GraphQL over Server-Sent Events https://github.com/enisdenjo/graphql-sse/blob/master/PROTOCOL.md
+pub(crate) const EVENT_STREAM_ACCEPT: &str = "text/event-stream";
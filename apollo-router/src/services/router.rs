@@ -392,4 +392,5 @@ pub(crate) struct ClientRequestAccepts {
     pub(crate) multipart_subscription: bool,
     pub(crate) json: bool,
     pub(crate) wildcard: bool,
+    pub(crate) event_stream: bool,
 }
@@ -15,6 +15,7 @@ use futures::future::BoxFuture;
 use futures::stream;
 use futures::stream::once;
 use futures::stream::StreamExt;
+use http::header::CACHE_CONTROL;
 use http::header::CONTENT_TYPE;
 use http::header::VARY;
 use http::request::Parts;
@@ -33,6 +34,7 @@ use tower::ServiceBuilder;
 use tower::ServiceExt;
 use tower_service::Service;
 use tracing::Instrument;
+use tracing::Span;
 
 use super::ClientRequestAccepts;
 use crate::axum_factory::CanceledRequest;
@@ -41,17 +43,23 @@ use crate::batching::BatchQuery;
 use crate::cache::DeduplicatingCache;
 use crate::configuration::Batching;
 use crate::configuration::BatchingMode;
+use crate::configuration::Limits;
 use crate::graphql;
 use crate::http_ext;
 #[cfg(test)]
 use crate::plugin::test::MockSupergraphService;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::plugins::telemetry::dynamic_attribute::DynAttribute;
+use crate::plugins::traffic_shaping::Elapsed;
 use crate::protocols::multipart::Multipart;
 use crate::protocols::multipart::ProtocolMode;
+use crate::protocols::sse::EventStream;
 use crate::query_planner::InMemoryCachePlanner;
 use crate::router_factory::RouterFactory;
 use crate::services::layers::apq::APQLayer;
 use crate::services::layers::content_negotiation;
 use crate::services::layers::content_negotiation::GRAPHQL_JSON_RESPONSE_HEADER_VALUE;
+use crate::services::layers::persisted_queries::OperationMetadata;
 use crate::services::layers::persisted_queries::PersistedQueryLayer;
 use crate::services::layers::query_analysis::QueryAnalysisLayer;
 use crate::services::layers::static_page::StaticPageLayer;
@@ -68,10 +76,13 @@ use crate::services::SupergraphCreator;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
 use crate::services::APPLICATION_JSON_HEADER_VALUE;
+use crate::services::EVENT_STREAM_ACCEPT;
 use crate::services::MULTIPART_DEFER_ACCEPT;
 use crate::services::MULTIPART_DEFER_CONTENT_TYPE;
 use crate::services::MULTIPART_SUBSCRIPTION_ACCEPT;
 use crate::services::MULTIPART_SUBSCRIPTION_CONTENT_TYPE;
+use crate::spec::operation_limits;
+use crate::spec::operation_limits::LIMITS_PROFILE;
 use crate::Configuration;
 use crate::Context;
 use crate::Endpoint;
@@ -81,9 +92,12 @@ pub(crate) static MULTIPART_DEFER_CONTENT_TYPE_HEADER_VALUE: HeaderValue =
     HeaderValue::from_static(MULTIPART_DEFER_CONTENT_TYPE);
 pub(crate) static MULTIPART_SUBSCRIPTION_CONTENT_TYPE_HEADER_VALUE: HeaderValue =
     HeaderValue::from_static(MULTIPART_SUBSCRIPTION_CONTENT_TYPE);
+pub(crate) static EVENT_STREAM_CONTENT_TYPE_HEADER_VALUE: HeaderValue =
+    HeaderValue::from_static(EVENT_STREAM_ACCEPT);
 static ACCEL_BUFFERING_HEADER_NAME: HeaderName = HeaderName::from_static("x-accel-buffering");
 static ACCEL_BUFFERING_HEADER_VALUE: HeaderValue = HeaderValue::from_static("no");
 static ORIGIN_HEADER_VALUE: HeaderValue = HeaderValue::from_static("origin");
+const LIMITS_PROFILE_SPAN_ATTRIBUTE: &str = "apollo.router.limits.profile";
 
 /// Containing [`Service`] in the request lifecyle.
 #[derive(Clone)]
@@ -92,7 +106,7 @@ pub(crate) struct RouterService {
     apq_layer: APQLayer,
     persisted_query_layer: Arc<PersistedQueryLayer>,
     query_analysis_layer: QueryAnalysisLayer,
-    http_max_request_bytes: usize,
+    limits: Arc<Limits>,
     batching: Batching,
 }
 
@@ -102,7 +116,7 @@ impl RouterService {
         apq_layer: APQLayer,
         persisted_query_layer: Arc<PersistedQueryLayer>,
         query_analysis_layer: QueryAnalysisLayer,
-        http_max_request_bytes: usize,
+        limits: Arc<Limits>,
         batching: Batching,
     ) -> Self {
         RouterService {
@@ -110,7 +124,7 @@ impl RouterService {
             apq_layer,
             persisted_query_layer,
             query_analysis_layer,
-            http_max_request_bytes,
+            limits,
             batching,
         }
     }
@@ -249,7 +263,21 @@ impl RouterService {
                     .await
                 {
                     Err(response) => response,
-                    Ok(request) => self.supergraph_creator.create().oneshot(request).await?,
+                    Ok(request) => {
+                        let timeout = request
+                            .context
+                            .extensions()
+                            .lock()
+                            .get::<OperationMetadata>()
+                            .and_then(|metadata| metadata.timeout);
+                        let response = self.supergraph_creator.create().oneshot(request);
+                        match timeout {
+                            Some(timeout) => tokio::time::timeout(timeout, response)
+                                .await
+                                .map_err(|_| Elapsed::new())??,
+                            None => response.await?,
+                        }
+                    }
                 },
             },
         };
@@ -259,6 +287,7 @@ impl RouterService {
             json: accepts_json,
             multipart_defer: accepts_multipart_defer,
             multipart_subscription: accepts_multipart_subscription,
+            event_stream: accepts_event_stream,
         } = context
             .extensions()
             .lock()
@@ -293,10 +322,24 @@ impl RouterService {
                 })
             }
             Some(response) => {
-                if !response.has_next.unwrap_or(false)
+                let operation_metadata = context
+                    .extensions()
+                    .lock()
+                    .get::<OperationMetadata>()
+                    .cloned();
+                let single_response = !response.has_next.unwrap_or(false)
                     && !response.subscribed.unwrap_or(false)
-                    && (accepts_json || accepts_wildcard)
-                {
+                    && (accepts_json || accepts_wildcard);
+                if let Some(operation_metadata) = operation_metadata {
+                    // Multipart responses are streamed, they are not cached
+                    operation_metadata.add_response_headers(
+                        &mut parts.headers,
+                        single_response && parts.status.is_success() && response.errors.is_empty(),
+                        context.contains_key(APOLLO_AUTHENTICATION_JWT_CLAIMS),
+                    );
+                }
+
+                if single_response {
                     if !response.errors.is_empty() {
                         Self::count_errors(&response.errors);
                     }
@@ -311,6 +354,47 @@ impl RouterService {
                             context,
                         })
                     })
+                } else if accepts_event_stream
+                    && !(if response.subscribed.unwrap_or(false) {
+                        accepts_multipart_subscription
+                    } else {
+                        accepts_multipart_defer
+                    })
+                {
+                    // The client doesn't accept the multipart format for this kind of response
+                    parts
+                        .headers
+                        .insert(CONTENT_TYPE, EVENT_STREAM_CONTENT_TYPE_HEADER_VALUE.clone());
+                    parts
+                        .headers
+                        .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+                    if !response.errors.is_empty() {
+                        Self::count_errors(&response.errors);
+                    }
+
+                    parts.headers.insert(
+                        ACCEL_BUFFERING_HEADER_NAME.clone(),
+                        ACCEL_BUFFERING_HEADER_VALUE.clone(),
+                    );
+                    let body = body.inspect(|response| {
+                        if !response.errors.is_empty() {
+                            Self::count_errors(&response.errors);
+                        }
+                    });
+                    // The primary response of a subscription is not sent to clients
+                    let event_stream = match response.subscribed {
+                        Some(true) => EventStream::new(body.boxed()),
+                        _ => EventStream::new(once(ready(response)).chain(body).boxed()),
+                    };
+
+                    Ok(RouterResponse {
+                        response: http::Response::from_parts(
+                            parts,
+                            Body::wrap_stream(event_stream),
+                        ),
+                        context,
+                    })
                 } else if accepts_multipart_defer || accepts_multipart_subscription {
                     if accepts_multipart_defer {
                         parts.headers.insert(
@@ -379,11 +463,12 @@ impl RouterService {
                             .error(
                                 graphql::Error::builder()
                                     .message(format!(
-                                        r#"'accept' header must be one of: \"*/*\", {:?}, {:?}, {:?} or {:?}"#,
+                                        r#"'accept' header must be one of: \"*/*\", {:?}, {:?}, {:?}, {:?} or {:?}"#,
                                         APPLICATION_JSON.essence_str(),
                                         GRAPHQL_JSON_RESPONSE_HEADER_VALUE,
                                         MULTIPART_DEFER_ACCEPT,
                                         MULTIPART_SUBSCRIPTION_ACCEPT,
+                                        EVENT_STREAM_ACCEPT,
                                     ))
                                     .extension_code("INVALID_ACCEPT_HEADER")
                                     .build(),
@@ -638,6 +723,24 @@ impl RouterService {
 
         let (parts, body) = router_request.into_parts();
 
+        let mut http_max_request_bytes = self.limits.http_max_request_bytes;
+        if let Some(profile) = operation_limits::select_profile(&self.limits, &context) {
+            let _ = context.insert(LIMITS_PROFILE, profile.to_string());
+            Span::current().set_dyn_attribute(
+                LIMITS_PROFILE_SPAN_ATTRIBUTE.into(),
+                profile.to_string().into(),
+            );
+            u64_counter!(
+                "apollo.router.operations.limits.profile",
+                "Number of requests a limit profile was applied to",
+                1,
+                "limits.profile" = profile.to_string()
+            );
+            if let Some(max) = self.limits.profiles[profile].http_max_request_bytes {
+                http_max_request_bytes = max;
+            }
+        }
+
         let graphql_requests: Result<(Vec<graphql::Request>, bool), TranslateError> = if parts
             .method
             == Method::GET
@@ -654,7 +757,7 @@ impl RouterService {
                     .parse()
                     .ok()
             })();
-            if content_length.unwrap_or(0) > self.http_max_request_bytes {
+            if content_length.unwrap_or(0) > http_max_request_bytes {
                 Err(TranslateError {
                     status: StatusCode::PAYLOAD_TOO_LARGE,
                     error: "payload too large for the `http_max_request_bytes` configuration",
@@ -662,7 +765,7 @@ impl RouterService {
                     extension_details: "payload too large".to_string(),
                 })
             } else {
-                let body = http_body::Limited::new(body, self.http_max_request_bytes);
+                let body = http_body::Limited::new(body, http_max_request_bytes);
                 hyper::body::to_bytes(body)
                     .instrument(tracing::debug_span!("receive_body"))
                     .await
@@ -842,7 +945,7 @@ pub(crate) struct RouterCreator {
     apq_layer: APQLayer,
     pub(crate) persisted_query_layer: Arc<PersistedQueryLayer>,
     query_analysis_layer: QueryAnalysisLayer,
-    http_max_request_bytes: usize,
+    limits: Arc<Limits>,
     batching: Batching,
 }
 
@@ -866,6 +969,7 @@ impl RouterFactory for RouterCreator {
             .plugins()
             .values()
             .for_each(|p| mm.extend(p.web_endpoints()));
+        mm.extend(self.persisted_query_layer.web_endpoints());
         mm
     }
 }
@@ -892,7 +996,7 @@ impl RouterCreator {
             static_page,
             apq_layer,
             query_analysis_layer,
-            http_max_request_bytes: configuration.limits.http_max_request_bytes,
+            limits: Arc::new(configuration.limits.clone()),
             persisted_query_layer,
             batching: configuration.batching.clone(),
         })
@@ -911,7 +1015,7 @@ impl RouterCreator {
             self.apq_layer.clone(),
             self.persisted_query_layer.clone(),
             self.query_analysis_layer.clone(),
-            self.http_max_request_bytes,
+            self.limits.clone(),
             self.batching.clone(),
         ));
 
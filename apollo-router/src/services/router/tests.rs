@@ -228,6 +228,89 @@ async fn test_http_max_request_bytes() {
     assert_eq!(response.status(), http::StatusCode::PAYLOAD_TOO_LARGE);
 }
 
+#[tokio::test]
+async fn test_limit_profiles() {
+    /// Size of the JSON serialization of the request created by `fn canned_new`
+    /// in `apollo-router/src/services/supergraph.rs`
+    const CANNED_REQUEST_LEN: usize = 391;
+
+    let config = serde_json::json!({
+        "limits": {
+            "http_max_request_bytes": CANNED_REQUEST_LEN - 1,
+            "max_depth": 1,
+            "profiles": {
+                "internal": {
+                    "http_max_request_bytes": CANNED_REQUEST_LEN,
+                    "max_depth": 10
+                },
+                "strict": {
+                    "http_max_request_bytes": CANNED_REQUEST_LEN
+                }
+            },
+            "profile_selection": {
+                "client_names": {
+                    "dashboard": "internal",
+                    "mobile": "strict",
+                    "unknown": "undefined"
+                }
+            }
+        }
+    });
+    let router = crate::TestHarness::builder()
+        .configuration_json(config)
+        .unwrap()
+        .build_router()
+        .await
+        .unwrap();
+
+    let call = |client_name: Option<&'static str>| {
+        let router = router.clone();
+        async move {
+            let mut http_request = supergraph::Request::canned_builder()
+                .build()
+                .unwrap()
+                .supergraph_request
+                .map(|body| hyper::Body::from(serde_json::to_vec(&body).unwrap()));
+            if let Some(client_name) = client_name {
+                http_request.headers_mut().insert(
+                    "apollographql-client-name",
+                    HeaderValue::from_static(client_name),
+                );
+            }
+            let request = router::Request::from(http_request);
+            let context = request.context.clone();
+            let mut response = router.oneshot(request).await.unwrap();
+            let status = response.response.status();
+            let body = response.next_response().await.unwrap().unwrap();
+            let profile = context
+                .get::<_, String>(crate::spec::operation_limits::LIMITS_PROFILE)
+                .unwrap();
+            (status, String::from_utf8_lossy(&body).to_string(), profile)
+        }
+    };
+
+    // Global limits
+    let (status, _, profile) = call(None).await;
+    assert_eq!(status, http::StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(profile, None);
+
+    // Profiles that are not defined are ignored
+    let (status, _, profile) = call(Some("unknown")).await;
+    assert_eq!(status, http::StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(profile, None);
+
+    // Relaxed limits
+    let (status, _, profile) = call(Some("dashboard")).await;
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(profile.as_deref(), Some("internal"));
+
+    // Limits that are not set in a profile keep their global value
+    let (status, body, profile) = call(Some("mobile")).await;
+    assert_eq!(status, http::StatusCode::BAD_REQUEST);
+    assert!(body.contains("MAX_DEPTH_LIMIT"));
+    assert_eq!(profile.as_deref(), Some("strict"));
+}
+
 //  Test query batching
 
 #[tokio::test]
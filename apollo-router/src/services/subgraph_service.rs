@@ -8,6 +8,8 @@ use std::task::Poll;
 
 use bytes::Bytes;
 use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::SinkExt;
 use futures::StreamExt;
 use futures::TryFutureExt;
 use http::header::ACCEPT;
@@ -25,10 +27,14 @@ use mediatype::MediaType;
 use mime::APPLICATION_JSON;
 use rustls::RootCertStore;
 use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tokio::sync::mpsc;
 use tokio::sync::oneshot;
 use tokio_tungstenite::connect_async;
 use tokio_tungstenite::connect_async_tls_with_config;
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::generate_key;
 use tower::util::BoxService;
 use tower::BoxError;
 use tower::Service;
@@ -51,20 +57,33 @@ use crate::error::FetchError;
 use crate::error::SubgraphBatchingError;
 use crate::graphql;
 use crate::json_ext::Object;
+use crate::notification::Handle;
+use crate::notification::HandleSink;
+use crate::notification::HandleStream;
+use crate::notification::TopicInfo;
 use crate::plugins::authentication::subgraph::SigningParamsConfig;
 use crate::plugins::file_uploads;
 use crate::plugins::subscription::create_verifier;
+use crate::plugins::subscription::event_id;
+use crate::plugins::subscription::parse_event_id;
 use crate::plugins::subscription::CallbackMode;
 use crate::plugins::subscription::SubscriptionConfig;
 use crate::plugins::subscription::SubscriptionMode;
 use crate::plugins::subscription::WebSocketConfiguration;
+use crate::plugins::subscription::LAST_EVENT_ID_HEADER_NAME;
+use crate::plugins::subscription::SUBSCRIPTION_EVENT_ID_EXTENSION;
+use crate::plugins::subscription::SUBSCRIPTION_LAST_EVENT_ID_EXTENSION;
+use crate::plugins::subscription::SUBSCRIPTION_RECONNECTING_EXTENSION;
 use crate::plugins::subscription::SUBSCRIPTION_WS_CUSTOM_CONNECTION_PARAMS;
 use crate::plugins::telemetry::LOGGING_DISPLAY_BODY;
 use crate::plugins::telemetry::LOGGING_DISPLAY_HEADERS;
 use crate::protocols::websocket::convert_websocket_stream;
+use crate::protocols::websocket::is_connection_lost;
 use crate::protocols::websocket::GraphqlWebSocket;
+use crate::protocols::websocket::WebSocketPool;
 use crate::query_planner::OperationKind;
 use crate::services::layers::apq;
+use crate::services::subgraph::BoxGqlStream;
 use crate::services::SubgraphRequest;
 use crate::services::SubgraphResponse;
 use crate::Configuration;
@@ -124,6 +143,8 @@ pub(crate) struct SubgraphService {
     /// Subscription config if enabled
     subscription_config: Option<SubscriptionConfig>,
     notify: Notify<String, graphql::Response>,
+    /// WebSocket connections shared by passthrough subscriptions
+    websocket_pool: WebSocketPool,
 }
 
 impl SubgraphService {
@@ -165,6 +186,7 @@ impl SubgraphService {
             apq: Arc::new(<AtomicBool>::new(enable_apq)),
             subscription_config,
             notify,
+            websocket_pool: WebSocketPool::default(),
         })
     }
 }
@@ -207,6 +229,12 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
             .flatten();
         let service_name = (*self.service).to_owned();
 
+        // Identifies the operation and the client allowed to resume a subscription topic
+        let request_hash = if request.operation_kind == OperationKind::Subscription {
+            request.to_sha256()
+        } else {
+            String::new()
+        };
         // Do it only for subscription to dedup them
         let hashed_request = if request.operation_kind == OperationKind::Subscription {
             let subscription_config = match &subscription_config {
@@ -221,7 +249,7 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
                 }
             };
             if subscription_config.enable_deduplication {
-                request.to_sha256()
+                request_hash.clone()
             } else {
                 Uuid::new_v4().to_string()
             }
@@ -242,6 +270,7 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
         let arc_apq_enabled = self.apq.clone();
 
         let mut notify = self.notify.clone();
+        let websocket_pool = self.websocket_pool.clone();
 
         let make_calls = async move {
             // Subscription handling
@@ -255,17 +284,48 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
                         status_code: None,
                     })?;
                 let mode = subscription_config.mode.get_subgraph_config(&service_name);
+                let hashed_request = match &mode {
+                    Some(SubscriptionMode::Callback(_)) => notify.callback_topic(hashed_request),
+                    _ => hashed_request,
+                };
+
+                if mode.is_some() {
+                    if let Some(stream) = resume_subscription(
+                        &mut notify,
+                        &request,
+                        &hashed_request,
+                        &request_hash,
+                        subscription_config.enable_deduplication,
+                    )
+                    .await?
+                    {
+                        let stream_tx = request.subscription_stream.clone().ok_or_else(|| {
+                            FetchError::SubrequestWsError {
+                                service: service_name.clone(),
+                                reason: "cannot get the subscription stream".to_string(),
+                            }
+                        })?;
+                        stream_tx.send(stream).await?;
+
+                        return Ok(SubgraphResponse::builder()
+                            .context(context)
+                            .extensions(Object::default())
+                            .build());
+                    }
+                }
 
                 match &mode {
                     Some(SubscriptionMode::Passthrough(ws_conf)) => {
                         // call_websocket for passthrough mode
                         return call_websocket(
                             notify,
+                            websocket_pool,
                             request,
                             context,
                             service_name,
                             ws_conf,
                             hashed_request,
+                            request_hash,
                         )
                         .await;
                     }
@@ -279,7 +339,12 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
 
                         // Call create_or_subscribe on notify
                         let (handle, created) = notify
-                            .create_or_subscribe(subscription_id.clone(), true)
+                            .create_or_subscribe_with_info(
+                                subscription_id.clone(),
+                                true,
+                                TopicInfo::new(service_name.clone(), "callback")
+                                    .with_owner(request_hash),
+                            )
                             .await?;
 
                         // If it existed before just send the right stream (handle) and early return
@@ -289,7 +354,9 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
                                 reason: "cannot get the callback stream".to_string(),
                             }
                         })?;
-                        stream_tx.send(Box::pin(handle.into_stream())).await?;
+                        stream_tx
+                            .send(event_stream(subscription_id.clone(), handle.into_stream()))
+                            .await?;
 
                         tracing::info!(
                             monotonic_counter.apollo.router.operations.subscriptions = 1u64,
@@ -309,6 +376,13 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
                                 .build());
                         }
 
+                        // Callbacks for this subscription can be received by other router instances
+                        if let Err(err) = notify.share(&subscription_id).await {
+                            tracing::error!(
+                                "cannot share the subscription {subscription_id}: {err}"
+                            );
+                        }
+
                         // If not then put the subscription_id in the extensions for callback mode and continue
                         // Do this if the topic doesn't already exist
                         let mut callback_url = public_url.clone();
@@ -454,11 +528,13 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
 /// call websocket makes websocket calls with modified graphql::Request (body)
 async fn call_websocket(
     mut notify: Notify<String, graphql::Response>,
+    websocket_pool: WebSocketPool,
     request: SubgraphRequest,
     context: Context,
     service_name: String,
     subgraph_cfg: &WebSocketConfiguration,
     subscription_hash: String,
+    request_hash: String,
 ) -> Result<SubgraphResponse, BoxError> {
     let operation_name = request
         .subgraph_request
@@ -479,7 +555,11 @@ async fn call_websocket(
         })?;
 
     let (handle, created) = notify
-        .create_or_subscribe(subscription_hash.clone(), false)
+        .create_or_subscribe_with_info(
+            subscription_hash.clone(),
+            false,
+            TopicInfo::new(service_name.clone(), "passthrough").with_owner(request_hash),
+        )
         .await?;
     tracing::info!(
         monotonic_counter.apollo.router.operations.subscriptions = 1u64,
@@ -489,7 +569,7 @@ async fn call_websocket(
     );
     if !created {
         subscription_stream_tx
-            .send(Box::pin(handle.into_stream()))
+            .send(event_stream(subscription_hash, handle.into_stream()))
             .await?;
         tracing::info!(
             monotonic_counter.apollo_router_deduplicated_subscriptions_total = 1u64,
@@ -520,120 +600,407 @@ async fn call_websocket(
 
     let request = get_websocket_request(service_name.clone(), parts, subgraph_cfg)?;
 
-    let display_headers = context.contains_key(LOGGING_DISPLAY_HEADERS);
-    let display_body = context.contains_key(LOGGING_DISPLAY_BODY);
-
-    let signing_params = context
-        .extensions()
-        .lock()
-        .get::<SigningParamsConfig>()
-        .cloned();
+    // Subscriptions with the same headers and connection parameters can share a connection
+    let connection_key = (subgraph_cfg.max_operations_per_connection.get() > 1)
+        .then(|| websocket_connection_key(&request, connection_params.as_ref()));
 
-    let request = if let Some(signing_params) = signing_params {
-        signing_params
-            .sign_empty(request, service_name.as_str())
-            .await?
-    } else {
-        request
+    let subscription_request = WebSocketSubscriptionRequest {
+        service_name,
+        operation_name,
+        request,
+        body,
+        connection_params,
+        connection_key,
+        subscription_hash: subscription_hash.clone(),
+        context: context.clone(),
+        subgraph_cfg: subgraph_cfg.clone(),
+        websocket_pool,
     };
+    let (gql_stream, resp) = subscription_request.subscribe().await?;
 
-    if display_headers {
-        tracing::info!(http.request.headers = ?request.headers(), apollo.subgraph.name = %service_name, "Websocket request headers to subgraph {service_name:?}");
-    }
+    forward_websocket_events(
+        gql_stream,
+        subscription_request,
+        handle,
+        subscription_stream_tx,
+        subscription_hash,
+    )
+    .await?;
 
-    if display_body {
-        tracing::info!(http.request.body = ?request.body(), apollo.subgraph.name = %service_name, "Websocket request body to subgraph {service_name:?}");
-    }
+    Ok(match resp {
+        Some(resp) => {
+            SubgraphResponse::new_from_response(resp.map(|_| graphql::Response::default()), context)
+        }
+        // The subscription was sent on a connection opened by another one
+        None => SubgraphResponse::builder()
+            .context(context)
+            .extensions(Object::default())
+            .build(),
+    })
+}
 
-    let uri = request.uri();
-    let path = uri.path();
-    let host = uri.host().unwrap_or_default();
-    let port = uri.port_u16().unwrap_or_else(|| {
-        let scheme = uri.scheme_str();
-        if scheme == Some("wss") {
-            443
-        } else if scheme == Some("ws") {
-            80
-        } else {
-            0
+/// A subscription sent to a subgraph through a WebSocket, kept to subscribe again after a
+/// reconnection
+struct WebSocketSubscriptionRequest {
+    service_name: String,
+    operation_name: String,
+    request: http::Request<()>,
+    body: graphql::Request,
+    connection_params: Option<serde_json_bytes::Value>,
+    connection_key: Option<String>,
+    subscription_hash: String,
+    context: Context,
+    subgraph_cfg: WebSocketConfiguration,
+    websocket_pool: WebSocketPool,
+}
+
+impl WebSocketSubscriptionRequest {
+    /// Sends the subscription on a pooled connection if one is available, or on a new one. The
+    /// handshake response is returned when a new connection was opened
+    async fn subscribe(
+        &self,
+    ) -> Result<
+        (
+            BoxStream<'static, graphql::Response>,
+            Option<http::Response<Option<Vec<u8>>>>,
+        ),
+        FetchError,
+    > {
+        let service_name = self.service_name.clone();
+        let max_operations = self.subgraph_cfg.max_operations_per_connection.get();
+        if let Some(subscription) = self
+            .connection_key
+            .as_ref()
+            .and_then(|key| self.websocket_pool.get(key, max_operations))
+            .and_then(|connection| {
+                connection
+                    .subscribe(Uuid::new_v4().to_string(), self.body.clone())
+                    .ok()
+            })
+        {
+            return Ok((subscription.boxed(), None));
         }
-    });
 
-    let subgraph_req_span = tracing::info_span!("subgraph_request",
-        "otel.kind" = "CLIENT",
-        "net.peer.name" = %host,
-        "net.peer.port" = %port,
-        "http.route" = %path,
-        "http.url" = %uri,
-        "net.transport" = "ip_tcp",
-        "apollo.subgraph.name" = %service_name,
-        "graphql.operation.name" = %operation_name,
-    );
+        let display_headers = self.context.contains_key(LOGGING_DISPLAY_HEADERS);
+        let display_body = self.context.contains_key(LOGGING_DISPLAY_BODY);
+
+        let signing_params = self
+            .context
+            .extensions()
+            .lock()
+            .get::<SigningParamsConfig>()
+            .cloned();
 
-    let (ws_stream, mut resp) = match request.uri().scheme_str() {
-        Some("wss") => {
-            connect_async_tls_with_config(request, None, false, None)
-                .instrument(subgraph_req_span)
+        let request = clone_websocket_request(&self.request);
+        let request = if let Some(signing_params) = signing_params {
+            signing_params
+                .sign_empty(request, service_name.as_str())
                 .await
+                .map_err(|err| FetchError::SubrequestWsError {
+                    service: service_name.clone(),
+                    reason: format!("cannot sign the websocket request: {err}"),
+                })?
+        } else {
+            request
+        };
+
+        if display_headers {
+            tracing::info!(http.request.headers = ?request.headers(), apollo.subgraph.name = %service_name, "Websocket request headers to subgraph {service_name:?}");
         }
-        _ => connect_async(request).instrument(subgraph_req_span).await,
-    }
-    .map_err(|err| {
-        if display_body || display_headers {
-            tracing::info!(
-                http.response.error = format!("{:?}", &err), apollo.subgraph.name = %service_name, "Websocket connection error from subgraph {service_name:?} received"
-            );
-        }
-        FetchError::SubrequestWsError {
-            service: service_name.clone(),
-            reason: format!("cannot connect websocket to subgraph: {err}"),
+
+        if display_body {
+            tracing::info!(http.request.body = ?request.body(), apollo.subgraph.name = %service_name, "Websocket request body to subgraph {service_name:?}");
         }
-    })?;
 
-    if display_headers {
-        tracing::info!(response.headers = ?resp.headers(), apollo.subgraph.name = %service_name, "Websocket response headers to subgraph {service_name:?}");
-    }
-    if display_body {
-        tracing::info!(
-            response.body = %String::from_utf8_lossy(&resp.body_mut().take().unwrap_or_default()), apollo.subgraph.name = %service_name, "Websocket response body from subgraph {service_name:?} received"
+        let uri = request.uri();
+        let path = uri.path();
+        let host = uri.host().unwrap_or_default();
+        let port = uri.port_u16().unwrap_or_else(|| {
+            let scheme = uri.scheme_str();
+            if scheme == Some("wss") {
+                443
+            } else if scheme == Some("ws") {
+                80
+            } else {
+                0
+            }
+        });
+
+        let subgraph_req_span = tracing::info_span!("subgraph_request",
+            "otel.kind" = "CLIENT",
+            "net.peer.name" = %host,
+            "net.peer.port" = %port,
+            "http.route" = %path,
+            "http.url" = %uri,
+            "net.transport" = "ip_tcp",
+            "apollo.subgraph.name" = %service_name,
+            "graphql.operation.name" = %self.operation_name,
         );
-    }
 
-    let gql_socket = GraphqlWebSocket::new(
-        convert_websocket_stream(ws_stream, subscription_hash.clone()),
-        subscription_hash,
-        subgraph_cfg.protocol,
-        connection_params,
-    )
-    .await
-    .map_err(|_| FetchError::SubrequestWsError {
-        service: service_name.clone(),
-        reason: "cannot get the GraphQL websocket stream".to_string(),
-    })?;
+        let (ws_stream, mut resp) = match request.uri().scheme_str() {
+            Some("wss") => {
+                connect_async_tls_with_config(request, None, false, None)
+                    .instrument(subgraph_req_span)
+                    .await
+            }
+            _ => connect_async(request).instrument(subgraph_req_span).await,
+        }
+        .map_err(|err| {
+            if display_body || display_headers {
+                tracing::info!(
+                    http.response.error = format!("{:?}", &err), apollo.subgraph.name = %service_name, "Websocket connection error from subgraph {service_name:?} received"
+                );
+            }
+            FetchError::SubrequestWsError {
+                service: service_name.clone(),
+                reason: format!("cannot connect websocket to subgraph: {err}"),
+            }
+        })?;
 
-    let gql_stream = gql_socket
-        .into_subscription(body, subgraph_cfg.heartbeat_interval.into_option())
+        if display_headers {
+            tracing::info!(response.headers = ?resp.headers(), apollo.subgraph.name = %service_name, "Websocket response headers to subgraph {service_name:?}");
+        }
+        if display_body {
+            tracing::info!(
+                response.body = %String::from_utf8_lossy(&resp.body_mut().take().unwrap_or_default()), apollo.subgraph.name = %service_name, "Websocket response body from subgraph {service_name:?} received"
+            );
+        }
+
+        let gql_socket = GraphqlWebSocket::new(
+            convert_websocket_stream(ws_stream, self.subscription_hash.clone()),
+            self.subscription_hash.clone(),
+            self.subgraph_cfg.protocol,
+            self.connection_params.clone(),
+        )
         .await
+        .map_err(|_| FetchError::SubrequestWsError {
+            service: service_name.clone(),
+            reason: "cannot get the GraphQL websocket stream".to_string(),
+        })?;
+
+        let heartbeat_interval = self.subgraph_cfg.heartbeat_interval.into_option();
+        let gql_stream = match &self.connection_key {
+            Some(connection_key) => {
+                let connection = gql_socket.into_multiplexed(heartbeat_interval);
+                self.websocket_pool
+                    .insert(connection_key.clone(), connection.clone());
+                connection
+                    .subscribe(Uuid::new_v4().to_string(), self.body.clone())
+                    .map(StreamExt::boxed)
+            }
+            None => gql_socket
+                .into_subscription(self.body.clone(), heartbeat_interval)
+                .await
+                .map(StreamExt::boxed),
+        }
         .map_err(|err| FetchError::SubrequestWsError {
             service: service_name,
             reason: format!("cannot send the subgraph request to websocket stream: {err:?}"),
         })?;
 
+        Ok((gql_stream, Some(resp)))
+    }
+
+    /// Publishes the events of the subscription to its topic. When the connection to the
+    /// subgraph is lost, the subscription is sent again on a new connection
+    async fn forward_reconnecting(
+        &self,
+        mut gql_stream: BoxStream<'static, graphql::Response>,
+        mut handle_sink: HandleSink<String, graphql::Response>,
+    ) {
+        let reconnect = &self.subgraph_cfg.reconnect;
+        'events: while let Some(response) = gql_stream.next().await {
+            if !is_connection_lost(&response) {
+                if handle_sink.send(response).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+
+            tracing::warn!(
+                apollo.subgraph.name = %self.service_name,
+                "lost the websocket connection to subgraph {:?}, reconnecting",
+                self.service_name
+            );
+            drop(gql_stream);
+            for attempt in 1..=reconnect.max_attempts {
+                if reconnect.notify_clients {
+                    let mut notice = graphql::Response::builder()
+                        .extension(
+                            SUBSCRIPTION_RECONNECTING_EXTENSION,
+                            serde_json_bytes::json!({
+                                "subgraph": self.service_name,
+                                "attempt": attempt,
+                            }),
+                        )
+                        .build();
+                    notice.router_notice = true;
+                    if handle_sink.send_notice(notice).is_err() {
+                        return;
+                    }
+                }
+
+                tokio::time::sleep(reconnect.delay(attempt)).await;
+                let result = self.subscribe().await;
+                u64_counter!(
+                    "apollo.router.subscriptions.reconnections",
+                    "Number of attempts to subscribe again after losing the connection to a subgraph",
+                    1,
+                    "subgraph.name" = self.service_name.clone(),
+                    "reconnection.succeeded" = result.is_ok()
+                );
+                match result {
+                    Ok((new_stream, _)) => {
+                        gql_stream = new_stream;
+                        continue 'events;
+                    }
+                    Err(err) => tracing::warn!(
+                        apollo.subgraph.name = %self.service_name,
+                        "cannot reconnect to subgraph {:?} (attempt {attempt}): {err}",
+                        self.service_name
+                    ),
+                }
+            }
+
+            // Every attempt failed, clients get the error of the lost connection
+            let _ = handle_sink.send(response).await;
+            break;
+        }
+
+        let _ = handle_sink.close().await;
+    }
+}
+
+/// Copies a WebSocket upgrade request, with a new handshake key
+fn clone_websocket_request(request: &http::Request<()>) -> http::Request<()> {
+    let mut cloned = http::Request::new(());
+    *cloned.method_mut() = request.method().clone();
+    *cloned.uri_mut() = request.uri().clone();
+    *cloned.version_mut() = request.version();
+    *cloned.headers_mut() = request.headers().clone();
+    if let Ok(key) = HeaderValue::from_str(&generate_key()) {
+        cloned
+            .headers_mut()
+            .insert(http::header::SEC_WEBSOCKET_KEY, key);
+    }
+
+    cloned
+}
+
+/// Publishes the events of a subgraph subscription to its topic, and sends the topic's events to
+/// the client
+async fn forward_websocket_events(
+    gql_stream: BoxStream<'static, graphql::Response>,
+    subscription_request: WebSocketSubscriptionRequest,
+    handle: Handle<String, graphql::Response>,
+    subscription_stream_tx: mpsc::Sender<BoxGqlStream>,
+    subscription_hash: String,
+) -> Result<(), BoxError> {
     let (handle_sink, handle_stream) = handle.split();
 
     tokio::task::spawn(async move {
-        let _ = gql_stream
-            .map(Ok::<_, graphql::Error>)
-            .forward(handle_sink)
-            .await;
+        if subscription_request.subgraph_cfg.reconnect.enabled {
+            subscription_request
+                .forward_reconnecting(gql_stream, handle_sink)
+                .await;
+        } else {
+            let _ = gql_stream
+                .map(Ok::<_, graphql::Error>)
+                .forward(handle_sink)
+                .await;
+        }
     });
 
-    subscription_stream_tx.send(Box::pin(handle_stream)).await?;
+    subscription_stream_tx
+        .send(event_stream(subscription_hash, handle_stream))
+        .await?;
+
+    Ok(())
+}
+
+/// Identifies the WebSocket connections a subscription can share: the ones opened with the same
+/// URL, headers and connection parameters
+fn websocket_connection_key(
+    request: &http::Request<()>,
+    connection_params: Option<&serde_json_bytes::Value>,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(request.uri().to_string());
+    for (name, value) in request
+        .headers()
+        .iter()
+        // The key is random for each connection
+        .filter(|(name, _)| *name != http::header::SEC_WEBSOCKET_KEY)
+        .sorted_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()))
+    {
+        hasher.update(name.as_str());
+        hasher.update(b":");
+        hasher.update(value.as_bytes());
+        hasher.update(b"\n");
+    }
+    if let Some(connection_params) = connection_params {
+        hasher.update(serde_json::to_vec(connection_params).expect("serialization will not fail"));
+    }
+
+    hex::encode(hasher.finalize())
+}
+
+/// Returns the events of a subscription topic, with the IDs clients can resume it from
+fn event_stream(topic: String, stream: HandleStream<String, graphql::Response>) -> BoxGqlStream {
+    Box::pin(
+        stream
+            .with_positions()
+            .map(move |(position, mut response)| {
+                if let Some(position) = position {
+                    response.extensions.insert(
+                        SUBSCRIPTION_EVENT_ID_EXTENSION,
+                        event_id(&topic, position).into(),
+                    );
+                }
+                response
+            }),
+    )
+}
+
+/// Resumes the subscription topic a client was receiving events from before a disconnection,
+/// starting with the events it missed, if the topic is still opened and was opened by the same
+/// operation and client
+async fn resume_subscription(
+    notify: &mut Notify<String, graphql::Response>,
+    request: &SubgraphRequest,
+    topic: &str,
+    request_hash: &str,
+    deduplication: bool,
+) -> Result<Option<BoxGqlStream>, BoxError> {
+    let supergraph_request = &request.supergraph_request;
+    let last_event_id = supergraph_request
+        .headers()
+        .get(LAST_EVENT_ID_HEADER_NAME)
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| {
+            supergraph_request
+                .body()
+                .extensions
+                .get(SUBSCRIPTION_LAST_EVENT_ID_EXTENSION)
+                .and_then(|value| value.as_str())
+        });
+    let Some((resumed_topic, position)) = last_event_id.and_then(parse_event_id) else {
+        return Ok(None);
+    };
+    // Deduplicated topics are shared by identical requests, so a client can only resume the
+    // topic of its own request. Other topics are random, only known by their client.
+    if deduplication && resumed_topic != topic {
+        return Ok(None);
+    }
 
-    Ok(SubgraphResponse::new_from_response(
-        resp.map(|_| graphql::Response::default()),
-        context,
-    ))
+    Ok(notify
+        .resume(
+            resumed_topic.to_string(),
+            position,
+            request_hash.to_string(),
+        )
+        .await?
+        .map(|handle| event_stream(resumed_topic.to_string(), handle.into_stream())))
 }
 
 // Utility function to extract uri details.
@@ -1379,8 +1746,13 @@ mod tests {
     use std::convert::Infallible;
     use std::net::SocketAddr;
     use std::net::TcpListener;
+    use std::num::NonZeroUsize;
     use std::str::FromStr;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
 
+    use axum::extract::ws::CloseFrame;
     use axum::extract::ws::Message;
     use axum::extract::ConnectInfo;
     use axum::extract::WebSocketUpgrade;
@@ -1411,6 +1783,7 @@ mod tests {
     use crate::plugins::subscription::HeartbeatInterval;
     use crate::plugins::subscription::SubgraphPassthroughMode;
     use crate::plugins::subscription::SubscriptionModeConfig;
+    use crate::plugins::subscription::WebSocketReconnectConfig;
     use crate::plugins::subscription::SUBSCRIPTION_CALLBACK_HMAC_KEY;
     use crate::plugins::traffic_shaping::Http2Config;
     use crate::protocols::websocket::ClientMessage;
@@ -1921,6 +2294,67 @@ mod tests {
         server.await.unwrap();
     }
 
+    async fn emulate_restarting_websocket_server(listener: TcpListener) {
+        static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+        async fn ws_handler(
+            ws: WebSocketUpgrade,
+            ConnectInfo(_addr): ConnectInfo<SocketAddr>,
+        ) -> Result<impl IntoResponse, Infallible> {
+            let res = ws.on_upgrade(move |mut socket| async move {
+                let connection = CONNECTIONS.fetch_add(1, Ordering::SeqCst) + 1;
+                let connection_init = socket.recv().await.unwrap().unwrap().into_text().unwrap();
+                let init_msg: ClientMessage = serde_json::from_str(&connection_init).unwrap();
+                assert!(matches!(init_msg, ClientMessage::ConnectionInit { .. }));
+
+                socket
+                    .send(Message::Text(
+                        serde_json::to_string(&ServerMessage::ConnectionAck).unwrap(),
+                    ))
+                    .await
+                    .unwrap();
+                let new_message = socket.recv().await.unwrap().unwrap().into_text().unwrap();
+                let id = match serde_json::from_str(&new_message).unwrap() {
+                    ClientMessage::Subscribe { id, .. } => id,
+                    _ => panic!("subscribe message should be sent"),
+                };
+
+                socket
+                    .send(Message::Text(
+                        serde_json::to_string(&ServerMessage::Next {
+                            id,
+                            payload: graphql::Response::builder()
+                                .data(serde_json_bytes::json!({ "connection": connection }))
+                                .build(),
+                        })
+                        .unwrap(),
+                    ))
+                    .await
+                    .unwrap();
+
+                // The first connection is closed as if the subgraph was restarting
+                if connection == 1 {
+                    let _ = socket
+                        .send(Message::Close(Some(CloseFrame {
+                            code: 1001,
+                            reason: "restarting".into(),
+                        })))
+                        .await;
+                } else {
+                    while let Some(Ok(_)) = socket.recv().await {}
+                }
+            });
+
+            Ok(res)
+        }
+
+        let app = Router::new().route("/ws", get(ws_handler));
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        server.await.unwrap();
+    }
+
     async fn emulate_incorrect_websocket_server(listener: TcpListener) {
         async fn ws_handler(
             _ws: WebSocketUpgrade,
@@ -2003,6 +2437,8 @@ mod tests {
                             path: Some(String::from("/ws")),
                             protocol: WebSocketProtocol::default(),
                             heartbeat_interval: HeartbeatInterval::new_disabled(),
+                            max_operations_per_connection: NonZeroUsize::MIN,
+                            reconnect: Default::default(),
                         },
                     )]
                     .into(),
@@ -2011,6 +2447,14 @@ mod tests {
             enable_deduplication: true,
             max_opened_subscriptions: None,
             queue_capacity: None,
+            client_websocket: Default::default(),
+            redis: None,
+            replay: Default::default(),
+            per_client: None,
+            max_lifetime: None,
+            idle_timeout: None,
+            admin: Default::default(),
+            drain: Default::default(),
         }
     }
 
@@ -2322,6 +2766,85 @@ mod tests {
         spawned_task.abort();
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_subgraph_service_websocket_reconnection() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        let spawned_task = tokio::task::spawn(emulate_restarting_websocket_server(listener));
+        let mut config = subscription_config();
+        config
+            .mode
+            .passthrough
+            .as_mut()
+            .unwrap()
+            .subgraphs
+            .get_mut("test")
+            .unwrap()
+            .reconnect = WebSocketReconnectConfig {
+            enabled: true,
+            initial_delay: Duration::from_millis(10),
+            notify_clients: true,
+            ..Default::default()
+        };
+        let subgraph_service = SubgraphService::new(
+            "test",
+            true,
+            config.into(),
+            Notify::builder().build(),
+            HttpClientServiceFactory::from_config(
+                "test",
+                &Configuration::default(),
+                Http2Config::Enable,
+            ),
+        )
+        .expect("can create a SubgraphService");
+        let (tx, rx) = mpsc::channel(2);
+        let mut rx_stream = ReceiverStream::new(rx);
+
+        let url = Uri::from_str(&format!("ws://{socket_addr}")).unwrap();
+        let response = subgraph_service
+            .oneshot(
+                SubgraphRequest::builder()
+                    .supergraph_request(supergraph_request(
+                        "subscription {\n  userWasCreated {\n    username\n  }\n}",
+                    ))
+                    .subgraph_request(subgraph_http_request(
+                        url,
+                        "subscription {\n  userWasCreated {\n    username\n  }\n}",
+                    ))
+                    .operation_kind(OperationKind::Subscription)
+                    .subscription_stream(tx)
+                    .subgraph_name(String::from("test"))
+                    .context(Context::new())
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert!(response.response.body().errors.is_empty());
+
+        let mut gql_stream = rx_stream.next().await.unwrap();
+        assert_eq!(
+            gql_stream.next().await.unwrap().data,
+            Some(serde_json_bytes::json!({ "connection": 1 }))
+        );
+        // The lost connection isn't reported as an error, clients are told about the reconnection
+        let mut notice = graphql::Response::builder()
+            .extension(
+                SUBSCRIPTION_RECONNECTING_EXTENSION,
+                serde_json_bytes::json!({ "subgraph": "test", "attempt": 1 }),
+            )
+            .build();
+        notice.router_notice = true;
+        assert_eq!(gql_stream.next().await.unwrap(), notice);
+        let message = gql_stream.next().await.unwrap();
+        assert_eq!(
+            message.data,
+            Some(serde_json_bytes::json!({ "connection": 2 }))
+        );
+        assert!(message.errors.is_empty());
+        spawned_task.abort();
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_subgraph_service_websocket_with_error() {
         let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
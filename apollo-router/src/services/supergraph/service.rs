@@ -1,8 +1,10 @@
 //! Implements the router phase of the request lifecycle.
 
+use std::pin::Pin;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::task::Poll;
+use std::time::Duration;
 use std::time::Instant;
 
 use futures::future::BoxFuture;
@@ -28,16 +30,19 @@ use crate::batching::BatchQuery;
 use crate::configuration::Batching;
 use crate::context::OPERATION_NAME;
 use crate::error::CacheResolverError;
+use crate::error::QueryPlannerError;
 use crate::graphql;
 use crate::graphql::IntoGraphQLErrors;
 use crate::graphql::Response;
 use crate::plugin::DynPlugin;
 use crate::plugins::subscription::SubscriptionConfig;
+use crate::plugins::subscription::SUBSCRIPTION_EVENT_ID_EXTENSION;
 use crate::plugins::telemetry::tracing::apollo_telemetry::APOLLO_PRIVATE_DURATION_NS;
 use crate::plugins::telemetry::Telemetry;
 use crate::plugins::telemetry::LOGGING_DISPLAY_BODY;
 use crate::plugins::traffic_shaping::TrafficShaping;
 use crate::plugins::traffic_shaping::APOLLO_TRAFFIC_SHAPING;
+use crate::query_planner::subscription::ClientSubscription;
 use crate::query_planner::subscription::SubscriptionHandle;
 use crate::query_planner::subscription::OPENED_SUBSCRIPTIONS;
 use crate::query_planner::subscription::SUBSCRIPTION_EVENT_SPAN_NAME;
@@ -51,6 +56,7 @@ use crate::services::execution::QueryPlan;
 use crate::services::layers::allow_only_http_post_mutations::AllowOnlyHttpPostMutationsLayer;
 use crate::services::layers::content_negotiation;
 use crate::services::layers::persisted_queries::PersistedQueryLayer;
+use crate::services::layers::query_analysis::ParsedDocument;
 use crate::services::layers::query_analysis::QueryAnalysisLayer;
 use crate::services::new_service::ServiceFactory;
 use crate::services::query_planner;
@@ -66,6 +72,8 @@ use crate::services::QueryPlannerContent;
 use crate::services::QueryPlannerResponse;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
+use crate::spec::operation_limits;
+use crate::spec::operation_limits::OperationLimits;
 use crate::spec::Schema;
 use crate::Configuration;
 use crate::Context;
@@ -83,6 +91,7 @@ pub(crate) struct SupergraphService {
     query_planner_service: CachingQueryPlanner<BridgeQueryPlannerPool>,
     schema: Arc<Schema>,
     notify: Notify<String, graphql::Response>,
+    configuration: Arc<Configuration>,
 }
 
 #[buildstructor::buildstructor]
@@ -93,12 +102,14 @@ impl SupergraphService {
         execution_service_factory: ExecutionServiceFactory,
         schema: Arc<Schema>,
         notify: Notify<String, graphql::Response>,
+        configuration: Arc<Configuration>,
     ) -> Self {
         SupergraphService {
             query_planner_service,
             execution_service_factory,
             schema,
             notify,
+            configuration,
         }
     }
 }
@@ -129,6 +140,7 @@ impl Service<SupergraphRequest> for SupergraphService {
             schema,
             req,
             self.notify.clone(),
+            self.configuration.clone(),
         )
         .or_else(|error: BoxError| async move {
             let errors = vec![crate::error::Error {
@@ -159,11 +171,30 @@ async fn service_call(
     schema: Arc<Schema>,
     req: SupergraphRequest,
     notify: Notify<String, graphql::Response>,
+    configuration: Arc<Configuration>,
 ) -> Result<SupergraphResponse, BoxError> {
     let context = req.context;
     let body = req.supergraph_request.body();
     let variables = body.variables.clone();
 
+    // With limit profiles, operation limits depend on the request
+    // so they are checked here rather than when planning
+    if !configuration.limits.profiles.is_empty() {
+        let document = context.extensions().lock().get::<ParsedDocument>().cloned();
+        if let Some(document) = document {
+            let profile = operation_limits::applied_profile(&configuration.limits, &context);
+            if let Err(exceeded) = operation_limits::check(
+                &configuration,
+                profile,
+                body.query.as_deref().unwrap_or_default(),
+                &document.executable,
+                body.operation_name.as_deref(),
+            ) {
+                return Ok(limit_exceeded_response(exceeded, context));
+            }
+        }
+    }
+
     let QueryPlannerResponse {
         content,
         context,
@@ -265,6 +296,7 @@ async fn service_call(
             let ClientRequestAccepts {
                 multipart_defer: accepts_multipart_defer,
                 multipart_subscription: accepts_multipart_subscription,
+                event_stream: accepts_event_stream,
                 ..
             } = context
                 .extensions()
@@ -273,8 +305,8 @@ async fn service_call(
                 .cloned()
                 .unwrap_or_default();
             let mut subscription_tx = None;
-            if (is_deferred && !accepts_multipart_defer)
-                || (is_subscription && !accepts_multipart_subscription)
+            if (is_deferred && !(accepts_multipart_defer || accepts_event_stream))
+                || (is_subscription && !(accepts_multipart_subscription || accepts_event_stream))
             {
                 let (error_message, error_code) = if is_deferred {
                     (String::from("the router received a query with the @defer directive but the client does not accept multipart/mixed HTTP responses. To enable @defer support, add the HTTP header 'Accept: multipart/mixed;deferSpec=20220824'"), "DEFER_BAD_HEADER")
@@ -296,6 +328,13 @@ async fn service_call(
                 let mut res = SupergraphResponse::new_from_graphql_response(err, context);
                 *res.response.status_mut() = StatusCode::BAD_REQUEST;
                 Ok(res)
+            } else if let Err(exceeded) = operation_limits::check_variables(
+                &configuration,
+                body.query.as_deref().unwrap_or_default(),
+                &variables,
+                operation_name.as_deref(),
+            ) {
+                Ok(limit_exceeded_response(exceeded, context))
             } else {
                 if is_subscription {
                     let ctx = context.clone();
@@ -353,6 +392,8 @@ pub struct SubscriptionTaskParams {
     pub(crate) subscription_config: SubscriptionConfig,
     pub(crate) stream_rx: ReceiverStream<BoxGqlStream>,
     pub(crate) service_name: String,
+    /// Subscription reserved for the client, if its number of subscriptions is limited
+    pub(crate) client_subscription: Option<ClientSubscription>,
 }
 
 async fn subscription_task(
@@ -374,6 +415,8 @@ async fn subscription_task(
     let service_name = sub_params.service_name;
     let mut receiver = sub_params.stream_rx;
     let sender = sub_params.client_sender;
+    // Released when the task ends
+    let _client_subscription = sub_params.client_subscription;
 
     // Get the rest of the query_plan to execute for subscription events
     let query_plan = match &query_plan.root {
@@ -432,10 +475,19 @@ async fn subscription_task(
 
     let mut configuration_updated_rx = notify.subscribe_configuration();
     let mut schema_updated_rx = notify.subscribe_schema();
+    let mut shutdown_rx = notify.subscribe_shutdown();
 
     let expires_in = crate::plugins::authentication::jwt_expires_in(&supergraph_req.context);
 
     let mut timeout = Box::pin(tokio::time::sleep(expires_in));
+    let mut lifetime_timeout = Box::pin(tokio::time::sleep(
+        subscription_config.max_lifetime.unwrap_or(Duration::MAX),
+    ));
+    let idle_timeout = subscription_config.idle_timeout;
+    let mut idle_timer = Box::pin(tokio::time::sleep(idle_timeout.unwrap_or(Duration::MAX)));
+    let drain = subscription_config.drain.clone();
+    let mut drain_timer = Box::pin(tokio::time::sleep(Duration::MAX));
+    let mut drain_reason = None;
 
     loop {
         tokio::select! {
@@ -457,9 +509,70 @@ async fn subscription_task(
                 let _ = sender.send(response).await;
                 break;
             },
+            _ = &mut lifetime_timeout => {
+                let max_lifetime = subscription_config.max_lifetime.unwrap_or_default();
+                let response = Response::builder()
+                    .subscribed(false)
+                    .error(
+                        crate::error::Error::builder()
+                            .message("subscription closed because it reached its maximum lifetime")
+                            .extension_code("SUBSCRIPTION_MAX_LIFETIME")
+                            .extension(
+                                "maxLifetime",
+                                humantime::format_duration(max_lifetime).to_string(),
+                            )
+                            .build(),
+                    )
+                    .build();
+                let _ = sender.send(response).await;
+                break;
+            },
+            _ = &mut idle_timer => {
+                let idle_timeout = idle_timeout.unwrap_or_default();
+                let response = Response::builder()
+                    .subscribed(false)
+                    .error(
+                        crate::error::Error::builder()
+                            .message("subscription closed because it didn't receive any event")
+                            .extension_code("SUBSCRIPTION_IDLE_TIMEOUT")
+                            .extension(
+                                "idleTimeout",
+                                humantime::format_duration(idle_timeout).to_string(),
+                            )
+                            .build(),
+                    )
+                    .build();
+                let _ = sender.send(response).await;
+                break;
+            },
+            _ = &mut drain_timer => {
+                let reason = drain_reason.unwrap_or(DrainReason::Reload);
+                let response = Response::builder()
+                    .subscribed(false)
+                    .error(
+                        crate::error::Error::builder()
+                            .message(reason.message())
+                            .extension_code("SUBSCRIPTION_RECONNECT")
+                            .extension("reason", reason.as_str())
+                            .build(),
+                    )
+                    .build();
+                let _ = sender.send(response).await;
+                break;
+            },
             message = receiver.next() => {
                 match message {
+                    // Router notices don't come from the subgraph, there is nothing to execute
+                    Some(val) if val.router_notice => {
+                        if let Err(err) = sender.send(val).await {
+                            tracing::error!("cannot send the subscription to the client: {err:?}");
+                            break;
+                        }
+                    }
                     Some(mut val) => {
+                        if let Some(idle_timeout) = idle_timeout {
+                            idle_timer.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                        }
                         if display_body {
                             tracing::info!(http.request.body = ?val, apollo.subgraph.name = %service_name, "Subscription event body from subgraph {service_name:?}");
                         }
@@ -480,9 +593,17 @@ async fn subscription_task(
                 }
             }
             Some(new_configuration) = configuration_updated_rx.next() => {
-                // If the configuration was dropped in the meantime, we ignore this update and will
-                // pick up the next one.
-                if let Some(conf) = new_configuration.upgrade() {
+                if let Some(reload_deadline) = drain.reload_deadline {
+                    // Drained subscriptions keep the previous pipeline until they are closed
+                    drain_before(
+                        &mut drain_timer,
+                        &mut drain_reason,
+                        DrainReason::Reload,
+                        reload_deadline,
+                    );
+                } else if let Some(conf) = new_configuration.upgrade() {
+                    // If the configuration was dropped in the meantime, we ignore this update and
+                    // will pick up the next one.
                     let plugins = match create_plugins(&conf, &execution_service_factory.schema, execution_service_factory.subgraph_schemas.clone(), None, None).await {
                         Ok(plugins) => Arc::new(plugins),
                         Err(err) => {
@@ -509,18 +630,32 @@ async fn subscription_task(
             }
             Some(new_schema) = schema_updated_rx.next() => {
                 if new_schema.raw_sdl != execution_service_factory.schema.raw_sdl {
-                    let _ = sender
-                        .send(
-                            Response::builder()
-                                .subscribed(false)
-                                .error(graphql::Error::builder().message("subscription has been closed due to a schema reload").extension_code("SUBSCRIPTION_SCHEMA_RELOAD").build())
-                                .build(),
-                        )
-                        .await;
-
-                    break;
+                    if let Some(reload_deadline) = drain.reload_deadline {
+                        drain_before(
+                            &mut drain_timer,
+                            &mut drain_reason,
+                            DrainReason::Reload,
+                            reload_deadline,
+                        );
+                    } else {
+                        let _ = sender
+                            .send(
+                                Response::builder()
+                                    .subscribed(false)
+                                    .error(graphql::Error::builder().message("subscription has been closed due to a schema reload").extension_code("SUBSCRIPTION_SCHEMA_RELOAD").build())
+                                    .build(),
+                            )
+                            .await;
+
+                        break;
+                    }
                 }
             }
+            Some(shutdown) = shutdown_rx.next() => {
+                let drained = shutdown.drain_subscription();
+                let delay = drain.shutdown_delay(drained);
+                drain_before(&mut drain_timer, &mut drain_reason, DrainReason::Shutdown, delay);
+            }
         }
     }
     drop(sender);
@@ -530,6 +665,47 @@ async fn subscription_task(
     }
 }
 
+/// Why a subscription is closed while it still runs
+#[derive(Clone, Copy)]
+enum DrainReason {
+    Reload,
+    Shutdown,
+}
+
+impl DrainReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            DrainReason::Reload => "reload",
+            DrainReason::Shutdown => "shutdown",
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            DrainReason::Reload => {
+                "subscription closed because the router was reloaded, please reconnect"
+            }
+            DrainReason::Shutdown => {
+                "subscription closed because the router is shutting down, please reconnect"
+            }
+        }
+    }
+}
+
+/// Closes the subscription after this delay, unless it was already going to be closed earlier
+fn drain_before(
+    drain_timer: &mut Pin<Box<tokio::time::Sleep>>,
+    drain_reason: &mut Option<DrainReason>,
+    reason: DrainReason,
+    delay: Duration,
+) {
+    let deadline = tokio::time::Instant::now() + delay;
+    if drain_reason.is_none() || deadline < drain_timer.deadline() {
+        drain_timer.as_mut().reset(deadline);
+        *drain_reason = Some(reason);
+    }
+}
+
 async fn dispatch_event(
     supergraph_req: &SupergraphRequest,
     execution_service_factory: &ExecutionServiceFactory,
@@ -579,6 +755,11 @@ async fn dispatch_event(
             if let Some(mut next_response) = next_response {
                 next_response.created_at = val.created_at;
                 next_response.subscribed = val.subscribed;
+                if let Some(event_id) = val.extensions.remove(SUBSCRIPTION_EVENT_ID_EXTENSION) {
+                    next_response
+                        .extensions
+                        .insert(SUBSCRIPTION_EVENT_ID_EXTENSION, event_id);
+                }
                 val.errors.append(&mut next_response.errors);
                 next_response.errors = val.errors;
 
@@ -597,6 +778,20 @@ async fn dispatch_event(
     res
 }
 
+fn limit_exceeded_response(
+    exceeded: OperationLimits<bool>,
+    context: Context,
+) -> SupergraphResponse {
+    let errors = QueryPlannerError::LimitExceeded(exceeded)
+        .into_graphql_errors()
+        .unwrap_or_default();
+    SupergraphResponse::infallible_builder()
+        .context(context)
+        .errors(errors)
+        .status_code(StatusCode::BAD_REQUEST)
+        .build()
+}
+
 async fn plan_query(
     mut planning: CachingQueryPlanner<BridgeQueryPlannerPool>,
     operation_name: Option<String>,
@@ -613,7 +808,7 @@ async fn plan_query(
     // Some tests do populate the document, so we only do it if it's not already there.
     if !{
         let lock = context.extensions().lock();
-        lock.contains_key::<crate::services::layers::query_analysis::ParsedDocument>()
+        lock.contains_key::<ParsedDocument>()
     } {
         let doc = crate::spec::Query::parse_document(
             &query_str,
@@ -621,11 +816,8 @@ async fn plan_query(
             &schema,
             &Configuration::default(),
         )
-        .map_err(crate::error::QueryPlannerError::from)?;
-        context
-            .extensions()
-            .lock()
-            .insert::<crate::services::layers::query_analysis::ParsedDocument>(doc);
+        .map_err(QueryPlannerError::from)?;
+        context.extensions().lock().insert::<ParsedDocument>(doc);
     }
 
     let qpr = planning
@@ -833,6 +1025,7 @@ impl SupergraphCreator {
             })
             .schema(self.schema.clone())
             .notify(self.config.notify.clone())
+            .configuration(self.config.clone())
             .build();
 
         let shaping = self
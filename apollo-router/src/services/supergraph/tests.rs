@@ -994,6 +994,69 @@ async fn subscription_with_callback() {
     insta::assert_json_snapshot!(stream.next_response().await.unwrap());
 }
 
+#[tokio::test]
+async fn subscription_reconnecting_notices_are_only_sent_by_the_router() {
+    let mut notify = Notify::builder().build();
+    let (handle, _) = notify
+        .create_or_subscribe("TEST_TOPIC".to_string(), false)
+        .await
+        .unwrap();
+    let subgraphs = MockedSubgraphs([
+            ("user", MockSubgraph::builder().with_json(
+                    serde_json::json!{{"query":"subscription{userWasCreated{name}}"}},
+                    serde_json::json!{{"data": {"userWasCreated": { "__typename": "User", "name": "test" }}}}
+                ).with_subscription_stream(handle.clone()).build()),
+        ].into_iter().collect());
+
+    let mut configuration: Configuration = serde_json::from_value(serde_json::json!({"include_subgraph_errors": { "all": true }, "subscription": { "enabled": true, "mode": {"callback": {"public_url": "http://localhost:4545/callback"}}}})).unwrap();
+    configuration.notify = notify.clone();
+    let service = TestHarness::builder()
+        .configuration(Arc::new(configuration))
+        .schema(SCHEMA)
+        .extra_plugin(subgraphs)
+        .build_supergraph()
+        .await
+        .unwrap();
+
+    let request = supergraph::Request::fake_builder()
+        .query("subscription { userWasCreated { name } }")
+        .context(subscription_context())
+        .build()
+        .unwrap();
+    let mut stream = service.oneshot(request).await.unwrap();
+    let _ = stream.next_response().await.unwrap();
+
+    // A subgraph event looking like a notice is executed like any other event
+    notify
+        .broadcast(
+            graphql::Response::builder()
+                .extension(
+                    "reconnecting",
+                    serde_json_bytes::json!({ "subgraph": "user", "attempt": 1 }),
+                )
+                .build(),
+        )
+        .await
+        .unwrap();
+    let response = stream.next_response().await.unwrap();
+    assert!(!response.extensions.contains_key("reconnecting"));
+
+    let mut notice = graphql::Response::builder()
+        .extension(
+            "reconnecting",
+            serde_json_bytes::json!({ "subgraph": "user", "attempt": 1 }),
+        )
+        .build();
+    notice.router_notice = true;
+    notify.broadcast(notice).await.unwrap();
+    let response = stream.next_response().await.unwrap();
+    assert_eq!(response.data, None);
+    assert_eq!(
+        response.extensions.get("reconnecting"),
+        Some(&serde_json_bytes::json!({ "subgraph": "user", "attempt": 1 }))
+    );
+}
+
 #[tokio::test]
 async fn subscription_callback_schema_reload() {
     let mut notify = Notify::builder().build();
@@ -1068,6 +1131,136 @@ async fn subscription_callback_schema_reload() {
     .unwrap());
 }
 
+#[tokio::test]
+async fn subscription_callback_schema_reload_drain() {
+    let mut notify = Notify::builder().build();
+    let (handle, _) = notify
+        .create_or_subscribe("TEST_TOPIC".to_string(), false)
+        .await
+        .unwrap();
+    let subgraphs = MockedSubgraphs([
+            ("user", MockSubgraph::builder().with_json(
+                    serde_json::json!{{"query":"subscription{userWasCreated{name}}"}},
+                    serde_json::json!{{"data": {"userWasCreated": { "__typename": "User", "id": "1" }}}}
+                ).with_subscription_stream(handle.clone()).build()),
+        ].into_iter().collect());
+
+    let mut configuration: Configuration = serde_json::from_value(serde_json::json!({"include_subgraph_errors": { "all": true }, "subscription": { "enabled": true, "drain": { "reload_deadline": "200ms" }, "mode": {"callback": {"public_url": "http://localhost:4545/callback"}}}})).unwrap();
+    configuration.notify = notify.clone();
+    let configuration = Arc::new(configuration);
+    let service = TestHarness::builder()
+        .configuration(configuration.clone())
+        .schema(SCHEMA)
+        .extra_plugin(subgraphs)
+        .build_supergraph()
+        .await
+        .unwrap();
+
+    let request = supergraph::Request::fake_builder()
+        .query("subscription { userWasCreated { name } }")
+        .context(subscription_context())
+        .build()
+        .unwrap();
+    let mut stream = service.oneshot(request).await.unwrap();
+    let res = stream.next_response().await.unwrap();
+    assert_eq!(res.subscribed, Some(true));
+
+    let new_schema = format!("{SCHEMA}  ");
+    let schema = Schema::parse_test(&new_schema, &configuration).unwrap();
+    notify.broadcast_schema(Arc::new(schema));
+
+    // Events are still sent with the previous schema until the deadline
+    notify
+        .broadcast(
+            graphql::Response::builder()
+                .data(serde_json_bytes::json!({"userWasCreated": { "name": "test" }}))
+                .build(),
+        )
+        .await
+        .unwrap();
+    let res = stream.next_response().await.unwrap();
+    assert_eq!(
+        res.data,
+        Some(serde_json_bytes::json!({"userWasCreated": { "name": "test" }}))
+    );
+
+    let res = tokio::time::timeout(Duration::from_secs(1), stream.next_response())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.subscribed, Some(false));
+    assert_eq!(
+        res.errors[0]
+            .extensions
+            .get("code")
+            .and_then(|code| code.as_str()),
+        Some("SUBSCRIPTION_RECONNECT")
+    );
+    assert_eq!(
+        res.errors[0]
+            .extensions
+            .get("reason")
+            .and_then(|reason| reason.as_str()),
+        Some("reload")
+    );
+}
+
+#[tokio::test]
+async fn subscription_callback_shutdown_drain() {
+    let mut notify = Notify::builder().build();
+    let (handle, _) = notify
+        .create_or_subscribe("TEST_TOPIC".to_string(), false)
+        .await
+        .unwrap();
+    let subgraphs = MockedSubgraphs([
+            ("user", MockSubgraph::builder().with_json(
+                    serde_json::json!{{"query":"subscription{userWasCreated{name}}"}},
+                    serde_json::json!{{"data": {"userWasCreated": { "__typename": "User", "id": "1" }}}}
+                ).with_subscription_stream(handle.clone()).build()),
+        ].into_iter().collect());
+
+    let mut configuration: Configuration = serde_json::from_value(serde_json::json!({"include_subgraph_errors": { "all": true }, "subscription": { "enabled": true, "drain": { "shutdown_grace_period": "100ms", "shutdown_batches": 2 }, "mode": {"callback": {"public_url": "http://localhost:4545/callback"}}}})).unwrap();
+    configuration.notify = notify.clone();
+    let service = TestHarness::builder()
+        .configuration(Arc::new(configuration))
+        .schema(SCHEMA)
+        .extra_plugin(subgraphs)
+        .build_supergraph()
+        .await
+        .unwrap();
+
+    let request = supergraph::Request::fake_builder()
+        .query("subscription { userWasCreated { name } }")
+        .context(subscription_context())
+        .build()
+        .unwrap();
+    let mut stream = service.oneshot(request).await.unwrap();
+    let res = stream.next_response().await.unwrap();
+    assert_eq!(res.subscribed, Some(true));
+
+    notify.broadcast_shutdown();
+    let res = tokio::time::timeout(Duration::from_secs(1), stream.next_response())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.subscribed, Some(false));
+    assert_eq!(
+        res.errors[0]
+            .extensions
+            .get("code")
+            .and_then(|code| code.as_str()),
+        Some("SUBSCRIPTION_RECONNECT")
+    );
+    assert_eq!(
+        res.errors[0]
+            .extensions
+            .get("reason")
+            .and_then(|reason| reason.as_str()),
+        Some("shutdown")
+    );
+    assert!(stream.next_response().await.is_none());
+}
+
 #[tokio::test]
 async fn subscription_with_callback_with_limit() {
     let mut notify = Notify::builder().build();
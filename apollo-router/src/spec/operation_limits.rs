@@ -1,19 +1,36 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 
+use apollo_compiler::ast;
 use apollo_compiler::executable;
 use apollo_compiler::ExecutableDocument;
+use apollo_compiler::Node;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::configuration::LimitProfile;
+use crate::configuration::Limits;
+use crate::json_ext::Object;
+use crate::json_ext::Value;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::plugins::telemetry::CLIENT_NAME;
 use crate::Configuration;
+use crate::Context;
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+/// Context key holding the name of the limit profile applied to a request
+pub(crate) const LIMITS_PROFILE: &str = "apollo_router::limits::profile";
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
 pub(crate) struct OperationLimits<T> {
     pub(crate) depth: T,
     pub(crate) height: T,
     pub(crate) root_fields: T,
     pub(crate) aliases: T,
+    pub(crate) variables: T,
+    pub(crate) directives: T,
+    pub(crate) fragments: T,
+    pub(crate) input_depth: T,
+    pub(crate) list_length: T,
 }
 
 /// If it swims like a burrito and quacks like a burrito…
@@ -24,6 +41,11 @@ impl<A> OperationLimits<A> {
             height: f(self.height),
             root_fields: f(self.root_fields),
             aliases: f(self.aliases),
+            variables: f(self.variables),
+            directives: f(self.directives),
+            fragments: f(self.fragments),
+            input_depth: f(self.input_depth),
+            list_length: f(self.list_length),
         }
     }
 
@@ -37,6 +59,11 @@ impl<A> OperationLimits<A> {
             height: f("height", self.height, other.height),
             root_fields: f("root_fields", self.root_fields, other.root_fields),
             aliases: f("aliases", self.aliases, other.aliases),
+            variables: f("variables", self.variables, other.variables),
+            directives: f("directives", self.directives, other.directives),
+            fragments: f("fragments", self.fragments, other.fragments),
+            input_depth: f("input_depth", self.input_depth, other.input_depth),
+            list_length: f("list_length", self.list_length, other.list_length),
         }
     }
 }
@@ -49,25 +76,134 @@ impl OperationLimits<bool> {
             height,
             root_fields,
             aliases,
+            variables,
+            directives,
+            fragments,
+            input_depth,
+            list_length,
         } = *self;
-        depth || height || root_fields || aliases
+        depth
+            || height
+            || root_fields
+            || aliases
+            || variables
+            || directives
+            || fragments
+            || input_depth
+            || list_length
+    }
+}
+
+impl OperationLimits<u32> {
+    /// Accumulate the directives and input values measured in a nested selection set
+    fn add_nested_inputs(&mut self, nested: OperationLimits<u32>) {
+        self.directives += nested.directives;
+        self.input_depth = self.input_depth.max(nested.input_depth);
+        self.list_length = self.list_length.max(nested.list_length);
+    }
+
+    /// Measure the input object nesting depth and the list lengths of an input value literal
+    fn measure_input_literal(&mut self, value: &ast::Value) {
+        let depth = literal_depth(value, &mut self.list_length);
+        self.input_depth = self.input_depth.max(depth);
+    }
+
+    fn measure_arguments(&mut self, arguments: &[Node<ast::Argument>]) {
+        for argument in arguments {
+            self.measure_input_literal(&argument.value);
+        }
+    }
+
+    fn measure_directives(&mut self, directives: &ast::DirectiveList) {
+        self.directives += directives.len() as u32;
+        for directive in directives.iter() {
+            self.measure_arguments(&directive.arguments);
+        }
+    }
+}
+
+/// Returns the name of the limit profile selected for a request, if any
+pub(crate) fn select_profile<'a>(config_limits: &'a Limits, context: &Context) -> Option<&'a str> {
+    if config_limits.profiles.is_empty() {
+        return None;
+    }
+    let selection = &config_limits.profile_selection;
+    let from_context = selection
+        .context_key
+        .as_ref()
+        .and_then(|key| context.get::<_, String>(key).ok().flatten());
+    let claims = context
+        .get_json_value(APOLLO_AUTHENTICATION_JWT_CLAIMS)
+        .filter(|claims| !claims.is_null());
+    let from_claim = selection.claim.as_ref().and_then(|claim| {
+        claims
+            .as_ref()?
+            .get(claim.as_str())?
+            .as_str()
+            .map(str::to_string)
+    });
+    let from_client_name = context
+        .get::<_, String>(CLIENT_NAME)
+        .ok()
+        .flatten()
+        .and_then(|client_name| selection.client_names.get(&client_name).cloned());
+    let unauthenticated = selection
+        .unauthenticated
+        .clone()
+        .filter(|_| claims.is_none());
+
+    [from_context, from_claim, from_client_name, unauthenticated]
+        .into_iter()
+        .flatten()
+        .find_map(|name| {
+            config_limits
+                .profiles
+                .get_key_value(&name)
+                .map(|(name, _)| name.as_str())
+        })
+}
+
+/// Returns the limit profile recorded in the context of a request, if any
+pub(crate) fn applied_profile<'a>(
+    config_limits: &'a Limits,
+    context: &Context,
+) -> Option<&'a LimitProfile> {
+    let name = context.get::<_, String>(LIMITS_PROFILE).ok().flatten()?;
+    config_limits.profiles.get(&name)
+}
+
+fn configured_limits(
+    config_limits: &Limits,
+    profile: Option<&LimitProfile>,
+) -> OperationLimits<Option<u32>> {
+    let profile_or_global = |from_profile: fn(&LimitProfile) -> Option<u32>, global| {
+        profile.and_then(from_profile).or(global)
+    };
+    OperationLimits {
+        depth: profile_or_global(|p| p.max_depth, config_limits.max_depth),
+        height: profile_or_global(|p| p.max_height, config_limits.max_height),
+        root_fields: profile_or_global(|p| p.max_root_fields, config_limits.max_root_fields),
+        aliases: profile_or_global(|p| p.max_aliases, config_limits.max_aliases),
+        variables: config_limits.max_variables,
+        directives: config_limits.max_directives,
+        fragments: config_limits.max_fragments,
+        input_depth: config_limits.max_input_depth,
+        list_length: config_limits.max_list_length,
     }
 }
 
 /// Returns which limits are exceeded by the given query, if any
+///
+/// The limits of the given profile replace the global ones.
 pub(crate) fn check(
     configuration: &Configuration,
+    profile: Option<&LimitProfile>,
     query: &str,
     document: &ExecutableDocument,
     operation_name: Option<&str>,
 ) -> Result<(), OperationLimits<bool>> {
     let config_limits = &configuration.limits;
-    let max = OperationLimits {
-        depth: config_limits.max_depth,
-        height: config_limits.max_height,
-        root_fields: config_limits.max_root_fields,
-        aliases: config_limits.max_aliases,
-    };
+    let max = configured_limits(config_limits, profile);
     if !max.map(|limit| limit.is_some()).any() {
         // No configured limit
         return Ok(());
@@ -81,7 +217,53 @@ pub(crate) fn check(
     };
 
     let mut fragment_cache = HashMap::new();
-    let measured = count(document, &mut fragment_cache, &operation.selection_set);
+    let mut measured = count(document, &mut fragment_cache, &operation.selection_set);
+    measured.variables = operation.variables.len() as u32;
+    measured.fragments = document.fragments.len() as u32;
+    measured.measure_directives(&operation.directives);
+    for variable in &operation.variables {
+        measured.measure_directives(&variable.directives);
+        if let Some(default_value) = &variable.default_value {
+            measured.measure_input_literal(default_value);
+        }
+    }
+    check_measured(config_limits, max, measured, query, operation_name)
+}
+
+/// Returns which limits are exceeded by the variables of a request, if any.
+///
+/// Only the input object nesting depth and the list length limits apply to variables.
+pub(crate) fn check_variables(
+    configuration: &Configuration,
+    query: &str,
+    variables: &Object,
+    operation_name: Option<&str>,
+) -> Result<(), OperationLimits<bool>> {
+    let config_limits = &configuration.limits;
+    let max = OperationLimits {
+        input_depth: config_limits.max_input_depth,
+        list_length: config_limits.max_list_length,
+        ..Default::default()
+    };
+    if !max.map(|limit| limit.is_some()).any() {
+        return Ok(());
+    }
+
+    let mut measured = OperationLimits::default();
+    for value in variables.values() {
+        let depth = variable_depth(value, &mut measured.list_length);
+        measured.input_depth = measured.input_depth.max(depth);
+    }
+    check_measured(config_limits, max, measured, query, operation_name)
+}
+
+fn check_measured(
+    config_limits: &Limits,
+    max: OperationLimits<Option<u32>>,
+    measured: OperationLimits<u32>,
+    query: &str,
+    operation_name: Option<&str>,
+) -> Result<(), OperationLimits<bool>> {
     let exceeded = max.combine(measured, |_, config, measured| {
         if let Some(limit) = config {
             measured > limit
@@ -121,12 +303,7 @@ fn count<'a>(
     fragment_cache: &mut HashMap<&'a executable::Name, Computation<OperationLimits<u32>>>,
     selection_set: &'a executable::SelectionSet,
 ) -> OperationLimits<u32> {
-    let mut counts = OperationLimits {
-        depth: 0,
-        height: 0,
-        root_fields: 0,
-        aliases: 0,
-    };
+    let mut counts = OperationLimits::default();
     let mut fields_seen = HashSet::new();
     for selection in &selection_set.selections {
         match selection {
@@ -135,6 +312,9 @@ fn count<'a>(
                 counts.depth = counts.depth.max(1 + nested.depth);
                 counts.height += nested.height;
                 counts.aliases += nested.aliases;
+                counts.add_nested_inputs(nested);
+                counts.measure_arguments(&field.arguments);
+                counts.measure_directives(&field.directives);
                 // Multiple aliases for the same field could use different arguments
                 // Until we do full merging for limit checking purpose,
                 // approximate measured height with an upper bound rather than a lower bound.
@@ -155,6 +335,8 @@ fn count<'a>(
                 counts.depth = counts.depth.max(nested.depth);
                 counts.height += nested.height;
                 counts.aliases += nested.aliases;
+                counts.add_nested_inputs(nested);
+                counts.measure_directives(&fragment.directives);
             }
             executable::Selection::FragmentSpread(fragment) => {
                 let name = &fragment.fragment_name;
@@ -163,7 +345,10 @@ fn count<'a>(
                     None => {
                         if let Some(definition) = document.fragments.get(name) {
                             fragment_cache.insert(name, Computation::InProgress);
-                            nested = count(document, fragment_cache, &definition.selection_set);
+                            let mut definition_counts =
+                                count(document, fragment_cache, &definition.selection_set);
+                            definition_counts.measure_directives(&definition.directives);
+                            nested = definition_counts;
                             fragment_cache.insert(name, Computation::Done(nested));
                         } else {
                             // Undefined fragment. The operation invalid
@@ -185,8 +370,54 @@ fn count<'a>(
                 counts.depth = counts.depth.max(nested.depth);
                 counts.height += nested.height;
                 counts.aliases += nested.aliases;
+                counts.add_nested_inputs(nested);
+                counts.measure_directives(&fragment.directives);
             }
         }
     }
     counts
 }
+
+/// Returns the input object nesting depth of a literal, updating the longest list length seen
+fn literal_depth(value: &ast::Value, list_length: &mut u32) -> u32 {
+    match value {
+        ast::Value::List(items) => {
+            *list_length = (*list_length).max(items.len() as u32);
+            items
+                .iter()
+                .map(|item| literal_depth(item, list_length))
+                .max()
+                .unwrap_or(0)
+        }
+        ast::Value::Object(fields) => {
+            1 + fields
+                .iter()
+                .map(|(_, value)| literal_depth(value, list_length))
+                .max()
+                .unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+/// Returns the input object nesting depth of a variable value, updating the longest list length seen
+fn variable_depth(value: &Value, list_length: &mut u32) -> u32 {
+    match value {
+        Value::Array(items) => {
+            *list_length = (*list_length).max(items.len() as u32);
+            items
+                .iter()
+                .map(|item| variable_depth(item, list_length))
+                .max()
+                .unwrap_or(0)
+        }
+        Value::Object(fields) => {
+            1 + fields
+                .values()
+                .map(|value| variable_depth(value, list_length))
+                .max()
+                .unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
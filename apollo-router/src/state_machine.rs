@@ -278,6 +278,7 @@ impl<FA: RouterSuperServiceFactory> State<FA> {
     {
         match self {
             Running {
+                configuration,
                 server_handle: Some(server_handle),
                 mut all_connections_stopped_signals,
                 ..
@@ -285,6 +286,8 @@ impl<FA: RouterSuperServiceFactory> State<FA> {
                 // We want to set the ready state to false before we start shutting down the server.
                 http_server_factory.ready(false);
                 tracing::info!("shutting down");
+                // Opened subscriptions are closed, so their connections can be shut down
+                configuration.notify.broadcast_shutdown();
                 let state = server_handle
                     .shutdown()
                     .map_ok_or_else(Errored, |_| Stopped)
@@ -364,6 +364,8 @@ impl<'a> TestHarness<'a> {
 
         let live = Arc::new(std::sync::atomic::AtomicBool::new(false));
         let ready = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        // Without a server, nothing stops the WebSocket connections
+        let (_, websocket_shutdown) = tokio::sync::watch::channel(false);
         let routers = make_axum_router(
             live,
             ready,
@@ -371,6 +373,7 @@ impl<'a> TestHarness<'a> {
             &config,
             web_endpoints,
             LicenseState::Unlicensed,
+            websocket_shutdown,
         )?;
         let ListenAddrAndRouter(_listener, router) = routers.main;
         Ok(router.boxed())
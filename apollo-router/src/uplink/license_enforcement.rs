@@ -354,6 +354,30 @@ impl LicenseEnforcementReport {
                 .path("$.limits.max_aliases")
                 .name("Operation aliases limiting")
                 .build(),
+            ConfigurationRestriction::builder()
+                .path("$.limits.max_variables")
+                .name("Operation variables limiting")
+                .build(),
+            ConfigurationRestriction::builder()
+                .path("$.limits.max_directives")
+                .name("Operation directives limiting")
+                .build(),
+            ConfigurationRestriction::builder()
+                .path("$.limits.max_fragments")
+                .name("Operation fragments limiting")
+                .build(),
+            ConfigurationRestriction::builder()
+                .path("$.limits.max_input_depth")
+                .name("Operation input depth limiting")
+                .build(),
+            ConfigurationRestriction::builder()
+                .path("$.limits.max_list_length")
+                .name("Operation input list length limiting")
+                .build(),
+            ConfigurationRestriction::builder()
+                .path("$.limits.profiles")
+                .name("Operation limit profiles")
+                .build(),
             ConfigurationRestriction::builder()
                 .path("$.persisted_queries")
                 .name("Persisted queries")
@@ -223,6 +223,116 @@ async fn test_warn_only() {
     assert_eq!(execution_count(), 2);
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_document_limits() {
+    let (mut service, execution_count) = build_test_harness(json!({
+        "max_variables": 1,
+        "max_directives": 2,
+        "max_fragments": 1,
+    }))
+    .await;
+
+    // This query is just under each limit
+    let query = "
+    query($first: Int) {
+        topProducts(first: $first) @skip(if: false) {
+            ...productName @include(if: true)
+        }
+    }
+
+    fragment productName on Product {
+        name
+    }
+    ";
+    expect_errors(run_request(&mut service, query).await, &[]);
+    assert_eq!(execution_count(), 1);
+
+    let query = "query($first: Int, $withName: Boolean!) {
+        topProducts(first: $first) {
+            name @include(if: $withName)
+        }
+    }";
+    expect_errors(
+        run_request(&mut service, query).await,
+        &["MAX_VARIABLES_LIMIT"],
+    );
+    assert_eq!(execution_count(), 1);
+
+    // Directives in fragments count each time the fragment is used
+    let query = "
+    {
+        topProducts {
+            ...productName
+            reviews @include(if: true) {
+                product { ...productName }
+            }
+        }
+    }
+
+    fragment productName on Product {
+        name @include(if: true)
+    }
+    ";
+    expect_errors(
+        run_request(&mut service, query).await,
+        &["MAX_DIRECTIVES_LIMIT"],
+    );
+    assert_eq!(execution_count(), 1);
+
+    let query = "
+    {
+        topProducts {
+            ...productName
+            ...productPrice
+        }
+    }
+
+    fragment productName on Product {
+        name
+    }
+
+    fragment productPrice on Product {
+        price
+    }
+    ";
+    expect_errors(
+        run_request(&mut service, query).await,
+        &["MAX_FRAGMENTS_LIMIT"],
+    );
+    assert_eq!(execution_count(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_variables_limits() {
+    let (mut service, execution_count) = build_test_harness(json!({
+        "max_input_depth": 2,
+        "max_list_length": 3,
+    }))
+    .await;
+    let query = "{ me { id } }";
+
+    let variables = json!({ "filter": { "price": { "lt": 10 } }, "ids": [1, 2, 3] });
+    expect_errors(
+        run_request_with_variables(&mut service, query, variables).await,
+        &[],
+    );
+    assert_eq!(execution_count(), 1);
+
+    let variables = json!({ "filter": { "price": { "lt": { "value": 10 } } } });
+    expect_errors(
+        run_request_with_variables(&mut service, query, variables).await,
+        &["MAX_INPUT_DEPTH_LIMIT"],
+    );
+    assert_eq!(execution_count(), 1);
+
+    let variables = json!({ "filters": [{ "ids": [1, 2, 3, 4] }] });
+    expect_errors(
+        run_request_with_variables(&mut service, query, variables).await,
+        &["MAX_LIST_LENGTH_LIMIT"],
+    );
+    assert_eq!(execution_count(), 1);
+}
+
 async fn build_test_harness(
     limits_config: serde_json::Value,
 ) -> (supergraph::BoxCloneService, impl Fn() -> u32) {
@@ -274,6 +384,28 @@ async fn run_request(service: &mut supergraph::BoxCloneService, query: &str) ->
         .unwrap()
 }
 
+async fn run_request_with_variables(
+    service: &mut supergraph::BoxCloneService,
+    query: &str,
+    variables: serde_json::Value,
+) -> graphql::Response {
+    let mut request = supergraph::Request::fake_builder()
+        .query(query)
+        .build()
+        .unwrap();
+    request.supergraph_request.body_mut().variables = serde_json_bytes::Value::from(variables)
+        .as_object()
+        .unwrap()
+        .clone();
+    service
+        .oneshot(request)
+        .await
+        .unwrap()
+        .next_response()
+        .await
+        .unwrap()
+}
+
 #[track_caller]
 fn expect_errors(response: graphql::Response, expected_error_codes: &[&str]) {
     let errors = response.errors;